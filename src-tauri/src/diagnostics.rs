@@ -0,0 +1,123 @@
+//! Environment diagnostics for support requests.
+//!
+//! Collects read-only facts about the app, its tools, and the repository
+//! in one place so users can paste them into a bug report.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::diff;
+
+/// An external command-line tool we depend on.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolInfo {
+    /// Path or bare command name used to invoke it
+    pub path: String,
+    /// First line of `--version` output
+    pub version: Option<String>,
+}
+
+/// Facts about the repository being viewed.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepoDiagnostics {
+    pub git_dir: String,
+    pub workdir: Option<String>,
+    pub is_bare: bool,
+    /// Current branch, if on one
+    pub branch: Option<String>,
+}
+
+/// Everything reported by the diagnostics command.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    pub app_version: String,
+    pub libgit2_version: String,
+    /// The `git` CLI (used for fetching), if found
+    pub git: Option<ToolInfo>,
+    /// The GitHub CLI (used for auth), if found
+    pub gh: Option<ToolInfo>,
+    pub repo: Option<RepoDiagnostics>,
+    /// Why the repository couldn't be opened, if it couldn't
+    pub repo_error: Option<String>,
+}
+
+/// Collect diagnostics for the repository containing `repo_path`.
+///
+/// Never fails: missing tools or a broken repo are reported as fields.
+pub fn collect(repo_path: &Path) -> Diagnostics {
+    let (major, minor, patch) = git2::Version::get().libgit2_version();
+
+    let (repo, repo_error) = match diff::open_repo(repo_path) {
+        Ok(repo) => (Some(repo_diagnostics(&repo)), None),
+        Err(e) => (None, Some(e.0)),
+    };
+
+    Diagnostics {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        libgit2_version: format!("{}.{}.{}", major, minor, patch),
+        git: tool_info(diff::git::find_git_command()),
+        gh: diff::github::find_gh_command().and_then(tool_info),
+        repo,
+        repo_error,
+    }
+}
+
+fn repo_diagnostics(repo: &git2::Repository) -> RepoDiagnostics {
+    RepoDiagnostics {
+        git_dir: repo.path().to_string_lossy().to_string(),
+        workdir: repo.workdir().map(|p| p.to_string_lossy().to_string()),
+        is_bare: repo.is_bare(),
+        branch: diff::git::current_branch(repo).ok().flatten(),
+    }
+}
+
+/// Run `<tool> --version`, returning None if it can't be run.
+fn tool_info(path: PathBuf) -> Option<ToolInfo> {
+    let output = Command::new(&path).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let version = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|l| l.trim().to_string());
+    Some(ToolInfo {
+        path: path.to_string_lossy().to_string(),
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_collect_for_temp_repo() {
+        let dir = TempDir::new().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+
+        let diag = collect(dir.path());
+        assert!(!diag.libgit2_version.is_empty());
+        assert!(diag.repo_error.is_none());
+        let repo = diag.repo.unwrap();
+        assert!(!repo.is_bare);
+        assert!(repo.workdir.is_some());
+        // No commits yet, but HEAD still names the branch to be created
+        assert!(repo.branch.is_some());
+    }
+
+    #[test]
+    fn test_collect_outside_repo() {
+        let dir = TempDir::new().unwrap();
+        let diag = collect(&dir.path().join("missing"));
+        assert!(diag.repo.is_none());
+        assert!(diag.repo_error.is_some());
+    }
+
+    #[test]
+    fn test_missing_tool() {
+        assert!(tool_info(PathBuf::from("definitely-not-a-real-tool-xyz")).is_none());
+    }
+}
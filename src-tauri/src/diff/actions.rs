@@ -0,0 +1,514 @@
+//! Working tree edits driven from the diff view.
+
+use std::path::Path;
+
+use git2::{
+    ApplyLocation, Diff, DiffOptions, ErrorCode, Repository, Signature, StashApplyOptions,
+    StashFlags,
+};
+use serde::Serialize;
+
+use super::git::{file_hunk_spans, DiffAlgorithm, GitError, WORKDIR};
+use super::review::Edit;
+use super::types::{decode_text, decode_with_encoding, encode_text, Span};
+
+type Result<T> = std::result::Result<T, GitError>;
+
+/// Undo one hunk of a file's working tree changes, keeping the rest.
+///
+/// The hunk is identified by its `before`/`after` line spans, i.e. a changed
+/// alignment from a `base_ref..WORKDIR` diff, and must still match a hunk git
+/// reports, so a stale view can't clobber newer edits. The hunk's new lines
+/// are replaced with its old lines from `base_ref`, and the file is written
+/// back in the encoding it was read in (e.g. UTF-16 stays UTF-16).
+///
+/// `algorithm` must be the one the view was diffed with, since the hunks
+/// differ between algorithms.
+pub fn revert_hunk(
+    repo: &Repository,
+    base_ref: &str,
+    path: &str,
+    before: Span,
+    after: Span,
+    algorithm: DiffAlgorithm,
+) -> Result<()> {
+    let hunks = file_hunk_spans(repo, base_ref, WORKDIR, path, algorithm)?;
+    if !hunks.contains(&(before, after)) {
+        return Err(GitError(format!(
+            "No such change in {} (the file may have changed since it was loaded)",
+            path
+        )));
+    }
+
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitError("Bare repository".into()))?;
+    let full_path = workdir.join(path);
+    let current =
+        std::fs::read(&full_path).map_err(|e| GitError(format!("Cannot read file: {}", e)))?;
+    let (current, encoding) = decode_with_encoding(&current);
+    let base = decode_text(&base_content(repo, base_ref, path)?);
+
+    // Keep each line's own ending so untouched lines are written back exactly
+    let current_lines: Vec<&str> = current.split_inclusive('\n').collect();
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let range = |span: Span, len: usize| {
+        let end = (span.end as usize).min(len);
+        (span.start as usize).min(end)..end
+    };
+
+    let mut reverted = String::with_capacity(current.len());
+    let after_range = range(after, current_lines.len());
+    for line in &current_lines[..after_range.start] {
+        reverted.push_str(line);
+    }
+    for line in &base_lines[range(before, base_lines.len())] {
+        reverted.push_str(line);
+    }
+    for line in &current_lines[after_range.end..] {
+        reverted.push_str(line);
+    }
+
+    std::fs::write(&full_path, encode_text(&reverted, encoding))
+        .map_err(|e| GitError(format!("Cannot write file: {}", e)))
+}
+
+/// Apply a recorded review edit to the working tree, adopting the suggestion.
+///
+/// The edit's diff may be a full patch or just its `@@` hunks; headers for
+/// `edit.path` are added when missing. Nothing is written unless every hunk
+/// applies cleanly, and a patch that touches other files is rejected.
+pub fn apply_edit(repo: &Repository, edit: &Edit) -> Result<()> {
+    let patch = with_file_header(&edit.path, &edit.diff);
+    let diff = Diff::from_buffer(patch.as_bytes()).map_err(|e| {
+        GitError(format!(
+            "Edit for {} is not a valid unified diff: {}",
+            edit.path,
+            e.message()
+        ))
+    })?;
+
+    for delta in diff.deltas() {
+        for file in [delta.old_file(), delta.new_file()] {
+            if let Some(path) = file.path() {
+                if path != Path::new(&edit.path) {
+                    return Err(GitError(format!(
+                        "Edit for {} also changes {}",
+                        edit.path,
+                        path.display()
+                    )));
+                }
+            }
+        }
+    }
+
+    repo.apply(&diff, ApplyLocation::WorkDir, None)
+        .map_err(|e| {
+            GitError(format!(
+                "Edit no longer applies to {} (the file may have changed since): {}",
+                edit.path,
+                e.message()
+            ))
+        })
+}
+
+/// Add the headers git needs to parse a patch: all of them for bare hunks, or
+/// just the `diff --git` line for a patch starting at `---`/`+++`.
+fn with_file_header(path: &str, diff: &str) -> String {
+    let mut patch = if diff.starts_with("diff --git ") {
+        String::new()
+    } else if diff.starts_with("--- ") {
+        let mut lines = diff.lines();
+        let side = |line: Option<&str>, marker: &str, prefix: &str| {
+            line.and_then(|l| l.strip_prefix(marker))
+                .map(|p| p.strip_prefix(prefix).unwrap_or(p).to_string())
+                .unwrap_or_else(|| path.to_string())
+        };
+        let old = side(lines.next(), "--- ", "a/");
+        let new = side(lines.next(), "+++ ", "b/");
+        format!("diff --git a/{} b/{}\n", old, new)
+    } else {
+        format!("diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n", path)
+    };
+    patch.push_str(diff);
+    if !patch.ends_with('\n') {
+        patch.push('\n');
+    }
+    patch
+}
+
+/// What `create_commit` would commit, without committing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommitPreview {
+    /// Paths whose content would change relative to HEAD
+    pub staged_paths: Vec<String>,
+    pub additions: u32,
+    pub deletions: u32,
+}
+
+/// Preview a commit of `paths`: build the tree `create_commit` would write
+/// (HEAD plus those files as they are on disk) and report how it differs
+/// from HEAD. Neither HEAD nor the index on disk is touched; blobs and trees
+/// are written to the object database, as `git add` would.
+pub fn commit_preview(repo: &Repository, paths: &[String]) -> Result<CommitPreview> {
+    if paths.is_empty() {
+        return Err(GitError("No files selected for commit".into()));
+    }
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitError("Bare repository".into()))?;
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None, // Initial commit - no parent
+    };
+
+    // Stage into the repo's index object, but only in memory. Reloading
+    // it from disk afterwards drops our changes even if staging failed.
+    let mut index = repo.index()?;
+    let staged = (|| {
+        match &head_tree {
+            Some(tree) => index.read_tree(tree)?,
+            None => index.clear()?,
+        }
+        for path in paths {
+            if workdir.join(path).exists() {
+                index.add_path(Path::new(path))?;
+            } else {
+                index.remove_path(Path::new(path))?;
+            }
+        }
+        index.write_tree()
+    })();
+    index.read(true)?;
+    let tree = repo.find_tree(staged?)?;
+
+    let mut opts = DiffOptions::new();
+    opts.context_lines(0);
+    let diff = repo.diff_tree_to_tree(head_tree.as_ref(), Some(&tree), Some(&mut opts))?;
+    let stats = diff.stats()?;
+    let staged_paths = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    Ok(CommitPreview {
+        staged_paths,
+        additions: stats.insertions() as u32,
+        deletions: stats.deletions() as u32,
+    })
+}
+
+/// Message marking the stashes `discard_all_with_backup` makes.
+const DISCARD_STASH_MESSAGE: &str = "staged: discard all";
+
+/// Discard every change in the index and working tree, untracked files
+/// included, keeping them in a stash so `undo_last_discard` can bring them
+/// back. Returns the stash commit's SHA.
+///
+/// Without a configured git identity the stash is signed as Staged, so
+/// discarding still works.
+pub fn discard_all_with_backup(repo: &mut Repository) -> Result<String> {
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("Staged", "staged@localhost"))?;
+    let oid = repo
+        .stash_save(
+            &signature,
+            DISCARD_STASH_MESSAGE,
+            Some(StashFlags::INCLUDE_UNTRACKED),
+        )
+        .map_err(|e| match e.code() {
+            ErrorCode::NotFound => GitError("No changes to discard".into()),
+            _ => GitError(format!("Cannot back up changes before discarding: {}", e)),
+        })?;
+    Ok(oid.to_string())
+}
+
+/// Restore the changes the latest `discard_all_with_backup` stashed, staged
+/// ones back into the index, and drop the stash. If they'd conflict with
+/// edits made since, nothing is restored and the stash is kept.
+pub fn undo_last_discard(repo: &mut Repository) -> Result<()> {
+    let mut found = None;
+    repo.stash_foreach(|index, message, _| {
+        // Saved as "On <branch>: <message>"
+        if message.ends_with(DISCARD_STASH_MESSAGE) {
+            found = Some(index);
+            return false;
+        }
+        true
+    })?;
+    let index = found.ok_or_else(|| GitError("No discarded changes to restore".into()))?;
+
+    let mut opts = StashApplyOptions::new();
+    opts.reinstantiate_index();
+    repo.stash_pop(index, Some(&mut opts))
+        .map_err(|e| GitError(format!("Cannot restore discarded changes: {}", e)))
+}
+
+/// Raw content of `path` at `base_ref`, or empty if it doesn't exist there.
+fn base_content(repo: &Repository, base_ref: &str, path: &str) -> Result<Vec<u8>> {
+    let tree = repo
+        .revparse_single(base_ref)
+        .map_err(|e| GitError(format!("Cannot resolve '{}': {}", base_ref, e)))?
+        .peel_to_tree()?;
+    match tree.get_path(Path::new(path)) {
+        Ok(entry) => Ok(entry.to_object(repo)?.peel_to_blob()?.content().to_vec()),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::git::{compute_diff, DiffConfig};
+    use tempfile::TempDir;
+
+    fn repo_with_file(content: impl AsRef<[u8]>) -> (TempDir, Repository) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("f.txt"), content).unwrap();
+        {
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("f.txt")).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+            repo.commit(Some("HEAD"), &sig, &sig, "Initial", &tree, &[])
+                .unwrap();
+        }
+        (dir, repo)
+    }
+
+    fn statuses(repo: &Repository) -> Vec<(String, git2::Status)> {
+        repo.statuses(None)
+            .unwrap()
+            .iter()
+            .map(|e| (e.path().unwrap().to_string(), e.status()))
+            .collect()
+    }
+
+    #[test]
+    fn test_discard_all_and_undo() {
+        let (dir, mut repo) = repo_with_file("a\nb\n");
+        std::fs::write(dir.path().join("f.txt"), "a\nB\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("f.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::write(dir.path().join("f.txt"), "a\nB\nc\n").unwrap();
+        std::fs::write(dir.path().join("new.txt"), "new\n").unwrap();
+        let before = statuses(&repo);
+
+        let sha = discard_all_with_backup(&mut repo).unwrap();
+        assert_eq!(sha.len(), 40);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "a\nb\n"
+        );
+        assert!(!dir.path().join("new.txt").exists());
+        assert!(statuses(&repo).is_empty());
+        assert!(discard_all_with_backup(&mut repo).is_err());
+
+        undo_last_discard(&mut repo).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "a\nB\nc\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("new.txt")).unwrap(),
+            "new\n"
+        );
+        // Staged changes are staged again
+        assert_eq!(statuses(&repo), before);
+        assert!(undo_last_discard(&mut repo).is_err());
+    }
+
+    #[test]
+    fn test_commit_preview_leaves_index_alone() {
+        let (dir, repo) = repo_with_file("a\nb\n");
+        std::fs::write(dir.path().join("f.txt"), "a\nB\nc\n").unwrap();
+        std::fs::write(dir.path().join("new.txt"), "x\n").unwrap();
+        std::fs::write(dir.path().join("staged.txt"), "s\n").unwrap();
+        {
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("staged.txt")).unwrap();
+            index.write().unwrap();
+        }
+        let before = statuses(&repo);
+        let head = repo.head().unwrap().target();
+
+        let paths = ["f.txt", "new.txt", "unchanged.txt"].map(String::from);
+        let preview = commit_preview(&repo, &paths).unwrap();
+        assert_eq!(
+            preview,
+            CommitPreview {
+                staged_paths: vec!["f.txt".into(), "new.txt".into()],
+                additions: 3,
+                deletions: 1,
+            }
+        );
+
+        assert_eq!(statuses(&repo), before);
+        assert_eq!(repo.head().unwrap().target(), head);
+        assert!(commit_preview(&repo, &[]).is_err());
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let (dir, repo) = repo_with_file("a\nb\nc\n");
+        let path = dir.path().join("f.txt");
+
+        let edit = Edit::new("f.txt", "@@ -2 +2 @@\n-b\n+B");
+        apply_edit(&repo, &edit).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nB\nc\n");
+
+        // Full patches work too
+        let full =
+            "diff --git a/f.txt b/f.txt\n--- a/f.txt\n+++ b/f.txt\n@@ -3 +3,2 @@\n-c\n+c\n+d\n";
+        apply_edit(&repo, &Edit::new("f.txt", full)).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nB\nc\nd\n");
+    }
+
+    #[test]
+    fn test_apply_edit_rejects_conflicts() {
+        let (dir, repo) = repo_with_file("a\nb\nc\n");
+        let path = dir.path().join("f.txt");
+        std::fs::write(&path, "a\nchanged\nc\n").unwrap();
+
+        let edit = Edit::new("f.txt", "@@ -2 +2 @@\n-b\n+B\n");
+        let err = apply_edit(&repo, &edit).unwrap_err();
+        assert!(err.0.contains("no longer applies"), "{}", err.0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nchanged\nc\n");
+
+        let other = "--- a/other.txt\n+++ b/other.txt\n@@ -1 +1 @@\n-x\n+y\n";
+        let err = apply_edit(&repo, &Edit::new("f.txt", other)).unwrap_err();
+        assert!(err.0.contains("also changes"), "{}", err.0);
+
+        assert!(apply_edit(&repo, &Edit::new("f.txt", "not a diff")).is_err());
+    }
+
+    #[test]
+    fn test_revert_second_hunk() {
+        let (dir, repo) = repo_with_file("a\nb\nc\nd\ne\nf\n");
+        std::fs::write(dir.path().join("f.txt"), "a\nB\nc\nd\nE\nE2\nf\n").unwrap();
+
+        let hunks = file_hunk_spans(&repo, "HEAD", WORKDIR, "f.txt", DiffAlgorithm::Myers).unwrap();
+        assert_eq!(hunks.len(), 2);
+        let (before, after) = hunks[1];
+        assert_eq!((before, after), (Span::new(4, 5), Span::new(4, 6)));
+
+        revert_hunk(&repo, "HEAD", "f.txt", before, after, DiffAlgorithm::Myers).unwrap();
+
+        let content = std::fs::read_to_string(dir.path().join("f.txt")).unwrap();
+        assert_eq!(content, "a\nB\nc\nd\ne\nf\n");
+        assert_eq!(
+            file_hunk_spans(&repo, "HEAD", WORKDIR, "f.txt", DiffAlgorithm::Myers).unwrap(),
+            [(Span::new(1, 2), Span::new(1, 2))]
+        );
+
+        // The reverted hunk no longer exists
+        assert!(revert_hunk(&repo, "HEAD", "f.txt", before, after, DiffAlgorithm::Myers).is_err());
+    }
+
+    #[test]
+    fn test_revert_utf16_hunk() {
+        let utf16 = |text: &str| encode_text(text, Some("UTF-16LE"));
+        let (dir, repo) = repo_with_file(utf16("one\ntwo\nthree\nfour\nfive\n"));
+        std::fs::write(
+            dir.path().join("f.txt"),
+            utf16("one\nTWO\nthree\nfour\nFIVE\n"),
+        )
+        .unwrap();
+
+        // Git alone sees a binary file with no hunks
+        let hunks = file_hunk_spans(&repo, "HEAD", WORKDIR, "f.txt", DiffAlgorithm::Myers).unwrap();
+        assert_eq!(
+            hunks,
+            [
+                (Span::new(1, 2), Span::new(1, 2)),
+                (Span::new(4, 5), Span::new(4, 5))
+            ]
+        );
+        let diff = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+        let file = &diff.files[0];
+        assert_eq!(
+            file.after.as_ref().unwrap().encoding.as_deref(),
+            Some("UTF-16LE")
+        );
+        assert_eq!(file.alignments.iter().filter(|a| a.changed).count(), 2);
+
+        let (before, after) = hunks[1];
+        revert_hunk(&repo, "HEAD", "f.txt", before, after, DiffAlgorithm::Myers).unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("f.txt")).unwrap(),
+            utf16("one\nTWO\nthree\nfour\nfive\n")
+        );
+    }
+
+    #[test]
+    fn test_revert_insertion_and_deletion() {
+        let (dir, repo) = repo_with_file("a\nb\nc\n");
+        std::fs::write(dir.path().join("f.txt"), "new\na\nc\n").unwrap();
+
+        // Undo the deletion of "b", then the insertion of "new"
+        let hunks = file_hunk_spans(&repo, "HEAD", WORKDIR, "f.txt", DiffAlgorithm::Myers).unwrap();
+        assert_eq!(hunks.len(), 2);
+        let (before, after) = hunks[1];
+        revert_hunk(&repo, "HEAD", "f.txt", before, after, DiffAlgorithm::Myers).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "new\na\nb\nc\n"
+        );
+
+        let (before, after) =
+            file_hunk_spans(&repo, "HEAD", WORKDIR, "f.txt", DiffAlgorithm::Myers).unwrap()[0];
+        revert_hunk(&repo, "HEAD", "f.txt", before, after, DiffAlgorithm::Myers).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "a\nb\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_revert_patience_hunk() {
+        // Myers splices `fib` into `fact`; patience shows it as one insertion
+        let before = "#include <stdio.h>\n\n// Frobs foo heartily\nint frobnitz(int foo)\n{\n    int i;\n    for(i = 0; i < 10; i++)\n    {\n        printf(\"Your answer is: \");\n        printf(\"%d\\n\", foo);\n    }\n}\n\nint fact(int n)\n{\n    if(n > 1)\n    {\n        return fact(n-1) * n;\n    }\n    return 1;\n}\n\nint main(int argc, char **argv)\n{\n    frobnitz(fact(10));\n}\n";
+        let after = "#include <stdio.h>\n\nint fib(int n)\n{\n    if(n > 2)\n    {\n        return fib(n-1) + fib(n-2);\n    }\n    return 1;\n}\n\n// Frobs foo heartily\nint frobnitz(int foo)\n{\n    int i;\n    for(i = 0; i < 10; i++)\n    {\n        printf(\"%d\\n\", foo);\n    }\n}\n\nint main(int argc, char **argv)\n{\n    frobnitz(fib(10));\n}\n";
+        let (dir, repo) = repo_with_file(before);
+        std::fs::write(dir.path().join("f.txt"), after).unwrap();
+
+        // The hunk as the view shows it under patience
+        let config = DiffConfig {
+            algorithm: DiffAlgorithm::Patience,
+            ..Default::default()
+        };
+        let diff = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        let fib = diff.files[0].alignments.iter().find(|a| a.changed).unwrap();
+        assert_eq!((fib.before, fib.after), (Span::new(2, 2), Span::new(2, 11)));
+
+        // Myers has no such hunk
+        assert!(revert_hunk(
+            &repo,
+            "HEAD",
+            "f.txt",
+            fib.before,
+            fib.after,
+            DiffAlgorithm::Myers
+        )
+        .is_err());
+
+        revert_hunk(
+            &repo,
+            "HEAD",
+            "f.txt",
+            fib.before,
+            fib.after,
+            DiffAlgorithm::Patience,
+        )
+        .unwrap();
+        let content = std::fs::read_to_string(dir.path().join("f.txt")).unwrap();
+        assert!(!content.contains("fib(int n)"));
+        assert!(content.starts_with("#include <stdio.h>\n\n// Frobs foo heartily\n"));
+        assert!(content.ends_with("frobnitz(fib(10));\n}\n"));
+    }
+}
@@ -0,0 +1,151 @@
+//! Line blame for review context.
+//!
+//! Lets reviewers see who last touched the lines they're commenting on.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use git2::{BlameOptions, Oid, Repository};
+use serde::{Deserialize, Serialize};
+
+use super::git::{author_name, count_lines, load_mailmap, GitError, WORKDIR};
+
+type Result<T> = std::result::Result<T, GitError>;
+
+/// Who last changed a single line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlameLine {
+    /// Line number (0-indexed, like `Span`)
+    pub lineno: u32,
+    /// Short SHA of the commit that last changed the line
+    pub sha: String,
+    pub author: String,
+    /// First line of that commit's message
+    pub summary: String,
+}
+
+/// Blame lines `start..end` (0-indexed, exclusive end) of `path` as of `ref_str`.
+///
+/// The working tree isn't blamed directly: `WORKDIR` blames HEAD, so
+/// uncommitted lines are attributed to whatever they replaced. The range is
+/// clamped to the file's length. Authors go through the repo's mailmap.
+pub fn blame_lines(
+    repo: &Repository,
+    ref_str: &str,
+    path: &str,
+    start: u32,
+    end: u32,
+) -> Result<Vec<BlameLine>> {
+    let ref_str = if ref_str == WORKDIR { "HEAD" } else { ref_str };
+    let commit = repo
+        .revparse_single(ref_str)
+        .map_err(|e| GitError(format!("Cannot resolve '{}': {}", ref_str, e)))?
+        .peel_to_commit()
+        .map_err(|e| GitError(format!("'{}' is not a commit: {}", ref_str, e)))?;
+
+    // Clamp to the file as it exists at this commit
+    let blob = commit
+        .tree()?
+        .get_path(Path::new(path))
+        .map_err(|_| GitError(format!("'{}' does not exist at {}", path, ref_str)))?
+        .to_object(repo)?
+        .peel_to_blob()?;
+    let end = end.min(count_lines(blob.content()));
+    if start >= end {
+        return Ok(Vec::new());
+    }
+
+    // Only blame the requested range; BlameOptions lines are 1-indexed, inclusive
+    let mut opts = BlameOptions::new();
+    opts.newest_commit(commit.id())
+        .min_line(start as usize + 1)
+        .max_line(end as usize);
+    let blame = repo.blame_file(Path::new(path), Some(&mut opts))?;
+
+    let mailmap = load_mailmap(repo);
+    let mut summaries: HashMap<Oid, String> = HashMap::new();
+    let mut lines = Vec::new();
+    for lineno in start..end {
+        let Some(hunk) = blame.get_line(lineno as usize + 1) else {
+            continue;
+        };
+        let id = hunk.final_commit_id();
+        let summary = match summaries.get(&id) {
+            Some(summary) => summary.clone(),
+            None => {
+                let summary = repo.find_commit(id)?.summary().unwrap_or("").to_string();
+                summaries.insert(id, summary.clone());
+                summary
+            }
+        };
+        let sha = id.to_string();
+        lines.push(BlameLine {
+            lineno,
+            sha: sha[..8.min(sha.len())].to_string(),
+            author: author_name(mailmap.as_ref(), &hunk.final_signature()),
+            summary,
+        });
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn commit_file(repo: &Repository, author: &str, content: &str, message: &str) {
+        std::fs::write(repo.workdir().unwrap().join("a.txt"), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now(author, "dev@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_blame_lines() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        commit_file(&repo, "Ada", "one\ntwo\nthree\n", "Initial");
+        commit_file(
+            &repo,
+            "Grace",
+            "one\nTWO\nthree\nfour\n",
+            "Edit two, add four",
+        );
+
+        let authors = |lines: &[BlameLine]| -> Vec<(u32, String)> {
+            lines.iter().map(|l| (l.lineno, l.author.clone())).collect()
+        };
+
+        let lines = blame_lines(&repo, "HEAD", "a.txt", 0, 4).unwrap();
+        assert_eq!(
+            authors(&lines),
+            [
+                (0, "Ada".to_string()),
+                (1, "Grace".to_string()),
+                (2, "Ada".to_string()),
+                (3, "Grace".to_string()),
+            ]
+        );
+        assert_eq!(lines[1].summary, "Edit two, add four");
+
+        // A sub-range, clamped to the file length, and the working tree
+        let lines = blame_lines(&repo, WORKDIR, "a.txt", 2, 100).unwrap();
+        assert_eq!(
+            authors(&lines),
+            [(2, "Ada".to_string()), (3, "Grace".to_string())]
+        );
+
+        // Earlier ref
+        let lines = blame_lines(&repo, "HEAD~1", "a.txt", 0, 10).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| l.author == "Ada"));
+    }
+}
@@ -3,12 +3,26 @@
 //! All functions are stateless - they discover the repo fresh each call.
 
 use std::cell::RefCell;
-use std::path::Path;
-
-use git2::{Delta, Diff, DiffOptions, Repository, Tree};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use git2::{
+    AttrCheckFlags, AttrValue, Delta, Diff, DiffFormat, DiffHunk, DiffOptions, ErrorCode, FileMode,
+    Mailmap, Patch, Repository, Signature, Status, StatusOptions, Tree,
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
 use serde::{Deserialize, Serialize};
 
-use super::types::{Alignment, File, FileContent, FileDiff, Span};
+use crate::executable::find_executable;
+
+use super::types::{
+    decode_text, is_binary_side, Alignment, ChangeKind, File, FileContent, FileDiff, LfsPointer,
+    ModeChange, Span, DEFAULT_BINARY_SCAN_LEN, SYMLINK_MODE,
+};
 
 /// Error type for git operations.
 #[derive(Debug)]
@@ -22,6 +36,20 @@ impl std::fmt::Display for GitError {
 
 impl std::error::Error for GitError {}
 
+impl GitError {
+    /// The error a cancelled diff returns.
+    pub fn cancelled() -> Self {
+        GitError(CANCELLED.to_string())
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0 == CANCELLED
+    }
+}
+
+/// Message of the error returned when a diff is cancelled.
+pub const CANCELLED: &str = "Diff cancelled";
+
 impl From<git2::Error> for GitError {
     fn from(e: git2::Error) -> Self {
         GitError(e.message().to_string())
@@ -34,11 +62,47 @@ type Result<T> = std::result::Result<T, GitError>;
 /// This is NOT a git ref - it's our own convention, handled specially in compute_diff.
 pub const WORKDIR: &str = "WORKDIR";
 
+/// Special ref representing the index, so `HEAD..STAGED` is the staged changes.
+pub const STAGED: &str = "STAGED";
+
+/// Special ref for just the unstaged changes. As a target it always diffs
+/// from the index to the working tree, whatever the base.
+pub const UNSTAGED: &str = "UNSTAGED";
+
+/// Special ref for the empty tree, so `EMPTY..<root-sha>` shows a root
+/// commit's files as all added.
+pub const EMPTY: &str = "EMPTY";
+
+/// SHA of git's empty tree. libgit2 knows this object without it being in
+/// the object database, so resolving it never writes to `.git/objects`.
+const EMPTY_TREE_SHA: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Git's empty tree, read without writing it to the object database.
+fn empty_tree(repo: &Repository) -> Result<Tree<'_>> {
+    Ok(repo.find_tree(git2::Oid::from_str(EMPTY_TREE_SHA)?)?)
+}
+
+/// True for the special refs whose side of a diff is the working tree.
+pub fn is_working_tree_ref(ref_str: &str) -> bool {
+    ref_str == WORKDIR || ref_str == UNSTAGED
+}
+
+/// The ref a diff actually starts from: unstaged changes start at the index.
+fn effective_base<'a>(before_ref: &'a str, after_ref: &str) -> &'a str {
+    if after_ref == UNSTAGED {
+        STAGED
+    } else {
+        before_ref
+    }
+}
+
 /// A git reference with its type for display purposes.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitRef {
     pub name: String,
     pub ref_type: RefType,
+    /// True for the currently checked-out branch
+    pub current: bool,
 }
 
 /// Result of fetching a PR branch.
@@ -72,100 +136,420 @@ pub fn get_refs(repo: &Repository) -> Result<Vec<GitRef>> {
     let mut refs = Vec::new();
 
     // Special refs first (most commonly used)
-    refs.push(GitRef {
-        name: WORKDIR.to_string(),
-        ref_type: RefType::Special,
-    });
-    refs.push(GitRef {
-        name: "HEAD".to_string(),
-        ref_type: RefType::Special,
-    });
-    refs.push(GitRef {
-        name: "HEAD~1".to_string(),
-        ref_type: RefType::Special,
-    });
+    for name in [WORKDIR, "HEAD", "HEAD~1"] {
+        refs.push(GitRef {
+            name: name.to_string(),
+            ref_type: RefType::Special,
+            current: false,
+        });
+    }
 
-    // Local branches
+    // Local branches, current one first
+    let mut branches = Vec::new();
     for branch in repo.branches(Some(git2::BranchType::Local))? {
         let (branch, _) = branch?;
         if let Some(name) = branch.name()? {
-            refs.push(GitRef {
+            branches.push(GitRef {
                 name: name.to_string(),
                 ref_type: RefType::Branch,
+                current: branch.is_head(),
             });
         }
     }
+    branches.sort_by_cached_key(|r| (!r.current, r.name.to_lowercase(), r.name.clone()));
+    refs.extend(branches);
 
     // Tags
+    let mut tags = Vec::new();
     repo.tag_foreach(|_oid, name| {
         if let Ok(name) = std::str::from_utf8(name) {
             let name = name.strip_prefix("refs/tags/").unwrap_or(name);
-            refs.push(GitRef {
+            tags.push(GitRef {
                 name: name.to_string(),
                 ref_type: RefType::Tag,
+                current: false,
             });
         }
         true
     })?;
+    tags.sort_by_cached_key(|r| (r.name.to_lowercase(), r.name.clone()));
+    refs.extend(tags);
 
     Ok(refs)
 }
 
 /// Resolve a ref to a short SHA for display, or validate it exists.
 ///
-/// Returns "working tree" for WORKDIR, otherwise returns the short (8-char) SHA.
+/// Returns "working tree" for WORKDIR (and similar for STAGED and UNSTAGED),
+/// otherwise returns the short (8-char) SHA of the commit it points to.
 pub fn resolve_ref(repo: &Repository, ref_str: &str) -> Result<String> {
-    if ref_str == WORKDIR {
-        return Ok("working tree".to_string());
+    match ref_str {
+        WORKDIR => return Ok("working tree".to_string()),
+        STAGED => return Ok("staged changes".to_string()),
+        UNSTAGED => return Ok("unstaged changes".to_string()),
+        EMPTY => return Ok("empty tree".to_string()),
+        _ => {}
     }
 
+    // Return short SHA (first 8 characters)
+    let full_sha = resolve_commit_sha(repo, ref_str)?;
+    Ok(full_sha[..8.min(full_sha.len())].to_string())
+}
+
+/// Resolve a ref to a full SHA, peeling annotated tags to the commit they
+/// point at, so a tag and its commit give the same answer. Objects that
+/// aren't commit-ish (e.g. a tree SHA) resolve to themselves.
+pub fn resolve_commit_sha(repo: &Repository, ref_str: &str) -> Result<String> {
     let obj = repo
         .revparse_single(ref_str)
         .map_err(|e| GitError(format!("Cannot resolve '{}': {}", ref_str, e)))?;
+    let id = obj
+        .peel(git2::ObjectType::Commit)
+        .map(|commit| commit.id())
+        .unwrap_or_else(|_| obj.id());
+    Ok(id.to_string())
+}
 
-    // Return short SHA (first 8 characters)
-    let full_sha = obj.id().to_string();
-    Ok(full_sha[..8.min(full_sha.len())].to_string())
+/// A ref paired with its short SHA, or the error message if it didn't resolve.
+pub type RefResolution = (String, std::result::Result<String, String>);
+
+/// Resolve several refs against one repository handle.
+///
+/// Each ref gets its own result, so one invalid ref doesn't fail the batch.
+/// Order matches the input.
+pub fn resolve_refs(repo: &Repository, refs: &[String]) -> Vec<RefResolution> {
+    refs.iter()
+        .map(|r| (r.clone(), resolve_ref(repo, r).map_err(|e| e.0)))
+        .collect()
+}
+
+/// Whether each side of a diff resolves, for checking refs typed by the
+/// user before starting a review.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffRefsValidation {
+    pub base_ok: bool,
+    pub head_ok: bool,
+    /// Full commit SHA, or the special ref itself (e.g. WORKDIR)
+    pub base_resolved: Option<String>,
+    pub head_resolved: Option<String>,
+    /// Why the pair can't be diffed, naming the side(s) at fault
+    pub error: Option<String>,
+}
+
+/// Check that `base` and `head` both resolve, and that a working tree ref
+/// isn't used as the base, which `compute_diff` would reject.
+pub fn validate_diff_refs(repo: &Repository, base: &str, head: &str) -> DiffRefsValidation {
+    let resolve = |ref_str: &str| -> std::result::Result<String, String> {
+        if [WORKDIR, STAGED, UNSTAGED, EMPTY].contains(&ref_str) {
+            return Ok(ref_str.to_string());
+        }
+        resolve_commit_sha(repo, ref_str).map_err(|e| e.0)
+    };
+    let base_result = if is_working_tree_ref(base) {
+        Err(format!(
+            "{} can only be used as the target (head), not the base",
+            base
+        ))
+    } else {
+        resolve(base)
+    };
+    let head_result = resolve(head);
+
+    let errors: Vec<String> = [("Base", &base_result), ("Head", &head_result)]
+        .into_iter()
+        .filter_map(|(side, result)| Some(format!("{}: {}", side, result.as_ref().err()?)))
+        .collect();
+    DiffRefsValidation {
+        base_ok: base_result.is_ok(),
+        head_ok: head_result.is_ok(),
+        base_resolved: base_result.ok(),
+        head_resolved: head_result.ok(),
+        error: (!errors.is_empty()).then(|| errors.join("; ")),
+    }
 }
 
 /// Get the current branch name.
+///
+/// In a repository with no commits yet this is the branch HEAD points at,
+/// which the first commit will create.
 pub fn current_branch(repo: &Repository) -> Result<Option<String>> {
     match repo.head() {
         Ok(head) if head.is_branch() => Ok(head.shorthand().map(String::from)),
-        Ok(_) => Ok(None),  // Detached HEAD
-        Err(_) => Ok(None), // No commits yet
+        Ok(_) => Ok(None), // Detached HEAD
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            let head = repo.find_reference("HEAD")?;
+            Ok(head
+                .symbolic_target()
+                .and_then(|target| target.strip_prefix("refs/heads/"))
+                .map(String::from))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// The commit HEAD is at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeadSha {
+    pub full: String,
+    /// The first 8 characters, as in `LogEntry::short_sha`
+    pub short: String,
+    /// True if HEAD points straight at a commit rather than a branch
+    pub detached: bool,
+    /// The checked-out branch, None when detached
+    pub branch: Option<String>,
+}
+
+/// Read HEAD's commit without resolving it as a general ref. None if the
+/// repository has no commits yet.
+pub fn head_sha(repo: &Repository) -> Result<Option<HeadSha>> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let oid = head
+        .target()
+        .ok_or_else(|| GitError("HEAD doesn't point at a commit".into()))?;
+    let full = oid.to_string();
+    let detached = !head.is_branch();
+    let branch = if detached {
+        None
+    } else {
+        head.shorthand().map(String::from)
+    };
+    Ok(Some(HeadSha {
+        short: full[..8].to_string(),
+        full,
+        detached,
+        branch,
+    }))
+}
+
+/// True if HEAD names a branch with no commits yet (a freshly initialized repo).
+fn head_is_unborn(repo: &Repository) -> bool {
+    matches!(repo.head(), Err(e) if e.code() == ErrorCode::UnbornBranch)
+}
+
+/// Resolve the current branch's upstream (e.g. `origin/main`) to a full SHA.
+pub fn upstream_of_head(repo: &Repository) -> Result<String> {
+    let name = current_branch(repo)?
+        .ok_or_else(|| GitError("HEAD is not on a branch, so it has no upstream".into()))?;
+    let branch = repo.find_branch(&name, git2::BranchType::Local)?;
+    let upstream = branch
+        .upstream()
+        .map_err(|_| GitError(format!("Branch '{}' has no upstream", name)))?;
+    let oid = upstream.get().peel_to_commit()?.id();
+    Ok(oid.to_string())
+}
+
+/// Whether git ignores a path, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoreStatus {
+    pub ignored: bool,
+    /// The ignore rule that matched, as written (e.g. `target/`)
+    pub matching_pattern: Option<String>,
+    /// The file the rule came from, relative to the repo root when inside it
+    pub source: Option<String>,
+}
+
+/// Check whether `path` (repo-relative) is ignored, to explain why a file is
+/// missing from status. Tracked files are never ignored.
+///
+/// libgit2 makes the decision; the matching rule is then looked up in the
+/// `.gitignore` files from the path's directory up to the root, then
+/// `.git/info/exclude`, then the global excludes file, in git's precedence.
+pub fn path_ignore_status(repo: &Repository, path: &str) -> Result<IgnoreStatus> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitError("Bare repository".into()))?;
+    let tracked = repo.index()?.get_path(Path::new(path), 0).is_some();
+    if tracked || !repo.status_should_ignore(Path::new(path))? {
+        return Ok(IgnoreStatus {
+            ignored: false,
+            matching_pattern: None,
+            source: None,
+        });
+    }
+
+    let is_dir = workdir.join(path).is_dir();
+    let mut ignore_files: Vec<PathBuf> = Path::new(path)
+        .ancestors()
+        .skip(1)
+        .map(|dir| workdir.join(dir).join(".gitignore"))
+        .collect();
+    ignore_files.push(repo.path().join("info").join("exclude"));
+
+    let mut matchers: Vec<Gitignore> = ignore_files
+        .iter()
+        .filter(|file| file.is_file())
+        .filter_map(|file| {
+            let mut builder = GitignoreBuilder::new(file.parent()?);
+            builder.add(file);
+            builder.build().ok()
+        })
+        .collect();
+    let (global, _) = Gitignore::global();
+    matchers.push(global);
+
+    let rule = matchers.iter().find_map(|matcher| {
+        match matcher.matched_path_or_any_parents(workdir.join(path), is_dir) {
+            ignore::Match::Ignore(glob) => Some(glob),
+            _ => None,
+        }
+    });
+
+    Ok(IgnoreStatus {
+        ignored: true,
+        matching_pattern: rule.map(|glob| glob.original().to_string()),
+        source: rule.and_then(|glob| glob.from()).map(|from| {
+            from.strip_prefix(workdir)
+                .unwrap_or(from)
+                .to_string_lossy()
+                .replace('\\', "/")
+        }),
+    })
+}
+
+/// Normalize a path from the frontend to the repo-relative, forward-slash
+/// form git uses: backslashes become slashes, `.` and empty components are
+/// dropped, `..` is resolved, and absolute paths inside the working tree are
+/// made relative. Absolute paths elsewhere, paths that climb above the root,
+/// and paths that name the root itself are rejected rather than silently
+/// matching nothing.
+pub fn repo_relative_path(repo: &Repository, path: &str) -> Result<String> {
+    let normalized = path.replace('\\', "/");
+    let bytes = normalized.as_bytes();
+    let has_drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    let outside = || GitError(format!("'{}' is outside the repository", path));
+
+    let relative = if normalized.starts_with('/') || has_drive {
+        let workdir = repo.workdir().ok_or_else(outside)?;
+        let root = workdir.to_string_lossy().replace('\\', "/");
+        normalized
+            .strip_prefix(root.trim_end_matches('/'))
+            .and_then(|rest| rest.strip_prefix('/'))
+            .ok_or_else(outside)?
+    } else {
+        normalized.as_str()
+    };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in relative.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop().ok_or_else(outside)?;
+            }
+            name => components.push(name),
+        }
     }
+    if components.is_empty() {
+        return Err(GitError(format!("'{}' does not name a file", path)));
+    }
+    Ok(components.join("/"))
 }
 
 /// Basic repository info needed by the frontend.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepoInfo {
-    /// Absolute path to the repository root.
+    /// Absolute path to the repository root (the git directory for bare repos).
     pub repo_path: String,
+    /// Working tree root, or None for a bare repository.
+    pub workdir: Option<String>,
     /// Current branch name, if on a branch.
     pub branch: Option<String>,
+    /// Merge, rebase, etc. in progress, if any.
+    pub operation: OperationState,
+}
+
+/// A multi-step git operation the repository is in the middle of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationState {
+    Clean,
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+    /// `git am`, applying patches from a mailbox
+    ApplyMailbox,
+}
+
+/// Report which git operation, if any, is in progress (e.g. a merge with
+/// conflicts left to resolve).
+pub fn operation_state(repo: &Repository) -> OperationState {
+    use git2::RepositoryState as State;
+    match repo.state() {
+        State::Clean => OperationState::Clean,
+        State::Merge => OperationState::Merge,
+        State::Rebase | State::RebaseInteractive | State::RebaseMerge => OperationState::Rebase,
+        State::CherryPick | State::CherryPickSequence => OperationState::CherryPick,
+        State::Revert | State::RevertSequence => OperationState::Revert,
+        State::Bisect => OperationState::Bisect,
+        State::ApplyMailbox | State::ApplyMailboxOrRebase => OperationState::ApplyMailbox,
+    }
 }
 
 /// Get basic repository info (path and branch).
+///
+/// Bare repositories are supported: ref-to-ref diffs work without a
+/// working tree, only working tree operations fail.
 pub fn get_repo_info(repo: &Repository) -> Result<RepoInfo> {
-    let repo_path = repo
-        .workdir()
-        .ok_or_else(|| GitError("Bare repository".into()))?
-        .to_string_lossy()
-        .to_string();
+    let workdir = repo.workdir().map(|p| p.to_string_lossy().to_string());
+    let repo_path = workdir
+        .clone()
+        .unwrap_or_else(|| repo.path().to_string_lossy().to_string());
 
     let branch = current_branch(repo)?;
 
-    Ok(RepoInfo { repo_path, branch })
+    Ok(RepoInfo {
+        repo_path,
+        workdir,
+        branch,
+        operation: operation_state(repo),
+    })
 }
 
-/// Get the last commit message (for amend).
+/// Get the last commit message (for amend). None if there are no commits yet.
 pub fn last_commit_message(repo: &Repository) -> Result<Option<String>> {
+    if head_is_unborn(repo) {
+        return Ok(None);
+    }
     let head = repo.head()?;
     let commit = head.peel_to_commit()?;
     Ok(commit.message().map(String::from))
 }
 
+/// A commit's full message, for reword UIs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitMessage {
+    pub sha: String,
+    pub message: String,
+}
+
+/// Get the messages of the last `n` commits reachable from HEAD, newest
+/// first. Empty if there are no commits yet.
+pub fn recent_commit_messages(repo: &Repository, n: usize) -> Result<Vec<CommitMessage>> {
+    if repo.head().is_err() {
+        return Ok(Vec::new()); // Unborn branch - nothing committed yet
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+    walk.take(n)
+        .map(|oid| {
+            let commit = repo.find_commit(oid?)?;
+            Ok(CommitMessage {
+                sha: commit.id().to_string(),
+                message: commit.message().unwrap_or("").to_string(),
+            })
+        })
+        .collect()
+}
+
 /// Create a commit with the specified files and message.
 ///
 /// This stages only the specified files (resetting the index first to avoid
@@ -182,19 +566,24 @@ pub fn create_commit(repo: &Repository, paths: &[String], message: &str) -> Resu
     }
 
     // Get the current HEAD commit (parent for new commit)
-    let parent_commit = match repo.head() {
-        Ok(head) => Some(head.peel_to_commit()?),
-        Err(_) => None, // Initial commit - no parent
+    let parent_commit = if head_is_unborn(repo) {
+        None // Initial commit - no parent
+    } else {
+        Some(repo.head()?.peel_to_commit()?)
     };
 
     // Get the index
     let mut index = repo.index()?;
 
     // Reset index to HEAD to start fresh (removes any previously staged changes)
-    if let Some(ref parent) = parent_commit {
-        repo.reset(parent.as_object(), git2::ResetType::Mixed, None)?;
-        // Reload index after reset
-        index = repo.index()?;
+    match parent_commit {
+        Some(ref parent) => {
+            repo.reset(parent.as_object(), git2::ResetType::Mixed, None)?;
+            // Reload index after reset
+            index = repo.index()?;
+        }
+        // Nothing to reset to: an empty index is what HEAD would give
+        None => index.clear()?,
     }
 
     // Stage only the specified files
@@ -240,6 +629,186 @@ pub fn create_commit(repo: &Repository, paths: &[String], message: &str) -> Resu
     Ok(full_sha[..8.min(full_sha.len())].to_string())
 }
 
+/// Reword the last commit, keeping its tree.
+///
+/// Unlike `create_commit`, nothing is taken from the index, so any staged
+/// changes stay staged. Returns the short SHA of the amended commit.
+pub fn amend_commit_message(repo: &Repository, message: &str) -> Result<String> {
+    if message.trim().is_empty() {
+        return Err(GitError("Commit message cannot be empty".into()));
+    }
+
+    let head = repo
+        .head()
+        .map_err(|_| GitError("No commit to amend".into()))?
+        .peel_to_commit()?;
+
+    // None for the tree (and author/committer/encoding) keeps the original
+    let commit_oid = head.amend(Some("HEAD"), None, None, None, Some(message), None)?;
+
+    let full_sha = commit_oid.to_string();
+    Ok(full_sha[..8.min(full_sha.len())].to_string())
+}
+
+/// Stage the working-tree version of one file (or its deletion).
+///
+/// If the file has partially staged changes, i.e. the index holds a version
+/// that is neither HEAD's nor the working tree's, this fails rather than
+/// overwrite them; `stage_file_force` stages it anyway. Staging a file that
+/// is already fully staged does nothing.
+pub fn stage_file(repo: &Repository, path: &str) -> Result<()> {
+    let status = repo.status_file(Path::new(path))?;
+    if !index_changes(status).is_empty() && !worktree_changes(status).is_empty() {
+        return Err(GitError(format!(
+            "'{}' has partially staged changes that staging the whole file would overwrite",
+            path
+        )));
+    }
+    stage_file_force(repo, path)
+}
+
+/// Stage the working-tree version of one file (or its deletion), replacing
+/// whatever is staged for it.
+pub fn stage_file_force(repo: &Repository, path: &str) -> Result<()> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitError("Bare repository has no working tree".to_string()))?;
+    let full_path = workdir.join(path);
+    let mut index = repo.index()?;
+    if full_path.exists() || full_path.is_symlink() {
+        index.add_path(Path::new(path))?;
+    } else {
+        index.remove_path(Path::new(path))?;
+    }
+    index.write()?;
+    Ok(())
+}
+
+/// Stage every working-tree change, optionally limited to paths under `prefix`.
+///
+/// Deletions are staged as removals. Untracked files are only added when
+/// `include_untracked` is set. Returns the number of paths staged.
+pub fn stage_all(
+    repo: &Repository,
+    include_untracked: bool,
+    prefix: Option<&str>,
+) -> Result<usize> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(include_untracked)
+        .recurse_untracked_dirs(include_untracked)
+        .exclude_submodules(true);
+    if let Some(prefix) = prefix {
+        opts.pathspec(prefix);
+    }
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let mut index = repo.index()?;
+    let mut count = 0;
+
+    for entry in statuses.iter() {
+        let status = entry.status();
+        let Some(path) = entry.path() else {
+            continue;
+        };
+        let path = Path::new(path);
+
+        if status.is_wt_deleted() {
+            index.remove_path(path)?;
+        } else if status.is_wt_new() {
+            if !include_untracked {
+                continue;
+            }
+            index.add_path(path)?;
+        } else if status
+            .intersects(Status::WT_MODIFIED | Status::WT_TYPECHANGE | Status::WT_RENAMED)
+        {
+            index.add_path(path)?;
+        } else {
+            continue;
+        }
+        count += 1;
+    }
+
+    index.write()?;
+    Ok(count)
+}
+
+/// Unstage every staged change, optionally limited to paths under `prefix`.
+///
+/// Returns the number of paths unstaged.
+pub fn unstage_all(repo: &Repository, prefix: Option<&str>) -> Result<usize> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false).exclude_submodules(true);
+    if let Some(prefix) = prefix {
+        opts.pathspec(prefix);
+    }
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let paths: Vec<String> = statuses
+        .iter()
+        .filter(|entry| !index_changes(entry.status()).is_empty())
+        .filter_map(|entry| entry.path().map(String::from))
+        .collect();
+
+    if paths.is_empty() {
+        return Ok(0);
+    }
+
+    match repo.head() {
+        Ok(head) => {
+            let head = head.peel_to_commit()?;
+            repo.reset_default(Some(head.as_object()), &paths)?;
+        }
+        // No commits yet: everything staged is new, so drop it from the index
+        Err(_) => {
+            let mut index = repo.index()?;
+            for path in &paths {
+                index.remove_path(Path::new(path))?;
+            }
+            index.write()?;
+        }
+    }
+
+    Ok(paths.len())
+}
+
+/// The unstaged (working tree vs index) part of a status.
+fn worktree_changes(status: Status) -> Status {
+    status
+        & (Status::WT_NEW
+            | Status::WT_MODIFIED
+            | Status::WT_DELETED
+            | Status::WT_RENAMED
+            | Status::WT_TYPECHANGE)
+}
+
+/// The staged (index vs HEAD) part of a status.
+fn index_changes(status: Status) -> Status {
+    status
+        & (Status::INDEX_NEW
+            | Status::INDEX_MODIFIED
+            | Status::INDEX_DELETED
+            | Status::INDEX_RENAMED
+            | Status::INDEX_TYPECHANGE)
+}
+
+/// Env var naming the `git` executable to use for CLI operations.
+pub const GIT_PATH_ENV: &str = "STAGED_GIT_PATH";
+
+/// Find the `git` CLI executable, for the few operations libgit2 can't do
+/// (fetching with the user's credential helpers).
+///
+/// `STAGED_GIT_PATH` wins if it names a file, then `git` from PATH, then
+/// common locations. Falls back to a bare `git` so errors still mention it.
+/// The result is cached for the life of the process.
+pub fn find_git_command() -> PathBuf {
+    static GIT: OnceLock<PathBuf> = OnceLock::new();
+    GIT.get_or_init(|| {
+        find_executable("git", Some(GIT_PATH_ENV)).unwrap_or_else(|| PathBuf::from("git"))
+    })
+    .clone()
+}
+
 /// Fetch a PR branch from the remote and set up a local tracking branch.
 ///
 /// This is idempotent - if the branch already exists locally, it will be updated.
@@ -255,8 +824,6 @@ pub fn create_commit(repo: &Repository, paths: &[String], message: &str) -> Resu
 /// Returns both the merge-base SHA and the PR head SHA, so the frontend
 /// can use stable SHAs for the diff (avoiding ref resolution issues).
 pub fn fetch_pr_branch(repo: &Repository, base_ref: &str, pr_number: u32) -> Result<PRFetchResult> {
-    use std::process::Command;
-
     let workdir = repo
         .workdir()
         .ok_or_else(|| GitError("Bare repository".into()))?;
@@ -299,7 +866,7 @@ pub fn fetch_pr_branch(repo: &Repository, base_ref: &str, pr_number: u32) -> Res
     let remote_ref = format!("refs/pull/{}/head", pr_number);
     let refspec = format!("{}:{}", remote_ref, local_ref);
 
-    let output = Command::new("git")
+    let output = Command::new(find_git_command())
         .args(["fetch", "origin", &refspec])
         .current_dir(workdir)
         .output()
@@ -318,7 +885,7 @@ pub fn fetch_pr_branch(repo: &Repository, base_ref: &str, pr_number: u32) -> Res
 
     // Also fetch the base branch to ensure we have the latest
     log::info!("Fetching base branch '{}' from origin", base_ref);
-    let _ = Command::new("git")
+    let _ = Command::new(find_git_command())
         .args(["fetch", "origin", base_ref])
         .current_dir(workdir)
         .output();
@@ -359,402 +926,5350 @@ pub fn get_merge_base(repo: &Repository, ref1: &str, ref2: &str) -> Result<Strin
     Ok(merge_base_oid.to_string())
 }
 
+/// How two refs have diverged from their merge-base.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Divergence {
+    pub merge_base: String,
+    /// Commits on `a` that aren't on `b`
+    pub ahead: usize,
+    /// Commits on `b` that aren't on `a`
+    pub behind: usize,
+}
+
+/// The merge-base of `a` and `b`, and how far each has moved on from it,
+/// as in "feature is 3 commits ahead, 1 behind main".
+pub fn divergence(repo: &Repository, a: &str, b: &str) -> Result<Divergence> {
+    let peel_commit = |refspec: &str| -> Result<git2::Oid> {
+        let obj = repo
+            .revparse_single(refspec)
+            .map_err(|e| GitError(format!("Cannot resolve '{}': {}", refspec, e)))?;
+        let commit = obj
+            .peel_to_commit()
+            .map_err(|e| GitError(format!("'{}' is not a commit: {}", refspec, e)))?;
+        Ok(commit.id())
+    };
+    let (a_oid, b_oid) = (peel_commit(a)?, peel_commit(b)?);
+
+    let merge_base = repo.merge_base(a_oid, b_oid).map_err(|e| {
+        if e.code() == ErrorCode::NotFound {
+            GitError(format!(
+                "'{}' and '{}' have no common ancestor (unrelated histories)",
+                a, b
+            ))
+        } else {
+            GitError(format!(
+                "Cannot find merge-base between '{}' and '{}': {}",
+                a, b, e
+            ))
+        }
+    })?;
+    let (ahead, behind) = repo.graph_ahead_behind(a_oid, b_oid)?;
+    Ok(Divergence {
+        merge_base: merge_base.to_string(),
+        ahead,
+        behind,
+    })
+}
+
 /// Resolve a ref string to a tree.
 ///
 /// Special values:
-/// - WORKDIR means the working tree (returns None, caller handles specially)
+/// - WORKDIR and UNSTAGED mean the working tree (returns None, caller handles specially)
+/// - STAGED is the index, built as a tree in memory
 /// - "HEAD" resolves to the current HEAD commit
+///
+/// Tree SHAs are accepted too (e.g. the empty tree, for root commits).
 fn resolve_to_tree<'a>(repo: &'a Repository, refspec: &str) -> Result<Option<Tree<'a>>> {
-    if refspec == WORKDIR {
+    if is_working_tree_ref(refspec) {
         return Ok(None); // Working tree - no tree object
     }
+    if refspec == STAGED {
+        // Writes through this handle land in memory from here on, so reading
+        // the index never touches .git/objects (which may be read-only)
+        repo.odb()?.add_new_mempack_backend(1000)?;
+        let oid = repo.index()?.write_tree().map_err(|e| {
+            GitError(format!(
+                "Cannot read staged changes (resolve conflicts first): {}",
+                e
+            ))
+        })?;
+        return Ok(Some(repo.find_tree(oid)?));
+    }
+
+    // Before the first commit, HEAD is the empty tree
+    if refspec == EMPTY || (refspec == "HEAD" && head_is_unborn(repo)) {
+        return Ok(Some(empty_tree(repo)?));
+    }
 
     let obj = repo
         .revparse_single(refspec)
         .map_err(|e| GitError(format!("Cannot resolve '{}': {}", refspec, e)))?;
 
-    let commit = obj
+    let tree = obj
+        .peel_to_tree()
+        .map_err(|e| GitError(format!("'{}' is not a commit or tree: {}", refspec, e)))?;
+
+    Ok(Some(tree))
+}
+
+/// The changes introduced by a single commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitDiff {
+    /// Full SHA of the commit being shown
+    pub sha: String,
+    /// Full SHA the commit is diffed against: its first parent, or the
+    /// empty tree for a root commit
+    pub base: String,
+    /// True if the commit has more than one parent (diffed against the first)
+    pub is_merge: bool,
+    pub files: Vec<FileDiff>,
+}
+
+/// Compute the diff introduced by a single commit (`sha~1..sha`) with `config`.
+pub fn compute_commit_diff(
+    repo: &Repository,
+    sha: &str,
+    config: &DiffConfig,
+) -> Result<CommitDiff> {
+    let commit = repo
+        .revparse_single(sha)
+        .map_err(|e| GitError(format!("Cannot resolve '{}': {}", sha, e)))?
         .peel_to_commit()
-        .map_err(|e| GitError(format!("'{}' is not a commit: {}", refspec, e)))?;
+        .map_err(|e| GitError(format!("'{}' is not a commit: {}", sha, e)))?;
+
+    let base = match commit.parent_id(0) {
+        Ok(parent) => parent.to_string(),
+        // Root commit: diff against the empty tree
+        Err(_) => EMPTY_TREE_SHA.to_string(),
+    };
 
-    Ok(Some(commit.tree()?))
+    let sha = commit.id().to_string();
+    let files = compute_diff(repo, &base, &sha, false, config)?.files;
+
+    Ok(CommitDiff {
+        sha,
+        base,
+        is_merge: commit.parent_count() > 1,
+        files,
+    })
 }
 
-/// Info about a changed file collected from git diff.
-struct FileChange {
-    before_path: Option<String>,
-    after_path: Option<String>,
-    status: Delta,
-    /// Hunks from git diff: (old_start, old_lines, new_start, new_lines)
-    /// Line numbers are 1-indexed from git, we convert to 0-indexed.
-    hunks: Vec<Hunk>,
+/// One commit's own changes, for stepping through a branch commit by commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitChanges {
+    pub sha: String,
+    /// First line of the commit message
+    pub summary: String,
+    /// Diff against the commit's first parent
+    pub files: Vec<FileDiff>,
 }
 
-/// A hunk from git diff, converted to 0-indexed line numbers.
-#[derive(Debug, Clone, Copy)]
-struct Hunk {
-    /// Start line in old file (0-indexed)
-    old_start: u32,
-    /// Number of lines in old file
-    old_lines: u32,
-    /// Start line in new file (0-indexed)
-    new_start: u32,
-    /// Number of lines in new file
-    new_lines: u32,
+/// The diff each commit in base..head introduces, oldest first, following
+/// first parents so the steps add up to the squashed base..head diff.
+/// At most `max_commits` are diffed, starting from the oldest, each with
+/// `config` as for the whole range.
+pub fn per_commit_diffs(
+    repo: &Repository,
+    base_ref: &str,
+    head_ref: &str,
+    max_commits: usize,
+    config: &DiffConfig,
+) -> Result<Vec<CommitChanges>> {
+    let mut log = commit_log(repo, base_ref, head_ref, true)?;
+    log.reverse();
+    log.into_iter()
+        .take(max_commits)
+        .map(|entry| {
+            let diff = compute_commit_diff(repo, &entry.sha, config)?;
+            Ok(CommitChanges {
+                sha: entry.sha,
+                summary: entry.summary,
+                files: diff.files,
+            })
+        })
+        .collect()
 }
 
-/// Compute the diff between two refs.
+/// Load the repo's mailmap (`.mailmap`, plus any `mailmap.file` or
+/// `mailmap.blob` config). None if it can't be read; a repo without one gets
+/// an empty mailmap, which maps nothing.
+pub(super) fn load_mailmap(repo: &Repository) -> Option<Mailmap> {
+    repo.mailmap()
+        .map_err(|e: git2::Error| log::warn!("Cannot load mailmap: {}", e.message()))
+        .ok()
+}
+
+/// A signature's display name, canonicalized through the mailmap if given,
+/// so one person's old and new identities show the same name.
+pub(super) fn author_name(mailmap: Option<&Mailmap>, signature: &Signature) -> String {
+    match mailmap.and_then(|m| m.resolve_signature(signature).ok()) {
+        Some(resolved) => resolved.name().unwrap_or("").to_string(),
+        None => signature.name().unwrap_or("").to_string(),
+    }
+}
+
+/// One commit in a `base..head` log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub sha: String,
+    pub short_sha: String,
+    pub author: String,
+    /// First line of the commit message
+    pub summary: String,
+    /// Commit time, seconds since the Unix epoch
+    pub time: i64,
+    pub is_merge: bool,
+}
+
+/// List the commits in `base..head`, newest first.
 ///
-/// If `use_merge_base` is true, diffs from the merge-base instead of `before_ref` directly.
-pub fn compute_diff(
+/// With `first_parent`, merges are followed only along their first parent, so
+/// a branch that merged main in shows just its own mainline commits (each
+/// merge appears once, and `compute_commit_diff` diffs it against that parent).
+/// WORKDIR as head means HEAD.
+pub fn commit_log(
     repo: &Repository,
-    before_ref: &str,
-    after_ref: &str,
-    use_merge_base: bool,
-) -> Result<Vec<FileDiff>> {
-    let effective_before = if use_merge_base {
-        let head_for_merge = if after_ref == WORKDIR {
-            "HEAD"
-        } else {
-            after_ref
-        };
-        get_merge_base(repo, before_ref, head_for_merge).unwrap_or_else(|_| before_ref.to_string())
-    } else {
-        before_ref.to_string()
+    base_ref: &str,
+    head_ref: &str,
+    first_parent: bool,
+) -> Result<Vec<LogEntry>> {
+    let resolve = |refspec: &str| {
+        repo.revparse_single(refspec)
+            .map_err(|e| GitError(format!("Cannot resolve '{}': {}", refspec, e)))?
+            .peel_to_commit()
+            .map_err(|e| GitError(format!("'{}' is not a commit: {}", refspec, e)))
     };
+    let head = resolve(if head_ref == WORKDIR {
+        "HEAD"
+    } else {
+        head_ref
+    })?;
 
-    compute_diff_inner(repo, &effective_before, after_ref)
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    // From the empty tree, every commit back to the root is included
+    if base_ref != EMPTY {
+        walk.hide(resolve(base_ref)?.id())?;
+    }
+    if first_parent {
+        walk.simplify_first_parent()?;
+    }
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+    let mailmap = load_mailmap(repo);
+    walk.map(|oid| {
+        let commit = repo.find_commit(oid?)?;
+        let sha = commit.id().to_string();
+        let author = author_name(mailmap.as_ref(), &commit.author());
+        Ok(LogEntry {
+            short_sha: sha[..8.min(sha.len())].to_string(),
+            sha,
+            author,
+            summary: commit.summary().unwrap_or("").to_string(),
+            time: commit.time().seconds(),
+            is_merge: commit.parent_count() > 1,
+        })
+    })
+    .collect()
 }
 
-fn compute_diff_inner(
+/// Diff a file at `base_ref` (or the working tree) against a file elsewhere on
+/// disk, such as the same file in another checkout.
+///
+/// The external file is the after side. `file_path` is the repo-relative path
+/// used for the before side and for binary detection. `config` applies as it
+/// does to `compute_diff`: its algorithm, binary overrides, size limit, line
+/// truncation and collapsing.
+pub fn compute_external_file_diff(
     repo: &Repository,
-    before_ref: &str,
-    after_ref: &str,
-) -> Result<Vec<FileDiff>> {
-    // Validate: WORKDIR can only be used as the "after" ref
-    if before_ref == WORKDIR {
-        return Err(GitError(
-            "WORKDIR can only be used as the target (head), not the base".to_string(),
-        ));
+    base_ref: &str,
+    file_path: &str,
+    external_path: &Path,
+    config: &DiffConfig,
+) -> Result<FileDiff> {
+    if !external_path.is_absolute() {
+        return Err(GitError(format!(
+            "External path must be absolute: {}",
+            external_path.display()
+        )));
+    }
+    if !external_path.is_file() {
+        return Err(GitError(format!("Not a file: {}", external_path.display())));
     }
 
-    let before_tree = resolve_to_tree(repo, before_ref)?;
-    let after_tree = resolve_to_tree(repo, after_ref)?;
-    let is_working_tree = after_ref == WORKDIR;
+    let policy = BinaryPolicy::from_config(config)?;
+    let path = Path::new(file_path);
+    let tree = if base_ref == WORKDIR {
+        None
+    } else {
+        resolve_to_tree(repo, base_ref)?
+    };
 
+    // Too big to diff: list it by path and size alone, as `compute_diff` does
+    if let Some(limit) = config.max_file_size {
+        let before_size = if base_ref == WORKDIR {
+            workdir_file_size(repo, file_path)
+        } else {
+            tree_blob_size(repo, tree.as_ref(), file_path)?
+        };
+        let after_size = std::fs::metadata(external_path).ok().map(|meta| meta.len());
+        if before_size.max(after_size).is_some_and(|size| size > limit) {
+            let change = FileChange {
+                before_path: Some(file_path.to_string()),
+                after_path: Some(file_path.to_string()),
+                status: if before_size.is_some() {
+                    Delta::Modified
+                } else {
+                    Delta::Added
+                },
+                old_mode: FileMode::Blob,
+                new_mode: FileMode::Blob,
+                hunks: Vec::new(),
+                same_blob: false,
+            };
+            return Ok(FileDiff {
+                too_large: true,
+                before_size,
+                after_size,
+                ..path_only_file_diff(&change)
+            });
+        }
+    }
+
+    let before = if base_ref == WORKDIR {
+        load_file_from_workdir(repo, path, &policy)?
+    } else {
+        load_file(repo, tree.as_ref(), path, &policy)?
+    };
+
+    let bytes =
+        std::fs::read(external_path).map_err(|e| GitError(format!("Cannot read file: {}", e)))?;
+    let after = Some(policy.load(repo, &external_path.to_string_lossy(), path, &bytes));
+
+    let hunks = hunks_between(before.as_ref(), after.as_ref(), config.algorithm)?;
+    let (mut before, mut after) = (before, after);
+    if let Some(max_len) = config.max_line_length {
+        for file in before.iter_mut().chain(after.iter_mut()) {
+            file.truncate_long_lines(max_len);
+        }
+    }
+    let alignments = compute_alignments_from_hunks(&hunks, &before, &after);
+
+    let mut file_diff = FileDiff {
+        before_binary: is_binary_side(&before),
+        after_binary: is_binary_side(&after),
+        too_large: false,
+        before_size: None,
+        after_size: None,
+        before,
+        after,
+        alignments,
+        mode_change: None,
+        collapsed_by_default: false,
+    };
+    if let Some(min_len) = config.collapse_unchanged {
+        file_diff.collapse_unchanged(min_len, COLLAPSE_CONTEXT_LINES);
+    }
+    Ok(file_diff)
+}
+
+/// Compute hunks between two loaded files by diffing their text directly.
+/// Binary files produce no hunks.
+fn hunks_between(
+    before: Option<&File>,
+    after: Option<&File>,
+    algorithm: DiffAlgorithm,
+) -> Result<Vec<Hunk>> {
+    let text = |file: Option<&File>| -> Vec<u8> {
+        let lines = file.map(|f| f.content.lines()).unwrap_or(&[]);
+        let mut text = lines.join("\n");
+        if !lines.is_empty() {
+            text.push('\n');
+        }
+        text.into_bytes()
+    };
+
+    let (old, new) = (text(before), text(after));
     let mut opts = DiffOptions::new();
-    opts.ignore_submodules(true);
-    // Use 0 context lines so hunks contain only the actual changes,
-    // not surrounding context. This gives us precise alignment boundaries.
     opts.context_lines(0);
+    algorithm.apply(&mut opts);
+    let patch = Patch::from_buffers(&old, None, &new, None, Some(&mut opts))?;
 
-    let diff = if is_working_tree {
-        // Diff from before_tree to working directory
-        // Include untracked files so new files show up
-        opts.include_untracked(true);
-        // Recurse into untracked directories to show individual files
-        opts.recurse_untracked_dirs(true);
-        repo.diff_tree_to_workdir_with_index(before_tree.as_ref(), Some(&mut opts))?
+    (0..patch.num_hunks())
+        .map(|i| Ok(Hunk::from_git(&patch.hunk(i)?.0)))
+        .collect()
+}
+
+/// Follow a file through renames between two refs.
+///
+/// Walks first-parent history from `base_ref` to `head_ref`, detecting
+/// renames at each commit, so a file renamed across several commits is still
+/// found. `head_ref` may be `WORKDIR`. Returns the file's path at `head_ref`,
+/// or None if it was deleted along the way.
+pub fn trace_rename(
+    repo: &Repository,
+    base_path: &str,
+    base_ref: &str,
+    head_ref: &str,
+) -> Result<Option<String>> {
+    let peel_commit = |refspec: &str| {
+        repo.revparse_single(refspec)
+            .map_err(|e| GitError(format!("Cannot resolve '{}': {}", refspec, e)))?
+            .peel_to_commit()
+            .map_err(|e| GitError(format!("'{}' is not a commit: {}", refspec, e)))
+    };
+    let base = peel_commit(base_ref)?;
+    let head = peel_commit(if head_ref == WORKDIR {
+        "HEAD"
     } else {
-        // Diff between two trees
-        repo.diff_tree_to_tree(before_tree.as_ref(), after_tree.as_ref(), Some(&mut opts))?
+        head_ref
+    })?;
+
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    walk.hide(base.id())?;
+    walk.simplify_first_parent()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+    let mut path = base_path.to_string();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        if !follow_path(&mut diff, &mut path)? {
+            return Ok(None);
+        }
+    }
+
+    if head_ref == WORKDIR {
+        let mut opts = DiffOptions::new();
+        opts.include_untracked(true).recurse_untracked_dirs(true);
+        let mut diff =
+            repo.diff_tree_to_workdir_with_index(Some(&head.tree()?), Some(&mut opts))?;
+        if !follow_path(&mut diff, &mut path)? {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(path))
+}
+
+/// The changed regions (as before/after spans) of one file between two refs,
+/// exactly as git reports its hunks under `algorithm`.
+pub(super) fn file_hunk_spans(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    path: &str,
+    algorithm: DiffAlgorithm,
+) -> Result<Vec<(Span, Span)>> {
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+    let mut opts = DiffOptions::new();
+    opts.context_lines(0)
+        .pathspec(path)
+        .disable_pathspec_match(true);
+    algorithm.apply(&mut opts);
+    let diff = diff_trees_with(
+        repo,
+        before_tree.as_ref(),
+        after_tree.as_ref(),
+        is_working_tree_ref(after_ref),
+        &mut opts,
+    )?;
+
+    let mut hunks: Vec<Hunk> = collect_file_changes(&diff)?
+        .into_iter()
+        .flat_map(|change| change.hunks)
+        .collect();
+    if hunks.is_empty() {
+        // Maybe a UTF-16 file, which git treats as binary
+        let load = |refspec: &str| -> Result<Option<File>> {
+            Ok(raw_content(repo, refspec, path)?.map(|bytes| File::from_bytes(path, &bytes)))
+        };
+        let before = load(effective_base(before_ref, after_ref))?;
+        let after = load(after_ref)?;
+        if is_utf16(&before) || is_utf16(&after) {
+            hunks = hunks_between(before.as_ref(), after.as_ref(), algorithm)?;
+        }
+    }
+
+    Ok(hunks
+        .into_iter()
+        .map(|h| {
+            (
+                Span::new(h.old_start, h.old_start + h.old_lines),
+                Span::new(h.new_start, h.new_start + h.new_lines),
+            )
+        })
+        .collect())
+}
+
+/// How much one hunk changes, for drawing a change-density minimap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HunkDensity {
+    /// Where the hunk sits in the after file (0-indexed, exclusive end;
+    /// empty for a pure deletion)
+    pub line_start: u32,
+    pub line_end: u32,
+    pub added: u32,
+    pub removed: u32,
+}
+
+/// Per-hunk change counts for one file, without loading its content.
+pub fn change_density(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    path: &str,
+    algorithm: DiffAlgorithm,
+) -> Result<Vec<HunkDensity>> {
+    Ok(
+        file_hunk_spans(repo, before_ref, after_ref, path, algorithm)?
+            .into_iter()
+            .map(|(before, after)| HunkDensity {
+                line_start: after.start,
+                line_end: after.end,
+                added: after.len(),
+                removed: before.len(),
+            })
+            .collect(),
+    )
+}
+
+/// The lines on each side of one changed region of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HunkSlices {
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// The lines of the changed alignment at `alignment_index` in `path`'s diff,
+/// so callers don't have to slice them out of a `FileDiff` themselves.
+///
+/// The file is diffed as `file_ref_diff` would with `config`, so the index
+/// matches what the UI shows, but lines are neither truncated nor collapsed.
+pub fn hunk_slices(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    path: &str,
+    alignment_index: usize,
+    config: &DiffConfig,
+) -> Result<HunkSlices> {
+    let config = DiffConfig {
+        max_line_length: None,
+        collapse_unchanged: None,
+        ..config.clone()
+    };
+    let file = file_ref_diff(
+        repo,
+        before_ref,
+        after_ref,
+        use_merge_base,
+        path,
+        None,
+        &config,
+    )?
+    .ok_or_else(|| GitError(format!("'{}' is unchanged", path)))?;
+
+    let alignment = file
+        .alignments
+        .get(alignment_index)
+        .filter(|a| a.changed)
+        .ok_or_else(|| {
+            GitError(format!(
+                "'{}' has no changed region at index {}",
+                path, alignment_index
+            ))
+        })?;
+    let slice = |side: &Option<File>, span: Span| -> Result<Vec<String>> {
+        match side.as_ref().map(|f| &f.content) {
+            None => Ok(Vec::new()),
+            Some(FileContent::Text { lines }) => Ok(lines
+                .get(span.start as usize..span.end as usize)
+                .unwrap_or_default()
+                .to_vec()),
+            Some(FileContent::Binary) => Err(GitError(format!("'{}' is binary", path))),
+        }
     };
+    Ok(HunkSlices {
+        before: slice(&file.before, alignment.before)?,
+        after: slice(&file.after, alignment.after)?,
+    })
+}
 
-    // Collect changed files with their paths, status, and hunks
-    let file_changes = collect_file_changes(&diff)?;
+/// Where one changed region of a diff is, for stepping between changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeLocation {
+    pub path: String,
+    /// Index of the region in the file's alignments
+    pub alignment_index: usize,
+    pub before_span: Span,
+    pub after_span: Span,
+}
 
-    // Build FileDiff for each changed file
-    let mut result: Vec<FileDiff> = Vec::new();
+/// Every changed region of the diff in display order: files sorted by path
+/// as `compute_diff` gives them, then regions top to bottom within each.
+/// Indices match `compute_diff` with the same `config`.
+pub fn change_index(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    config: &DiffConfig,
+) -> Result<Vec<ChangeLocation>> {
+    let result = compute_diff(repo, before_ref, after_ref, use_merge_base, config)?;
+    Ok(result
+        .files
+        .iter()
+        .flat_map(|file| {
+            let path = file.path();
+            file.alignments
+                .iter()
+                .enumerate()
+                .filter(|(_, alignment)| alignment.changed)
+                .map(move |(alignment_index, alignment)| ChangeLocation {
+                    path: path.to_string(),
+                    alignment_index,
+                    before_span: alignment.before,
+                    after_span: alignment.after,
+                })
+        })
+        .collect())
+}
 
-    for change in file_changes {
-        let before_file = if let Some(ref path) = change.before_path {
-            if change.status != Delta::Added {
-                load_file(repo, before_tree.as_ref(), Path::new(path))?
+/// Update `path` if `diff` renames it. Returns false if `diff` deletes it.
+fn follow_path(diff: &mut Diff, path: &mut String) -> Result<bool> {
+    let mut find = git2::DiffFindOptions::new();
+    find.renames(true).for_untracked(true);
+    diff.find_similar(Some(&mut find))?;
+
+    for delta in diff.deltas() {
+        let old_path = delta.old_file().path().map(|p| p.to_string_lossy());
+        if old_path.as_deref() != Some(path.as_str()) {
+            continue;
+        }
+        match delta.status() {
+            Delta::Renamed => {
+                if let Some(new_path) = delta.new_file().path() {
+                    *path = new_path.to_string_lossy().to_string();
+                }
+                return Ok(true);
+            }
+            Delta::Deleted => return Ok(false),
+            _ => {}
+        }
+    }
+    Ok(true)
+}
+
+/// Old path to new path for each file renamed between two refs, by git's
+/// similarity detection. Diffs themselves don't pair renames up, so this is
+/// how comments on either side of a rename are matched.
+pub fn renamed_paths(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+) -> Result<HashMap<String, String>> {
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+    let mut diff = diff_trees_with(
+        repo,
+        before_tree.as_ref(),
+        after_tree.as_ref(),
+        is_working_tree_ref(after_ref),
+        &mut DiffOptions::new(),
+    )?;
+    let mut find = git2::DiffFindOptions::new();
+    find.renames(true).for_untracked(true);
+    diff.find_similar(Some(&mut find))?;
+
+    Ok(diff
+        .deltas()
+        .filter(|delta| delta.status() == Delta::Renamed)
+        .filter_map(|delta| {
+            let path = |file: git2::DiffFile| Some(file.path()?.to_string_lossy().to_string());
+            Some((path(delta.old_file())?, path(delta.new_file())?))
+        })
+        .collect())
+}
+
+/// Info about a changed file collected from git diff.
+struct FileChange {
+    before_path: Option<String>,
+    after_path: Option<String>,
+    status: Delta,
+    old_mode: FileMode,
+    new_mode: FileMode,
+    /// Hunks from git diff: (old_start, old_lines, new_start, new_lines)
+    /// Line numbers are 1-indexed from git, we convert to 0-indexed.
+    hunks: Vec<Hunk>,
+    /// Both sides are the same (known) blob, so the content is byte-identical
+    same_blob: bool,
+}
+
+impl FileChange {
+    /// The kind of change, from git's status alone.
+    fn kind(&self) -> ChangeKind {
+        match self.status {
+            Delta::Added | Delta::Untracked => ChangeKind::Added,
+            Delta::Deleted => ChangeKind::Deleted,
+            _ => ChangeKind::Modified,
+        }
+    }
+}
+
+/// A hunk from git diff, converted to 0-indexed line numbers.
+#[derive(Debug, Clone, Copy)]
+struct Hunk {
+    /// Start line in old file (0-indexed)
+    old_start: u32,
+    /// Number of lines in old file
+    old_lines: u32,
+    /// Start line in new file (0-indexed)
+    new_start: u32,
+    /// Number of lines in new file
+    new_lines: u32,
+}
+
+impl Hunk {
+    fn from_git(hunk: &DiffHunk) -> Self {
+        // Git uses 1-indexed line numbers, convert to 0-indexed. An empty side
+        // (pure insertion/deletion) reports the line *before* the change, which
+        // is already the 0-indexed insertion point (and 0 for empty files).
+        let to_index = |start: u32, lines: u32| {
+            if lines == 0 {
+                start
             } else {
-                None
+                start - 1
             }
-        } else {
-            None
         };
+        let old_start = to_index(hunk.old_start(), hunk.old_lines());
+        let new_start = to_index(hunk.new_start(), hunk.new_lines());
+
+        Hunk {
+            old_start,
+            old_lines: hunk.old_lines(),
+            new_start,
+            new_lines: hunk.new_lines(),
+        }
+    }
+}
 
-        let after_file = if let Some(ref path) = change.after_path {
-            if change.status != Delta::Deleted {
-                if is_working_tree {
-                    load_file_from_workdir(repo, Path::new(path))?
-                } else {
-                    load_file(repo, after_tree.as_ref(), Path::new(path))?
-                }
-            } else {
-                None
+/// Options controlling which files a diff includes and how they're loaded.
+///
+/// Defaults to including everything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiffConfig {
+    /// Gitignore-style globs for paths to drop from the result
+    /// (e.g. `package-lock.json`, `*.min.js`, `vendor/`).
+    pub exclude: Vec<String>,
+    /// Globs for generated files (e.g. `*.lock`, `*.snap`) to list without
+    /// loading their content, marked `FileDiff::collapsed_by_default`.
+    /// `file_ref_diff` loads one in full.
+    pub collapse_files: Vec<String>,
+    /// How many leading bytes to scan for null bytes when sniffing binary files.
+    pub binary_scan_len: usize,
+    /// Globs for paths always shown as text, even if they look binary.
+    pub force_text: Vec<String>,
+    /// Globs for paths always treated as binary.
+    pub force_binary: Vec<String>,
+    /// Cut lines longer than this many characters (e.g. minified files) for
+    /// transport, marking them in `File::truncated_lines`. None disables.
+    pub max_line_length: Option<usize>,
+    /// Trim unchanged regions longer than this many lines from the payload,
+    /// keeping `COLLAPSE_CONTEXT_LINES` at each end. None ships everything.
+    pub collapse_unchanged: Option<u32>,
+    /// Line-matching algorithm for the hunks alignments are built from.
+    pub algorithm: DiffAlgorithm,
+    /// Keep only files with these kinds of change (e.g. just deletions).
+    /// Empty keeps every kind.
+    pub kinds: Vec<ChangeKind>,
+    /// Files with a side bigger than this many bytes are listed without
+    /// their content, marked `FileDiff::too_large`, rather than diffed.
+    /// `file_ref_diff` loads one in full. None diffs files of any size.
+    pub max_file_size: Option<u64>,
+}
+
+/// How changed lines are matched up. libgit2 offers these three; it has no
+/// histogram mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAlgorithm {
+    /// git's default
+    #[default]
+    Myers,
+    /// Myers, spending extra time to find the smallest diff
+    Minimal,
+    /// Anchors on lines that appear once on each side, which keeps moved or
+    /// reordered blocks from being interleaved with unrelated lines
+    Patience,
+}
+
+impl DiffAlgorithm {
+    fn apply(self, opts: &mut DiffOptions) {
+        match self {
+            DiffAlgorithm::Myers => {}
+            DiffAlgorithm::Minimal => {
+                opts.minimal(true);
             }
-        } else {
-            None
+            DiffAlgorithm::Patience => {
+                opts.patience(true);
+            }
+        }
+    }
+}
+
+/// Default limit for `DiffConfig::max_line_length`.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 10_000;
+
+/// Lines of context kept at each end of a collapsed unchanged region.
+pub const COLLAPSE_CONTEXT_LINES: u32 = 3;
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            collapse_files: Vec::new(),
+            binary_scan_len: DEFAULT_BINARY_SCAN_LEN,
+            force_text: Vec::new(),
+            force_binary: Vec::new(),
+            max_line_length: Some(DEFAULT_MAX_LINE_LENGTH),
+            collapse_unchanged: None,
+            algorithm: DiffAlgorithm::default(),
+            kinds: Vec::new(),
+            max_file_size: None,
+        }
+    }
+}
+
+/// The computed diff, plus a count of files filtered out by `DiffConfig`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffResult {
+    pub files: Vec<FileDiff>,
+    /// Number of changed files dropped by `DiffConfig::exclude` or
+    /// `DiffConfig::kinds`.
+    pub excluded_count: usize,
+}
+
+/// The options a repository's diffs are computed with, for explaining why
+/// a file does or doesn't show as changed. Read-only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveDiffOptions {
+    /// The config used when a command isn't given one
+    pub config: DiffConfig,
+    /// Settings the repository overrides (`RepoSettings` field names);
+    /// everything else in `config` is a default
+    pub overrides: Vec<String>,
+    /// Always false: whitespace-only changes are shown like any other
+    pub ignore_whitespace: bool,
+    /// `core.autocrlf`, if set. With `true` or `input`, working-tree CRLFs
+    /// are compared as LFs
+    pub autocrlf: Option<String>,
+    /// `core.eol`, if set
+    pub eol: Option<String>,
+    /// Similarity (percent) at which a delete plus an add is shown as a
+    /// rename. None: diffs don't detect renames
+    pub rename_threshold: Option<u16>,
+}
+
+/// Report the options diffs in `repo` use with `config` (the repository's
+/// settings applied to the defaults), alongside the git config that affects
+/// how content is compared.
+pub fn effective_diff_options(
+    repo: &Repository,
+    config: DiffConfig,
+    overrides: Vec<String>,
+) -> EffectiveDiffOptions {
+    let git_config = repo.config().ok();
+    let get = |key: &str| git_config.as_ref().and_then(|c| c.get_string(key).ok());
+    EffectiveDiffOptions {
+        config,
+        overrides,
+        ignore_whitespace: false,
+        autocrlf: get("core.autocrlf"),
+        eol: get("core.eol"),
+        rename_threshold: None,
+    }
+}
+
+/// Compute the diff between two refs.
+///
+/// If `use_merge_base` is true, diffs from the merge-base instead of `before_ref` directly.
+pub fn compute_diff(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    config: &DiffConfig,
+) -> Result<DiffResult> {
+    let never = AtomicBool::new(false);
+    compute_diff_cancellable(repo, before_ref, after_ref, use_merge_base, config, &never)
+}
+
+/// Like `compute_diff`, but gives up with `GitError::cancelled()` soon after
+/// `cancel` is set, e.g. when the user has moved on to another diff.
+pub fn compute_diff_cancellable(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    config: &DiffConfig,
+    cancel: &AtomicBool,
+) -> Result<DiffResult> {
+    compute_diff_streaming(
+        repo,
+        before_ref,
+        after_ref,
+        use_merge_base,
+        config,
+        cancel,
+        |_| {},
+    )
+}
+
+/// Like `compute_diff_cancellable`, but also hands each file to `on_file` as
+/// soon as it's computed, so it can be shown before the slower files are
+/// done. Files arrive in git's order; the returned result is sorted by path
+/// as usual.
+pub fn compute_diff_streaming(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    config: &DiffConfig,
+    cancel: &AtomicBool,
+    mut on_file: impl FnMut(&FileDiff),
+) -> Result<DiffResult> {
+    let effective_before = diff_base(repo, before_ref, after_ref, use_merge_base);
+    let scope = if is_working_tree_ref(after_ref) {
+        PathScope::Status
+    } else {
+        PathScope::All
+    };
+    compute_diff_inner(
+        repo,
+        &effective_before,
+        after_ref,
+        config,
+        scope,
+        cancel,
+        &mut on_file,
+    )
+}
+
+/// The full diff of one file, as `compute_diff` would give it with `config`
+/// but ignoring `exclude`, `collapse_files`, `max_file_size`, and `kinds`.
+/// For loading a file that was listed with `FileDiff::collapsed_by_default`
+/// or `FileDiff::too_large`.
+///
+/// Pass a renamed file's `old_path` too, or it's shown as added. None if
+/// the file has no changes.
+pub fn file_ref_diff(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    path: &str,
+    old_path: Option<&str>,
+    config: &DiffConfig,
+) -> Result<Option<FileDiff>> {
+    let config = DiffConfig {
+        exclude: Vec::new(),
+        collapse_files: Vec::new(),
+        kinds: Vec::new(),
+        max_file_size: None,
+        ..config.clone()
+    };
+    let paths: Vec<String> = std::iter::once(path)
+        .chain(old_path)
+        .map(String::from)
+        .collect();
+    let effective_before = diff_base(repo, before_ref, after_ref, use_merge_base);
+    let never = AtomicBool::new(false);
+    let result = compute_diff_inner(
+        repo,
+        &effective_before,
+        after_ref,
+        &config,
+        PathScope::Only(&paths),
+        &never,
+        &mut |_| {},
+    )?;
+    Ok(result.files.into_iter().find(|f| f.path() == path))
+}
+
+/// The diffs of just `paths` with every line intact, for quoting code from
+/// them: as `file_ref_diff` gives them, but with unchanged regions kept and
+/// long lines left whole. Include both paths of a renamed file so it's
+/// paired up. Paths without changes are left out.
+pub fn full_file_diffs(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    use_merge_base: bool,
+    paths: &[String],
+    config: &DiffConfig,
+) -> Result<Vec<FileDiff>> {
+    let config = DiffConfig {
+        exclude: Vec::new(),
+        collapse_files: Vec::new(),
+        kinds: Vec::new(),
+        max_line_length: None,
+        collapse_unchanged: None,
+        max_file_size: None,
+        ..config.clone()
+    };
+    let effective_before = diff_base(repo, before_ref, after_ref, use_merge_base);
+    let never = AtomicBool::new(false);
+    let result = compute_diff_inner(
+        repo,
+        &effective_before,
+        after_ref,
+        &config,
+        PathScope::Only(paths),
+        &never,
+        &mut |_| {},
+    )?;
+    Ok(result.files)
+}
+
+/// One file's staged and unstaged changes together, for a three-column
+/// HEAD | index | working tree view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripleDiff {
+    /// None if the file is the same in HEAD and the index
+    pub head_vs_index: Option<FileDiff>,
+    /// None if the file is the same in the index and the working tree
+    pub index_vs_workdir: Option<FileDiff>,
+    /// Every line of all three versions, in display order, lined up through
+    /// the index
+    pub rows: Vec<TripleRow>,
+}
+
+/// A line's number (0-indexed) in each version that has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TripleRow {
+    pub head: Option<u32>,
+    pub index: Option<u32>,
+    pub workdir: Option<u32>,
+}
+
+/// Diff `path` from HEAD to the index and from the index to the working
+/// tree, with rows lining up all three versions. A file missing from some
+/// versions (e.g. newly added) just has no lines there.
+pub fn triple_diff(repo: &Repository, path: &str, config: &DiffConfig) -> Result<TripleDiff> {
+    let head_vs_index = file_ref_diff(repo, "HEAD", STAGED, false, path, None, config)?;
+    let index_vs_workdir = file_ref_diff(repo, STAGED, UNSTAGED, false, path, None, config)?;
+
+    // Without changes a stage lines up one to one
+    let index_lines = file_line_count(repo, STAGED, path)?.unwrap_or(0);
+    let pairs = |diff: &Option<FileDiff>| match diff {
+        Some(diff) => line_pairs(&diff.alignments),
+        None => (0..index_lines).map(|i| (Some(i), Some(i))).collect(),
+    };
+    let rows = merge_through_index(pairs(&head_vs_index), pairs(&index_vs_workdir));
+    Ok(TripleDiff {
+        head_vs_index,
+        index_vs_workdir,
+        rows,
+    })
+}
+
+/// Lines of both sides of a diff as (before, after) pairs in order. In a
+/// changed region lines pair up top to bottom, the longer side's extra
+/// lines standing alone.
+fn line_pairs(alignments: &[Alignment]) -> Vec<(Option<u32>, Option<u32>)> {
+    let mut pairs = Vec::new();
+    for alignment in alignments {
+        let (before, after) = (alignment.before, alignment.after);
+        let len = (before.end - before.start).max(after.end - after.start);
+        for offset in 0..len {
+            let line = |span: Span| Some(span.start + offset).filter(|&l| l < span.end);
+            pairs.push((line(before), line(after)));
+        }
+    }
+    pairs
+}
+
+/// Join (head, index) and (index, workdir) pairs on their index line.
+fn merge_through_index(
+    head_index: Vec<(Option<u32>, Option<u32>)>,
+    index_workdir: Vec<(Option<u32>, Option<u32>)>,
+) -> Vec<TripleRow> {
+    let mut rows = Vec::new();
+    let mut upper = head_index.into_iter().peekable();
+    let mut lower = index_workdir.into_iter().peekable();
+    loop {
+        // Lines not in the index go before the next index line
+        while let Some(&(head, None)) = upper.peek() {
+            rows.push(TripleRow {
+                head,
+                index: None,
+                workdir: None,
+            });
+            upper.next();
+        }
+        while let Some(&(None, workdir)) = lower.peek() {
+            rows.push(TripleRow {
+                head: None,
+                index: None,
+                workdir,
+            });
+            lower.next();
+        }
+        let (head, index) = upper.next().unwrap_or_default();
+        let (lower_index, workdir) = lower.next().unwrap_or_default();
+        if index.is_none() && lower_index.is_none() {
+            break;
+        }
+        rows.push(TripleRow {
+            head,
+            index: index.or(lower_index),
+            workdir,
+        });
+    }
+    rows
+}
+
+/// The ref a diff starts from: `before_ref`, or with `use_merge_base` its
+/// merge-base with `after_ref` (HEAD for the working tree or index).
+fn diff_base(repo: &Repository, before_ref: &str, after_ref: &str, use_merge_base: bool) -> String {
+    if !use_merge_base {
+        return before_ref.to_string();
+    }
+    let head_for_merge = if is_working_tree_ref(after_ref) || after_ref == STAGED {
+        "HEAD"
+    } else {
+        after_ref
+    };
+    get_merge_base(repo, before_ref, head_for_merge).unwrap_or_else(|_| before_ref.to_string())
+}
+
+/// Which paths `compute_diff_inner` diffs.
+#[derive(Clone, Copy)]
+enum PathScope<'a> {
+    /// Every path git's diff reports
+    All,
+    /// For a working-tree diff from HEAD, only the paths `git status`
+    /// reports, rather than scanning every clean file
+    Status,
+    /// Exactly these paths
+    Only(&'a [String]),
+}
+
+/// `scope` picks the paths to look at. Each file is passed to `on_file` as
+/// it's built, before the result is sorted.
+fn compute_diff_inner(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    config: &DiffConfig,
+    scope: PathScope,
+    cancel: &AtomicBool,
+    on_file: &mut dyn FnMut(&FileDiff),
+) -> Result<DiffResult> {
+    // Validate: WORKDIR can only be used as the "after" ref
+    if is_working_tree_ref(before_ref) {
+        return Err(GitError(format!(
+            "{} can only be used as the target (head), not the base",
+            before_ref
+        )));
+    }
+    let is_working_tree = is_working_tree_ref(after_ref);
+    if is_working_tree && repo.is_bare() {
+        return Err(GitError(
+            "Bare repository has no working tree to diff against".to_string(),
+        ));
+    }
+
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+
+    let changed_paths = match scope {
+        PathScope::Only(paths) => Some(paths.to_vec()),
+        PathScope::Status if is_working_tree => {
+            status_pathspec(repo, before_tree.as_ref(), after_ref)?
+        }
+        PathScope::Status | PathScope::All => None,
+    };
+
+    // Collect changed files with their paths, status, and hunks
+    let mut file_changes = match &changed_paths {
+        Some(paths) if paths.is_empty() => Vec::new(),
+        _ => {
+            let mut opts = DiffOptions::new();
+            opts.context_lines(0);
+            config.algorithm.apply(&mut opts);
+            if let Some(paths) = &changed_paths {
+                for path in paths {
+                    opts.pathspec(path);
+                }
+                opts.disable_pathspec_match(true);
+            }
+            let diff = diff_trees_with(
+                repo,
+                before_tree.as_ref(),
+                after_tree.as_ref(),
+                is_working_tree,
+                &mut opts,
+            )?;
+            collect_file_changes_until(&diff, Some(cancel))?
+        }
+    };
+    if is_working_tree {
+        file_changes = expand_untracked_dirs(repo, file_changes)?;
+    }
+    if after_ref == WORKDIR {
+        file_changes =
+            reconcile_index_deletions(repo, before_tree.as_ref(), file_changes, config.algorithm)?;
+    }
+
+    // Drop excluded files before loading any content
+    let mut excluded_count = if config.exclude.is_empty() {
+        0
+    } else {
+        let matcher = build_glob_matcher(&config.exclude)?;
+        let total = file_changes.len();
+        file_changes.retain(|change| !is_excluded(&matcher, change));
+        total - file_changes.len()
+    };
+    let collapse_matcher = if config.collapse_files.is_empty() {
+        None
+    } else {
+        Some(build_glob_matcher(&config.collapse_files)?)
+    };
+
+    // Build FileDiff for each changed file
+    let policy = BinaryPolicy::from_config(config)?;
+    let mut result: Vec<FileDiff> = Vec::new();
+
+    for change in file_changes {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(GitError::cancelled());
+        }
+
+        // Drop kinds that weren't asked for before reading anything
+        if !config.kinds.is_empty() && !config.kinds.contains(&change.kind()) {
+            excluded_count += 1;
+            continue;
+        }
+
+        // Generated files are listed by path alone, without reading them
+        if let Some(matcher) = &collapse_matcher {
+            if matches_either_side(matcher, &change) {
+                let file_diff = FileDiff {
+                    collapsed_by_default: true,
+                    ..path_only_file_diff(&change)
+                };
+                on_file(&file_diff);
+                result.push(file_diff);
+                continue;
+            }
+        }
+        // As are files too big to diff without freezing the UI
+        if let Some(limit) = config.max_file_size {
+            let before_size = change
+                .before_path
+                .as_ref()
+                .filter(|_| change.status != Delta::Added)
+                .map(|path| tree_blob_size(repo, before_tree.as_ref(), path))
+                .transpose()?
+                .flatten();
+            let after_size = change
+                .after_path
+                .as_ref()
+                .filter(|_| change.status != Delta::Deleted)
+                .map(|path| {
+                    if is_working_tree {
+                        Ok(workdir_file_size(repo, path))
+                    } else {
+                        tree_blob_size(repo, after_tree.as_ref(), path)
+                    }
+                })
+                .transpose()?
+                .flatten();
+            if before_size.max(after_size).is_some_and(|size| size > limit) {
+                let file_diff = FileDiff {
+                    too_large: true,
+                    before_size,
+                    after_size,
+                    ..path_only_file_diff(&change)
+                };
+                on_file(&file_diff);
+                result.push(file_diff);
+                continue;
+            }
+        }
+        let before_file = if let Some(ref path) = change.before_path {
+            if change.status != Delta::Added {
+                load_file(repo, before_tree.as_ref(), Path::new(path), &policy)?
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let after_file = if let Some(ref path) = change.after_path {
+            if change.status != Delta::Deleted {
+                if is_working_tree {
+                    load_file_from_workdir(repo, Path::new(path), &policy)?
+                } else {
+                    load_file(repo, after_tree.as_ref(), Path::new(path), &policy)?
+                }
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        // Skip entries where we couldn't load either file (e.g., submodules, directories)
+        if before_file.is_none() && after_file.is_none() {
+            log::debug!(
+                "Skipping diff entry with no loadable files: before={:?}, after={:?}",
+                change.before_path,
+                change.after_path
+            );
+            continue;
+        }
+
+        // Skip entries git lists but whose content is identical, e.g. a
+        // working-tree file that only differs by autocrlf line endings.
+        // With a mode change the entry stays, but only as a mode change.
+        let identical = change.status != Delta::Typechange
+            && has_identical_content(&change, &before_file, &after_file);
+        if identical && change.old_mode == change.new_mode {
+            log::debug!(
+                "Skipping diff entry with identical content: {:?}",
+                change.after_path
+            );
+            continue;
+        }
+
+        // A typechange (e.g. file -> symlink) isn't a meaningful text diff:
+        // show both sides whole, as a single changed region
+        let hunks = if change.status == Delta::Typechange {
+            vec![whole_file_hunk(&before_file, &after_file)]
+        } else if identical {
+            Vec::new()
+        } else if is_lfs(&before_file) || is_lfs(&after_file) {
+            // Git's hunks are over the pointer text, not the summary shown
+            hunks_between(before_file.as_ref(), after_file.as_ref(), config.algorithm)?
+        } else if change.hunks.is_empty() && (is_utf16(&before_file) || is_utf16(&after_file)) {
+            hunks_between(before_file.as_ref(), after_file.as_ref(), config.algorithm)?
+        } else {
+            change.hunks
+        };
+
+        // Only now that content has been compared: hunks count real lines,
+        // so truncating doesn't shift any alignment
+        let (mut before_file, mut after_file) = (before_file, after_file);
+        if let Some(max_len) = config.max_line_length {
+            for file in before_file.iter_mut().chain(after_file.iter_mut()) {
+                file.truncate_long_lines(max_len);
+            }
+        }
+
+        let alignments = compute_alignments_from_hunks(&hunks, &before_file, &after_file);
+
+        let mode_change = match (&before_file, &after_file) {
+            (Some(_), Some(_)) if change.old_mode != change.new_mode => Some(ModeChange {
+                before: change.old_mode.into(),
+                after: change.new_mode.into(),
+            }),
+            _ => None,
+        };
+
+        let mut file_diff = FileDiff {
+            before_binary: is_binary_side(&before_file),
+            after_binary: is_binary_side(&after_file),
+            too_large: false,
+            before_size: None,
+            after_size: None,
+            before: before_file,
+            after: after_file,
+            alignments,
+            mode_change,
+            collapsed_by_default: false,
+        };
+        if let Some(min_len) = config.collapse_unchanged {
+            file_diff.collapse_unchanged(min_len, COLLAPSE_CONTEXT_LINES);
+        }
+        on_file(&file_diff);
+        result.push(file_diff);
+    }
+
+    // Sort by path
+    result.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(DiffResult {
+        files: result,
+        excluded_count,
+    })
+}
+
+/// Check if an entry's content is the same on both sides, whatever its mode:
+/// the same blob, or equal text with no hunks from git.
+fn has_identical_content(change: &FileChange, before: &Option<File>, after: &Option<File>) -> bool {
+    if change.same_blob {
+        return true;
+    }
+    if !change.hunks.is_empty() {
+        return false;
+    }
+    match (before, after) {
+        (
+            Some(File {
+                content: FileContent::Text { lines: before },
+                ..
+            }),
+            Some(File {
+                content: FileContent::Text { lines: after },
+                ..
+            }),
+        ) => before == after,
+        _ => false,
+    }
+}
+
+/// Git sees UTF-16 text as binary (it's full of NUL bytes) and reports no
+/// hunks for it, so such files are diffed from their decoded text instead.
+fn is_utf16(file: &Option<File>) -> bool {
+    file.as_ref()
+        .and_then(|f| f.encoding.as_deref())
+        .is_some_and(|encoding| encoding.starts_with("UTF-16"))
+}
+
+/// LFS pointers are shown as a summary of their object, so their hunks are
+/// recomputed from that rather than taken from git's diff of the pointer.
+fn is_lfs(file: &Option<File>) -> bool {
+    file.as_ref().is_some_and(|f| f.lfs.is_some())
+}
+
+/// A hunk covering both files entirely.
+fn whole_file_hunk(before: &Option<File>, after: &Option<File>) -> Hunk {
+    let len = |file: &Option<File>| file.as_ref().map_or(0, |f| f.content.lines().len() as u32);
+    Hunk {
+        old_start: 0,
+        old_lines: len(before),
+        new_start: 0,
+        new_lines: len(after),
+    }
+}
+
+/// The paths `git status` reports as changed, when they're all a working-tree
+/// diff from `before_tree` can contain: status compares against HEAD (and
+/// the index), so this is None for any other base.
+fn status_pathspec(
+    repo: &Repository,
+    before_tree: Option<&Tree>,
+    after_ref: &str,
+) -> Result<Option<Vec<String>>> {
+    if after_ref != UNSTAGED {
+        let head_tree = resolve_to_tree(repo, "HEAD")?;
+        if before_tree.map(Tree::id) != head_tree.as_ref().map(Tree::id) {
+            return Ok(None);
+        }
+    }
+
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let paths = statuses
+        .iter()
+        .filter(|entry| !entry.status().is_ignored())
+        .filter_map(|entry| entry.path().map(String::from))
+        .collect();
+    Ok(Some(paths))
+}
+
+/// Run a git diff from `before_tree` to `after_tree` (or the working tree).
+fn diff_trees<'a>(
+    repo: &'a Repository,
+    before_tree: Option<&Tree>,
+    after_tree: Option<&Tree>,
+    is_working_tree: bool,
+) -> Result<Diff<'a>> {
+    let mut opts = DiffOptions::new();
+    // Use 0 context lines so hunks contain only the actual changes,
+    // not surrounding context. This gives us precise alignment boundaries.
+    opts.context_lines(0);
+    diff_trees_with(repo, before_tree, after_tree, is_working_tree, &mut opts)
+}
+
+/// Like `diff_trees`, but starting from caller-supplied options.
+fn diff_trees_with<'a>(
+    repo: &'a Repository,
+    before_tree: Option<&Tree>,
+    after_tree: Option<&Tree>,
+    is_working_tree: bool,
+    opts: &mut DiffOptions,
+) -> Result<Diff<'a>> {
+    // Report file <-> symlink changes as one typechange entry,
+    // not a deletion plus an addition
+    opts.ignore_submodules(true).include_typechange(true);
+
+    let diff = if is_working_tree {
+        // Diff from before_tree to working directory
+        // Include untracked files so new files show up
+        opts.include_untracked(true);
+        // Recurse into untracked directories to show individual files
+        opts.recurse_untracked_dirs(true);
+        // Produce hunks for untracked files too, so they get line counts
+        opts.show_untracked_content(true);
+        repo.diff_tree_to_workdir_with_index(before_tree, Some(opts))?
+    } else {
+        // Diff between two trees
+        repo.diff_tree_to_tree(before_tree, after_tree, Some(opts))?
+    };
+    Ok(diff)
+}
+
+/// Render a standard unified diff (with 3 lines of context) for one file,
+/// suitable for copying elsewhere.
+///
+/// Binary files produce git's "Binary files ... differ" line. Returns an
+/// empty string if the file is unchanged.
+pub fn file_patch(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    path: &str,
+    algorithm: DiffAlgorithm,
+) -> Result<String> {
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+
+    let mut opts = DiffOptions::new();
+    opts.pathspec(path).disable_pathspec_match(true);
+    algorithm.apply(&mut opts);
+    let diff = diff_trees_with(
+        repo,
+        before_tree.as_ref(),
+        after_tree.as_ref(),
+        is_working_tree_ref(after_ref),
+        &mut opts,
+    )?;
+
+    let mut patch = String::new();
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        // Content lines carry their +/-/space marker separately
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin());
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+
+    Ok(patch)
+}
+
+/// Totals for a diff, for warning before loading a very large one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSummary {
+    pub files: usize,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// Count the files and lines changed between two refs from git's diff stats,
+/// without loading any content into `File`s.
+pub fn diff_summary(repo: &Repository, before_ref: &str, after_ref: &str) -> Result<DiffSummary> {
+    if is_working_tree_ref(before_ref) {
+        return Err(GitError(format!(
+            "{} can only be used as the target (head), not the base",
+            before_ref
+        )));
+    }
+
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+    let diff = diff_trees(
+        repo,
+        before_tree.as_ref(),
+        after_tree.as_ref(),
+        is_working_tree_ref(after_ref),
+    )?;
+
+    let stats = diff.stats()?;
+    Ok(DiffSummary {
+        files: stats.files_changed(),
+        additions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
+
+/// A changed file without its content, for listing the file tree cheaply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangedPath {
+    pub path: String,
+    pub status: ChangeKind,
+    /// Number of lines added
+    pub added: u32,
+    /// Number of lines removed
+    pub removed: u32,
+}
+
+/// List the files changed between two refs, with line counts.
+///
+/// Lighter than `compute_diff`: no file content is loaded or transported,
+/// so the UI can show the file tree first and fetch diffs per file later.
+pub fn get_changed_paths(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+) -> Result<Vec<ChangedPath>> {
+    if is_working_tree_ref(before_ref) {
+        return Err(GitError(format!(
+            "{} can only be used as the target (head), not the base",
+            before_ref
+        )));
+    }
+
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+    let is_working_tree = is_working_tree_ref(after_ref);
+
+    let diff = diff_trees(
+        repo,
+        before_tree.as_ref(),
+        after_tree.as_ref(),
+        is_working_tree,
+    )?;
+
+    let mut changes = collect_file_changes(&diff)?;
+    if is_working_tree {
+        changes = expand_untracked_dirs(repo, changes)?;
+    }
+    if after_ref == WORKDIR {
+        changes = reconcile_index_deletions(
+            repo,
+            before_tree.as_ref(),
+            changes,
+            DiffAlgorithm::default(),
+        )?;
+    }
+
+    let mut paths: Vec<ChangedPath> = changes
+        .into_iter()
+        .filter_map(|change| {
+            let status = change.kind();
+            // Hunks have no context lines, so their sizes are the change counts
+            let added = change.hunks.iter().map(|h| h.new_lines).sum();
+            let removed = change.hunks.iter().map(|h| h.old_lines).sum();
+            let path = change.after_path.or(change.before_path)?;
+            Some(ChangedPath {
+                path,
+                status,
+                added,
+                removed,
+            })
+        })
+        .collect();
+
+    paths.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(paths)
+}
+
+/// One file's line counts, like a line of `git diff --numstat`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NumstatEntry {
+    pub path: String,
+    /// Lines added; None for binary files (git prints `-`)
+    pub added: Option<u32>,
+    /// Lines removed; None for binary files
+    pub removed: Option<u32>,
+    pub binary: bool,
+}
+
+/// Per-file added/removed line counts between two refs, for tooling that
+/// wants `git diff --numstat` without shelling out. Sorted by path.
+pub fn numstat(repo: &Repository, before_ref: &str, after_ref: &str) -> Result<Vec<NumstatEntry>> {
+    if is_working_tree_ref(before_ref) {
+        return Err(GitError(format!(
+            "{} can only be used as the target (head), not the base",
+            before_ref
+        )));
+    }
+
+    let before_tree = resolve_to_tree(repo, effective_base(before_ref, after_ref))?;
+    let after_tree = resolve_to_tree(repo, after_ref)?;
+    let diff = diff_trees(
+        repo,
+        before_tree.as_ref(),
+        after_tree.as_ref(),
+        is_working_tree_ref(after_ref),
+    )?;
+    numstat_of(&diff)
+}
+
+/// Line counts for each file in `diff`, sorted by path.
+fn numstat_of(diff: &Diff) -> Result<Vec<NumstatEntry>> {
+    let mut entries = Vec::new();
+    for index in 0..diff.deltas().len() {
+        // Submodules and other unloadable entries have no patch
+        let Some(patch) = Patch::from_diff(diff, index)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let binary = delta.flags().is_binary();
+        let (_, added, removed) = patch.line_stats()?;
+        entries.push(NumstatEntry {
+            path: path.to_string_lossy().to_string(),
+            added: (!binary).then_some(added as u32),
+            removed: (!binary).then_some(removed as u32),
+            binary,
+        });
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// A file with uncommitted changes, as listed in the sidebar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkingTreeFile {
+    pub path: String,
+    /// Change staged in the index (HEAD to index), if any
+    pub staged_status: Option<ChangeKind>,
+    /// Change not yet staged (index to working tree), if any
+    pub unstaged_status: Option<ChangeKind>,
+    /// Lines added from HEAD to the working tree; 0 for binary files
+    pub added: u32,
+    /// Lines removed from HEAD to the working tree; 0 for binary files
+    pub removed: u32,
+    pub binary: bool,
+}
+
+/// Every file with staged or unstaged changes, with its status on each side
+/// and its line counts from HEAD to the working tree. Only the files status
+/// reports are diffed. Sorted by path.
+pub fn working_tree_overview(repo: &Repository) -> Result<Vec<WorkingTreeFile>> {
+    if repo.is_bare() {
+        return Err(GitError("Bare repository has no working tree".to_string()));
+    }
+
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let mut files: Vec<WorkingTreeFile> = statuses
+        .iter()
+        .filter_map(|entry| {
+            let status = entry.status();
+            let staged_status = change_kind_of(
+                status,
+                Status::INDEX_NEW,
+                Status::INDEX_DELETED,
+                Status::INDEX_MODIFIED | Status::INDEX_RENAMED | Status::INDEX_TYPECHANGE,
+            );
+            let unstaged_status = change_kind_of(
+                status,
+                Status::WT_NEW,
+                Status::WT_DELETED,
+                Status::WT_MODIFIED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE
+                    | Status::CONFLICTED,
+            );
+            if staged_status.is_none() && unstaged_status.is_none() {
+                return None;
+            }
+            Some(WorkingTreeFile {
+                path: entry.path()?.to_string(),
+                staged_status,
+                unstaged_status,
+                added: 0,
+                removed: 0,
+                binary: false,
+            })
+        })
+        .collect();
+    if files.is_empty() {
+        return Ok(files);
+    }
+
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.context_lines(0).disable_pathspec_match(true);
+    for file in &files {
+        diff_opts.pathspec(&file.path);
+    }
+    let head_tree = resolve_to_tree(repo, "HEAD")?;
+    let diff = diff_trees_with(repo, head_tree.as_ref(), None, true, &mut diff_opts)?;
+    let mut stats: HashMap<String, NumstatEntry> = numstat_of(&diff)?
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    // A file deleted from the index but still in the working tree (as after
+    // `git rm --cached`) is counted straight from HEAD, like
+    // `reconcile_index_deletions` does, not as a deletion
+    let recreated: Vec<&str> = files
+        .iter()
+        .filter(|f| {
+            f.staged_status == Some(ChangeKind::Deleted)
+                && f.unstaged_status == Some(ChangeKind::Added)
+        })
+        .map(|f| f.path.as_str())
+        .collect();
+    if !recreated.is_empty() {
+        let mut opts = DiffOptions::new();
+        opts.context_lines(0)
+            .include_typechange(true)
+            .ignore_submodules(true)
+            .disable_pathspec_match(true);
+        for path in &recreated {
+            opts.pathspec(path);
+            stats.remove(*path);
+        }
+        let diff = repo.diff_tree_to_workdir(head_tree.as_ref(), Some(&mut opts))?;
+        stats.extend(
+            numstat_of(&diff)?
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry)),
+        );
+    }
+
+    for file in &mut files {
+        if let Some(entry) = stats.get(&file.path) {
+            file.added = entry.added.unwrap_or(0);
+            file.removed = entry.removed.unwrap_or(0);
+            file.binary = entry.binary;
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Which part of `git status` a change belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusBucket {
+    /// HEAD to index
+    Staged,
+    /// Index to working tree, for tracked files (conflicts included)
+    Unstaged,
+    /// Not in the index at all
+    Untracked,
+}
+
+/// One path's change in one status bucket. A partly staged file has two.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusEntry {
+    pub path: String,
+    pub bucket: StatusBucket,
+    pub status: ChangeKind,
+}
+
+/// The working tree's status, one entry per path and bucket, in status order.
+///
+/// Only `git status` is consulted: no diff is run and no file content is
+/// loaded, so this is cheap enough to take on every watcher event. Works
+/// before the first commit.
+pub fn status_entries(repo: &Repository) -> Result<Vec<StatusEntry>> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut opts))?;
+
+    let mut entries = Vec::new();
+    for entry in statuses.iter() {
+        let Some(path) = entry.path() else {
+            continue;
+        };
+        let status = entry.status();
+        let staged = change_kind_of(
+            status,
+            Status::INDEX_NEW,
+            Status::INDEX_DELETED,
+            Status::INDEX_MODIFIED | Status::INDEX_RENAMED | Status::INDEX_TYPECHANGE,
+        );
+        let unstaged = change_kind_of(
+            status,
+            Status::empty(),
+            Status::WT_DELETED,
+            Status::WT_MODIFIED | Status::WT_RENAMED | Status::WT_TYPECHANGE | Status::CONFLICTED,
+        );
+        let untracked = status.is_wt_new().then_some(ChangeKind::Added);
+
+        for (bucket, kind) in [
+            (StatusBucket::Staged, staged),
+            (StatusBucket::Unstaged, unstaged),
+            (StatusBucket::Untracked, untracked),
+        ] {
+            if let Some(status) = kind {
+                entries.push(StatusEntry {
+                    path: path.to_string(),
+                    bucket,
+                    status,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Classify the side of a status picked out by the three flag sets.
+fn change_kind_of(
+    status: Status,
+    added: Status,
+    deleted: Status,
+    modified: Status,
+) -> Option<ChangeKind> {
+    if status.intersects(added) {
+        Some(ChangeKind::Added)
+    } else if status.intersects(deleted) {
+        Some(ChangeKind::Deleted)
+    } else if status.intersects(modified) {
+        Some(ChangeKind::Modified)
+    } else {
+        None
+    }
+}
+
+/// Build a gitignore-style matcher from path globs.
+pub(super) fn build_glob_matcher(globs: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("");
+    for glob in globs {
+        builder
+            .add_line(None, glob)
+            .map_err(|e| GitError(format!("Invalid path pattern '{}': {}", glob, e)))?;
+    }
+    builder
+        .build()
+        .map_err(|e| GitError(format!("Invalid path patterns: {}", e)))
+}
+
+/// Decides whether a file's content is loaded as text or binary.
+struct BinaryPolicy {
+    scan_len: usize,
+    force_text: Gitignore,
+    force_binary: Gitignore,
+}
+
+impl BinaryPolicy {
+    fn from_config(config: &DiffConfig) -> Result<Self> {
+        Ok(Self {
+            scan_len: config.binary_scan_len,
+            force_text: build_glob_matcher(&config.force_text)?,
+            force_binary: build_glob_matcher(&config.force_binary)?,
+        })
+    }
+
+    /// Check overrides first (user globs, then the `diff` gitattribute, which
+    /// `binary` unsets), falling back to sniffing the content.
+    fn is_binary(&self, repo: &Repository, path: &Path, bytes: &[u8]) -> bool {
+        if self
+            .force_text
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+        {
+            return false;
+        }
+        if self
+            .force_binary
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+        {
+            return true;
+        }
+        // LFS files are usually marked binary, but their pointers are shown
+        if LfsPointer::parse(bytes).is_some() {
+            return false;
+        }
+
+        let diff_attr = repo
+            .get_attr(path, "diff", AttrCheckFlags::FILE_THEN_INDEX)
+            .ok()
+            .flatten();
+        match AttrValue::from_string(diff_attr) {
+            AttrValue::True => false,
+            AttrValue::False => true,
+            _ => FileContent::is_binary_data(bytes, self.scan_len),
+        }
+    }
+
+    /// Turn raw file bytes into a file according to this policy.
+    /// `name` is the path shown, `path` the one attributes are looked up by.
+    fn load(&self, repo: &Repository, name: &str, path: &Path, bytes: &[u8]) -> File {
+        if self.is_binary(repo, path, bytes) {
+            File::new(name, FileContent::Binary)
+        } else {
+            File::from_bytes(name, bytes)
+        }
+    }
+}
+
+/// Check if a change should be excluded. Renames are excluded only if
+/// both sides match, so moving a file out of an excluded dir still shows.
+fn is_excluded(matcher: &Gitignore, change: &FileChange) -> bool {
+    let matches = |path: &Option<String>| {
+        path.as_ref().map_or(true, |p| {
+            matcher.matched_path_or_any_parents(p, false).is_ignore()
+        })
+    };
+    matches(&change.before_path) && matches(&change.after_path)
+}
+
+/// Check if either side of a change matches, so a generated file stays
+/// collapsed when it's renamed.
+fn matches_either_side(matcher: &Gitignore, change: &FileChange) -> bool {
+    [&change.before_path, &change.after_path]
+        .into_iter()
+        .flatten()
+        .any(|p| matcher.matched_path_or_any_parents(p, false).is_ignore())
+}
+
+/// A `FileDiff` for a change whose sides carry only their paths, for files
+/// listed without loading them (collapsed or too large).
+fn path_only_file_diff(change: &FileChange) -> FileDiff {
+    let side = |path: &Option<String>, absent: Delta| {
+        path.as_ref()
+            .filter(|_| change.status != absent)
+            .map(|path| File {
+                path: path.clone(),
+                content: FileContent::Text { lines: Vec::new() },
+                language: None,
+                truncated_lines: Vec::new(),
+                is_symlink: false,
+                encoding: None,
+                lfs: None,
+            })
+    };
+    let before = side(&change.before_path, Delta::Added);
+    let after = side(&change.after_path, Delta::Deleted);
+    let mode_change = (before.is_some() && after.is_some() && change.old_mode != change.new_mode)
+        .then(|| ModeChange {
+            before: change.old_mode.into(),
+            after: change.new_mode.into(),
+        });
+    FileDiff {
+        before,
+        after,
+        alignments: Vec::new(),
+        mode_change,
+        collapsed_by_default: false,
+        before_binary: false,
+        after_binary: false,
+        too_large: false,
+        before_size: None,
+        after_size: None,
+    }
+}
+
+/// Size in bytes of the blob at `path` in `tree`, without loading it. None
+/// if it's missing or not a blob (e.g. a submodule).
+fn tree_blob_size(repo: &Repository, tree: Option<&Tree>, path: &str) -> Result<Option<u64>> {
+    let Some(entry) = tree.and_then(|t| t.get_path(Path::new(path)).ok()) else {
+        return Ok(None);
+    };
+    if entry.kind() != Some(git2::ObjectType::Blob) {
+        return Ok(None);
+    }
+    let (size, _) = repo.odb()?.read_header(entry.id())?;
+    Ok(Some(size as u64))
+}
+
+/// Size in bytes of the working-tree file at `path` (for a symlink, the
+/// link itself). None if it's missing.
+fn workdir_file_size(repo: &Repository, path: &str) -> Option<u64> {
+    let full_path = repo.workdir()?.join(path);
+    std::fs::symlink_metadata(full_path)
+        .ok()
+        .map(|meta| meta.len())
+}
+
+/// Collect file changes with hunks from a git diff.
+fn collect_file_changes(diff: &Diff) -> Result<Vec<FileChange>> {
+    collect_file_changes_until(diff, None)
+}
+
+/// Like `collect_file_changes`, stopping with `GitError::cancelled()` once
+/// `cancel` is set.
+fn collect_file_changes_until(diff: &Diff, cancel: Option<&AtomicBool>) -> Result<Vec<FileChange>> {
+    let cancelled = || cancel.is_some_and(|c| c.load(Ordering::Relaxed));
+    // We need to collect hunks per file. The foreach callback gives us deltas and hunks,
+    // but we need to associate hunks with their files.
+    let file_changes: RefCell<Vec<FileChange>> = RefCell::new(Vec::new());
+    let current_file_idx: RefCell<Option<usize>> = RefCell::new(None);
+
+    diff.foreach(
+        &mut |delta, _progress| {
+            let before_path = delta
+                .old_file()
+                .path()
+                .map(|p| p.to_string_lossy().to_string());
+            let after_path = delta
+                .new_file()
+                .path()
+                .map(|p| p.to_string_lossy().to_string());
+            if cancelled() {
+                return false;
+            }
+
+            let old_id = delta.old_file().id();
+            let mut changes = file_changes.borrow_mut();
+            changes.push(FileChange {
+                before_path,
+                after_path,
+                status: delta.status(),
+                old_mode: delta.old_file().mode(),
+                new_mode: delta.new_file().mode(),
+                hunks: Vec::new(),
+                same_blob: !old_id.is_zero() && old_id == delta.new_file().id(),
+            });
+            *current_file_idx.borrow_mut() = Some(changes.len() - 1);
+            true
+        },
+        None, // binary callback
+        Some(&mut |_delta, hunk| {
+            if cancelled() {
+                return false;
+            }
+            let h = Hunk::from_git(&hunk);
+
+            if let Some(idx) = *current_file_idx.borrow() {
+                file_changes.borrow_mut()[idx].hunks.push(h);
+            }
+            true
+        }),
+        None, // line callback
+    )
+    .map_err(|e| {
+        if cancelled() {
+            GitError::cancelled()
+        } else {
+            e.into()
+        }
+    })?;
+
+    Ok(file_changes.into_inner())
+}
+
+/// Fix up files deleted from the index but still (or again) in the working
+/// tree, as after `git rm --cached`.
+///
+/// A diff to the working tree goes through the index, so git reports such a
+/// file as deleted and drops its working-tree copy. Diff those paths from
+/// `before_tree` straight to the working tree instead: the file shows as
+/// modified if its content changed, and disappears if it didn't.
+fn reconcile_index_deletions(
+    repo: &Repository,
+    before_tree: Option<&Tree>,
+    changes: Vec<FileChange>,
+    algorithm: DiffAlgorithm,
+) -> Result<Vec<FileChange>> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(changes);
+    };
+    let present = |change: &FileChange| {
+        change.status == Delta::Deleted
+            && change.before_path.as_ref().is_some_and(|path| {
+                let full_path = workdir.join(path);
+                full_path.is_symlink() || full_path.is_file()
+            })
+    };
+    let (recreated, mut changes): (Vec<_>, Vec<_>) = changes.into_iter().partition(present);
+    if recreated.is_empty() {
+        return Ok(changes);
+    }
+
+    let mut opts = DiffOptions::new();
+    opts.context_lines(0)
+        .include_typechange(true)
+        .ignore_submodules(true)
+        .disable_pathspec_match(true);
+    algorithm.apply(&mut opts);
+    for change in &recreated {
+        if let Some(path) = &change.before_path {
+            opts.pathspec(path);
+        }
+    }
+    let diff = repo.diff_tree_to_workdir(before_tree, Some(&mut opts))?;
+    changes.extend(collect_file_changes(&diff)?);
+    Ok(changes)
+}
+
+/// Replace untracked directory entries with one entry per file inside.
+///
+/// We ask git to recurse into untracked directories, but a directory can
+/// still come back as a single `dir/` entry, which has no content to load.
+/// Walking it (with ignore rules applied) lets every file be listed, diffed
+/// and staged on its own. Nested repositories are left as they are.
+fn expand_untracked_dirs(repo: &Repository, changes: Vec<FileChange>) -> Result<Vec<FileChange>> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(changes);
+    };
+
+    let mut expanded = Vec::with_capacity(changes.len());
+    for change in changes {
+        let dir = match (&change.status, &change.after_path) {
+            (Delta::Untracked, Some(path)) if workdir.join(path).is_dir() => workdir.join(path),
+            _ => {
+                expanded.push(change);
+                continue;
+            }
+        };
+        if dir.join(".git").exists() {
+            expanded.push(change);
+            continue;
+        }
+
+        let walker = WalkBuilder::new(&dir)
+            .hidden(false)
+            .parents(true)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        let mut files: Vec<_> = walker
+            .flatten()
+            .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
+            .filter_map(|entry| {
+                let relative = entry.path().strip_prefix(workdir).ok()?;
+                Some(relative.to_string_lossy().replace('\\', "/"))
+            })
+            .collect();
+        files.sort();
+
+        for path in files {
+            // Untracked, so the whole file is one added hunk
+            let bytes = std::fs::read(workdir.join(&path)).unwrap_or_default();
+            expanded.push(FileChange {
+                before_path: Some(path.clone()),
+                after_path: Some(path),
+                status: Delta::Untracked,
+                old_mode: FileMode::Unreadable,
+                new_mode: FileMode::Blob,
+                hunks: vec![Hunk {
+                    old_start: 0,
+                    old_lines: 0,
+                    new_start: 0,
+                    new_lines: count_lines(&bytes),
+                }],
+                same_blob: false,
+            });
+        }
+    }
+    Ok(expanded)
+}
+
+/// Number of lines in `path` at `refspec` (or the working tree), or None if
+/// the file doesn't exist there.
+pub fn file_line_count(repo: &Repository, refspec: &str, path: &str) -> Result<Option<u32>> {
+    Ok(raw_content(repo, refspec, path)?.map(|bytes| count_lines(&bytes)))
+}
+
+/// Both sides of a file as whole text, for frontends that run their own diff
+/// algorithm instead of using alignments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileContents {
+    /// None if the file doesn't exist on this side, or is binary
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub before_binary: bool,
+    pub after_binary: bool,
+}
+
+/// Load the text of `path` on both sides of `before_ref..after_ref`.
+///
+/// Binary detection (under `config`'s binary policy) and line-ending
+/// normalization match `compute_diff`, so the text is what its alignments
+/// were computed from.
+pub fn file_contents(
+    repo: &Repository,
+    before_ref: &str,
+    after_ref: &str,
+    path: &str,
+    config: &DiffConfig,
+) -> Result<FileContents> {
+    let policy = BinaryPolicy::from_config(config)?;
+    let side = |refspec: &str| -> Result<(Option<String>, bool)> {
+        let Some(mut bytes) = raw_content(repo, refspec, path)? else {
+            return Ok((None, false));
+        };
+        if policy.is_binary(repo, Path::new(path), &bytes) {
+            return Ok((None, true));
+        }
+        if is_working_tree_ref(refspec) && converts_eol(repo, Path::new(path)) {
+            bytes = normalize_eol(&bytes);
+        }
+        Ok((Some(decode_text(&bytes)), false))
+    };
+
+    let (before, before_binary) = side(effective_base(before_ref, after_ref))?;
+    let (after, after_binary) = side(after_ref)?;
+    Ok(FileContents {
+        before,
+        after,
+        before_binary,
+        after_binary,
+    })
+}
+
+/// A conflicted file's three index stages, for resolving it in another tool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictExport {
+    /// The common ancestor (stage 1); None for an add/add conflict
+    pub base: Option<String>,
+    /// Our side (stage 2); empty if we deleted the file
+    pub ours: String,
+    /// Their side (stage 3); empty if they deleted the file
+    pub theirs: String,
+    /// The merge with diff3-style markers (`<<<<<<<`, `|||||||`, `=======`,
+    /// `>>>>>>>`) around each conflicting region, ready to copy
+    pub diff3: String,
+}
+
+/// Read the conflicted `path`'s stages from the index and merge them with
+/// diff3-style conflict markers. Fails if `path` isn't conflicted or any
+/// stage is binary under `config`'s binary policy.
+pub fn export_conflict(
+    repo: &Repository,
+    path: &str,
+    config: &DiffConfig,
+) -> Result<ConflictExport> {
+    let index = repo.index()?;
+    let policy = BinaryPolicy::from_config(config)?;
+    let stage = |stage: i32| -> Result<Option<String>> {
+        let Some(entry) = index.get_path(Path::new(path), stage) else {
+            return Ok(None);
+        };
+        let blob = repo.find_blob(entry.id)?;
+        if policy.is_binary(repo, Path::new(path), blob.content()) {
+            return Err(GitError(format!(
+                "Cannot export a conflict in binary file '{}'",
+                path
+            )));
+        }
+        Ok(Some(decode_text(blob.content())))
+    };
+
+    let (base, ours, theirs) = (stage(1)?, stage(2)?, stage(3)?);
+    if ours.is_none() && theirs.is_none() {
+        return Err(GitError(format!("'{}' has no conflict", path)));
+    }
+    let (ours, theirs) = (ours.unwrap_or_default(), theirs.unwrap_or_default());
+    let diff3 = merge_diff3(base.as_deref().unwrap_or(""), &ours, &theirs)?;
+    Ok(ConflictExport {
+        base,
+        ours,
+        theirs,
+        diff3,
+    })
+}
+
+/// Three-way merge `ours` and `theirs` from `base`, by lines. Changes from
+/// one side are taken as-is; where both sides changed the same (or
+/// touching) base lines differently, the region is written with diff3
+/// conflict markers, as `git merge-file --diff3` would.
+fn merge_diff3(base: &str, ours: &str, theirs: &str) -> Result<String> {
+    let lines = |text: &str| {
+        text.split_inclusive('\n')
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    let (base_lines, our_lines, their_lines) = (lines(base), lines(ours), lines(theirs));
+
+    // Each side's changes, as (side, hunk) in base order
+    let mut opts = DiffOptions::new();
+    opts.context_lines(0);
+    let mut changes = Vec::new();
+    for (side, text) in [(0, ours), (1, theirs)] {
+        let patch = Patch::from_buffers(
+            base.as_bytes(),
+            None,
+            text.as_bytes(),
+            None,
+            Some(&mut opts),
+        )?;
+        for i in 0..patch.num_hunks() {
+            changes.push((side, Hunk::from_git(&patch.hunk(i)?.0)));
+        }
+    }
+    changes.sort_by_key(|(_, hunk)| (hunk.old_start, hunk.old_start + hunk.old_lines));
+
+    let mut merged = String::new();
+    let push = |merged: &mut String, lines: &[String]| {
+        for line in lines {
+            merged.push_str(line);
+        }
+    };
+    let marker = |merged: &mut String, marker: &str| {
+        if !merged.is_empty() && !merged.ends_with('\n') {
+            merged.push('\n');
+        }
+        merged.push_str(marker);
+        merged.push('\n');
+    };
+
+    // How far each side's line numbers have drifted from base's so far
+    let mut offsets = [0i64; 2];
+    let mut base_pos = 0usize;
+    let mut i = 0;
+    while i < changes.len() {
+        // Group changes that overlap or touch into one region of base
+        let start = changes[i].1.old_start as usize;
+        let mut end = (changes[i].1.old_start + changes[i].1.old_lines) as usize;
+        let mut growth = [0i64; 2];
+        let mut sides = [false; 2];
+        while i < changes.len() && changes[i].1.old_start as usize <= end {
+            let (side, hunk) = &changes[i];
+            end = end.max((hunk.old_start + hunk.old_lines) as usize);
+            growth[*side] += hunk.new_lines as i64 - hunk.old_lines as i64;
+            sides[*side] = true;
+            i += 1;
+        }
+
+        push(&mut merged, &base_lines[base_pos..start]);
+        let side_lines = |side: usize| {
+            let side_start = (start as i64 + offsets[side]) as usize;
+            let side_end = (end as i64 + offsets[side] + growth[side]) as usize;
+            let all = if side == 0 { &our_lines } else { &their_lines };
+            &all[side_start..side_end]
+        };
+        let (ours, theirs) = (side_lines(0), side_lines(1));
+        match sides {
+            [true, false] => push(&mut merged, ours),
+            [false, true] => push(&mut merged, theirs),
+            _ if ours == theirs => push(&mut merged, ours),
+            _ => {
+                marker(&mut merged, "<<<<<<< ours");
+                push(&mut merged, ours);
+                marker(&mut merged, "||||||| base");
+                push(&mut merged, &base_lines[start..end]);
+                marker(&mut merged, "=======");
+                push(&mut merged, theirs);
+                marker(&mut merged, ">>>>>>> theirs");
+            }
+        }
+
+        for (offset, growth) in offsets.iter_mut().zip(growth) {
+            *offset += growth;
+        }
+        base_pos = end;
+    }
+    push(&mut merged, &base_lines[base_pos..]);
+    Ok(merged)
+}
+
+/// The blob id `path` has at a ref, or would have if the working tree file
+/// were staged. None if it doesn't exist there.
+pub fn blob_oid(repo: &Repository, refspec: &str, path: &str) -> Result<Option<String>> {
+    let Some(mut bytes) = raw_content(repo, refspec, path)? else {
+        return Ok(None);
+    };
+    if is_working_tree_ref(refspec) && converts_eol(repo, Path::new(path)) {
+        bytes = normalize_eol(&bytes);
+    }
+    let oid = git2::Oid::hash_object(git2::ObjectType::Blob, &bytes)?;
+    Ok(Some(oid.to_string()))
+}
+
+/// Why a blob's bytes couldn't be returned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlobError {
+    /// The blob is bigger than the caller's limit (both in bytes)
+    TooLarge { size: u64, limit: u64 },
+    /// The ref or the file couldn't be read
+    Failed { message: String },
+    /// The file doesn't exist on that side. `blob_bytes` returns None
+    /// instead; this is for callers that can't send a null.
+    Missing,
+}
+
+impl std::fmt::Display for BlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlobError::TooLarge { size, limit } => write!(
+                f,
+                "File is too large to load ({} bytes, limit {})",
+                size, limit
+            ),
+            BlobError::Failed { message } => write!(f, "{}", message),
+            BlobError::Missing => write!(f, "File doesn't exist"),
+        }
+    }
+}
+
+impl std::error::Error for BlobError {}
+
+impl From<GitError> for BlobError {
+    fn from(e: GitError) -> Self {
+        BlobError::Failed { message: e.0 }
+    }
+}
+
+impl From<git2::Error> for BlobError {
+    fn from(e: git2::Error) -> Self {
+        GitError::from(e).into()
+    }
+}
+
+/// Exact bytes of `path` at a ref or in the working tree, for previewing
+/// binary files such as images. None if it doesn't exist there.
+///
+/// The size is checked before anything is read, so a huge file is refused
+/// without loading it. Unlike `raw_content`, no line ending conversion is
+/// applied.
+pub fn blob_bytes(
+    repo: &Repository,
+    refspec: &str,
+    path: &str,
+    limit: u64,
+) -> std::result::Result<Option<Vec<u8>>, BlobError> {
+    let check = |size: u64| {
+        if size > limit {
+            Err(BlobError::TooLarge { size, limit })
+        } else {
+            Ok(())
+        }
+    };
+
+    if is_working_tree_ref(refspec) {
+        let full_path = workdir_file(repo, path)?;
+        match std::fs::symlink_metadata(&full_path) {
+            Ok(meta) if meta.is_file() => check(meta.len())?,
+            // Symlinks give their target path, which is always small
+            Ok(_) => return Ok(raw_content(repo, refspec, path)?),
+            Err(_) => return Ok(None),
+        }
+        return std::fs::read(&full_path)
+            .map(Some)
+            .map_err(|e| BlobError::Failed {
+                message: format!("Cannot read '{}': {}", path, e),
+            });
+    }
+
+    let tree = resolve_to_tree(repo, refspec)?;
+    let entry = match tree.and_then(|t| t.get_path(Path::new(path)).ok()) {
+        Some(entry) if entry.kind() == Some(git2::ObjectType::Blob) => entry,
+        _ => return Ok(None), // Missing, a directory, or a submodule
+    };
+    let (size, _) = repo.odb()?.read_header(entry.id())?;
+    check(size as u64)?;
+    Ok(Some(repo.find_blob(entry.id())?.content().to_vec()))
+}
+
+/// Where the file at repo-relative `path` is in the working tree.
+///
+/// With `core.ignorecase` (as on macOS and Windows) the filesystem opens
+/// `Foo.txt` when asked for `foo.txt`, which git may track as a different
+/// file. A path that only exists on disk with different case is an error
+/// rather than a read of the wrong file.
+fn workdir_file(repo: &Repository, path: &str) -> Result<PathBuf> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitError("Bare repository".into()))?;
+    let ignorecase = repo
+        .config()
+        .and_then(|config| config.get_bool("core.ignorecase"))
+        .unwrap_or(false);
+    if ignorecase {
+        check_path_case(workdir, path)?;
+    }
+    Ok(workdir.join(path))
+}
+
+/// Fail if some component of `path` is under `root` only with other case.
+/// A path that's simply missing is fine.
+fn check_path_case(root: &Path, path: &str) -> Result<()> {
+    let mut dir = root.to_path_buf();
+    let mut on_disk: Vec<String> = Vec::new();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Ok(());
+        };
+        let names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        if !names.iter().any(|name| name == component) {
+            let lower = component.to_lowercase();
+            return match names.iter().find(|name| name.to_lowercase() == lower) {
+                Some(actual) => {
+                    on_disk.push(actual.clone());
+                    Err(GitError(format!(
+                        "'{}' differs only in case from '{}' in the working tree",
+                        path,
+                        on_disk.join("/")
+                    )))
+                }
+                None => Ok(()),
+            };
+        }
+        on_disk.push(component.to_string());
+        dir.push(component);
+    }
+    Ok(())
+}
+
+/// Raw bytes of `path` at a ref or in the working tree, or None if it doesn't
+/// exist there. Symlinks give their target path, as git stores them.
+fn raw_content(repo: &Repository, refspec: &str, path: &str) -> Result<Option<Vec<u8>>> {
+    if is_working_tree_ref(refspec) {
+        let full_path = workdir_file(repo, path)?;
+        if full_path.is_symlink() {
+            return Ok(std::fs::read_link(&full_path)
+                .ok()
+                .map(|target| target.to_string_lossy().into_owned().into_bytes()));
+        }
+        return Ok(std::fs::read(full_path).ok());
+    }
+
+    let tree = resolve_to_tree(repo, refspec)?;
+    let entry = match tree.and_then(|t| t.get_path(Path::new(path)).ok()) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    match entry.to_object(repo)?.into_blob() {
+        Ok(blob) => Ok(Some(blob.content().to_vec())),
+        Err(_) => Ok(None), // Not a file (maybe a submodule)
+    }
+}
+
+/// Number of lines in `bytes`, counting a final unterminated line.
+pub(super) fn count_lines(bytes: &[u8]) -> u32 {
+    let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+    let unterminated = !bytes.is_empty() && !bytes.ends_with(b"\n");
+    (newlines + usize::from(unterminated)) as u32
+}
+
+/// Compute alignments from git hunks.
+///
+/// This ensures our alignments match what git diff reports.
+/// Hunks define the changed regions; we fill in unchanged regions between them.
+fn compute_alignments_from_hunks(
+    hunks: &[Hunk],
+    before: &Option<File>,
+    after: &Option<File>,
+) -> Vec<Alignment> {
+    let before_len = before
+        .as_ref()
+        .map(|f| f.content.lines().len() as u32)
+        .unwrap_or(0);
+    let after_len = after
+        .as_ref()
+        .map(|f| f.content.lines().len() as u32)
+        .unwrap_or(0);
+
+    // Handle empty files
+    if before_len == 0 && after_len == 0 {
+        return vec![];
+    }
+
+    // If no hunks but files exist, it's either all added or all deleted
+    if hunks.is_empty() {
+        if before_len == 0 {
+            // All added
+            return vec![Alignment {
+                before: Span::new(0, 0),
+                after: Span::new(0, after_len),
+                changed: true,
+                collapsed: None,
+            }];
+        } else if after_len == 0 {
+            // All deleted
+            return vec![Alignment {
+                before: Span::new(0, before_len),
+                after: Span::new(0, 0),
+                changed: true,
+                collapsed: None,
+            }];
+        } else {
+            // No changes (shouldn't happen for files in a diff, but handle gracefully)
+            return vec![Alignment {
+                before: Span::new(0, before_len),
+                after: Span::new(0, after_len),
+                changed: false,
+                collapsed: None,
+            }];
+        }
+    }
+
+    let mut alignments = Vec::new();
+    let mut before_pos = 0u32;
+    let mut after_pos = 0u32;
+
+    for hunk in hunks {
+        // Unchanged region before this hunk
+        if before_pos < hunk.old_start || after_pos < hunk.new_start {
+            // The gap should be the same size on both sides for unchanged content
+            let before_gap = hunk.old_start - before_pos;
+            let after_gap = hunk.new_start - after_pos;
+
+            // They should match for truly unchanged content, but handle edge cases
+            if before_gap > 0 || after_gap > 0 {
+                alignments.push(Alignment {
+                    before: Span::new(before_pos, hunk.old_start),
+                    after: Span::new(after_pos, hunk.new_start),
+                    changed: false,
+                    collapsed: None,
+                });
+            }
+        }
+
+        // The hunk itself (changed region)
+        let hunk_before_end = hunk.old_start + hunk.old_lines;
+        let hunk_after_end = hunk.new_start + hunk.new_lines;
+
+        alignments.push(Alignment {
+            before: Span::new(hunk.old_start, hunk_before_end),
+            after: Span::new(hunk.new_start, hunk_after_end),
+            changed: true,
+            collapsed: None,
+        });
+
+        before_pos = hunk_before_end;
+        after_pos = hunk_after_end;
+    }
+
+    // Unchanged region after the last hunk
+    if before_pos < before_len || after_pos < after_len {
+        alignments.push(Alignment {
+            before: Span::new(before_pos, before_len),
+            after: Span::new(after_pos, after_len),
+            changed: false,
+            collapsed: None,
+        });
+    }
+
+    alignments
+}
+
+/// Load a file from a git tree.
+fn load_file(
+    repo: &Repository,
+    tree: Option<&Tree>,
+    path: &Path,
+    policy: &BinaryPolicy,
+) -> Result<Option<File>> {
+    let tree = match tree {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+
+    let entry = match tree.get_path(path) {
+        Ok(e) => e,
+        Err(_) => return Ok(None), // File doesn't exist in this tree
+    };
+
+    let obj = entry
+        .to_object(repo)
+        .map_err(|e| GitError(format!("Cannot load object: {}", e)))?;
+
+    let blob = match obj.as_blob() {
+        Some(b) => b,
+        None => return Ok(None), // Not a file (maybe a submodule)
+    };
+
+    // A symlink's blob is its target path, never binary-sniffed
+    if entry.filemode() as u32 == SYMLINK_MODE {
+        let target = String::from_utf8_lossy(blob.content());
+        return Ok(Some(File::symlink(path.to_string_lossy(), &target)));
+    }
+
+    Ok(Some(policy.load(
+        repo,
+        &path.to_string_lossy(),
+        path,
+        blob.content(),
+    )))
+}
+
+/// Load a file from the working directory.
+fn load_file_from_workdir(
+    repo: &Repository,
+    path: &Path,
+    policy: &BinaryPolicy,
+) -> Result<Option<File>> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitError("Bare repository".into()))?;
+    let full_path = workdir.join(path);
+
+    // Git stores a symlink as its target path, so show that rather than
+    // following the link (which may also dangle)
+    if full_path.is_symlink() {
+        let target = std::fs::read_link(&full_path)
+            .map_err(|e| GitError(format!("Cannot read symlink: {}", e)))?;
+        return Ok(Some(File::symlink(
+            path.to_string_lossy(),
+            &target.to_string_lossy(),
+        )));
+    }
+
+    if !full_path.exists() {
+        return Ok(None);
+    }
+
+    // Skip directories (e.g., submodules)
+    if full_path.is_dir() {
+        log::debug!(
+            "Skipping directory in load_file_from_workdir: {}",
+            path.display()
+        );
+        return Ok(None);
+    }
+
+    let mut bytes =
+        std::fs::read(&full_path).map_err(|e| GitError(format!("Cannot read file: {}", e)))?;
+
+    // Compare in repository form: git converts CRLF to LF when staging, so
+    // the blob side never has the CRLFs the working tree file does
+    let is_binary = policy.is_binary(repo, path, &bytes);
+    if !is_binary && converts_eol(repo, path) {
+        bytes = normalize_eol(&bytes);
+    }
+
+    let name = path.to_string_lossy();
+    if is_binary {
+        return Ok(Some(File::new(name, FileContent::Binary)));
+    }
+    Ok(Some(File::from_bytes(name, &bytes)))
+}
+
+/// Check if git converts line endings for this path when staging it,
+/// via the `text`/`eol` attributes or `core.autocrlf`.
+fn converts_eol(repo: &Repository, path: &Path) -> bool {
+    let attr = |name: &str| {
+        let value = repo
+            .get_attr(path, name, AttrCheckFlags::FILE_THEN_INDEX)
+            .ok()
+            .flatten();
+        AttrValue::from_string(value)
+    };
+
+    match attr("text") {
+        AttrValue::False => return false,
+        AttrValue::True | AttrValue::String(_) => return true,
+        _ => {}
+    }
+    if matches!(attr("eol"), AttrValue::String(_)) {
+        return true;
+    }
+
+    repo.config()
+        .and_then(|c| c.get_string("core.autocrlf"))
+        .map(|v| matches!(v.to_lowercase().as_str(), "true" | "input"))
+        .unwrap_or(false)
+}
+
+/// Convert CRLF line endings to LF.
+fn normalize_eol(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().peekable();
+    while let Some(&b) = iter.next() {
+        if b == b'\r' && iter.peek() == Some(&&b'\n') {
+            continue;
+        }
+        out.push(b);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Create a repo in a temp dir with an initial commit of the given files.
+    fn repo_with_commit(files: &[(&str, &str)]) -> (TempDir, Repository) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+        }
+        write_files(&repo, files);
+        commit_all(&repo, "initial");
+        (dir, repo)
+    }
+
+    /// Write files into the repo's working directory.
+    fn write_files(repo: &Repository, files: &[(&str, &str)]) {
+        let workdir = repo.workdir().unwrap();
+        for (path, content) in files {
+            let full_path = workdir.join(path);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(full_path, content).unwrap();
+        }
+    }
+
+    /// Stage everything in the working directory and commit it.
+    fn commit_all(repo: &Repository, message: &str) -> git2::Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_head_sha() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let commit = repo.head().unwrap().peel_to_commit().unwrap().id();
+        let branch = current_branch(&repo).unwrap();
+        assert_eq!(
+            head_sha(&repo).unwrap(),
+            Some(HeadSha {
+                full: commit.to_string(),
+                short: commit.to_string()[..8].to_string(),
+                detached: false,
+                branch,
+            })
+        );
+
+        repo.set_head_detached(commit).unwrap();
+        let head = head_sha(&repo).unwrap().unwrap();
+        assert_eq!(head.full, commit.to_string());
+        assert!(head.detached);
+        assert_eq!(head.branch, None);
+
+        let empty_dir = TempDir::new().unwrap();
+        let empty = Repository::init(empty_dir.path()).unwrap();
+        assert_eq!(head_sha(&empty).unwrap(), None);
+    }
+
+    #[test]
+    fn test_empty_repo_first_commit() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+        }
+        repo.set_head("refs/heads/trunk").unwrap();
+        write_files(&repo, &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+
+        // Info and status work before anything is committed
+        let info = get_repo_info(&repo).unwrap();
+        assert_eq!(info.branch.as_deref(), Some("trunk"));
+        assert_eq!(last_commit_message(&repo).unwrap(), None);
+        let mut paths: Vec<String> = get_changed_paths(&repo, "HEAD", WORKDIR)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["a.txt", "b.txt"]);
+
+        // A stale staged file must not sneak into the root commit
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("b.txt")).unwrap();
+        index.write().unwrap();
+
+        create_commit(&repo, &["a.txt".to_string()], "First").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_count(), 0);
+        assert!(head.tree().unwrap().get_path(Path::new("a.txt")).is_ok());
+        assert!(head.tree().unwrap().get_path(Path::new("b.txt")).is_err());
+        assert_eq!(current_branch(&repo).unwrap().as_deref(), Some("trunk"));
+        assert_eq!(
+            last_commit_message(&repo).unwrap().as_deref(),
+            Some("First")
+        );
+    }
+
+    #[test]
+    fn test_amend_commit_message_keeps_tree() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let before = repo.head().unwrap().peel_to_commit().unwrap();
+
+        // Staged changes must not leak into the amended commit
+        write_files(&repo, &[("a.txt", "changed\n")]);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        amend_commit_message(&repo, "better message").unwrap();
+        let after = repo.head().unwrap().peel_to_commit().unwrap();
+
+        assert_ne!(after.id(), before.id());
+        assert_eq!(after.tree_id(), before.tree_id());
+        assert_eq!(after.message(), Some("better message"));
+        assert_eq!(after.parent_count(), before.parent_count());
+
+        assert!(amend_commit_message(&repo, "  ").is_err());
+    }
+
+    #[test]
+    fn test_per_commit_diffs() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "1\n")]);
+        let config = DiffConfig::default();
+        let base = repo
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id()
+            .to_string();
+        for (n, message) in ["two", "three", "four"].iter().enumerate() {
+            write_files(&repo, &[("a.txt", &format!("{}\n", n + 2))]);
+            commit_all(&repo, message);
+        }
+
+        let steps = per_commit_diffs(&repo, &base, "HEAD", 10, &config).unwrap();
+        let summary: Vec<_> = steps
+            .iter()
+            .map(|step| {
+                let file = &step.files[0];
+                (
+                    step.summary.as_str(),
+                    file.before.as_ref().unwrap().content.lines()[0].clone(),
+                    file.after.as_ref().unwrap().content.lines()[0].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("two", "1".to_string(), "2".to_string()),
+                ("three", "2".to_string(), "3".to_string()),
+                ("four", "3".to_string(), "4".to_string()),
+            ]
+        );
+        assert_eq!(
+            steps[2].sha,
+            repo.head()
+                .unwrap()
+                .peel_to_commit()
+                .unwrap()
+                .id()
+                .to_string()
+        );
+
+        // Capped from the oldest
+        let capped = per_commit_diffs(&repo, &base, "HEAD", 2, &config).unwrap();
+        let shas: Vec<_> = capped.iter().map(|s| s.summary.as_str()).collect();
+        assert_eq!(shas, ["two", "three"]);
+
+        // Files the range view hides are hidden from each step too
+        write_files(&repo, &[("a.txt", "5\n"), ("gen.lock", "x\n")]);
+        commit_all(&repo, "five");
+        let config = DiffConfig {
+            exclude: vec!["*.lock".into()],
+            ..Default::default()
+        };
+        let steps = per_commit_diffs(&repo, &base, "HEAD", 10, &config).unwrap();
+        let paths: Vec<&str> = steps[3].files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, ["a.txt"]);
+    }
+
+    #[test]
+    fn test_commit_log_first_parent() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let base = repo.head().unwrap().target().unwrap();
+        let main = repo.head().unwrap().name().unwrap().to_string();
+
+        // Side branch off the base
+        repo.branch("side", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+        repo.set_head("refs/heads/side").unwrap();
+        write_files(&repo, &[("side.txt", "s\n")]);
+        let side = commit_all(&repo, "side work");
+
+        // Mainline commit, then merge side in
+        repo.set_head(&main).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        write_files(&repo, &[("a.txt", "main\n")]);
+        let mainline = commit_all(&repo, "main work");
+        write_files(&repo, &[("side.txt", "s\n")]);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("side.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        let parents = [
+            &repo.find_commit(mainline).unwrap(),
+            &repo.find_commit(side).unwrap(),
+        ];
+        let merge = repo
+            .commit(Some("HEAD"), &sig, &sig, "merge side", &tree, &parents)
+            .unwrap();
+        write_files(&repo, &[("a.txt", "after\n")]);
+        commit_all(&repo, "after merge");
+
+        let base = base.to_string();
+        let summaries = |first_parent| {
+            commit_log(&repo, &base, "HEAD", first_parent)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.summary)
+                .collect::<Vec<_>>()
+        };
+
+        let mut all = summaries(false);
+        all.sort();
+        assert_eq!(all, ["after merge", "main work", "merge side", "side work"]);
+        assert_eq!(summaries(true), ["after merge", "merge side", "main work"]);
+
+        let log = commit_log(&repo, &base, WORKDIR, true).unwrap();
+        assert!(log[1].is_merge);
+        assert_eq!(log[1].sha, merge.to_string());
+
+        // The merge's own diff is against its first parent
+        let diff = compute_commit_diff(&repo, &merge.to_string(), &DiffConfig::default()).unwrap();
+        assert_eq!(diff.base, mainline.to_string());
+        assert_eq!(diff.files.len(), 1);
+    }
+
+    #[test]
+    fn test_recent_commit_messages() {
+        let dir = TempDir::new().unwrap();
+        let empty = Repository::init(dir.path()).unwrap();
+        assert!(recent_commit_messages(&empty, 5).unwrap().is_empty());
+
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "1\n")]);
+        write_files(&repo, &[("a.txt", "2\n")]);
+        let second = commit_all(&repo, "second\n\nwith a body");
+        write_files(&repo, &[("a.txt", "3\n")]);
+        let third = commit_all(&repo, "third");
+
+        let messages = recent_commit_messages(&repo, 2).unwrap();
+        assert_eq!(
+            messages,
+            [
+                CommitMessage {
+                    sha: third.to_string(),
+                    message: "third".into()
+                },
+                CommitMessage {
+                    sha: second.to_string(),
+                    message: "second\n\nwith a body".into()
+                },
+            ]
+        );
+        assert_eq!(recent_commit_messages(&repo, 10).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_mailmap_canonicalizes_authors() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let base = repo.head().unwrap().target().unwrap().to_string();
+        write_files(&repo, &[("a.txt", "b\n")]);
+        commit_all(&repo, "change");
+
+        let log_author = || {
+            commit_log(&repo, &base, "HEAD", false).unwrap()[0]
+                .author
+                .clone()
+        };
+        let blame_author = || {
+            crate::diff::blame_lines(&repo, "HEAD", "a.txt", 0, 1).unwrap()[0]
+                .author
+                .clone()
+        };
+
+        // No mailmap: the recorded identity
+        assert_eq!(log_author(), "Test");
+        assert_eq!(blame_author(), "Test");
+
+        write_files(
+            &repo,
+            &[(
+                ".mailmap",
+                "Canonical Name <canon@example.com> <test@example.com>\n",
+            )],
+        );
+        assert_eq!(log_author(), "Canonical Name");
+        assert_eq!(blame_author(), "Canonical Name");
+    }
+
+    #[test]
+    fn test_empty_tree_ref() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "1\n2\n"), ("dir/b.txt", "b\n")]);
+        let root = repo
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id()
+            .to_string();
+
+        let files = compute_diff(&repo, EMPTY, &root, false, &DiffConfig::default())
+            .unwrap()
+            .files;
+        let added: Vec<_> = files
+            .iter()
+            .map(|f| (f.path(), f.before.is_none(), f.change_kind()))
+            .collect();
+        assert_eq!(
+            added,
+            [
+                ("a.txt", true, ChangeKind::Added),
+                ("dir/b.txt", true, ChangeKind::Added),
+            ]
+        );
+        assert_eq!(resolve_ref(&repo, EMPTY).unwrap(), "empty tree");
+
+        let log = commit_log(&repo, EMPTY, &root, false).unwrap();
+        assert_eq!(log.len(), 1);
+    }
+
+    #[test]
+    fn test_commit_diff_normal_and_root() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]);
+        let root = repo.head().unwrap().target().unwrap();
+        write_files(&repo, &[("a.txt", "changed\n")]);
+        let second = commit_all(&repo, "change a");
+
+        let config = DiffConfig::default();
+        let diff = compute_commit_diff(&repo, &second.to_string(), &config).unwrap();
+        assert_eq!(diff.base, root.to_string());
+        assert!(!diff.is_merge);
+        let paths: Vec<&str> = diff.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["a.txt"]);
+
+        // The root commit shows every file as added
+        let diff = compute_commit_diff(&repo, &root.to_string(), &config).unwrap();
+        assert_eq!(diff.base, EMPTY_TREE_SHA);
+        assert!(!diff.is_merge);
+        let paths: Vec<&str> = diff.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["a.txt", "b.txt"]);
+        assert!(diff
+            .files
+            .iter()
+            .all(|f| f.change_kind() == ChangeKind::Added));
+
+        // Reading the empty tree doesn't write it into the object database
+        let loose = repo
+            .path()
+            .join("objects/4b/825dc642cb6eb9a060e54bf8d69288fbee4904");
+        assert!(!loose.exists());
+
+        // The repo's exclude globs apply as they do to any other diff
+        let config = DiffConfig {
+            exclude: vec!["b.txt".into()],
+            ..Default::default()
+        };
+        let diff = compute_commit_diff(&repo, &root.to_string(), &config).unwrap();
+        let paths: Vec<&str> = diff.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["a.txt"]);
+    }
+
+    #[test]
+    fn test_annotated_tag_resolves_to_commit() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let v1 = repo.head().unwrap().peel_to_commit().unwrap();
+        let sig = repo.signature().unwrap();
+        let tag_oid = repo
+            .tag("v1.0", v1.as_object(), &sig, "Release 1.0", false)
+            .unwrap();
+        write_files(&repo, &[("a.txt", "b\n")]);
+        commit_all(&repo, "second");
+
+        let sha = v1.id().to_string();
+        assert_ne!(tag_oid, v1.id());
+        assert_eq!(resolve_commit_sha(&repo, "v1.0").unwrap(), sha);
+        assert_eq!(
+            resolve_commit_sha(&repo, &tag_oid.to_string()).unwrap(),
+            sha
+        );
+        assert_eq!(resolve_ref(&repo, "v1.0").unwrap(), sha[..8]);
+
+        let result = compute_diff(&repo, "v1.0", "HEAD", false, &DiffConfig::default()).unwrap();
+        assert_eq!(result.files.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_refs_mixed() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let head_sha = repo.head().unwrap().target().unwrap().to_string();
+
+        let refs = vec![
+            "HEAD".to_string(),
+            "no-such-branch".to_string(),
+            WORKDIR.to_string(),
+        ];
+        let results = resolve_refs(&repo, &refs);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, "HEAD");
+        assert_eq!(results[0].1, Ok(head_sha[..8].to_string()));
+        assert_eq!(results[1].0, "no-such-branch");
+        assert!(results[1].1.is_err());
+        assert_eq!(results[2].1, Ok("working tree".to_string()));
+    }
+
+    #[test]
+    fn test_validate_diff_refs() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let head_sha = repo.head().unwrap().target().unwrap().to_string();
+
+        let both = validate_diff_refs(&repo, "HEAD", WORKDIR);
+        assert_eq!(
+            both,
+            DiffRefsValidation {
+                base_ok: true,
+                head_ok: true,
+                base_resolved: Some(head_sha.clone()),
+                head_resolved: Some(WORKDIR.to_string()),
+                error: None,
+            }
+        );
+        let special = validate_diff_refs(&repo, STAGED, UNSTAGED);
+        assert!(special.base_ok && special.head_ok);
+
+        let bad_base = validate_diff_refs(&repo, "no-such-branch", "HEAD");
+        assert!(!bad_base.base_ok);
+        assert!(bad_base.head_ok);
+        assert_eq!(bad_base.base_resolved, None);
+        assert_eq!(bad_base.head_resolved, Some(head_sha));
+        let error = bad_base.error.unwrap();
+        assert!(error.starts_with("Base: "), "{}", error);
+        assert!(error.contains("no-such-branch"), "{}", error);
+
+        let bad_head = validate_diff_refs(&repo, "HEAD", "nope");
+        assert!(bad_head.base_ok && !bad_head.head_ok);
+        assert!(bad_head.error.unwrap().starts_with("Head: "));
+
+        // The working tree is only ever the head
+        let reversed = validate_diff_refs(&repo, WORKDIR, "HEAD");
+        assert!(!reversed.base_ok && reversed.head_ok);
+    }
+
+    #[test]
+    fn test_change_index() {
+        let before: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let after = before
+            .replace("line 2\n", "two\n")
+            .replace("line 9\n", "nine\n");
+        let (_dir, repo) = repo_with_commit(&[("b.txt", &before), ("a.txt", &before)]);
+        write_files(&repo, &[("b.txt", &after), ("a.txt", &after)]);
+        write_files(&repo, &[("0-new.txt", "new\n")]);
+
+        let config = DiffConfig::default();
+        let index = change_index(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        let order: Vec<(&str, usize)> = index
+            .iter()
+            .map(|c| (c.path.as_str(), c.alignment_index))
+            .collect();
+        // Unchanged alignments (0, 2, 4) are skipped
+        assert_eq!(
+            order,
+            [
+                ("0-new.txt", 0),
+                ("a.txt", 1),
+                ("a.txt", 3),
+                ("b.txt", 1),
+                ("b.txt", 3)
+            ]
+        );
+        assert_eq!(index[1].before_span, Span::new(1, 2));
+        assert_eq!(index[2].after_span, Span::new(8, 9));
+
+        let diff = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        for change in &index {
+            let file = diff.files.iter().find(|f| f.path() == change.path).unwrap();
+            let alignment = &file.alignments[change.alignment_index];
+            assert!(alignment.changed);
+            assert_eq!(alignment.before, change.before_span);
+            assert_eq!(alignment.after, change.after_span);
+        }
+    }
+
+    #[test]
+    fn test_hunk_slices() {
+        let before: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let after = before
+            .replace("line 3\n", "three\n")
+            .replace("line 8\n", "eight\nnine-ish\n");
+        let (_dir, repo) = repo_with_commit(&[("a.txt", &before)]);
+        write_files(&repo, &[("a.txt", &after)]);
+
+        let config = DiffConfig::default();
+        let diff = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        let file = &diff.files[0];
+        let lines = |side: &Option<File>| match &side.as_ref().unwrap().content {
+            FileContent::Text { lines } => lines.clone(),
+            FileContent::Binary => panic!("binary"),
+        };
+        let (before_lines, after_lines) = (lines(&file.before), lines(&file.after));
+
+        let mut changed = 0;
+        for (i, alignment) in file.alignments.iter().enumerate() {
+            let result = hunk_slices(&repo, "HEAD", WORKDIR, false, "a.txt", i, &config);
+            if !alignment.changed {
+                assert!(result.is_err());
+                continue;
+            }
+            changed += 1;
+            let span = |s: Span| s.start as usize..s.end as usize;
+            assert_eq!(
+                result.unwrap(),
+                HunkSlices {
+                    before: before_lines[span(alignment.before)].to_vec(),
+                    after: after_lines[span(alignment.after)].to_vec(),
+                }
+            );
+        }
+        assert_eq!(changed, 2);
+        assert_eq!(
+            hunk_slices(&repo, "HEAD", WORKDIR, false, "a.txt", 3, &config).unwrap(),
+            HunkSlices {
+                before: vec!["line 8".to_string()],
+                after: vec!["eight".to_string(), "nine-ish".to_string()],
+            }
+        );
+        assert!(hunk_slices(&repo, "HEAD", WORKDIR, false, "other.txt", 0, &config).is_err());
+
+        // Only the one file is diffed, whatever the view leaves out
+        let config = DiffConfig {
+            exclude: vec!["*.txt".into()],
+            ..config
+        };
+        assert_eq!(
+            hunk_slices(&repo, "HEAD", WORKDIR, false, "a.txt", 1, &config)
+                .unwrap()
+                .after,
+            ["three"]
+        );
+    }
+
+    #[test]
+    fn test_working_tree_overview() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("both.txt", "1\n2\n3\n"),
+            ("clean.txt", "same\n"),
+            ("gone.txt", "x\ny\n"),
+            ("staged.txt", "old\n"),
+        ]);
+        write_files(
+            &repo,
+            &[
+                ("both.txt", "1\ntwo\n3\n"),
+                ("staged.txt", "new\n"),
+                ("added.txt", "a\n"),
+            ],
+        );
+        let mut index = repo.index().unwrap();
+        for path in ["both.txt", "staged.txt", "added.txt"] {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        write_files(
+            &repo,
+            &[("both.txt", "1\ntwo\n3\nfour\n"), ("bin.dat", "a\0b")],
+        );
+        std::fs::remove_file(repo.workdir().unwrap().join("gone.txt")).unwrap();
+
+        let file = |path: &str, staged, unstaged, added, removed, binary| WorkingTreeFile {
+            path: path.to_string(),
+            staged_status: staged,
+            unstaged_status: unstaged,
+            added,
+            removed,
+            binary,
+        };
+        use ChangeKind::*;
+        let overview = working_tree_overview(&repo).unwrap();
+        assert_eq!(
+            overview,
+            [
+                file("added.txt", Some(Added), None, 1, 0, false),
+                file("bin.dat", None, Some(Added), 0, 0, true),
+                file("both.txt", Some(Modified), Some(Modified), 2, 1, false),
+                file("gone.txt", None, Some(Deleted), 0, 2, false),
+                file("staged.txt", Some(Modified), None, 1, 1, false),
+            ]
+        );
+
+        // Counts are those of the whole working-tree diff
+        let totals = numstat(&repo, "HEAD", WORKDIR).unwrap();
+        let sum = |counts: &mut dyn Iterator<Item = u32>| counts.sum::<u32>();
+        assert_eq!(
+            sum(&mut overview.iter().map(|f| f.added + f.removed)),
+            sum(&mut totals
+                .iter()
+                .map(|e| e.added.unwrap_or(0) + e.removed.unwrap_or(0)))
+        );
+    }
+
+    #[test]
+    fn test_working_tree_overview_counts_recreated_file() {
+        let (_dir, repo) = repo_with_commit(&[
+            (
+                "edited.txt",
+                "a
+b
+c
+",
+            ),
+            (
+                "same.txt", "same
+",
+            ),
+        ]);
+        let mut index = repo.index().unwrap();
+        for path in ["edited.txt", "same.txt"] {
+            index.remove_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        // Deleted from the index, then written back to the working tree
+        write_files(
+            &repo,
+            &[(
+                "edited.txt",
+                "a
+B
+c
+",
+            )],
+        );
+
+        let overview = working_tree_overview(&repo).unwrap();
+        let counts: Vec<_> = overview
+            .iter()
+            .map(|f| {
+                (
+                    f.path.as_str(),
+                    f.staged_status,
+                    f.unstaged_status,
+                    f.added,
+                    f.removed,
+                )
+            })
+            .collect();
+        use ChangeKind::*;
+        assert_eq!(
+            counts,
+            [
+                ("edited.txt", Some(Deleted), Some(Added), 1, 1),
+                ("same.txt", Some(Deleted), Some(Added), 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blob_oid() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let committed = repo
+            .head()
+            .unwrap()
+            .peel_to_tree()
+            .unwrap()
+            .get_path(Path::new("a.txt"))
+            .unwrap()
+            .id()
+            .to_string();
+        assert_eq!(
+            blob_oid(&repo, "HEAD", "a.txt").unwrap(),
+            Some(committed.clone())
+        );
+        assert_eq!(
+            blob_oid(&repo, WORKDIR, "a.txt").unwrap(),
+            Some(committed.clone())
+        );
+
+        write_files(&repo, &[("a.txt", "edited\n")]);
+        let edited = blob_oid(&repo, WORKDIR, "a.txt").unwrap().unwrap();
+        assert_ne!(edited, committed);
+        assert_eq!(blob_oid(&repo, WORKDIR, "missing.txt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_blob_bytes() {
+        let png: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff];
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        std::fs::write(workdir.join("img.png"), png).unwrap();
+        commit_all(&repo, "add image");
+
+        assert_eq!(
+            blob_bytes(&repo, "HEAD", "img.png", 1024).unwrap(),
+            Some(png.to_vec())
+        );
+        assert_eq!(
+            blob_bytes(&repo, WORKDIR, "img.png", 1024).unwrap(),
+            Some(png.to_vec())
+        );
+        assert_eq!(blob_bytes(&repo, "HEAD~1", "img.png", 1024).unwrap(), None);
+        assert_eq!(
+            blob_bytes(&repo, "HEAD", "img.png", 4),
+            Err(BlobError::TooLarge {
+                size: png.len() as u64,
+                limit: 4
+            })
+        );
+        assert!(matches!(
+            blob_bytes(&repo, WORKDIR, "img.png", 4),
+            Err(BlobError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_case_mismatched_workdir_path() {
+        let (_dir, repo) = repo_with_commit(&[("Src/Foo.txt", "upper\n")]);
+        // As git sets it on a case-insensitive filesystem, so this behaves
+        // the same on every platform (the check reads directory entries)
+        repo.config()
+            .unwrap()
+            .set_bool("core.ignorecase", true)
+            .unwrap();
+        let config = DiffConfig::default();
+        let contents = |path| file_contents(&repo, "HEAD", WORKDIR, path, &config);
+
+        let upper = contents("Src/Foo.txt").unwrap();
+        assert_eq!(upper.after.as_deref(), Some("upper\n"));
+
+        for path in ["Src/foo.txt", "src/Foo.txt"] {
+            let err = contents(path).unwrap_err();
+            assert!(err.0.contains("differs only in case"), "{}", err.0);
+            assert!(matches!(
+                blob_bytes(&repo, WORKDIR, path, 1024),
+                Err(BlobError::Failed { .. })
+            ));
+        }
+        let err = contents("src/foo.txt").unwrap_err();
+        assert!(err.0.contains("from 'Src' in"), "{}", err.0);
+
+        // A file that's simply missing isn't a mismatch
+        assert_eq!(contents("Src/bar.txt").unwrap().after, None);
+    }
+
+    #[test]
+    fn test_numstat() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("a.txt", "1\n2\n3\n"),
+            ("bin.dat", "a\0b"),
+            ("gone.txt", "x\ny\n"),
+        ]);
+        write_files(
+            &repo,
+            &[
+                ("a.txt", "1\nx\n3\n4\n"),
+                ("bin.dat", "a\0c"),
+                ("new.txt", "n\n"),
+            ],
+        );
+        std::fs::remove_file(repo.workdir().unwrap().join("gone.txt")).unwrap();
+        commit_all(&repo, "Change things");
+
+        let entry = |path: &str, added, removed| NumstatEntry {
+            path: path.to_string(),
+            added: Some(added),
+            removed: Some(removed),
+            binary: false,
+        };
+        // What `git diff --numstat HEAD~1 HEAD` prints
+        let expected = vec![
+            entry("a.txt", 2, 1),
+            NumstatEntry {
+                path: "bin.dat".to_string(),
+                added: None,
+                removed: None,
+                binary: true,
+            },
+            entry("gone.txt", 0, 2),
+            entry("new.txt", 1, 0),
+        ];
+        assert_eq!(numstat(&repo, "HEAD~1", "HEAD").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_changed_paths_match_compute_diff() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("keep.txt", "same\n"),
+            ("edit.txt", "one\ntwo\nthree\n"),
+            ("gone.txt", "bye\n"),
+        ]);
+        std::fs::remove_file(repo.workdir().unwrap().join("gone.txt")).unwrap();
+        write_files(
+            &repo,
+            &[
+                ("edit.txt", "one\n2\nthree\nfour\n"),
+                ("new/file.txt", "hello\n"),
+            ],
+        );
+
+        let changed = get_changed_paths(&repo, "HEAD", WORKDIR).unwrap();
+        let full = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+
+        let changed_paths: Vec<&str> = changed.iter().map(|c| c.path.as_str()).collect();
+        let full_paths: Vec<&str> = full.files.iter().map(|f| f.path()).collect();
+        assert_eq!(changed_paths, full_paths);
+
+        let edit = changed.iter().find(|c| c.path == "edit.txt").unwrap();
+        assert_eq!(edit.status, ChangeKind::Modified);
+        assert_eq!((edit.added, edit.removed), (2, 1));
+
+        let gone = changed.iter().find(|c| c.path == "gone.txt").unwrap();
+        assert_eq!(gone.status, ChangeKind::Deleted);
+        assert_eq!((gone.added, gone.removed), (0, 1));
+
+        let new = changed.iter().find(|c| c.path == "new/file.txt").unwrap();
+        assert_eq!(new.status, ChangeKind::Added);
+        assert_eq!((new.added, new.removed), (1, 0));
+    }
+
+    #[test]
+    fn test_autocrlf_only_change_is_empty_diff() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "one\ntwo\n"), ("b.txt", "x\n")]);
+        repo.config()
+            .unwrap()
+            .set_str("core.autocrlf", "true")
+            .unwrap();
+
+        // Same content checked out with CRLF, plus one real change
+        write_files(&repo, &[("a.txt", "one\r\ntwo\r\n"), ("b.txt", "y\r\n")]);
+
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+        let paths: Vec<&str> = result.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["b.txt"]);
+        assert_eq!(
+            result.files[0].after.as_ref().unwrap().content.lines(),
+            ["y"]
+        );
+    }
+
+    #[test]
+    fn test_pure_insertion_and_deletion_hunks() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\nb\nc\n"), ("b.txt", "a\nb\nc\n")]);
+        // A line inserted after "b", and "b" deleted
+        write_files(&repo, &[("a.txt", "a\nb\nX\nc\n"), ("b.txt", "a\nc\n")]);
+
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+        let changed = |path: &str| -> Vec<(Span, Span)> {
+            let file = result.files.iter().find(|f| f.path() == path).unwrap();
+            file.alignments
+                .iter()
+                .filter(|a| a.changed)
+                .map(|a| (a.before, a.after))
+                .collect()
+        };
+
+        assert_eq!(changed("a.txt"), vec![(Span::new(2, 2), Span::new(2, 3))]);
+        assert_eq!(changed("b.txt"), vec![(Span::new(1, 2), Span::new(1, 1))]);
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        // Both start at 10 bytes, then grow to 20 (the limit) and 21
+        let (_dir, repo) =
+            repo_with_commit(&[("under.txt", "aaaa\nbbbb\n"), ("over.txt", "aaaa\nbbbb\n")]);
+        write_files(
+            &repo,
+            &[
+                ("under.txt", "aaaa\nbbbb\ncccc\ndddd\n"),
+                ("over.txt", "aaaa\nbbbb\ncccc\ndddd\nx"),
+            ],
+        );
+        commit_all(&repo, "grow");
+        write_files(&repo, &[("over.txt", "small\n")]);
+
+        let config = DiffConfig {
+            max_file_size: Some(20),
+            ..DiffConfig::default()
+        };
+        let files = compute_diff(&repo, "HEAD~1", "HEAD", false, &config)
+            .unwrap()
+            .files;
+        // Exactly at the limit is still diffed
+        let under = &files[1];
+        assert_eq!(under.path(), "under.txt");
+        assert!(!under.too_large);
+        assert!(!under.alignments.is_empty());
+        assert_eq!((under.before_size, under.after_size), (None, None));
+
+        let over = &files[0];
+        assert!(over.too_large);
+        assert!(over.alignments.is_empty());
+        assert!(over.before.as_ref().unwrap().content.lines().is_empty());
+        assert_eq!((over.before_size, over.after_size), (Some(10), Some(21)));
+
+        // Either side being too large is enough, including a working-tree one
+        let files = compute_diff(&repo, "HEAD", WORKDIR, false, &config)
+            .unwrap()
+            .files;
+        assert!(files[0].too_large);
+        assert_eq!(
+            (files[0].before_size, files[0].after_size),
+            (Some(21), Some(6))
+        );
+
+        // file_ref_diff loads a too-large file in full
+        let full = file_ref_diff(&repo, "HEAD~1", "HEAD", false, "over.txt", None, &config)
+            .unwrap()
+            .unwrap();
+        assert!(!full.too_large);
+        assert!(!full.alignments.is_empty());
+    }
+
+    #[test]
+    fn test_collapse_unchanged() {
+        let before: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let (_dir, repo) = repo_with_commit(&[("big.txt", &before)]);
+        let after = before.replace("line 1000\n", "changed\n");
+        write_files(&repo, &[("big.txt", &after)]);
+
+        let config = DiffConfig {
+            collapse_unchanged: Some(100),
+            ..DiffConfig::default()
+        };
+        let files = compute_diff(&repo, "HEAD", WORKDIR, false, &config)
+            .unwrap()
+            .files;
+        let diff = &files[0];
+
+        // Spans keep their original line numbers
+        let spans: Vec<_> = diff
+            .alignments
+            .iter()
+            .map(|a| (a.before, a.changed, a.collapsed))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (Span::new(0, 1000), false, Some(Span::new(3, 997))),
+                (Span::new(1000, 1001), true, None),
+                (Span::new(1001, 2000), false, Some(Span::new(3, 996))),
+            ]
+        );
+
+        // Only the context around the change survives
+        assert_eq!(
+            diff.after.as_ref().unwrap().content.lines(),
+            [
+                "line 0",
+                "line 1",
+                "line 2",
+                "line 997",
+                "line 998",
+                "line 999",
+                "changed",
+                "line 1001",
+                "line 1002",
+                "line 1003",
+                "line 1997",
+                "line 1998",
+                "line 1999",
+            ]
+        );
+        assert_eq!(
+            diff.before.as_ref().unwrap().content.lines()[6],
+            "line 1000"
+        );
+    }
+
+    #[test]
+    fn test_full_file_diffs_keep_every_line() {
+        use crate::diff::review::{export_markdown, Comment, Review};
+        use crate::diff::types::DiffId;
+
+        let before: String = (0..200).map(|i| format!("line {}\n", i)).collect();
+        let (_dir, repo) = repo_with_commit(&[("big.txt", &before), ("other.txt", "a\n")]);
+        let after = before.replace("line 100\n", &format!("{}\n", "x".repeat(50)));
+        write_files(&repo, &[("big.txt", &after), ("other.txt", "b\n")]);
+
+        // What the view is configured with would cut out the commented lines
+        let config = DiffConfig {
+            collapse_unchanged: Some(20),
+            max_line_length: Some(10),
+            ..DiffConfig::default()
+        };
+        let files = full_file_diffs(
+            &repo,
+            "HEAD",
+            WORKDIR,
+            false,
+            &["big.txt".to_string()],
+            &config,
+        )
+        .unwrap();
+        assert_eq!(files.len(), 1);
+        let file = files[0].after.as_ref().unwrap();
+        assert_eq!(file.path, "big.txt");
+        assert_eq!(file.content.lines().len(), 200);
+        assert!(file.truncated_lines.is_empty());
+
+        let mut review = Review::new(DiffId::new("HEAD", WORKDIR));
+        review
+            .comments
+            .push(Comment::new("big.txt", Span::new(150, 151), "Far away"));
+        review
+            .comments
+            .push(Comment::new("big.txt", Span::new(100, 101), "Long"));
+        let md = export_markdown(&review, None, &files);
+        assert!(md.contains("line 149\n  line 150\n  line 151\n"), "{}", md);
+        assert!(md.contains(&"x".repeat(50)), "{}", md);
+    }
+
+    #[test]
+    fn test_export_groups_renamed_file() {
+        use crate::diff::review::{export_markdown, Comment, Review};
+        use crate::diff::types::DiffId;
+
+        let before: String = (0..10).map(|i| format!("line {}\n", i)).collect();
+        let (_dir, repo) = repo_with_commit(&[("old.txt", &before), ("other.txt", "x\n")]);
+        std::fs::remove_file(repo.workdir().unwrap().join("old.txt")).unwrap();
+        write_files(&repo, &[("new.txt", &before.replace("line 9\n", "nine\n"))]);
+
+        let aliases = renamed_paths(&repo, "HEAD", WORKDIR).unwrap();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases["old.txt"], "new.txt");
+        commit_all(&repo, "rename");
+        assert_eq!(renamed_paths(&repo, "HEAD~1", "HEAD").unwrap(), aliases);
+        assert!(renamed_paths(&repo, "HEAD~1", "HEAD~1").unwrap().is_empty());
+
+        let mut review = Review::new(DiffId::new("HEAD~1", "HEAD"));
+        review
+            .comments
+            .push(Comment::new("old.txt", Span::new(2, 3), "Before"));
+        review
+            .comments
+            .push(Comment::new("new.txt", Span::new(9, 10), "After"));
+        let md = export_markdown(&review, Some(&aliases), &[]);
+        assert!(md.contains("## old.txt → new.txt"), "{}", md);
+        assert_eq!(md.matches("## ").count(), 1);
+        assert!(md.contains("Before") && md.contains("After"));
+    }
+
+    #[test]
+    fn test_external_file_diff() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "one\ntwo\nthree\n")]);
+        let other = TempDir::new().unwrap();
+        let external = other.path().join("a.txt");
+        std::fs::write(&external, "one\nTWO\nthree\nfour\n").unwrap();
+
+        let config = DiffConfig::default();
+        let diff = compute_external_file_diff(&repo, "HEAD", "a.txt", &external, &config).unwrap();
+
+        assert_eq!(diff.before.as_ref().unwrap().path, "a.txt");
+        assert_eq!(
+            diff.after.as_ref().unwrap().content.lines(),
+            ["one", "TWO", "three", "four"]
+        );
+        let spans: Vec<_> = diff
+            .alignments
+            .iter()
+            .map(|a| (a.before, a.after, a.changed))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (Span::new(0, 1), Span::new(0, 1), false),
+                (Span::new(1, 2), Span::new(1, 2), true),
+                (Span::new(2, 3), Span::new(2, 3), false),
+                (Span::new(3, 3), Span::new(3, 4), true),
+            ]
+        );
+
+        // The repo's settings apply: forced binary, and the size limit
+        let forced = DiffConfig {
+            force_binary: vec!["*.txt".into()],
+            ..Default::default()
+        };
+        let diff = compute_external_file_diff(&repo, "HEAD", "a.txt", &external, &forced).unwrap();
+        assert!(diff.before_binary && diff.after_binary);
+        assert!(diff.alignments.is_empty());
+        let limited = DiffConfig {
+            max_file_size: Some(16),
+            ..Default::default()
+        };
+        let diff = compute_external_file_diff(&repo, "HEAD", "a.txt", &external, &limited).unwrap();
+        assert!(diff.too_large);
+        assert_eq!((diff.before_size, diff.after_size), (Some(14), Some(19)));
+
+        // Directories and missing paths are rejected
+        assert!(compute_external_file_diff(&repo, "HEAD", "a.txt", other.path(), &config).is_err());
+        let missing = other.path().join("missing.txt");
+        assert!(compute_external_file_diff(&repo, "HEAD", "a.txt", &missing, &config).is_err());
+    }
+
+    #[test]
+    fn test_stage_and_unstage_all() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]);
+        write_files(&repo, &[("a.txt", "changed\n"), ("new.txt", "new\n")]);
+        std::fs::remove_file(repo.workdir().unwrap().join("b.txt")).unwrap();
+
+        let staged = |repo: &Repository| -> Vec<(String, Status)> {
+            let statuses = repo.statuses(None).unwrap();
+            let mut staged: Vec<_> = statuses
+                .iter()
+                .map(|e| (e.path().unwrap().to_string(), index_changes(e.status())))
+                .filter(|(_, status)| !status.is_empty())
+                .collect();
+            staged.sort_by(|a, b| a.0.cmp(&b.0));
+            staged
+        };
+
+        // Tracked changes only, including the deletion
+        assert_eq!(stage_all(&repo, false, None).unwrap(), 2);
+        assert_eq!(
+            staged(&repo),
+            vec![
+                ("a.txt".to_string(), Status::INDEX_MODIFIED),
+                ("b.txt".to_string(), Status::INDEX_DELETED),
+            ]
+        );
+
+        assert_eq!(unstage_all(&repo, None).unwrap(), 2);
+        assert!(staged(&repo).is_empty());
+
+        // With untracked files
+        assert_eq!(stage_all(&repo, true, None).unwrap(), 3);
+        assert_eq!(
+            staged(&repo).last(),
+            Some(&("new.txt".to_string(), Status::INDEX_NEW))
+        );
+    }
+
+    #[test]
+    fn test_stage_file_keeps_partial_staging() {
+        let (_dir, repo) = repo_with_commit(&[("f.txt", "1\n2\n3\n4\n5\n6\n")]);
+        let staged_content = |repo: &Repository| {
+            let index = repo.index().unwrap();
+            let entry = index.get_path(Path::new("f.txt"), 0).unwrap();
+            let blob = repo.find_blob(entry.id).unwrap();
+            String::from_utf8(blob.content().to_vec()).unwrap()
+        };
+
+        // Stage the first hunk only, then make a second change
+        write_files(&repo, &[("f.txt", "one\n2\n3\n4\n5\n6\n")]);
+        stage_file(&repo, "f.txt").unwrap();
+        stage_file(&repo, "f.txt").unwrap();
+        write_files(&repo, &[("f.txt", "one\n2\n3\n4\n5\nsix\n")]);
+
+        let err = stage_file(&repo, "f.txt").unwrap_err();
+        assert!(err.0.contains("partially staged"), "{}", err.0);
+        assert_eq!(staged_content(&repo), "one\n2\n3\n4\n5\n6\n");
+
+        stage_file_force(&repo, "f.txt").unwrap();
+        assert_eq!(staged_content(&repo), "one\n2\n3\n4\n5\nsix\n");
+
+        // Deletions are staged as removals
+        std::fs::remove_file(repo.workdir().unwrap().join("f.txt")).unwrap();
+        stage_file_force(&repo, "f.txt").unwrap();
+        assert_eq!(
+            repo.status_file(Path::new("f.txt")).unwrap(),
+            Status::INDEX_DELETED
+        );
+    }
+
+    #[test]
+    fn test_get_refs_ordering() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let initial = repo.head().unwrap().shorthand().unwrap().to_string();
+        for name in ["zeta", "Alpha", "beta"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        for name in ["v2", "V1", "v10"] {
+            repo.tag_lightweight(name, head.as_object(), false).unwrap();
+        }
+
+        // Check out zeta and drop the initial branch (its name depends on git config)
+        repo.set_head("refs/heads/zeta").unwrap();
+        repo.find_branch(&initial, git2::BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+
+        let refs = get_refs(&repo).unwrap();
+        let names: Vec<_> = refs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [WORKDIR, "HEAD", "HEAD~1", "zeta", "Alpha", "beta", "V1", "v10", "v2"]
+        );
+        let current: Vec<_> = refs.iter().filter(|r| r.current).map(|r| &r.name).collect();
+        assert_eq!(current, ["zeta"]);
+    }
+
+    #[test]
+    fn test_file_patch() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "1\n2\n3\n4\n5\n"), ("other.txt", "x\n")]);
+        write_files(
+            &repo,
+            &[("a.txt", "1\n2\nthree\n4\n5\n"), ("other.txt", "y\n")],
+        );
+        commit_all(&repo, "edit");
+
+        let patch = file_patch(&repo, "HEAD~1", "HEAD", "a.txt", DiffAlgorithm::Myers).unwrap();
+        let lines: Vec<_> = patch.lines().collect();
+
+        assert!(lines[0].starts_with("diff --git a/a.txt b/a.txt"));
+        assert!(lines.contains(&"--- a/a.txt"));
+        assert!(lines.contains(&"+++ b/a.txt"));
+        let hunk = lines.iter().position(|l| l.starts_with("@@")).unwrap();
+        assert_eq!(lines[hunk], "@@ -1,5 +1,5 @@");
+        assert_eq!(&lines[hunk + 1..], [" 1", " 2", "-3", "+three", " 4", " 5"]);
+        assert!(!patch.contains("other.txt"));
+    }
+
+    #[test]
+    fn test_file_patch_binary() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        std::fs::write(repo.workdir().unwrap().join("blob.bin"), b"\0\x01\x02").unwrap();
+
+        let patch = file_patch(&repo, "HEAD", WORKDIR, "blob.bin", DiffAlgorithm::Myers).unwrap();
+        assert!(patch.contains("Binary files"), "{}", patch);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_typechange_file_to_symlink() {
+        let (dir, repo) = repo_with_commit(&[("target.txt", "real\n"), ("link", "one\ntwo\n")]);
+        let link = dir.path().join("link");
+        std::fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+
+        let check = |files: Vec<FileDiff>| {
+            assert_eq!(files.len(), 1, "expected a single typechange entry");
+            let diff = &files[0];
+            assert_eq!(diff.path(), "link");
+
+            let mode = diff.mode_change.unwrap();
+            assert_eq!((mode.before, mode.after), (0o100644, 0o120000));
+            assert!(mode.is_type_change());
+
+            assert_eq!(
+                diff.before.as_ref().unwrap().content.lines(),
+                ["one", "two"]
+            );
+            assert_eq!(diff.after.as_ref().unwrap().content.lines(), ["target.txt"]);
+            assert!(!diff.before.as_ref().unwrap().is_symlink);
+            assert!(diff.after.as_ref().unwrap().is_symlink);
+            let spans: Vec<_> = diff
+                .alignments
+                .iter()
+                .map(|a| (a.before, a.after, a.changed))
+                .collect();
+            assert_eq!(spans, vec![(Span::new(0, 2), Span::new(0, 1), true)]);
+        };
+
+        // Uncommitted, then committed
+        check(
+            compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default())
+                .unwrap()
+                .files,
+        );
+        commit_all(&repo, "make link");
+        check(
+            compute_diff(&repo, "HEAD~1", "HEAD", false, &DiffConfig::default())
+                .unwrap()
+                .files,
+        );
+    }
+
+    #[test]
+    fn test_bare_repo_tree_diff() {
+        let (_dir, source) = repo_with_commit(&[("a.txt", "one\n")]);
+        write_files(&source, &[("a.txt", "two\n")]);
+        commit_all(&source, "edit");
+
+        let bare_dir = TempDir::new().unwrap();
+        let bare = git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(source.workdir().unwrap().to_str().unwrap(), bare_dir.path())
+            .unwrap();
+
+        let files = compute_diff(&bare, "HEAD~1", "HEAD", false, &DiffConfig::default())
+            .unwrap()
+            .files;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].after.as_ref().unwrap().content.lines(), ["two"]);
+
+        let info = get_repo_info(&bare).unwrap();
+        assert_eq!(info.workdir, None);
+        assert_eq!(Path::new(&info.repo_path), bare.path());
+
+        assert!(compute_diff(&bare, "HEAD", WORKDIR, false, &DiffConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_trace_rename() {
+        let content = "a fairly long line so rename detection has something to match\n";
+        let (dir, repo) = repo_with_commit(&[("old.rs", content), ("other.rs", "x\n")]);
+        let base = repo
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id()
+            .to_string();
+        let workdir = dir.path();
+
+        // Renamed in an intermediate commit, then the file changes elsewhere
+        std::fs::rename(workdir.join("old.rs"), workdir.join("mid.rs")).unwrap();
+        commit_all(&repo, "rename to mid");
+        write_files(&repo, &[("other.rs", "y\n")]);
+        commit_all(&repo, "unrelated");
+
+        assert_eq!(
+            trace_rename(&repo, "old.rs", &base, "HEAD").unwrap(),
+            Some("mid.rs".to_string())
+        );
+        assert_eq!(
+            trace_rename(&repo, "other.rs", &base, "HEAD").unwrap(),
+            Some("other.rs".to_string())
+        );
+
+        // Renamed again in the working tree
+        std::fs::rename(workdir.join("mid.rs"), workdir.join("new.rs")).unwrap();
+        assert_eq!(
+            trace_rename(&repo, "old.rs", &base, WORKDIR).unwrap(),
+            Some("new.rs".to_string())
+        );
+
+        // Deleted
+        std::fs::remove_file(workdir.join("new.rs")).unwrap();
+        assert_eq!(trace_rename(&repo, "old.rs", &base, WORKDIR).unwrap(), None);
+    }
+
+    #[test]
+    fn test_long_lines_truncated() {
+        let (_dir, repo) = repo_with_commit(&[("min.js", "a\nb\n")]);
+        let long = "x".repeat(2 * 1024 * 1024);
+        write_files(&repo, &[("min.js", &format!("a\n{}\nb\n", long))]);
+
+        let files = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default())
+            .unwrap()
+            .files;
+        let after = files[0].after.as_ref().unwrap();
+        assert_eq!(after.truncated_lines, vec![1]);
+        assert_eq!(after.content.lines()[1].len(), DEFAULT_MAX_LINE_LENGTH);
+
+        // The inserted line is still a single changed line
+        let spans: Vec<_> = files[0]
+            .alignments
+            .iter()
+            .map(|a| (a.before, a.after, a.changed))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (Span::new(0, 1), Span::new(0, 1), false),
+                (Span::new(1, 1), Span::new(1, 2), true),
+                (Span::new(1, 2), Span::new(2, 3), false),
+            ]
+        );
+
+        // Disabled
+        let config = DiffConfig {
+            max_line_length: None,
+            ..DiffConfig::default()
+        };
+        let files = compute_diff(&repo, "HEAD", WORKDIR, false, &config)
+            .unwrap()
+            .files;
+        assert_eq!(files[0].after.as_ref().unwrap().content.lines()[1], long);
+    }
+
+    #[test]
+    fn test_divergence() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("other", &base, false).unwrap();
+        write_files(&repo, &[("a.txt", "b\n")]);
+        commit_all(&repo, "second");
+        write_files(&repo, &[("a.txt", "c\n")]);
+        commit_all(&repo, "third");
+
+        // One commit on `other` since the branches split
+        let sig = repo.signature().unwrap();
+        repo.commit(
+            Some("refs/heads/other"),
+            &sig,
+            &sig,
+            "side",
+            &base.tree().unwrap(),
+            &[&base],
+        )
+        .unwrap();
+
+        let expected = Divergence {
+            merge_base: base.id().to_string(),
+            ahead: 2,
+            behind: 1,
+        };
+        assert_eq!(divergence(&repo, "HEAD", "other").unwrap(), expected);
+        assert_eq!(
+            divergence(&repo, "other", "HEAD").unwrap(),
+            Divergence {
+                ahead: 1,
+                behind: 2,
+                ..expected
+            }
+        );
+
+        // A root commit with no history in common
+        let orphan = repo
+            .commit(None, &sig, &sig, "orphan", &base.tree().unwrap(), &[])
+            .unwrap();
+        let err = divergence(&repo, "HEAD", &orphan.to_string()).unwrap_err();
+        assert!(err.0.contains("unrelated histories"), "{}", err.0);
+    }
+
+    #[test]
+    fn test_upstream_of_head() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let branch = current_branch(&repo).unwrap().unwrap();
+        assert!(upstream_of_head(&repo).is_err());
+
+        // Track a local branch left at the first commit, then get ahead of it
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("tracking", &first, false).unwrap();
+        let mut local = repo.find_branch(&branch, git2::BranchType::Local).unwrap();
+        local.set_upstream(Some("tracking")).unwrap();
+        write_files(&repo, &[("a.txt", "ahead\n")]);
+        commit_all(&repo, "ahead");
+
+        let upstream = upstream_of_head(&repo).unwrap();
+        assert_eq!(upstream, first.id().to_string());
+
+        // What would be pushed: upstream..HEAD
+        let outgoing = compute_diff(&repo, &upstream, "HEAD", true, &DiffConfig::default())
+            .unwrap()
+            .files;
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(
+            outgoing[0].after.as_ref().unwrap().content.lines(),
+            ["ahead"]
+        );
+
+        // Nothing incoming: HEAD..upstream from the merge-base is empty
+        let incoming = compute_diff(&repo, "HEAD", &upstream, true, &DiffConfig::default())
+            .unwrap()
+            .files;
+        assert!(incoming.is_empty());
+    }
+
+    #[test]
+    fn test_status_fast_path_matches_full_diff() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("clean.txt", "same\n"),
+            ("edited.txt", "one\ntwo\n"),
+            ("removed.txt", "gone\n"),
+            ("staged.txt", "old\n"),
+        ]);
+        let config = DiffConfig::default();
+        let diff = |base: &str, head: &str, use_status: bool| {
+            let scope = if use_status {
+                PathScope::Status
+            } else {
+                PathScope::All
+            };
+            let result = compute_diff_inner(
+                &repo,
+                base,
+                head,
+                &config,
+                scope,
+                &AtomicBool::new(false),
+                &mut |_| {},
+            )
+            .unwrap();
+            serde_json::to_value(result.files).unwrap()
+        };
+        assert_eq!(diff("HEAD", WORKDIR, true), serde_json::json!([]));
+
+        write_files(
+            &repo,
+            &[
+                ("edited.txt", "one\n2\n"),
+                ("staged.txt", "new\n"),
+                ("added/deep/file.txt", "hi\n"),
+            ],
+        );
+        std::fs::remove_file(repo.workdir().unwrap().join("removed.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("staged.txt")).unwrap();
+        index.write().unwrap();
+        write_files(&repo, &[("staged.txt", "newer\n")]);
+
+        for head in [WORKDIR, UNSTAGED] {
+            let fast = diff("HEAD", head, true);
+            assert_eq!(fast, diff("HEAD", head, false), "{}", head);
+            assert!(!fast.as_array().unwrap().is_empty());
+        }
+        let paths: Vec<_> = compute_diff(&repo, "HEAD", WORKDIR, false, &config)
+            .unwrap()
+            .files
+            .iter()
+            .map(|f| f.path().to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "added/deep/file.txt",
+                "edited.txt",
+                "removed.txt",
+                "staged.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compute_diff_cancelled() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        write_files(&repo, &[("a.txt", "b\n"), ("new.txt", "new\n")]);
+        let config = DiffConfig::default();
+
+        // Set before starting, as when a newer diff has already been requested
+        let cancel = AtomicBool::new(true);
+        let err =
+            compute_diff_cancellable(&repo, "HEAD", WORKDIR, false, &config, &cancel).unwrap_err();
+        assert!(err.is_cancelled(), "{}", err);
+
+        let cancel = AtomicBool::new(false);
+        let result = compute_diff_cancellable(&repo, "HEAD", WORKDIR, false, &config, &cancel);
+        assert_eq!(result.unwrap().files.len(), 2);
+    }
+
+    #[test]
+    fn test_untracked_directory_lists_files() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        write_files(
+            &repo,
+            &[("newdir/one.txt", "1\n"), ("newdir/sub/two.txt", "2\n2\n")],
+        );
+
+        let paths = get_changed_paths(&repo, "HEAD", WORKDIR).unwrap();
+        let names: Vec<_> = paths.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(names, ["newdir/one.txt", "newdir/sub/two.txt"]);
+
+        // Even if git reports just the directory, its files are listed
+        let dir_entry = FileChange {
+            before_path: Some("newdir/".into()),
+            after_path: Some("newdir/".into()),
+            status: Delta::Untracked,
+            old_mode: FileMode::Unreadable,
+            new_mode: FileMode::Tree,
+            hunks: vec![],
+            same_blob: false,
+        };
+        let expanded = expand_untracked_dirs(&repo, vec![dir_entry]).unwrap();
+        let files: Vec<_> = expanded
+            .iter()
+            .map(|c| (c.after_path.clone().unwrap(), c.hunks[0].new_lines))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("newdir/one.txt".to_string(), 1),
+                ("newdir/sub/two.txt".to_string(), 2),
+            ]
+        );
+    }
+
+    /// Put `path` into the index as a conflict with the given stages.
+    fn add_conflict(repo: &Repository, path: &str, stages: [Option<&str>; 3]) {
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new(path)).unwrap();
+        for (stage, content) in (1..).zip(stages) {
+            let Some(content) = content else { continue };
+            index
+                .add(&git2::IndexEntry {
+                    ctime: git2::IndexTime::new(0, 0),
+                    mtime: git2::IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: 0o100644,
+                    uid: 0,
+                    gid: 0,
+                    file_size: content.len() as u32,
+                    id: repo.blob(content.as_bytes()).unwrap(),
+                    flags: (stage << 12) | path.len() as u16,
+                    flags_extended: 0,
+                    path: path.as_bytes().to_vec(),
+                })
+                .unwrap();
+        }
+        index.write().unwrap();
+    }
+
+    #[test]
+    fn test_export_conflict() {
+        let config = DiffConfig::default();
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "one\ntwo\nthree\nfour\nfive\n")]);
+        add_conflict(
+            &repo,
+            "a.txt",
+            [
+                Some("one\ntwo\nthree\nfour\nfive\n"),
+                Some("ONE\ntwo\nthree\nours\nfive\n"),
+                Some("one\ntwo\nthree\ntheirs\nfive\nsix\n"),
+            ],
+        );
+        let conflict = export_conflict(&repo, "a.txt", &config).unwrap();
+        assert_eq!(
+            conflict.base.as_deref(),
+            Some("one\ntwo\nthree\nfour\nfive\n")
+        );
+        assert_eq!(conflict.ours, "ONE\ntwo\nthree\nours\nfive\n");
+        assert_eq!(conflict.theirs, "one\ntwo\nthree\ntheirs\nfive\nsix\n");
+        // One-sided changes merge cleanly around the conflicting line
+        assert_eq!(
+            conflict.diff3,
+            "ONE\ntwo\nthree\n\
+             <<<<<<< ours\nours\n\
+             ||||||| base\nfour\n\
+             =======\ntheirs\n\
+             >>>>>>> theirs\n\
+             five\nsix\n"
+        );
+
+        // add/add: no base stage, and no trailing newline on one side
+        add_conflict(
+            &repo,
+            "new.txt",
+            [None, Some("same\nours"), Some("same\ntheirs\n")],
+        );
+        let conflict = export_conflict(&repo, "new.txt", &config).unwrap();
+        assert_eq!(conflict.base, None);
+        assert_eq!(
+            conflict.diff3,
+            "<<<<<<< ours\nsame\nours\n\
+             ||||||| base\n\
+             =======\nsame\ntheirs\n\
+             >>>>>>> theirs\n"
+        );
+
+        assert!(export_conflict(&repo, "missing.txt", &config)
+            .unwrap_err()
+            .0
+            .contains("has no conflict"));
+
+        // The binary policy comes from the caller's config
+        let binary = DiffConfig {
+            force_binary: vec!["*.txt".to_string()],
+            ..DiffConfig::default()
+        };
+        assert!(export_conflict(&repo, "a.txt", &binary)
+            .unwrap_err()
+            .0
+            .contains("binary file"));
+    }
+
+    #[test]
+    fn test_file_line_count() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "1\n2\n3\n")]);
+        write_files(&repo, &[("a.txt", "1\n2"), ("new.txt", "")]);
+
+        assert_eq!(file_line_count(&repo, "HEAD", "a.txt").unwrap(), Some(3));
+        assert_eq!(file_line_count(&repo, WORKDIR, "a.txt").unwrap(), Some(2));
+        assert_eq!(file_line_count(&repo, WORKDIR, "new.txt").unwrap(), Some(0));
+        assert_eq!(file_line_count(&repo, "HEAD", "new.txt").unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_target_change() {
+        let (dir, repo) = repo_with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]);
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink("a.txt", &link).unwrap();
+        commit_all(&repo, "add link");
+
+        std::fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink("b.txt", &link).unwrap();
+
+        let check = |files: Vec<FileDiff>| {
+            assert_eq!(files.len(), 1);
+            let (before, after) = (
+                files[0].before.as_ref().unwrap(),
+                files[0].after.as_ref().unwrap(),
+            );
+            assert!(before.is_symlink && after.is_symlink);
+            assert_eq!(before.content.lines(), ["a.txt"]);
+            assert_eq!(after.content.lines(), ["b.txt"]);
+            assert_eq!(files[0].mode_change, None);
+            assert!(files[0].alignments.iter().any(|a| a.changed));
+        };
+
+        check(
+            compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default())
+                .unwrap()
+                .files,
+        );
+        commit_all(&repo, "retarget link");
+        check(
+            compute_diff(&repo, "HEAD~1", "HEAD", false, &DiffConfig::default())
+                .unwrap()
+                .files,
+        );
+    }
+
+    #[test]
+    fn test_change_density() {
+        let before: String = (0..20).map(|i| format!("{}\n", i)).collect();
+        let (_dir, repo) = repo_with_commit(&[("f.txt", &before)]);
+        let after: String = (0..20)
+            .filter_map(|i| match i {
+                2 => Some("two\n".to_string()),
+                10 | 11 => None,
+                15 => Some("15\nnew\nnew\n".to_string()),
+                _ => Some(format!("{}\n", i)),
+            })
+            .collect();
+        write_files(&repo, &[("f.txt", &after)]);
+
+        let density =
+            change_density(&repo, "HEAD", WORKDIR, "f.txt", DiffAlgorithm::Myers).unwrap();
+        let ranges: Vec<_> = density.iter().map(|d| (d.line_start, d.line_end)).collect();
+        assert_eq!(ranges, [(2, 3), (10, 10), (14, 16)]);
+
+        let changed = get_changed_paths(&repo, "HEAD", WORKDIR).unwrap();
+        let added: u32 = density.iter().map(|d| d.added).sum();
+        let removed: u32 = density.iter().map(|d| d.removed).sum();
+        assert_eq!((added, removed), (changed[0].added, changed[0].removed));
+        assert_eq!((added, removed), (3, 3));
+    }
+
+    #[test]
+    fn test_file_contents() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("mod.txt", "one\ntwo\n"),
+            ("del.txt", "gone\n"),
+            ("bin.dat", "a\0b"),
+        ]);
+        write_files(
+            &repo,
+            &[
+                ("mod.txt", "one\n2\n"),
+                ("new.txt", "fresh"),
+                ("bin.dat", "c\0d"),
+            ],
+        );
+        std::fs::remove_file(repo.workdir().unwrap().join("del.txt")).unwrap();
+
+        let default = DiffConfig::default();
+        let contents = |path| file_contents(&repo, "HEAD", WORKDIR, path, &default).unwrap();
+        let text = |s: &str| Some(s.to_string());
+
+        let modified = contents("mod.txt");
+        assert_eq!(modified.before, text("one\ntwo\n"));
+        assert_eq!(modified.after, text("one\n2\n"));
+
+        let added = contents("new.txt");
+        assert_eq!((added.before, added.after), (None, text("fresh")));
+        assert!(!added.before_binary && !added.after_binary);
+
+        let deleted = contents("del.txt");
+        assert_eq!((deleted.before, deleted.after), (text("gone\n"), None));
+
+        let binary = contents("bin.dat");
+        assert_eq!((binary.before, binary.after), (None, None));
+        assert!(binary.before_binary && binary.after_binary);
+
+        // The binary policy is the caller's, as for compute_diff
+        let config = DiffConfig {
+            force_text: vec!["*.dat".into()],
+            force_binary: vec!["mod.txt".into()],
+            ..Default::default()
+        };
+        let forced = file_contents(&repo, "HEAD", WORKDIR, "bin.dat", &config).unwrap();
+        assert_eq!(forced.after, text("c\0d"));
+        let forced = file_contents(&repo, "HEAD", WORKDIR, "mod.txt", &config).unwrap();
+        assert!(forced.before_binary && forced.after_binary);
+    }
+
+    #[test]
+    fn test_index_deletion_with_file_in_workdir() {
+        let (_dir, repo) = repo_with_commit(&[("f.txt", "committed\n"), ("g.txt", "g\n")]);
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("f.txt")).unwrap();
+        index.write().unwrap();
+
+        let sides = |after_ref| {
+            compute_diff(&repo, "HEAD", after_ref, false, &DiffConfig::default())
+                .unwrap()
+                .files
+                .iter()
+                .map(|f| {
+                    let first = |file: &Option<File>| {
+                        file.as_ref().map(|file| file.content.lines()[0].clone())
+                    };
+                    (f.path().to_string(), first(&f.before), first(&f.after))
+                })
+                .collect::<Vec<_>>()
+        };
+        let side = |before: Option<&str>, after: Option<&str>| {
+            (
+                "f.txt".to_string(),
+                before.map(String::from),
+                after.map(String::from),
+            )
+        };
+        let statuses = |after_ref| {
+            get_changed_paths(&repo, "HEAD", after_ref)
+                .unwrap()
+                .into_iter()
+                .map(|p| (p.path, p.status, p.added, p.removed))
+                .collect::<Vec<_>>()
+        };
+
+        // Staged for deletion, still in the working tree unchanged (`D ` + `??`)
+        assert_eq!(sides(STAGED), [side(Some("committed"), None)]);
+        assert_eq!(sides(UNSTAGED), [side(None, Some("committed"))]);
+        assert_eq!(sides(WORKDIR), []);
+        assert_eq!(statuses(WORKDIR), []);
+
+        // Recreated with new content after staging the deletion
+        write_files(&repo, &[("f.txt", "recreated\n")]);
+        assert_eq!(sides(STAGED), [side(Some("committed"), None)]);
+        assert_eq!(sides(UNSTAGED), [side(None, Some("recreated"))]);
+        assert_eq!(sides(WORKDIR), [side(Some("committed"), Some("recreated"))]);
+        assert_eq!(
+            statuses(WORKDIR),
+            [("f.txt".to_string(), ChangeKind::Modified, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_staged_diff_writes_no_objects() {
+        let (_dir, repo) = repo_with_commit(&[("f.txt", "committed\n")]);
+        write_files(&repo, &[("f.txt", "staged\n")]);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("f.txt")).unwrap();
+        index.write().unwrap();
+
+        let files = compute_diff(&repo, "HEAD", STAGED, false, &DiffConfig::default())
+            .unwrap()
+            .files;
+        assert_eq!(files.len(), 1);
+
+        // The staged tree was only ever built in memory
+        let tree_oid = resolve_to_tree(&repo, STAGED).unwrap().unwrap().id();
+        let on_disk = Repository::open(repo.path()).unwrap();
+        assert!(on_disk.find_tree(tree_oid).is_err());
+    }
+
+    #[test]
+    fn test_staged_and_unstaged_scopes() {
+        let (_dir, repo) = repo_with_commit(&[("f.txt", "committed\n"), ("g.txt", "g\n")]);
+        write_files(&repo, &[("f.txt", "staged\n")]);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("f.txt")).unwrap();
+        index.write().unwrap();
+        write_files(&repo, &[("f.txt", "unstaged\n"), ("g.txt", "g2\n")]);
+
+        let sides = |after_ref| {
+            let files = compute_diff(&repo, "HEAD", after_ref, false, &DiffConfig::default())
+                .unwrap()
+                .files;
+            files
+                .iter()
+                .map(|f| {
+                    let lines =
+                        |file: &Option<File>| file.as_ref().unwrap().content.lines()[0].clone();
+                    (f.path().to_string(), lines(&f.before), lines(&f.after))
+                })
+                .collect::<Vec<_>>()
+        };
+        let side = |path: &str, before: &str, after: &str| {
+            (path.to_string(), before.to_string(), after.to_string())
         };
 
-        // Skip entries where we couldn't load either file (e.g., submodules, directories)
-        if before_file.is_none() && after_file.is_none() {
-            log::debug!(
-                "Skipping diff entry with no loadable files: before={:?}, after={:?}",
-                change.before_path,
-                change.after_path
-            );
-            continue;
-        }
+        assert_eq!(sides(STAGED), [side("f.txt", "committed", "staged")]);
+        assert_eq!(
+            sides(UNSTAGED),
+            [
+                side("f.txt", "staged", "unstaged"),
+                side("g.txt", "g", "g2")
+            ]
+        );
+        assert_eq!(
+            sides(WORKDIR),
+            [
+                side("f.txt", "committed", "unstaged"),
+                side("g.txt", "g", "g2")
+            ]
+        );
 
-        let alignments = compute_alignments_from_hunks(&change.hunks, &before_file, &after_file);
+        let paths = |after_ref| {
+            get_changed_paths(&repo, "HEAD", after_ref)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(STAGED), ["f.txt"]);
+        assert_eq!(paths(UNSTAGED), ["f.txt", "g.txt"]);
 
-        result.push(FileDiff {
-            before: before_file,
-            after: after_file,
-            alignments,
-        });
+        // UNSTAGED is only valid as the target
+        assert!(compute_diff(&repo, UNSTAGED, WORKDIR, false, &DiffConfig::default()).is_err());
     }
 
-    // Sort by path
-    result.sort_by(|a, b| a.path().cmp(b.path()));
-    Ok(result)
-}
-
-/// Collect file changes with hunks from a git diff.
-fn collect_file_changes(diff: &Diff) -> Result<Vec<FileChange>> {
-    // We need to collect hunks per file. The foreach callback gives us deltas and hunks,
-    // but we need to associate hunks with their files.
-    let file_changes: RefCell<Vec<FileChange>> = RefCell::new(Vec::new());
-    let current_file_idx: RefCell<Option<usize>> = RefCell::new(None);
+    #[test]
+    fn test_operation_state() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        assert_eq!(operation_state(&repo), OperationState::Clean);
+        assert_eq!(
+            get_repo_info(&repo).unwrap().operation,
+            OperationState::Clean
+        );
 
-    diff.foreach(
-        &mut |delta, _progress| {
-            let before_path = delta
-                .old_file()
-                .path()
-                .map(|p| p.to_string_lossy().to_string());
-            let after_path = delta
-                .new_file()
-                .path()
-                .map(|p| p.to_string_lossy().to_string());
+        // Git is mid-merge while MERGE_HEAD exists
+        let head = repo.head().unwrap().target().unwrap();
+        std::fs::write(repo.path().join("MERGE_HEAD"), format!("{}\n", head)).unwrap();
+        assert_eq!(operation_state(&repo), OperationState::Merge);
+        assert_eq!(
+            serde_json::to_value(OperationState::CherryPick).unwrap(),
+            "cherry_pick"
+        );
 
-            let mut changes = file_changes.borrow_mut();
-            changes.push(FileChange {
-                before_path,
-                after_path,
-                status: delta.status(),
-                hunks: Vec::new(),
-            });
-            *current_file_idx.borrow_mut() = Some(changes.len() - 1);
-            true
-        },
-        None, // binary callback
-        Some(&mut |_delta, hunk| {
-            // Git uses 1-indexed line numbers, convert to 0-indexed
-            // Also handle the special case where old_start/new_start is 0 for empty files
-            let old_start = if hunk.old_start() == 0 {
-                0
-            } else {
-                hunk.old_start() - 1
-            };
-            let new_start = if hunk.new_start() == 0 {
-                0
-            } else {
-                hunk.new_start() - 1
-            };
+        repo.cleanup_state().unwrap();
+        assert_eq!(operation_state(&repo), OperationState::Clean);
+    }
 
-            let h = Hunk {
-                old_start,
-                old_lines: hunk.old_lines(),
-                new_start,
-                new_lines: hunk.new_lines(),
-            };
+    #[test]
+    fn test_repo_relative_path() {
+        let (_dir, repo) = repo_with_commit(&[("src/a.rs", "a\n")]);
+        let workdir = repo.workdir().unwrap();
+        let rel = |path: &str| repo_relative_path(&repo, path).unwrap();
+
+        assert_eq!(rel("src/a.rs"), "src/a.rs");
+        assert_eq!(rel("./src/a.rs"), "src/a.rs");
+        assert_eq!(rel("src\\a.rs"), "src/a.rs");
+        assert_eq!(rel(&workdir.join("src/a.rs").to_string_lossy()), "src/a.rs");
+        let backslashed = format!(
+            "{}\\src\\a.rs",
+            workdir.to_string_lossy().trim_end_matches('/')
+        );
+        assert_eq!(rel(&backslashed), "src/a.rs");
+
+        assert!(repo_relative_path(&repo, "/elsewhere/src/a.rs").is_err());
+        assert!(repo_relative_path(&repo, "C:\\elsewhere\\a.rs").is_err());
+        // A sibling directory sharing the workdir's name as a prefix
+        let sibling = format!(
+            "{}-other/a.rs",
+            workdir.to_string_lossy().trim_end_matches('/')
+        );
+        assert!(repo_relative_path(&repo, &sibling).is_err());
+
+        assert_eq!(rel("src//./b/../a.rs"), "src/a.rs");
+        assert!(repo_relative_path(&repo, "../x").is_err());
+        assert!(repo_relative_path(&repo, "a/../../x").is_err());
+        let escaping = format!("{}/../x", workdir.to_string_lossy().trim_end_matches('/'));
+        assert!(repo_relative_path(&repo, &escaping).is_err());
+        assert!(repo_relative_path(&repo, "./").is_err());
+        assert!(repo_relative_path(&repo, &workdir.to_string_lossy()).is_err());
+    }
 
-            if let Some(idx) = *current_file_idx.borrow() {
-                file_changes.borrow_mut()[idx].hunks.push(h);
-            }
-            true
-        }),
-        None, // line callback
-    )?;
+    #[test]
+    fn test_path_ignore_status() {
+        let (_dir, repo) = repo_with_commit(&[("kept.log", "tracked anyway\n")]);
+        write_files(
+            &repo,
+            &[
+                (".gitignore", "*.log\ntarget/\n"),
+                ("src/.gitignore", "generated.rs\n"),
+                ("debug.log", "x\n"),
+                ("target/debug/app", "x\n"),
+                ("src/generated.rs", "x\n"),
+            ],
+        );
+        let status = |path: &str| path_ignore_status(&repo, path).unwrap();
 
-    Ok(file_changes.into_inner())
-}
+        let log = status("debug.log");
+        assert!(log.ignored);
+        assert_eq!(log.matching_pattern.as_deref(), Some("*.log"));
+        assert_eq!(log.source.as_deref(), Some(".gitignore"));
 
-/// Compute alignments from git hunks.
-///
-/// This ensures our alignments match what git diff reports.
-/// Hunks define the changed regions; we fill in unchanged regions between them.
-fn compute_alignments_from_hunks(
-    hunks: &[Hunk],
-    before: &Option<File>,
-    after: &Option<File>,
-) -> Vec<Alignment> {
-    let before_len = before
-        .as_ref()
-        .map(|f| f.content.lines().len() as u32)
-        .unwrap_or(0);
-    let after_len = after
-        .as_ref()
-        .map(|f| f.content.lines().len() as u32)
-        .unwrap_or(0);
+        // Committed before the rule applied, so still tracked
+        assert!(!status("kept.log").ignored);
+        assert!(!status("src/main.rs").ignored);
 
-    // Handle empty files
-    if before_len == 0 && after_len == 0 {
-        return vec![];
-    }
+        let nested = status("target/debug/app");
+        assert!(nested.ignored);
+        assert_eq!(nested.matching_pattern.as_deref(), Some("target/"));
 
-    // If no hunks but files exist, it's either all added or all deleted
-    if hunks.is_empty() {
-        if before_len == 0 {
-            // All added
-            return vec![Alignment {
-                before: Span::new(0, 0),
-                after: Span::new(0, after_len),
-                changed: true,
-            }];
-        } else if after_len == 0 {
-            // All deleted
-            return vec![Alignment {
-                before: Span::new(0, before_len),
-                after: Span::new(0, 0),
-                changed: true,
-            }];
-        } else {
-            // No changes (shouldn't happen for files in a diff, but handle gracefully)
-            return vec![Alignment {
-                before: Span::new(0, before_len),
-                after: Span::new(0, after_len),
-                changed: false,
-            }];
-        }
+        let generated = status("src/generated.rs");
+        assert_eq!(generated.matching_pattern.as_deref(), Some("generated.rs"));
+        assert_eq!(generated.source.as_deref(), Some("src/.gitignore"));
     }
 
-    let mut alignments = Vec::new();
-    let mut before_pos = 0u32;
-    let mut after_pos = 0u32;
+    #[test]
+    fn test_patience_keeps_moved_function_intact() {
+        // The classic case from the patience diff write-up: Myers matches the
+        // braces and blank lines of unrelated functions, patience anchors on
+        // the unique lines and shows `fib` as one added block
+        let before = "#include <stdio.h>\n\n// Frobs foo heartily\nint frobnitz(int foo)\n{\n    int i;\n    for(i = 0; i < 10; i++)\n    {\n        printf(\"Your answer is: \");\n        printf(\"%d\\n\", foo);\n    }\n}\n\nint fact(int n)\n{\n    if(n > 1)\n    {\n        return fact(n-1) * n;\n    }\n    return 1;\n}\n\nint main(int argc, char **argv)\n{\n    frobnitz(fact(10));\n}\n";
+        let after = "#include <stdio.h>\n\nint fib(int n)\n{\n    if(n > 2)\n    {\n        return fib(n-1) + fib(n-2);\n    }\n    return 1;\n}\n\n// Frobs foo heartily\nint frobnitz(int foo)\n{\n    int i;\n    for(i = 0; i < 10; i++)\n    {\n        printf(\"%d\\n\", foo);\n    }\n}\n\nint main(int argc, char **argv)\n{\n    frobnitz(fib(10));\n}\n";
+        let (_dir, repo) = repo_with_commit(&[("f.c", before)]);
+        write_files(&repo, &[("f.c", after)]);
+
+        let changed_regions = |algorithm| {
+            let config = DiffConfig {
+                algorithm,
+                ..Default::default()
+            };
+            let files = compute_diff(&repo, "HEAD", WORKDIR, false, &config)
+                .unwrap()
+                .files;
+            files[0]
+                .alignments
+                .iter()
+                .filter(|a| a.changed)
+                .map(|a| (a.before, a.after))
+                .collect::<Vec<_>>()
+        };
+        let myers = changed_regions(DiffAlgorithm::Myers);
+        let patience = changed_regions(DiffAlgorithm::Patience);
 
-    for hunk in hunks {
-        // Unchanged region before this hunk
-        if before_pos < hunk.old_start || after_pos < hunk.new_start {
-            // The gap should be the same size on both sides for unchanged content
-            let before_gap = hunk.old_start - before_pos;
-            let after_gap = hunk.new_start - after_pos;
+        // All of fib (lines 2..11) is one insertion, not spliced into fact
+        assert_eq!(patience[0], (Span::new(2, 2), Span::new(2, 11)));
+        assert!(!myers.contains(&patience[0]));
+        assert!(patience.len() < myers.len());
+    }
 
-            // They should match for truly unchanged content, but handle edge cases
-            if before_gap > 0 || after_gap > 0 {
-                alignments.push(Alignment {
-                    before: Span::new(before_pos, hunk.old_start),
-                    after: Span::new(after_pos, hunk.new_start),
-                    changed: false,
-                });
+    #[test]
+    fn test_diff_summary_matches_full_diff() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("a.txt", "1\n2\n3\n"),
+            ("b.txt", "gone\nsoon\n"),
+            ("c.txt", "same\n"),
+        ]);
+        write_files(&repo, &[("a.txt", "1\ntwo\n3\n4\n"), ("new.txt", "x\ny\n")]);
+        std::fs::remove_file(repo.workdir().unwrap().join("b.txt")).unwrap();
+
+        let summary = diff_summary(&repo, "HEAD", WORKDIR).unwrap();
+        let files = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default())
+            .unwrap()
+            .files;
+        let changed = || {
+            files
+                .iter()
+                .flat_map(|f| &f.alignments)
+                .filter(|a| a.changed)
+        };
+        let additions: u32 = changed().map(|a| a.after.len()).sum();
+        let deletions: u32 = changed().map(|a| a.before.len()).sum();
+
+        assert_eq!(
+            summary,
+            DiffSummary {
+                files: files.len(),
+                additions: additions as usize,
+                deletions: deletions as usize,
             }
-        }
+        );
+        assert_eq!(
+            (summary.files, summary.additions, summary.deletions),
+            (3, 4, 3)
+        );
+    }
 
-        // The hunk itself (changed region)
-        let hunk_before_end = hunk.old_start + hunk.old_lines;
-        let hunk_after_end = hunk.new_start + hunk.new_lines;
+    #[test]
+    fn test_normalize_eol() {
+        assert_eq!(normalize_eol(b"a\r\nb\r\n"), b"a\nb\n");
+        assert_eq!(normalize_eol(b"a\rb\n"), b"a\rb\n");
+    }
 
-        alignments.push(Alignment {
-            before: Span::new(hunk.old_start, hunk_before_end),
-            after: Span::new(hunk.new_start, hunk_after_end),
-            changed: true,
-        });
+    #[test]
+    fn test_untracked_file_matches_committed_added_file() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        let content = "first\nsecond\nthird\n";
+        write_files(&repo, &[("new.txt", content)]);
+
+        // Untracked: HEAD..WORKDIR
+        let untracked = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default())
+            .unwrap()
+            .files;
+
+        // Same file committed: HEAD~1..HEAD
+        commit_all(&repo, "add new.txt");
+        let committed = compute_diff(&repo, "HEAD~1", "HEAD", false, &DiffConfig::default())
+            .unwrap()
+            .files;
+
+        assert_eq!(untracked.len(), 1);
+        assert_eq!(committed.len(), 1);
+        let (untracked, committed) = (&untracked[0], &committed[0]);
+
+        assert!(untracked.before.is_none());
+        assert!(committed.before.is_none());
+        assert_eq!(untracked.change_kind(), committed.change_kind());
+        assert_eq!(
+            untracked.after.as_ref().unwrap().content.lines(),
+            committed.after.as_ref().unwrap().content.lines()
+        );
 
-        before_pos = hunk_before_end;
-        after_pos = hunk_after_end;
+        let spans = |d: &FileDiff| -> Vec<(Span, Span, bool)> {
+            d.alignments
+                .iter()
+                .map(|a| (a.before, a.after, a.changed))
+                .collect()
+        };
+        assert_eq!(spans(untracked), spans(committed));
+        assert_eq!(
+            spans(untracked),
+            vec![(Span::new(0, 0), Span::new(0, 3), true)]
+        );
     }
 
-    // Unchanged region after the last hunk
-    if before_pos < before_len || after_pos < after_len {
-        alignments.push(Alignment {
-            before: Span::new(before_pos, before_len),
-            after: Span::new(after_pos, after_len),
-            changed: false,
-        });
-    }
+    #[test]
+    fn test_binary_overrides() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "a\n")]);
+        write_files(
+            &repo,
+            &[
+                ("data.dump", "has\0null\n"),
+                ("notes.txt", "plain\n"),
+                ("attr.bin", "also\0null\n"),
+                (".gitattributes", "*.bin diff\nnotes.txt binary\n"),
+            ],
+        );
 
-    alignments
-}
+        let is_binary = |config: &DiffConfig, path: &str| {
+            compute_diff(&repo, "HEAD", WORKDIR, false, config)
+                .unwrap()
+                .files
+                .into_iter()
+                .find(|f| f.path() == path)
+                .unwrap()
+                .is_binary()
+        };
 
-/// Load a file from a git tree.
-fn load_file(repo: &Repository, tree: Option<&Tree>, path: &Path) -> Result<Option<File>> {
-    let tree = match tree {
-        Some(t) => t,
-        None => return Ok(None),
-    };
+        let default = DiffConfig::default();
+        assert!(is_binary(&default, "data.dump"));
+        // Attributes: `diff` forces text, `binary` forces binary
+        assert!(!is_binary(&default, "attr.bin"));
+        assert!(is_binary(&default, "notes.txt"));
 
-    let entry = match tree.get_path(path) {
-        Ok(e) => e,
-        Err(_) => return Ok(None), // File doesn't exist in this tree
-    };
+        let config = DiffConfig {
+            force_text: vec!["*.dump".into()],
+            ..Default::default()
+        };
+        assert!(!is_binary(&config, "data.dump"));
 
-    let obj = entry
-        .to_object(repo)
-        .map_err(|e| GitError(format!("Cannot load object: {}", e)))?;
+        let config = DiffConfig {
+            force_binary: vec!["*.bin".into()],
+            ..Default::default()
+        };
+        assert!(is_binary(&config, "attr.bin"));
+    }
 
-    let blob = match obj.as_blob() {
-        Some(b) => b,
-        None => return Ok(None), // Not a file (maybe a submodule)
-    };
+    #[test]
+    fn test_text_binary_transitions() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("was_text.dat", "line one\nline two\n"),
+            ("was_binary.dat", "bin\0ary"),
+        ]);
+        write_files(
+            &repo,
+            &[
+                ("was_text.dat", "now\0binary"),
+                ("was_binary.dat", "plain\ntext\n"),
+            ],
+        );
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+        let file = |path: &str| result.files.iter().find(|f| f.path() == path).unwrap();
+
+        let to_binary = file("was_text.dat");
+        assert!(!to_binary.before_binary && to_binary.after_binary);
+        assert_eq!(
+            to_binary.before.as_ref().unwrap().content.lines(),
+            ["line one", "line two"]
+        );
+        // The old text shows as removed
+        assert_eq!(to_binary.alignments.len(), 1);
+        assert_eq!(to_binary.alignments[0].before, Span::new(0, 2));
+        assert!(to_binary.alignments[0].changed);
+
+        let to_text = file("was_binary.dat");
+        assert!(to_text.before_binary && !to_text.after_binary);
+        assert_eq!(
+            to_text.after.as_ref().unwrap().content.lines(),
+            ["plain", "text"]
+        );
+    }
 
-    let bytes = blob.content();
-    let content = if FileContent::is_binary_data(bytes) {
-        FileContent::Binary
-    } else {
-        let text = String::from_utf8_lossy(bytes);
-        FileContent::from_text(&text)
-    };
+    #[test]
+    fn test_lfs_pointer_diff() {
+        let pointer = |oid: char, size: u64| {
+            format!(
+                "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize {}\n",
+                oid.to_string().repeat(64),
+                size
+            )
+        };
+        let (_dir, repo) = repo_with_commit(&[
+            (
+                ".gitattributes",
+                "*.psd filter=lfs diff=lfs merge=lfs -text\n",
+            ),
+            ("art.psd", &pointer('a', 2048)),
+        ]);
+        write_files(&repo, &[("art.psd", &pointer('b', 4096))]);
+        commit_all(&repo, "Update art");
+
+        let result = compute_diff(&repo, "HEAD~1", "HEAD", false, &DiffConfig::default()).unwrap();
+        assert_eq!(result.files.len(), 1);
+        let file = &result.files[0];
+        let (before, after) = (file.before.as_ref().unwrap(), file.after.as_ref().unwrap());
+        assert_eq!(before.lfs.as_ref().unwrap().size, 2048);
+        assert_eq!(after.lfs.as_ref().unwrap().size, 4096);
+        assert_eq!(
+            after.content.lines(),
+            [
+                "Git LFS object (4.0 KB)".to_string(),
+                format!("oid sha256:{}", "b".repeat(64)),
+            ]
+        );
+        // Alignments cover the two summary lines, both changed
+        let changed: Vec<_> = file.alignments.iter().filter(|a| a.changed).collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].before, Span::new(0, 2));
+        assert_eq!(changed[0].after, Span::new(0, 2));
+    }
 
-    Ok(Some(File {
-        path: path.to_string_lossy().to_string(),
-        content,
-    }))
-}
+    #[test]
+    fn test_identical_content_is_mode_only() {
+        let (_dir, repo) = repo_with_commit(&[("run.sh", "echo one\necho two\n")]);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+
+        // Same blob, now executable
+        let blob = head.tree().unwrap().get_name("run.sh").unwrap().id();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("run.sh", blob, 0o100755).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "chmod", &tree, &[&head])
+            .unwrap();
+
+        let result = compute_diff(&repo, "HEAD~1", "HEAD", false, &DiffConfig::default()).unwrap();
+        assert_eq!(result.files.len(), 1);
+        let file = &result.files[0];
+        assert_eq!(
+            file.mode_change,
+            Some(ModeChange {
+                before: 0o100644,
+                after: 0o100755
+            })
+        );
+        assert!(file.alignments.iter().all(|a| !a.changed));
+
+        // Even if git were to report hunks for it, the same blob is unchanged
+        let mut change = FileChange {
+            before_path: Some("run.sh".into()),
+            after_path: Some("run.sh".into()),
+            status: Delta::Modified,
+            old_mode: FileMode::Blob,
+            new_mode: FileMode::BlobExecutable,
+            hunks: vec![Hunk {
+                old_start: 0,
+                old_lines: 1,
+                new_start: 0,
+                new_lines: 1,
+            }],
+            same_blob: true,
+        };
+        let (before, after) = (
+            text_file("run.sh", vec!["a"]),
+            text_file("run.sh", vec!["a"]),
+        );
+        assert!(has_identical_content(&change, &before, &after));
+        change.same_blob = false;
+        assert!(!has_identical_content(&change, &before, &after));
+        change.hunks.clear();
+        assert!(has_identical_content(&change, &before, &after));
+        let other = text_file("run.sh", vec!["b"]);
+        assert!(!has_identical_content(&change, &before, &other));
+    }
 
-/// Load a file from the working directory.
-fn load_file_from_workdir(repo: &Repository, path: &Path) -> Result<Option<File>> {
-    let workdir = repo
-        .workdir()
-        .ok_or_else(|| GitError("Bare repository".into()))?;
-    let full_path = workdir.join(path);
+    #[test]
+    fn test_compute_diff_excludes_globs() {
+        let (_dir, repo) = repo_with_commit(&[("src/main.rs", "fn main() {}\n")]);
+        write_files(
+            &repo,
+            &[
+                ("src/main.rs", "fn main() { run(); }\n"),
+                ("package-lock.json", "{}\n"),
+                ("static/app.min.js", "x\n"),
+                ("static/app.js", "x\n"),
+                ("vendor/dep/lib.rs", "pub fn dep() {}\n"),
+            ],
+        );
 
-    if !full_path.exists() {
-        return Ok(None);
+        let config = DiffConfig {
+            exclude: vec![
+                "package-lock.json".into(),
+                "*.min.js".into(),
+                "vendor/".into(),
+            ],
+            ..Default::default()
+        };
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        let paths: Vec<&str> = result.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["src/main.rs", "static/app.js"]);
+        assert_eq!(result.excluded_count, 3);
+
+        // Default config excludes nothing
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+        assert_eq!(result.files.len(), 5);
+        assert_eq!(result.excluded_count, 0);
     }
 
-    // Skip directories (e.g., submodules)
-    if full_path.is_dir() {
-        log::debug!(
-            "Skipping directory in load_file_from_workdir: {}",
-            path.display()
+    #[test]
+    fn test_collapse_files() {
+        let (_dir, repo) =
+            repo_with_commit(&[("src/main.rs", "fn main() {}\n"), ("Cargo.lock", "old\n")]);
+        write_files(
+            &repo,
+            &[
+                ("src/main.rs", "fn main() { run(); }\n"),
+                ("Cargo.lock", "new\nlines\n"),
+            ],
+        );
+
+        let config = DiffConfig {
+            collapse_files: vec!["*.lock".into()],
+            ..Default::default()
+        };
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        assert_eq!(result.files.len(), 2);
+        let lock = result
+            .files
+            .iter()
+            .find(|f| f.path() == "Cargo.lock")
+            .unwrap();
+        assert!(lock.collapsed_by_default);
+        assert_eq!(lock.change_kind(), ChangeKind::Modified);
+        assert!(lock.alignments.is_empty());
+        for side in [&lock.before, &lock.after] {
+            assert!(side.as_ref().unwrap().content.lines().is_empty());
+        }
+        let main = result
+            .files
+            .iter()
+            .find(|f| f.path() == "src/main.rs")
+            .unwrap();
+        assert!(!main.collapsed_by_default);
+
+        // Loaded on request, in full
+        let full = file_ref_diff(&repo, "HEAD", WORKDIR, false, "Cargo.lock", None, &config)
+            .unwrap()
+            .unwrap();
+        assert!(!full.collapsed_by_default);
+        assert_eq!(full.after.unwrap().content.lines(), ["new", "lines"]);
+        assert!(!full.alignments.is_empty());
+        assert!(
+            file_ref_diff(&repo, "HEAD", WORKDIR, false, "missing.lock", None, &config)
+                .unwrap()
+                .is_none()
         );
-        return Ok(None);
     }
 
-    let bytes =
-        std::fs::read(&full_path).map_err(|e| GitError(format!("Cannot read file: {}", e)))?;
+    #[test]
+    fn test_triple_diff() {
+        let (_dir, repo) = repo_with_commit(&[("a.txt", "one\ntwo\nthree\n")]);
+        // Staged: "two" changed; unstaged on top: a line added at the end
+        write_files(&repo, &[("a.txt", "one\nTWO\nthree\n")]);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        write_files(&repo, &[("a.txt", "one\nTWO\nthree\nfour\n")]);
+
+        let config = DiffConfig::default();
+        let triple = triple_diff(&repo, "a.txt", &config).unwrap();
+        let staged = triple.head_vs_index.unwrap();
+        assert_eq!(
+            staged.after.unwrap().content.lines(),
+            ["one", "TWO", "three"]
+        );
+        let unstaged = triple.index_vs_workdir.unwrap();
+        assert_eq!(
+            unstaged.after.unwrap().content.lines(),
+            ["one", "TWO", "three", "four"]
+        );
 
-    let content = if FileContent::is_binary_data(&bytes) {
-        FileContent::Binary
-    } else {
-        let text = String::from_utf8_lossy(&bytes);
-        FileContent::from_text(&text)
-    };
+        let row = |head, index, workdir| TripleRow {
+            head,
+            index,
+            workdir,
+        };
+        assert_eq!(
+            triple.rows,
+            vec![
+                row(Some(0), Some(0), Some(0)),
+                row(Some(1), Some(1), Some(1)),
+                row(Some(2), Some(2), Some(2)),
+                row(None, None, Some(3)),
+            ]
+        );
 
-    Ok(Some(File {
-        path: path.to_string_lossy().to_string(),
-        content,
-    }))
-}
+        // A new file that's only in the working tree
+        write_files(&repo, &[("new.txt", "x\ny\n")]);
+        let triple = triple_diff(&repo, "new.txt", &config).unwrap();
+        assert!(triple.head_vs_index.is_none());
+        assert!(triple.index_vs_workdir.unwrap().before.is_none());
+        assert_eq!(
+            triple.rows,
+            vec![row(None, None, Some(0)), row(None, None, Some(1))]
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_compute_diff_filters_kinds() {
+        let (_dir, repo) = repo_with_commit(&[
+            ("keep.txt", "a\n"),
+            ("old.txt", "gone\n"),
+            ("older.txt", "gone too\n"),
+        ]);
+        write_files(&repo, &[("keep.txt", "b\n"), ("new.txt", "new\n")]);
+        let workdir = repo.workdir().unwrap();
+        std::fs::remove_file(workdir.join("old.txt")).unwrap();
+        std::fs::remove_file(workdir.join("older.txt")).unwrap();
+
+        let all = compute_diff(&repo, "HEAD", WORKDIR, false, &DiffConfig::default()).unwrap();
+        let deleted_count = all
+            .files
+            .iter()
+            .filter(|f| f.change_kind() == ChangeKind::Deleted)
+            .count();
+        assert_eq!(all.files.len(), 4);
+
+        let config = DiffConfig {
+            kinds: vec![ChangeKind::Deleted],
+            ..Default::default()
+        };
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        let paths: Vec<&str> = result.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["old.txt", "older.txt"]);
+        assert_eq!(result.files.len(), deleted_count);
+        assert_eq!(result.excluded_count, 2);
+
+        // Listed-only files are filtered the same way
+        let config = DiffConfig {
+            collapse_files: vec!["*.txt".into()],
+            max_file_size: Some(0),
+            ..config
+        };
+        let result = compute_diff(&repo, "HEAD", WORKDIR, false, &config).unwrap();
+        let paths: Vec<&str> = result.files.iter().map(|f| f.path()).collect();
+        assert_eq!(paths, vec!["old.txt", "older.txt"]);
+        assert_eq!(result.excluded_count, 2);
+    }
 
     /// Helper to create a File with text content
     fn text_file(path: &str, lines: Vec<&str>) -> Option<File> {
-        Some(File {
-            path: path.into(),
-            content: FileContent::Text {
-                lines: lines.into_iter().map(String::from).collect(),
-            },
-        })
+        let lines = lines.into_iter().map(String::from).collect();
+        Some(File::new(path, FileContent::Text { lines }))
     }
 
     #[test]
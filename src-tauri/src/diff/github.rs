@@ -10,6 +10,8 @@ use std::process::Command;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 
+use crate::executable::find_executable;
+
 // =============================================================================
 // Types
 // =============================================================================
@@ -120,37 +122,9 @@ pub fn invalidate_cache(repo: &GitHubRepo) {
 // GitHub CLI Integration
 // =============================================================================
 
-/// Common paths where `gh` might be installed.
-/// GUI apps on macOS don't inherit the shell's PATH, so we check these explicitly.
-const GH_SEARCH_PATHS: &[&str] = &[
-    "/opt/homebrew/bin",              // Homebrew on Apple Silicon
-    "/usr/local/bin",                 // Homebrew on Intel Mac, common Linux location
-    "/usr/bin",                       // System binaries
-    "/home/linuxbrew/.linuxbrew/bin", // Linuxbrew
-];
-
-/// Find the `gh` CLI executable.
-///
-/// First tries the bare command (works if already in PATH), then checks common locations.
-fn find_gh_command() -> Option<std::path::PathBuf> {
-    use std::path::PathBuf;
-
-    // First, check if `gh` is directly available (e.g., already in PATH)
-    if let Ok(output) = Command::new("gh").arg("--version").output() {
-        if output.status.success() {
-            return Some(PathBuf::from("gh"));
-        }
-    }
-
-    // Check common installation paths
-    for dir in GH_SEARCH_PATHS {
-        let path = PathBuf::from(dir).join("gh");
-        if path.exists() {
-            return Some(path);
-        }
-    }
-
-    None
+/// Find the `gh` CLI executable, from PATH or a common install location.
+pub fn find_gh_command() -> Option<std::path::PathBuf> {
+    find_executable("gh", None)
 }
 
 /// Get the GitHub token from `gh auth token`.
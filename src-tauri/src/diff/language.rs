@@ -0,0 +1,195 @@
+//! Language detection for syntax highlighting.
+//!
+//! Classifies a file from its name, extension, or shebang line. Language IDs
+//! match the frontend highlighter (Shiki), so both panes of a diff agree.
+
+use std::path::Path;
+
+/// Special filenames (lowercased) that don't have a useful extension.
+const FILENAMES: &[(&str, &str)] = &[
+    ("dockerfile", "dockerfile"),
+    ("containerfile", "dockerfile"),
+    ("makefile", "make"),
+    ("gnumakefile", "make"),
+    ("justfile", "make"),
+    ("cmakelists.txt", "cmake"),
+    ("gemfile", "ruby"),
+    ("rakefile", "ruby"),
+    ("vagrantfile", "ruby"),
+    (".bashrc", "bash"),
+    (".bash_profile", "bash"),
+    (".zshrc", "bash"),
+    (".profile", "bash"),
+    (".env", "bash"),
+    (".gitconfig", "ini"),
+    (".gitignore", "ini"),
+    (".gitattributes", "ini"),
+    (".editorconfig", "ini"),
+];
+
+/// File extensions (lowercased) to language IDs.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("mts", "typescript"),
+    ("js", "javascript"),
+    ("jsx", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("py", "python"),
+    ("pyi", "python"),
+    ("go", "go"),
+    ("zig", "zig"),
+    ("json", "json"),
+    ("jsonc", "json"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("toml", "toml"),
+    ("xml", "xml"),
+    ("svg", "xml"),
+    ("html", "html"),
+    ("htm", "html"),
+    ("css", "css"),
+    ("scss", "scss"),
+    ("svelte", "svelte"),
+    ("vue", "vue"),
+    ("sh", "bash"),
+    ("bash", "bash"),
+    ("zsh", "bash"),
+    ("md", "markdown"),
+    ("markdown", "markdown"),
+    ("sql", "sql"),
+    ("diff", "diff"),
+    ("patch", "diff"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cpp", "cpp"),
+    ("cc", "cpp"),
+    ("hpp", "cpp"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("scala", "scala"),
+    ("cs", "csharp"),
+    ("swift", "swift"),
+    ("m", "objective-c"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("pl", "perl"),
+    ("lua", "lua"),
+    ("hs", "haskell"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("erl", "erlang"),
+    ("ml", "ocaml"),
+    ("nix", "nix"),
+    ("tf", "terraform"),
+    ("graphql", "graphql"),
+];
+
+/// Shebang interpreters (version suffix stripped) to language IDs.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "python"),
+    ("node", "javascript"),
+    ("deno", "typescript"),
+    ("bash", "bash"),
+    ("sh", "bash"),
+    ("zsh", "bash"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+];
+
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, lang)| *lang)
+}
+
+/// Detect the language of a file from its path, falling back to the shebang
+/// on its first line. Returns None if unknown.
+pub fn detect_language(path: &Path, first_line: Option<&str>) -> Option<String> {
+    let filename = path.file_name()?.to_string_lossy().to_lowercase();
+
+    if let Some(lang) = lookup(FILENAMES, &filename) {
+        return Some(lang.to_string());
+    }
+
+    if let Some(ext) = path.extension() {
+        let ext = ext.to_string_lossy().to_lowercase();
+        if let Some(lang) = lookup(EXTENSIONS, &ext) {
+            return Some(lang.to_string());
+        }
+    }
+
+    first_line.and_then(language_from_shebang).map(String::from)
+}
+
+/// Parse a `#!` line like `#!/usr/bin/env python3` or `#!/bin/bash -e`.
+fn language_from_shebang(line: &str) -> Option<&'static str> {
+    let rest = line.strip_prefix("#!")?.trim();
+    let mut parts = rest.split_whitespace();
+    let mut program = parts.next()?.rsplit('/').next()?;
+
+    // `env` execs the next non-flag argument
+    if program == "env" {
+        program = parts.find(|p| !p.starts_with('-'))?;
+    }
+
+    // python3, python3.11, etc.
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    lookup(INTERPRETERS, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_by_extension() {
+        assert_eq!(
+            detect_language(Path::new("src/main.rs"), None),
+            Some("rust".to_string())
+        );
+        assert_eq!(
+            detect_language(Path::new("web/App.TSX"), None),
+            Some("typescript".to_string())
+        );
+    }
+
+    #[test]
+    fn test_detect_unknown_extension() {
+        assert_eq!(detect_language(Path::new("data.unknownext"), None), None);
+        assert_eq!(
+            detect_language(Path::new("data.unknownext"), Some("plain text")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detect_special_filename() {
+        assert_eq!(
+            detect_language(Path::new("docker/Dockerfile"), None),
+            Some("dockerfile".to_string())
+        );
+    }
+
+    #[test]
+    fn test_detect_by_shebang() {
+        assert_eq!(
+            detect_language(Path::new("bin/tool"), Some("#!/usr/bin/env python")),
+            Some("python".to_string())
+        );
+        assert_eq!(
+            detect_language(Path::new("bin/tool"), Some("#!/usr/bin/python3.11")),
+            Some("python".to_string())
+        );
+        assert_eq!(
+            detect_language(Path::new("bin/run"), Some("#!/bin/bash -e")),
+            Some("bash".to_string())
+        );
+        assert_eq!(
+            detect_language(Path::new("bin/tool"), Some("echo hi")),
+            None
+        );
+    }
+}
@@ -2,25 +2,52 @@
 //!
 //! This module provides:
 //! - `types`: Core data structures (DiffId, FileDiff, etc.)
+//! - `actions`: Working tree edits from the diff view (reverting hunks)
+//! - `blame`: Line blame for review context
 //! - `git`: Git operations for computing diffs
 //! - `github`: GitHub API integration for PR fetching
+//! - `language`: Syntax language detection for highlighting
 //! - `review`: SQLite-backed review storage
 
+pub mod actions;
+pub mod blame;
 pub mod git;
 pub mod github;
+pub mod language;
 pub mod review;
 pub mod types;
 
 // Re-export types used by lib.rs Tauri commands
+pub use actions::{
+    apply_edit, commit_preview, discard_all_with_backup, revert_hunk, undo_last_discard,
+    CommitPreview,
+};
+pub use blame::{blame_lines, BlameLine};
 pub use git::{
-    compute_diff, create_commit, fetch_pr_branch, get_merge_base, get_refs, get_repo_info,
-    last_commit_message, open_repo, resolve_ref, GitRef, PRFetchResult, RepoInfo, WORKDIR,
+    amend_commit_message, blob_bytes, blob_oid, change_density, change_index, commit_log,
+    compute_commit_diff, compute_diff, compute_diff_cancellable, compute_diff_streaming,
+    compute_external_file_diff, create_commit, diff_summary, divergence, effective_diff_options,
+    export_conflict, fetch_pr_branch, file_contents, file_line_count, file_patch, file_ref_diff,
+    full_file_diffs, get_changed_paths, get_merge_base, get_refs, get_repo_info, head_sha,
+    hunk_slices, last_commit_message, numstat, open_repo, operation_state, path_ignore_status,
+    per_commit_diffs, recent_commit_messages, renamed_paths, repo_relative_path,
+    resolve_commit_sha, resolve_ref, resolve_refs, stage_all, stage_file, stage_file_force,
+    status_entries, trace_rename, triple_diff, unstage_all, upstream_of_head, validate_diff_refs,
+    working_tree_overview, BlobError, ChangeLocation, ChangedPath, CommitChanges, CommitDiff,
+    CommitMessage, ConflictExport, DiffAlgorithm, DiffConfig, DiffRefsValidation, DiffResult,
+    DiffSummary, Divergence, EffectiveDiffOptions, FileContents, GitRef, HeadSha, HunkDensity,
+    HunkSlices, IgnoreStatus, LogEntry, NumstatEntry, OperationState, PRFetchResult, RefResolution,
+    RepoInfo, StatusBucket, StatusEntry, TripleDiff, WorkingTreeFile, EMPTY, STAGED, UNSTAGED,
+    WORKDIR,
 };
 pub use github::{
     check_github_auth, get_github_remote, list_pull_requests, GitHubAuthStatus, GitHubRepo,
     PullRequest,
 };
 pub use review::{
-    export_markdown, get_store, init_store, Comment, Edit, NewComment, NewEdit, Review,
+    anchor_hash, export_jsonl_to_writer, export_markdown, get_store, init_store, init_store_at,
+    relocate_comment, suggestion_block, summary_markdown, validate_columns, validate_span, Comment,
+    CommentScope, Edit, ExportFilter, NewComment, NewEdit, PathAliases, RepoSettings, Review,
+    ReviewStore,
 };
 pub use types::{DiffId, FileDiff};
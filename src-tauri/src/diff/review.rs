@@ -2,14 +2,20 @@
 //!
 //! Reviews are stored separately from git, keyed by DiffId.
 
-use std::path::PathBuf;
-use std::sync::{Mutex, OnceLock};
-
-use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use rusqlite::{params, Connection, ErrorCode, OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager};
+use unicode_segmentation::UnicodeSegmentation;
 
-use super::types::{DiffId, Span};
+use super::git::{build_glob_matcher, DiffAlgorithm, DiffConfig, DiffSummary};
+use super::types::{DiffId, FileContent, FileDiff, Span};
 
 // =============================================================================
 // Types
@@ -21,10 +27,17 @@ pub struct Review {
     pub id: DiffId,
     /// Paths that have been marked as reviewed
     pub reviewed: Vec<String>,
+    /// Blob id each reviewed file had when it was marked, where known,
+    /// so a file changed since can be flagged (see `stale_reviewed`)
+    #[serde(default)]
+    pub reviewed_oids: BTreeMap<String, String>,
     /// Comments attached to specific locations
     pub comments: Vec<Comment>,
     /// Edits made during review (stored as diffs)
     pub edits: Vec<Edit>,
+    /// Free-form notes on whole files, by path
+    #[serde(default)]
+    pub notes: BTreeMap<String, String>,
 }
 
 impl Review {
@@ -32,8 +45,10 @@ impl Review {
         Self {
             id,
             reviewed: Vec::new(),
+            reviewed_oids: BTreeMap::new(),
             comments: Vec::new(),
             edits: Vec::new(),
+            notes: BTreeMap::new(),
         }
     }
 }
@@ -45,6 +60,22 @@ pub struct Comment {
     pub path: String,
     pub span: Span,
     pub content: String,
+    /// Hash of the commented lines' content when the comment was made,
+    /// used to re-place the comment after the file changes
+    pub anchor_hash: Option<String>,
+    /// Where on its first and last lines the comment starts and ends, for
+    /// comments on part of a line. None covers the lines whole.
+    #[serde(default)]
+    pub columns: Option<ColumnRange>,
+}
+
+/// Byte offsets of a comment's start within the first line of its span and
+/// its end within the last line. Both lie on grapheme boundaries (see
+/// `validate_columns`), so an emoji or an accented letter is never split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnRange {
+    pub start: u32,
+    pub end: u32,
 }
 
 impl Comment {
@@ -54,8 +85,146 @@ impl Comment {
             path: path.into(),
             span,
             content: content.into(),
+            anchor_hash: None,
+            columns: None,
+        }
+    }
+
+    /// Anchor this comment to the content of the lines it covers.
+    pub fn anchored_to(mut self, lines: &[String]) -> Self {
+        self.anchor_hash = Some(anchor_hash(lines));
+        self
+    }
+}
+
+/// Check that a comment's span lies within a file of `line_count` lines.
+///
+/// Used when comments come from the UI, which may be showing a stale file;
+/// `ReviewStore::add_comment` itself accepts any span so imports still work.
+pub fn validate_span(span: Span, line_count: u32) -> Result<()> {
+    if span.start > span.end {
+        return Err(ReviewError::new(format!(
+            "Invalid comment range: lines {}..{}",
+            span.start, span.end
+        )));
+    }
+    if span.end > line_count {
+        return Err(ReviewError::new(format!(
+            "Comment range {}..{} is past the end of the file ({} lines)",
+            span.start, span.end, line_count
+        )));
+    }
+    Ok(())
+}
+
+/// The grapheme column (user-perceived character) a byte offset falls at
+/// in `line`, or None if it's inside a grapheme or past the end.
+pub fn grapheme_column(line: &str, byte_offset: usize) -> Option<usize> {
+    if byte_offset == line.len() {
+        return Some(grapheme_len(line));
+    }
+    line.grapheme_indices(true)
+        .position(|(offset, _)| offset == byte_offset)
+}
+
+/// Length of `line` in graphemes, e.g. 1 for a family emoji made of
+/// several code points.
+pub fn grapheme_len(line: &str) -> usize {
+    line.graphemes(true).count()
+}
+
+/// Check that a comment's columns lie on grapheme boundaries of the lines
+/// its (already validated) span covers, and that a single-line comment
+/// doesn't end before it starts.
+pub fn validate_columns(span: Span, columns: ColumnRange, lines: &[String]) -> Result<()> {
+    let line = |index: u32| {
+        lines.get(index as usize).ok_or_else(|| {
+            ReviewError::new(format!(
+                "Comment line {} is past the end of the file",
+                index
+            ))
+        })
+    };
+    if span.start >= span.end {
+        return Err(ReviewError::new(
+            "A comment with columns must cover at least one line",
+        ));
+    }
+    let first = line(span.start)?;
+    let last = line(span.end - 1)?;
+
+    let column = |text: &str, offset: u32, index: u32| {
+        grapheme_column(text, offset as usize).ok_or_else(|| {
+            if offset as usize > text.len() {
+                ReviewError::new(format!(
+                    "Column {} is past the end of line {} ({} bytes, {} characters)",
+                    offset,
+                    index,
+                    text.len(),
+                    grapheme_len(text)
+                ))
+            } else {
+                ReviewError::new(format!(
+                    "Column {} is inside a character on line {}",
+                    offset, index
+                ))
+            }
+        })
+    };
+    let start = column(first, columns.start, span.start)?;
+    let end = column(last, columns.end, span.end - 1)?;
+    if span.end - span.start == 1 && start > end {
+        return Err(ReviewError::new(format!(
+            "Invalid comment columns: {}..{}",
+            columns.start, columns.end
+        )));
+    }
+    Ok(())
+}
+
+/// Hash lines for anchoring, ignoring leading/trailing whitespace so
+/// re-indentation doesn't lose the anchor.
+///
+/// FNV-1a rather than `DefaultHasher`, whose output isn't guaranteed to be
+/// stable across Rust releases - these hashes are stored.
+pub fn anchor_hash(lines: &[String]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for line in lines {
+        for byte in line.trim().bytes().chain(std::iter::once(b'\n')) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
         }
     }
+    format!("{:016x}", hash)
+}
+
+/// Find where an anchored comment's lines are now, given the file's new content.
+///
+/// Searches outward from the original line so the nearest match wins.
+/// Returns the new start line, or None if the anchored content is gone.
+/// Comments without an anchor stay on their stored line.
+pub fn relocate_comment(comment: &Comment, new_lines: &[String]) -> Option<u32> {
+    let Some(hash) = &comment.anchor_hash else {
+        return Some(comment.span.start);
+    };
+
+    let len = (comment.span.len() as usize).max(1);
+    if new_lines.len() < len {
+        return None;
+    }
+    let last_start = new_lines.len() - len;
+    let origin = (comment.span.start as usize).min(last_start);
+    let matches = |start: usize| anchor_hash(&new_lines[start..start + len]) == *hash;
+
+    for distance in 0..=last_start {
+        if origin + distance <= last_start && matches(origin + distance) {
+            return Some((origin + distance) as u32);
+        }
+        if distance > 0 && distance <= origin && matches(origin - distance) {
+            return Some((origin - distance) as u32);
+        }
+    }
+    None
 }
 
 /// An edit made during review, stored as a unified diff.
@@ -83,6 +252,12 @@ pub struct NewComment {
     pub path: String,
     pub span: Span,
     pub content: String,
+    /// Content of the commented lines, to anchor the comment to
+    #[serde(default)]
+    pub anchor_lines: Option<Vec<String>>,
+    /// Start and end within the first and last lines, for part of a line
+    #[serde(default)]
+    pub columns: Option<ColumnRange>,
 }
 
 /// Input for recording a new edit (from frontend).
@@ -92,6 +267,60 @@ pub struct NewEdit {
     pub diff: String,
 }
 
+/// Per-repository preferences, stored alongside reviews. Each field is saved
+/// under its own key, so unset fields (and settings saved by older versions)
+/// fall back to their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoSettings {
+    /// Globs for paths left out of diffs by default (see `DiffConfig::exclude`)
+    pub exclude: Vec<String>,
+    /// Globs for generated files listed collapsed, without their content
+    /// (see `DiffConfig::collapse_files`)
+    pub collapse_files: Vec<String>,
+    /// Default line-matching algorithm
+    pub algorithm: DiffAlgorithm,
+    /// Default for `DiffConfig::collapse_unchanged`
+    pub collapse_unchanged: Option<u32>,
+    /// Size in bytes above which files aren't diffed (see
+    /// `DiffConfig::max_file_size`)
+    pub max_file_size: Option<u64>,
+    /// Command template for opening files, e.g. `code -g {path}:{line}`
+    /// (falls back to `$VISUAL`, `$EDITOR`, then `core.editor`)
+    pub editor: Option<String>,
+}
+
+impl RepoSettings {
+    /// The diff options to use when a request doesn't specify any.
+    pub fn diff_config(&self) -> DiffConfig {
+        DiffConfig {
+            exclude: self.exclude.clone(),
+            collapse_files: self.collapse_files.clone(),
+            algorithm: self.algorithm,
+            collapse_unchanged: self.collapse_unchanged,
+            max_file_size: self.max_file_size,
+            ..DiffConfig::default()
+        }
+    }
+
+    /// The fields (by name, sorted) that differ from their defaults. Every
+    /// field is saved, so what's stored says nothing about what was changed.
+    pub fn overrides(&self) -> Vec<String> {
+        let encode = |settings: &RepoSettings| match serde_json::to_value(settings) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => serde_json::Map::new(),
+        };
+        let defaults = encode(&RepoSettings::default());
+        let mut overrides: Vec<String> = encode(self)
+            .into_iter()
+            .filter(|(key, value)| defaults.get(key) != Some(value))
+            .map(|(key, _)| key)
+            .collect();
+        overrides.sort();
+        overrides
+    }
+}
+
 // =============================================================================
 // Error type
 // =============================================================================
@@ -126,19 +355,44 @@ type Result<T> = std::result::Result<T, ReviewError>;
 // =============================================================================
 
 /// Global store instance - initialized during app setup.
-static STORE: OnceLock<std::result::Result<ReviewStore, String>> = OnceLock::new();
+/// Keeps the path it was opened at, so re-initialization can be checked.
+static STORE: OnceLock<(PathBuf, std::result::Result<ReviewStore, String>)> = OnceLock::new();
+
+/// Environment variable that overrides where the review database lives,
+/// e.g. a project-local file, or a scratch path for tests.
+pub const REVIEW_DB_ENV: &str = "STAGED_REVIEW_DB";
 
-/// Initialize the global store with the app's data directory.
-/// Call this once during Tauri app setup.
+/// Initialize the global store with the app's data directory, or the path in
+/// `STAGED_REVIEW_DB` if set. Call this once during Tauri app setup.
 pub fn init_store(app_handle: &AppHandle) -> Result<()> {
+    if let Some(path) = std::env::var_os(REVIEW_DB_ENV).filter(|p| !p.is_empty()) {
+        return init_store_at(PathBuf::from(path));
+    }
+
     let app_data_dir = app_handle
         .path()
         .app_data_dir()
         .map_err(|e| ReviewError::new(format!("Cannot get app data dir: {}", e)))?;
 
-    let db_path = app_data_dir.join("reviews.db");
+    init_store_at(app_data_dir.join("reviews.db"))
+}
 
-    STORE.get_or_init(|| ReviewStore::open(db_path).map_err(|e| e.0));
+/// Initialize the global store at an explicit database path.
+///
+/// The store can only be initialized once per process; calling this again
+/// with the same path is a no-op, and with a different path is an error.
+pub fn init_store_at(db_path: PathBuf) -> Result<()> {
+    let (path, _) = STORE.get_or_init(|| {
+        let store = ReviewStore::open(db_path.clone()).map_err(|e| e.0);
+        (db_path.clone(), store)
+    });
+
+    if *path != db_path {
+        return Err(ReviewError::new(format!(
+            "Review store already initialized at {}",
+            path.display()
+        )));
+    }
 
     // Check if initialization succeeded
     get_store()?;
@@ -147,7 +401,7 @@ pub fn init_store(app_handle: &AppHandle) -> Result<()> {
 
 /// Get the global store. Must call init_store first during app setup.
 pub fn get_store() -> Result<&'static ReviewStore> {
-    let result = STORE
+    let (_, result) = STORE
         .get()
         .ok_or_else(|| ReviewError::new("Review store not initialized"))?;
 
@@ -161,13 +415,31 @@ pub fn get_store() -> Result<&'static ReviewStore> {
 // Review storage
 // =============================================================================
 
+/// Read-only connections a `ReviewStore` keeps alongside its writer.
+const READER_CONNECTIONS: usize = 4;
+
+/// How long a connection waits on a lock held by another before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Review storage backed by SQLite.
+///
+/// Writes go through one connection, one at a time, as SQLite only allows a
+/// single writer anyway. Reads use a small pool of read-only connections:
+/// in WAL mode they see the last committed state without waiting for a
+/// write in progress, so loading a review doesn't queue behind a burst of
+/// comment saves.
 pub struct ReviewStore {
     conn: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
 }
 
 impl ReviewStore {
     /// Open or create the review database at the given path.
+    ///
+    /// A corrupted database (say, after a power loss) is moved aside to
+    /// `<path>.corrupt` and replaced with an empty one, so review features
+    /// keep working instead of failing for the rest of the session.
     pub fn open(db_path: PathBuf) -> Result<Self> {
         // Ensure parent directory exists
         if let Some(parent) = db_path.parent() {
@@ -175,14 +447,61 @@ impl ReviewStore {
                 .map_err(|e| ReviewError(format!("Cannot create directory: {}", e)))?;
         }
 
+        if let Some(reason) = corruption(&db_path) {
+            let backup = quarantine(&db_path)?;
+            log::warn!(
+                "Review database {} is corrupted ({}); moved it to {} and started a new one",
+                db_path.display(),
+                reason,
+                backup.display()
+            );
+        }
+
         let conn = Connection::open(&db_path)?;
-        let store = Self {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // WAL lets reads proceed during writes, and a forced quit loses at
+        // most the last uncheckpointed transactions, not a torn database
+        let mode: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            log::warn!("Review database is using journal mode '{}', not WAL", mode);
+        }
+
+        let mut store = Self {
             conn: Mutex::new(conn),
+            readers: Vec::new(),
+            next_reader: AtomicUsize::new(0),
         };
         store.init_schema()?;
+        // Opened after the schema exists, so readers never see it half-made
+        for _ in 0..READER_CONNECTIONS {
+            store.readers.push(Mutex::new(open_reader(&db_path)?));
+        }
         Ok(store)
     }
 
+    /// Lock a read-only connection, preferring one that's idle.
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        let count = self.readers.len();
+        (0..count)
+            .find_map(|i| self.readers[(start + i) % count].try_lock().ok())
+            .unwrap_or_else(|| self.readers[start % count].lock().unwrap())
+    }
+
+    /// Flush the write-ahead log into the main database file.
+    /// Call before exiting so the database is self-contained on disk.
+    pub fn checkpoint(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        // Returns (busy, log frames, checkpointed frames)
+        let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        if busy != 0 {
+            return Err(ReviewError::new(
+                "Review database is busy, checkpoint incomplete",
+            ));
+        }
+        Ok(())
+    }
+
     /// Initialize the database schema.
     fn init_schema(&self) -> Result<()> {
         let conn = self.conn.lock().unwrap();
@@ -193,6 +512,9 @@ impl ReviewStore {
             DROP TABLE IF EXISTS comments;
             DROP TABLE IF EXISTS reviewed_files;
             DROP TABLE IF EXISTS edits;
+            DROP TABLE IF EXISTS file_notes;
+            DROP TABLE IF EXISTS view_state;
+            DROP TABLE IF EXISTS ref_shas;
             DROP TABLE IF EXISTS reviews;
 
             CREATE TABLE reviews (
@@ -205,6 +527,7 @@ impl ReviewStore {
                 before_ref TEXT NOT NULL,
                 after_ref TEXT NOT NULL,
                 path TEXT NOT NULL,
+                blob_oid TEXT,
                 PRIMARY KEY (before_ref, after_ref, path),
                 FOREIGN KEY (before_ref, after_ref) REFERENCES reviews(before_ref, after_ref) ON DELETE CASCADE
             );
@@ -217,6 +540,9 @@ impl ReviewStore {
                 span_start INTEGER NOT NULL,
                 span_end INTEGER NOT NULL,
                 content TEXT NOT NULL,
+                anchor_hash TEXT,
+                col_start INTEGER,
+                col_end INTEGER,
                 FOREIGN KEY (before_ref, after_ref) REFERENCES reviews(before_ref, after_ref) ON DELETE CASCADE
             );
 
@@ -229,6 +555,42 @@ impl ReviewStore {
                 FOREIGN KEY (before_ref, after_ref) REFERENCES reviews(before_ref, after_ref) ON DELETE CASCADE
             );
 
+            CREATE TABLE file_notes (
+                before_ref TEXT NOT NULL,
+                after_ref TEXT NOT NULL,
+                path TEXT NOT NULL,
+                note TEXT NOT NULL,
+                PRIMARY KEY (before_ref, after_ref, path),
+                FOREIGN KEY (before_ref, after_ref) REFERENCES reviews(before_ref, after_ref) ON DELETE CASCADE
+            );
+
+            -- UI state (expanded regions, folded files), opaque to the backend
+            CREATE TABLE view_state (
+                before_ref TEXT NOT NULL,
+                after_ref TEXT NOT NULL,
+                path TEXT NOT NULL,
+                state_json TEXT NOT NULL,
+                PRIMARY KEY (before_ref, after_ref, path),
+                FOREIGN KEY (before_ref, after_ref) REFERENCES reviews(before_ref, after_ref) ON DELETE CASCADE
+            );
+
+            -- The SHA each named ref last resolved to, so reviews keyed by a
+            -- branch stay reachable after the branch is deleted
+            CREATE TABLE ref_shas (
+                repo_path TEXT NOT NULL,
+                name TEXT NOT NULL,
+                sha TEXT NOT NULL,
+                PRIMARY KEY (repo_path, name)
+            );
+
+            -- Settings outlive reviews, so they aren't reset with them
+            CREATE TABLE IF NOT EXISTS repo_settings (
+                repo_path TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (repo_path, key)
+            );
+
             PRAGMA foreign_keys = ON;
             "#,
         )?;
@@ -250,7 +612,7 @@ impl ReviewStore {
 
     /// Get a review by its DiffId.
     pub fn get(&self, id: &DiffId) -> Result<Review> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
         self.get_with_conn(&conn, id)
     }
 
@@ -271,23 +633,37 @@ impl ReviewStore {
         }
 
         // Load reviewed files
-        let mut stmt = conn
-            .prepare("SELECT path FROM reviewed_files WHERE before_ref = ?1 AND after_ref = ?2")?;
-        let reviewed: Vec<String> = stmt
-            .query_map(params![&id.before, &id.after], |row| row.get(0))?
+        let mut stmt = conn.prepare(
+            "SELECT path, blob_oid FROM reviewed_files WHERE before_ref = ?1 AND after_ref = ?2",
+        )?;
+        let rows: Vec<(String, Option<String>)> = stmt
+            .query_map(params![&id.before, &id.after], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
             .collect::<std::result::Result<Vec<_>, _>>()?;
+        let reviewed_oids = rows
+            .iter()
+            .filter_map(|(path, oid)| Some((path.clone(), oid.clone()?)))
+            .collect();
+        let reviewed = rows.into_iter().map(|(path, _)| path).collect();
 
         let mut stmt = conn.prepare(
-            "SELECT id, path, span_start, span_end, content 
+            "SELECT id, path, span_start, span_end, content, anchor_hash, col_start, col_end
              FROM comments WHERE before_ref = ?1 AND after_ref = ?2",
         )?;
         let comments: Vec<Comment> = stmt
             .query_map(params![&id.before, &id.after], |row| {
+                let columns = match (row.get(6)?, row.get(7)?) {
+                    (Some(start), Some(end)) => Some(ColumnRange { start, end }),
+                    _ => None,
+                };
                 Ok(Comment {
                     id: row.get(0)?,
                     path: row.get(1)?,
                     span: Span::new(row.get(2)?, row.get(3)?),
                     content: row.get(4)?,
+                    anchor_hash: row.get(5)?,
+                    columns,
                 })
             })?
             .collect::<std::result::Result<Vec<_>, _>>()?;
@@ -305,25 +681,82 @@ impl ReviewStore {
             })?
             .collect::<std::result::Result<Vec<_>, _>>()?;
 
+        let mut stmt = conn.prepare(
+            "SELECT path, note FROM file_notes WHERE before_ref = ?1 AND after_ref = ?2",
+        )?;
+        let notes: BTreeMap<String, String> = stmt
+            .query_map(params![&id.before, &id.after], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+
         Ok(Review {
             id: id.clone(),
             reviewed,
+            reviewed_oids,
             comments,
             edits,
+            notes,
         })
     }
 
-    /// Mark a file as reviewed.
-    pub fn mark_reviewed(&self, id: &DiffId, path: &str) -> Result<()> {
+    /// Mark a file as reviewed, recording the blob id of the content that was
+    /// reviewed if known. Marking it again updates the recorded id.
+    pub fn mark_reviewed(&self, id: &DiffId, path: &str, blob_oid: Option<&str>) -> Result<()> {
         self.get_or_create(id)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT OR IGNORE INTO reviewed_files (before_ref, after_ref, path) VALUES (?1, ?2, ?3)",
-            params![&id.before, &id.after, path],
+            "INSERT INTO reviewed_files (before_ref, after_ref, path, blob_oid) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (before_ref, after_ref, path) DO UPDATE SET blob_oid = excluded.blob_oid",
+            params![&id.before, &id.after, path, blob_oid],
         )?;
         Ok(())
     }
 
+    /// Reviewed files whose content has changed since they were marked:
+    /// those with a recorded blob id that differs from their id in
+    /// `current_oids`. Files without either id aren't reported. Sorted.
+    pub fn stale_reviewed(
+        &self,
+        id: &DiffId,
+        current_oids: &HashMap<String, String>,
+    ) -> Result<Vec<String>> {
+        let review = self.get(id)?;
+        Ok(review
+            .reviewed_oids
+            .into_iter()
+            .filter(|(path, oid)| current_oids.get(path).is_some_and(|current| current != oid))
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Save the frontend's view state for a file (which regions are
+    /// expanded, whether it's folded), replacing any saved before.
+    /// The JSON is stored as given.
+    pub fn set_view_state(&self, id: &DiffId, path: &str, state_json: &str) -> Result<()> {
+        self.get_or_create(id)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO view_state (before_ref, after_ref, path, state_json) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (before_ref, after_ref, path) DO UPDATE SET state_json = excluded.state_json",
+            params![&id.before, &id.after, path, state_json],
+        )?;
+        Ok(())
+    }
+
+    /// Get the view state saved for a file, if any.
+    pub fn get_view_state(&self, id: &DiffId, path: &str) -> Result<Option<String>> {
+        let conn = self.reader();
+        let state = conn
+            .query_row(
+                "SELECT state_json FROM view_state WHERE before_ref = ?1 AND after_ref = ?2 AND path = ?3",
+                params![&id.before, &id.after, path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(state)
+    }
+
     /// Unmark a file as reviewed.
     pub fn unmark_reviewed(&self, id: &DiffId, path: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
@@ -334,13 +767,62 @@ impl ReviewStore {
         Ok(())
     }
 
+    /// Set the note on a file, replacing any previous one. A blank note
+    /// removes it.
+    pub fn set_file_note(&self, id: &DiffId, path: &str, note: &str) -> Result<()> {
+        if note.trim().is_empty() {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "DELETE FROM file_notes WHERE before_ref = ?1 AND after_ref = ?2 AND path = ?3",
+                params![&id.before, &id.after, path],
+            )?;
+            return Ok(());
+        }
+        self.get_or_create(id)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO file_notes (before_ref, after_ref, path, note) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (before_ref, after_ref, path) DO UPDATE SET note = excluded.note",
+            params![&id.before, &id.after, path, note],
+        )?;
+        Ok(())
+    }
+
+    /// Get the note on a file, if it has one.
+    pub fn get_file_note(&self, id: &DiffId, path: &str) -> Result<Option<String>> {
+        let conn = self.reader();
+        let note = conn
+            .query_row(
+                "SELECT note FROM file_notes WHERE before_ref = ?1 AND after_ref = ?2 AND path = ?3",
+                params![&id.before, &id.after, path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(note)
+    }
+
+    /// Number of comments on each file that has any, without loading them.
+    pub fn comment_counts(&self, id: &DiffId) -> Result<HashMap<String, usize>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT path, COUNT(*) FROM comments WHERE before_ref = ?1 AND after_ref = ?2
+             GROUP BY path",
+        )?;
+        let counts = stmt
+            .query_map(params![&id.before, &id.after], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(counts)
+    }
+
     /// Add a comment.
     pub fn add_comment(&self, id: &DiffId, comment: &Comment) -> Result<()> {
         self.get_or_create(id)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO comments (id, before_ref, after_ref, path, span_start, span_end, content)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO comments (id, before_ref, after_ref, path, span_start, span_end, content, anchor_hash, col_start, col_end)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             params![
                 &comment.id,
                 &id.before,
@@ -348,7 +830,10 @@ impl ReviewStore {
                 &comment.path,
                 comment.span.start,
                 comment.span.end,
-                &comment.content
+                &comment.content,
+                &comment.anchor_hash,
+                comment.columns.map(|c| c.start),
+                comment.columns.map(|c| c.end)
             ],
         )?;
         Ok(())
@@ -371,6 +856,32 @@ impl ReviewStore {
         Ok(())
     }
 
+    /// Delete every comment on one file of a review. Returns how many were deleted.
+    pub fn delete_comments_for_path(&self, id: &DiffId, path: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(
+            "DELETE FROM comments WHERE before_ref = ?1 AND after_ref = ?2 AND path = ?3",
+            params![&id.before, &id.after, path],
+        )?;
+        Ok(deleted)
+    }
+
+    /// Delete several comments at once, all or none. Unknown IDs are skipped.
+    /// Returns how many were deleted.
+    pub fn delete_comments(&self, comment_ids: &[String]) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut deleted = 0;
+        {
+            let mut stmt = tx.prepare("DELETE FROM comments WHERE id = ?1")?;
+            for comment_id in comment_ids {
+                deleted += stmt.execute(params![comment_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
     /// Add an edit.
     pub fn add_edit(&self, id: &DiffId, edit: &Edit) -> Result<()> {
         self.get_or_create(id)?;
@@ -389,6 +900,173 @@ impl ReviewStore {
         Ok(())
     }
 
+    /// Remember the SHA a named ref resolved to in a repository.
+    pub fn remember_ref(&self, repo_path: &str, name: &str, sha: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO ref_shas (repo_path, name, sha) VALUES (?1, ?2, ?3)
+             ON CONFLICT (repo_path, name) DO UPDATE SET sha = excluded.sha
+             WHERE sha != excluded.sha",
+            params![repo_path, name, sha],
+        )?;
+        Ok(())
+    }
+
+    /// The SHA a named ref last resolved to, if it was ever remembered.
+    pub fn last_known_sha(&self, repo_path: &str, name: &str) -> Result<Option<String>> {
+        let conn = self.reader();
+        let sha = conn
+            .query_row(
+                "SELECT sha FROM ref_shas WHERE repo_path = ?1 AND name = ?2",
+                params![repo_path, name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(sha)
+    }
+
+    /// Get one raw setting (a JSON value) for a repository, if set.
+    pub fn get_setting(&self, repo_path: &str, key: &str) -> Result<Option<String>> {
+        let conn = self.reader();
+        let value = conn
+            .query_row(
+                "SELECT value FROM repo_settings WHERE repo_path = ?1 AND key = ?2",
+                params![repo_path, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    /// Set one raw setting (a JSON value) for a repository.
+    pub fn set_setting(&self, repo_path: &str, key: &str, value: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO repo_settings (repo_path, key, value) VALUES (?1, ?2, ?3)",
+            params![repo_path, key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Load a repository's settings, with defaults for anything unset.
+    /// Stored values that no longer parse are ignored with a warning.
+    pub fn repo_settings(&self, repo_path: &str) -> Result<RepoSettings> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare("SELECT key, value FROM repo_settings WHERE repo_path = ?1")?;
+        let rows = stmt
+            .query_map(params![repo_path], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // Overlay each stored field on the defaults, one at a time, so one
+        // bad value doesn't discard the rest
+        let mut settings = serde_json::to_value(RepoSettings::default())
+            .map_err(|e| ReviewError(format!("Cannot encode settings: {}", e)))?;
+        for (key, value) in rows {
+            let parsed = serde_json::from_str(&value);
+            let mut candidate = settings.clone();
+            if let (Ok(value), Some(fields)) = (parsed, candidate.as_object_mut()) {
+                fields.insert(key.clone(), value);
+                if serde_json::from_value::<RepoSettings>(candidate.clone()).is_ok() {
+                    settings = candidate;
+                    continue;
+                }
+            }
+            log::warn!("Ignoring invalid setting '{}' for {}", key, repo_path);
+        }
+        serde_json::from_value(settings)
+            .map_err(|e| ReviewError(format!("Cannot decode settings: {}", e)))
+    }
+
+    /// Save all of a repository's settings, one key per field.
+    pub fn save_repo_settings(&self, repo_path: &str, settings: &RepoSettings) -> Result<()> {
+        let value = serde_json::to_value(settings)
+            .map_err(|e| ReviewError(format!("Cannot encode settings: {}", e)))?;
+        let serde_json::Value::Object(fields) = value else {
+            return Err(ReviewError::new("Settings must encode as an object"));
+        };
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO repo_settings (repo_path, key, value) VALUES (?1, ?2, ?3)",
+            )?;
+            for (key, value) in fields {
+                stmt.execute(params![repo_path, key, value.to_string()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Move a review to a new DiffId, e.g. after the branch it was keyed by
+    /// was renamed or rebased. Comments, edits, and reviewed files all move,
+    /// in one transaction. If a review already exists under `new`, the old
+    /// one is merged into it.
+    pub fn rekey(&self, old: &DiffId, new: &DiffId) -> Result<()> {
+        if old == new {
+            return Ok(());
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let exists = tx
+            .query_row(
+                "SELECT 1 FROM reviews WHERE before_ref = ?1 AND after_ref = ?2",
+                params![&old.before, &old.after],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !exists {
+            return Err(ReviewError(format!(
+                "No review for {}..{}",
+                old.before, old.after
+            )));
+        }
+
+        let refs = params![&new.before, &new.after, &old.before, &old.after];
+        tx.execute(
+            "INSERT OR IGNORE INTO reviews (before_ref, after_ref) VALUES (?1, ?2)",
+            params![&new.before, &new.after],
+        )?;
+        tx.execute(
+            "UPDATE comments SET before_ref = ?1, after_ref = ?2
+             WHERE before_ref = ?3 AND after_ref = ?4",
+            refs,
+        )?;
+        tx.execute(
+            "UPDATE edits SET before_ref = ?1, after_ref = ?2
+             WHERE before_ref = ?3 AND after_ref = ?4",
+            refs,
+        )?;
+        // Copied rather than updated: the file may already be marked reviewed
+        // or have a note under `new`, and the old rows go with the old review below
+        tx.execute(
+            "INSERT OR IGNORE INTO reviewed_files (before_ref, after_ref, path, blob_oid)
+             SELECT ?1, ?2, path, blob_oid FROM reviewed_files WHERE before_ref = ?3 AND after_ref = ?4",
+            refs,
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO file_notes (before_ref, after_ref, path, note)
+             SELECT ?1, ?2, path, note FROM file_notes WHERE before_ref = ?3 AND after_ref = ?4",
+            refs,
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO view_state (before_ref, after_ref, path, state_json)
+             SELECT ?1, ?2, path, state_json FROM view_state WHERE before_ref = ?3 AND after_ref = ?4",
+            refs,
+        )?;
+        tx.execute(
+            "DELETE FROM reviews WHERE before_ref = ?1 AND after_ref = ?2",
+            params![&old.before, &old.after],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Delete an entire review and all associated data.
     pub fn delete(&self, id: &DiffId) -> Result<()> {
         let conn = self.conn.lock().unwrap();
@@ -399,60 +1077,307 @@ impl ReviewStore {
         )?;
         Ok(())
     }
+
+    /// Delete every review, with its comments, edits, notes, view state, and
+    /// reviewed files. Repo settings are kept.
+    pub fn clear_all(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch(
+            "BEGIN;
+             DELETE FROM comments;
+             DELETE FROM edits;
+             DELETE FROM reviewed_files;
+             DELETE FROM file_notes;
+             DELETE FROM view_state;
+             DELETE FROM ref_shas;
+             DELETE FROM reviews;
+             COMMIT;",
+        )?;
+        Ok(())
+    }
+
+    /// Delete reviews whose refs no longer exist, e.g. commits from deleted
+    /// or rebased branches. `ref_exists` is asked about each stored ref;
+    /// working-tree refs always exist. Returns how many reviews were deleted.
+    pub fn prune_missing(&self, ref_exists: impl Fn(&str) -> bool) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let ids: Vec<DiffId> = {
+            let mut stmt = tx.prepare("SELECT before_ref, after_ref FROM reviews")?;
+            let rows = stmt.query_map([], |row| {
+                Ok(DiffId::new(
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                ))
+            })?;
+            rows.collect::<std::result::Result<_, _>>()?
+        };
+
+        let mut pruned = 0;
+        for id in ids {
+            let after_exists = id.is_working_tree() || ref_exists(&id.after);
+            if ref_exists(&id.before) && after_exists {
+                continue;
+            }
+            // Foreign key cascades handle child tables
+            pruned += tx.execute(
+                "DELETE FROM reviews WHERE before_ref = ?1 AND after_ref = ?2",
+                params![&id.before, &id.after],
+            )?;
+        }
+        tx.commit()?;
+        Ok(pruned)
+    }
+
+    /// Rebuild the database file, returning the space freed by deleted
+    /// reviews to the filesystem.
+    pub fn vacuum(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+}
+
+/// Why the database at `db_path` is unusable, if it's corrupted. Errors that
+/// aren't corruption (locks, permissions) are left for the real open to report.
+fn corruption(db_path: &Path) -> Option<String> {
+    if !db_path.exists() {
+        return None;
+    }
+    let status = Connection::open(db_path).and_then(|conn| {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.query_row("PRAGMA integrity_check", [], |row| row.get::<_, String>(0))
+    });
+    match status {
+        Ok(status) if status == "ok" => None,
+        Ok(status) => Some(status),
+        Err(e) => matches!(
+            e.sqlite_error_code(),
+            Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+        )
+        .then(|| e.to_string()),
+    }
+}
+
+/// Move a corrupted database and its WAL files to `<path>.corrupt`,
+/// replacing any earlier backup. Returns the backup's path.
+fn quarantine(db_path: &Path) -> Result<PathBuf> {
+    let with_suffix = |path: &Path, suffix: &str| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let backup = with_suffix(db_path, ".corrupt");
+    std::fs::rename(db_path, &backup)
+        .map_err(|e| ReviewError(format!("Cannot move corrupted database aside: {}", e)))?;
+    for sidecar in ["-wal", "-shm"] {
+        let path = with_suffix(db_path, sidecar);
+        if path.exists() {
+            // Kept with the backup, so opening it sees the same data
+            let _ = std::fs::rename(&path, with_suffix(&backup, sidecar));
+        }
+    }
+    Ok(backup)
+}
+
+/// Open a read-only connection to an existing review database.
+fn open_reader(db_path: &Path) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
 }
 
 // =============================================================================
 // Export
 // =============================================================================
 
+/// Maps a file's old path to its new path, for files renamed in the diff
+/// (see `renamed_paths`).
+pub type PathAliases = HashMap<String, String>;
+
+/// Which comments a filtered export keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentScope {
+    #[default]
+    All,
+    /// File-level comments, which have an empty `0..0` span
+    Global,
+    /// Comments on a range of lines
+    Lines,
+}
+
+/// Narrows an export to part of a review. The default keeps everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportFilter {
+    /// Gitignore-style globs; if any are given, only comments and edits on
+    /// matching paths are kept
+    pub paths: Vec<String>,
+    /// Which comments to keep. Edits are filtered by path only.
+    pub comments: CommentScope,
+}
+
+impl ExportFilter {
+    /// Copy of `review` with only the comments, edits, and notes this filter keeps.
+    pub fn apply(&self, review: &Review) -> Result<Review> {
+        let matcher = if self.paths.is_empty() {
+            None
+        } else {
+            Some(build_glob_matcher(&self.paths).map_err(|e| ReviewError(e.0))?)
+        };
+        let path_matches = |path: &str| {
+            matcher.as_ref().map_or(true, |m| {
+                m.matched_path_or_any_parents(path, false).is_ignore()
+            })
+        };
+        let scope_matches = |comment: &Comment| {
+            let global = comment.span.start == 0 && comment.span.end == 0;
+            match self.comments {
+                CommentScope::All => true,
+                CommentScope::Global => global,
+                CommentScope::Lines => !global,
+            }
+        };
+
+        Ok(Review {
+            id: review.id.clone(),
+            reviewed: review.reviewed.clone(),
+            reviewed_oids: review.reviewed_oids.clone(),
+            comments: review
+                .comments
+                .iter()
+                .filter(|c| path_matches(&c.path) && scope_matches(c))
+                .cloned()
+                .collect(),
+            edits: review
+                .edits
+                .iter()
+                .filter(|e| path_matches(&e.path))
+                .cloned()
+                .collect(),
+            notes: review
+                .notes
+                .iter()
+                .filter(|(path, _)| path_matches(path))
+                .map(|(path, note)| (path.clone(), note.clone()))
+                .collect(),
+        })
+    }
+}
+
+/// Most lines of code quoted above a line comment in the markdown export.
+const MAX_QUOTED_LINES: usize = 3;
+
 /// Export a review as markdown for clipboard.
-pub fn export_markdown(review: &Review) -> String {
+///
+/// Each file's note comes first, under its heading. If `aliases` is given,
+/// comments and edits on a renamed file's old and new paths are merged under
+/// a single "old → new" heading. Line comments on
+/// files in `diffs` are preceded by the code they refer to.
+pub fn export_markdown(
+    review: &Review,
+    aliases: Option<&PathAliases>,
+    diffs: &[FileDiff],
+) -> String {
     let mut md = String::new();
 
+    // Resolve a path to the key it's grouped under (the new path for renames)
+    let group_key = |path: &str| -> String {
+        aliases
+            .and_then(|a| a.get(path))
+            .cloned()
+            .unwrap_or_else(|| path.to_string())
+    };
+
+    // Reverse lookup so headings can show where a renamed file came from
+    let renamed_from: HashMap<&str, &str> = aliases
+        .map(|a| {
+            a.iter()
+                .map(|(old, new)| (new.as_str(), old.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+
     // Group comments by file
-    let mut comments_by_file: std::collections::HashMap<&str, Vec<&Comment>> =
-        std::collections::HashMap::new();
+    let mut comments_by_file: HashMap<String, Vec<&Comment>> = HashMap::new();
     for comment in &review.comments {
         comments_by_file
-            .entry(&comment.path)
+            .entry(group_key(&comment.path))
             .or_default()
             .push(comment);
     }
 
     // Group edits by file
-    let mut edits_by_file: std::collections::HashMap<&str, Vec<&Edit>> =
-        std::collections::HashMap::new();
+    let mut edits_by_file: HashMap<String, Vec<&Edit>> = HashMap::new();
     for edit in &review.edits {
-        edits_by_file.entry(&edit.path).or_default().push(edit);
+        edits_by_file
+            .entry(group_key(&edit.path))
+            .or_default()
+            .push(edit);
+    }
+
+    // Notes on a renamed file's old and new paths are joined
+    let mut notes_by_file: HashMap<String, Vec<&str>> = HashMap::new();
+    for (path, note) in &review.notes {
+        notes_by_file.entry(group_key(path)).or_default().push(note);
     }
 
     // Collect all files
     let mut all_files: Vec<&str> = comments_by_file
         .keys()
         .chain(edits_by_file.keys())
-        .copied()
+        .chain(notes_by_file.keys())
+        .map(String::as_str)
         .collect();
     all_files.sort();
     all_files.dedup();
 
     for file in all_files {
-        md.push_str(&format!("## {}\n\n", file));
+        match renamed_from.get(file) {
+            Some(old) => md.push_str(&format!("## {} → {}\n\n", old, file)),
+            None => md.push_str(&format!("## {}\n\n", file)),
+        }
+
+        if let Some(notes) = notes_by_file.get(file) {
+            for note in notes {
+                md.push_str(note.trim_end());
+                md.push_str("\n\n");
+            }
+        }
 
         if let Some(comments) = comments_by_file.get(file) {
             for comment in comments {
-                let span = &comment.span;
-                let location = if span.end == span.start + 1 {
-                    format!("Line {}", span.start + 1)
-                } else {
-                    format!("Lines {}-{}", span.start + 1, span.end)
-                };
-                md.push_str(&format!("- **{}**: {}\n", location, comment.content));
+                let location = location(comment.span);
+                match quoted_code(comment, diffs) {
+                    Some((language, lines)) => {
+                        md.push_str(&format!(
+                            "- **{}**:\n  ```{}\n",
+                            location,
+                            language.unwrap_or("")
+                        ));
+                        for line in lines {
+                            md.push_str(&format!("  {}\n", line));
+                        }
+                        md.push_str(&format!("  ```\n  {}\n", comment.content));
+                    }
+                    None => md.push_str(&format!("- **{}**: {}\n", location, comment.content)),
+                }
             }
             md.push('\n');
         }
 
         if let Some(edits) = edits_by_file.get(file) {
             for edit in edits {
+                if let Some(block) = suggestion_block(&edit.diff) {
+                    md.push_str("**Suggested change:**\n");
+                    md.push_str(&block);
+                    md.push('\n');
+                    continue;
+                }
                 md.push_str("**Edit applied:**\n```diff\n");
                 md.push_str(&edit.diff);
                 if !edit.diff.ends_with('\n') {
@@ -470,6 +1395,176 @@ pub fn export_markdown(review: &Review) -> String {
     md
 }
 
+/// The code a line comment refers to, with its language: the commented
+/// lines (at most `MAX_QUOTED_LINES`), plus a line either side for a
+/// single-line comment. The file is looked up on the side the comment was
+/// made on, the new one unless the file was deleted.
+///
+/// None for file-level comments, binary or missing files, and comments on
+/// lines the file no longer has.
+fn quoted_code<'a>(
+    comment: &Comment,
+    diffs: &'a [FileDiff],
+) -> Option<(Option<&'a str>, &'a [String])> {
+    let file = diffs
+        .iter()
+        .find_map(|d| d.after.as_ref().filter(|f| f.path == comment.path))
+        .or_else(|| {
+            diffs
+                .iter()
+                .find_map(|d| d.before.as_ref().filter(|f| f.path == comment.path))
+        })?;
+    let FileContent::Text { lines } = &file.content else {
+        return None;
+    };
+
+    let start = comment.span.start as usize;
+    let end = (comment.span.end as usize).min(lines.len());
+    if start >= end {
+        return None;
+    }
+    let quoted = if end - start == 1 {
+        start.saturating_sub(1)..(end + 1).min(lines.len())
+    } else {
+        start..end.min(start + MAX_QUOTED_LINES)
+    };
+    Some((file.language.as_deref(), &lines[quoted]))
+}
+
+/// Format an edit as a GitHub suggestion block: the hunk's new lines (added
+/// and context), which replace the lines it covers when accepted.
+///
+/// Only a single contiguous hunk can be expressed this way; returns None for
+/// multi-hunk edits, or a diff that changes nothing.
+pub fn suggestion_block(diff: &str) -> Option<String> {
+    let mut hunks = 0;
+    let mut changed = false;
+    let mut new_lines = Vec::new();
+
+    for line in diff.lines() {
+        if line.starts_with("@@") {
+            hunks += 1;
+            if hunks > 1 {
+                return None;
+            }
+        } else if line.starts_with("diff --git ")
+            || line.starts_with("index ")
+            || line.starts_with("--- ")
+            || line.starts_with("+++ ")
+            || line.starts_with('\\')
+        {
+            // File headers and "\ No newline at end of file"
+        } else if let Some(added) = line.strip_prefix('+') {
+            changed = true;
+            new_lines.push(added);
+        } else if line.starts_with('-') {
+            changed = true;
+        } else {
+            new_lines.push(line.strip_prefix(' ').unwrap_or(line));
+        }
+    }
+
+    if !changed {
+        return None;
+    }
+    let mut block = String::from("```suggestion\n");
+    for line in new_lines {
+        block.push_str(line);
+        block.push('\n');
+    }
+    block.push_str("```\n");
+    Some(block)
+}
+
+/// Describe where a comment sits: "Line 3", "Lines 3-5", or "File" for a
+/// file-level comment (the empty `0..0` span).
+fn location(span: Span) -> String {
+    if span.start == 0 && span.end == 0 {
+        "File".to_string()
+    } else if span.end == span.start + 1 {
+        format!("Line {}", span.start + 1)
+    } else {
+        format!("Lines {}-{}", span.start + 1, span.end)
+    }
+}
+
+/// Draft a PR description from a review: the size of the change, then the
+/// review's comments as concerns and the files with suggested edits.
+pub fn summary_markdown(review: &Review, summary: &DiffSummary) -> String {
+    let mut md = String::from("## Summary\n\n");
+    md.push_str(&format!(
+        "{} file{} changed, +{} -{}\n",
+        summary.files,
+        if summary.files == 1 { "" } else { "s" },
+        summary.additions,
+        summary.deletions
+    ));
+
+    if !review.comments.is_empty() {
+        let mut comments: Vec<&Comment> = review.comments.iter().collect();
+        comments.sort_by_key(|c| (&c.path, c.span.start));
+
+        md.push_str("\n## Review notes\n\n");
+        for comment in comments {
+            // Keep each note to one line; the full text is in the review
+            let first_line = comment.content.lines().next().unwrap_or("");
+            md.push_str(&format!(
+                "- `{}` ({}): {}\n",
+                comment.path,
+                location(comment.span),
+                first_line
+            ));
+        }
+    }
+
+    if !review.edits.is_empty() {
+        let mut paths: Vec<&str> = review.edits.iter().map(|e| e.path.as_str()).collect();
+        paths.sort();
+        paths.dedup();
+
+        md.push_str("\n## Suggested edits\n\n");
+        for path in paths {
+            md.push_str(&format!("- `{}`\n", path));
+        }
+    }
+
+    md
+}
+
+/// One line of a JSON-lines review export.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonlRecord<'a> {
+    /// First line: which diff this is, and the files marked reviewed
+    Review {
+        id: &'a DiffId,
+        reviewed: &'a [String],
+    },
+    Comment(&'a Comment),
+    Edit(&'a Edit),
+}
+
+/// Stream a review as JSON lines: a `review` header, then one object per
+/// comment and per edit, each tagged with its `type`. Large reviews are
+/// written record by record rather than built up as one string.
+pub fn export_jsonl_to_writer(review: &Review, mut writer: impl Write) -> Result<()> {
+    let header = JsonlRecord::Review {
+        id: &review.id,
+        reviewed: &review.reviewed,
+    };
+    let records = std::iter::once(header)
+        .chain(review.comments.iter().map(JsonlRecord::Comment))
+        .chain(review.edits.iter().map(JsonlRecord::Edit));
+
+    let io_error = |e: std::io::Error| ReviewError(format!("Cannot write export: {}", e));
+    for record in records {
+        serde_json::to_writer(&mut writer, &record)
+            .map_err(|e| ReviewError(format!("Cannot write export: {}", e)))?;
+        writer.write_all(b"\n").map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
 // =============================================================================
 // Tests
 // =============================================================================
@@ -477,6 +1572,7 @@ pub fn export_markdown(review: &Review) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diff::git::effective_diff_options;
     use tempfile::tempdir;
 
     #[test]
@@ -486,7 +1582,7 @@ mod tests {
         let store = ReviewStore::open(db_path).unwrap();
         let id = DiffId::new("main", "feature");
 
-        store.mark_reviewed(&id, "src/main.rs").unwrap();
+        store.mark_reviewed(&id, "src/main.rs", None).unwrap();
         let review = store.get(&id).unwrap();
         assert_eq!(review.reviewed, vec!["src/main.rs"]);
 
@@ -495,6 +1591,86 @@ mod tests {
         assert!(review.reviewed.is_empty());
     }
 
+    #[test]
+    fn test_view_state() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+        assert_eq!(store.get_view_state(&id, "src/lib.rs").unwrap(), None);
+
+        store
+            .set_view_state(&id, "src/lib.rs", r#"{"expanded":[3]}"#)
+            .unwrap();
+        store
+            .set_view_state(&id, "src/lib.rs", r#"{"expanded":[3,7],"folded":false}"#)
+            .unwrap();
+        store
+            .set_view_state(&id, "README.md", r#"{"folded":true}"#)
+            .unwrap();
+        assert_eq!(
+            store.get_view_state(&id, "src/lib.rs").unwrap().as_deref(),
+            Some(r#"{"expanded":[3,7],"folded":false}"#)
+        );
+        let other = DiffId::new("main", "other");
+        assert_eq!(store.get_view_state(&other, "src/lib.rs").unwrap(), None);
+
+        // Deleting the review takes its view state with it
+        store.delete(&id).unwrap();
+        assert_eq!(store.get_view_state(&id, "src/lib.rs").unwrap(), None);
+        assert_eq!(store.get_view_state(&id, "README.md").unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_notes() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+
+        store
+            .set_file_note(&id, "src/lib.rs", "First pass")
+            .unwrap();
+        store
+            .set_file_note(&id, "src/lib.rs", "Needs a second look")
+            .unwrap();
+        assert_eq!(
+            store.get_file_note(&id, "src/lib.rs").unwrap().as_deref(),
+            Some("Needs a second look")
+        );
+        assert_eq!(store.get_file_note(&id, "src/other.rs").unwrap(), None);
+
+        store
+            .add_comment(&id, &Comment::new("src/lib.rs", Span::new(0, 1), "Typo"))
+            .unwrap();
+        let md = export_markdown(&store.get(&id).unwrap(), None, &[]);
+        assert!(md.contains("## src/lib.rs\n\nNeeds a second look\n\n- **Line 1**: Typo"));
+
+        store.set_file_note(&id, "src/lib.rs", "  ").unwrap();
+        assert_eq!(store.get_file_note(&id, "src/lib.rs").unwrap(), None);
+        assert!(store.get(&id).unwrap().notes.is_empty());
+    }
+
+    #[test]
+    fn test_stale_reviewed() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "WORKDIR");
+
+        store.mark_reviewed(&id, "a.rs", Some("aaa1")).unwrap();
+        store.mark_reviewed(&id, "b.rs", Some("bbb1")).unwrap();
+        store.mark_reviewed(&id, "untracked.rs", None).unwrap();
+        let current: HashMap<String, String> =
+            [("a.rs", "aaa1"), ("b.rs", "bbb2"), ("untracked.rs", "ccc1")]
+                .into_iter()
+                .map(|(path, oid)| (path.to_string(), oid.to_string()))
+                .collect();
+        assert_eq!(store.stale_reviewed(&id, &current).unwrap(), ["b.rs"]);
+
+        // Re-marking records the new content
+        store.mark_reviewed(&id, "b.rs", Some("bbb2")).unwrap();
+        assert!(store.stale_reviewed(&id, &current).unwrap().is_empty());
+        assert_eq!(store.get(&id).unwrap().reviewed.len(), 3);
+    }
+
     #[test]
     fn test_comments() {
         let dir = tempdir().unwrap();
@@ -523,6 +1699,43 @@ mod tests {
         assert!(review.comments.is_empty());
     }
 
+    #[test]
+    fn test_delete_comments_in_bulk() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+        let other = DiffId::new("main", "other");
+
+        let on_a: Vec<Comment> = (0..3)
+            .map(|i| Comment::new("a.rs", Span::new(i, i + 1), "a"))
+            .collect();
+        let on_b = Comment::new("b.rs", Span::new(0, 1), "b");
+        let elsewhere = Comment::new("a.rs", Span::new(0, 1), "other review");
+        for comment in on_a.iter().chain([&on_b]) {
+            store.add_comment(&id, comment).unwrap();
+        }
+        store.add_comment(&other, &elsewhere).unwrap();
+
+        // By path: only that file, only that review
+        assert_eq!(store.delete_comments_for_path(&id, "a.rs").unwrap(), 3);
+        let review = store.get(&id).unwrap();
+        assert_eq!(review.comments.len(), 1);
+        assert_eq!(review.comments[0].id, on_b.id);
+        assert_eq!(store.get(&other).unwrap().comments.len(), 1);
+
+        // By ID: unknown and already-deleted IDs don't count
+        let ids = vec![
+            on_b.id.clone(),
+            elsewhere.id.clone(),
+            on_a[0].id.clone(),
+            "missing".to_string(),
+        ];
+        assert_eq!(store.delete_comments(&ids).unwrap(), 2);
+        assert!(store.get(&id).unwrap().comments.is_empty());
+        assert!(store.get(&other).unwrap().comments.is_empty());
+        assert_eq!(store.delete_comments(&[]).unwrap(), 0);
+    }
+
     #[test]
     fn test_edits() {
         let dir = tempdir().unwrap();
@@ -549,7 +1762,7 @@ mod tests {
         let store = ReviewStore::open(db_path).unwrap();
         let id = DiffId::new("main", "feature");
 
-        store.mark_reviewed(&id, "src/main.rs").unwrap();
+        store.mark_reviewed(&id, "src/main.rs", None).unwrap();
         // Range comment spanning lines 0-10
         store
             .add_comment(&id, &Comment::new("src/main.rs", Span::new(0, 10), "test"))
@@ -561,6 +1774,211 @@ mod tests {
         assert!(review.comments.is_empty());
     }
 
+    #[test]
+    fn test_repo_settings() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+
+        // Unset: all defaults
+        assert_eq!(
+            store.repo_settings("/repo").unwrap(),
+            RepoSettings::default()
+        );
+        assert_eq!(store.get_setting("/repo", "exclude").unwrap(), None);
+
+        let settings = RepoSettings {
+            exclude: vec!["*.lock".into()],
+            collapse_files: vec!["*.snap".into()],
+            algorithm: DiffAlgorithm::Patience,
+            collapse_unchanged: Some(20),
+            max_file_size: Some(1 << 20),
+            editor: Some("zed {path}:{line}".into()),
+        };
+        store.save_repo_settings("/repo", &settings).unwrap();
+        assert_eq!(store.repo_settings("/repo").unwrap(), settings);
+        assert_eq!(
+            store.get_setting("/repo", "algorithm").unwrap().as_deref(),
+            Some("\"patience\"")
+        );
+        // Settings are per repository
+        assert_eq!(
+            store.repo_settings("/other").unwrap(),
+            RepoSettings::default()
+        );
+
+        // A bad value falls back to its default without losing the others
+        store
+            .set_setting("/repo", "algorithm", "\"histogram\"")
+            .unwrap();
+        let loaded = store.repo_settings("/repo").unwrap();
+        assert_eq!(loaded.algorithm, DiffAlgorithm::Myers);
+        assert_eq!(loaded.exclude, ["*.lock"]);
+
+        let config = loaded.diff_config();
+        assert_eq!(config.exclude, ["*.lock"]);
+        assert_eq!(config.collapse_files, ["*.snap"]);
+        assert_eq!(config.collapse_unchanged, Some(20));
+        assert_eq!(config.max_file_size, Some(1 << 20));
+    }
+
+    #[test]
+    fn test_clear_all() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+        store.mark_reviewed(&id, "src/a.rs", None).unwrap();
+        store
+            .add_comment(&id, &Comment::new("src/a.rs", Span::new(1, 2), "Hm"))
+            .unwrap();
+        store
+            .add_edit(&id, &Edit::new("src/a.rs", "-x\n+y"))
+            .unwrap();
+        store.set_file_note(&id, "src/a.rs", "Later").unwrap();
+        store.set_view_state(&id, "src/a.rs", "{}").unwrap();
+        store.set_setting("/repo", "exclude", "[]").unwrap();
+
+        store.clear_all().unwrap();
+        store.vacuum().unwrap();
+
+        let conn = store.conn.lock().unwrap();
+        for table in [
+            "reviews",
+            "reviewed_files",
+            "comments",
+            "edits",
+            "file_notes",
+            "view_state",
+        ] {
+            let rows: i64 = conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(rows, 0, "{} is not empty", table);
+        }
+        drop(conn);
+        assert!(store.get_setting("/repo", "exclude").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_prune_missing() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let kept = DiffId::new("aaa", "bbb");
+        let working_tree = DiffId::new("aaa", "WORKDIR");
+        let gone_before = DiffId::new("deleted", "bbb");
+        let gone_after = DiffId::new("aaa", "deleted");
+        for id in [&kept, &working_tree, &gone_before, &gone_after] {
+            store
+                .add_comment(id, &Comment::new("f.txt", Span::new(0, 1), "Hm"))
+                .unwrap();
+        }
+
+        let pruned = store.prune_missing(|r| r == "aaa" || r == "bbb").unwrap();
+        assert_eq!(pruned, 2);
+
+        let conn = store.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT after_ref FROM reviews ORDER BY after_ref")
+            .unwrap();
+        let left: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(left, ["WORKDIR", "bbb"]);
+        let comments: i64 = conn
+            .query_row("SELECT COUNT(*) FROM comments", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(comments, 2);
+    }
+
+    #[test]
+    fn test_effective_diff_options() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let repo = git2::Repository::init(dir.path().join("repo")).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("core.autocrlf", "input")
+            .unwrap();
+        let key = "/repo";
+        store.set_setting(key, "algorithm", "\"patience\"").unwrap();
+
+        let settings = store.repo_settings(key).unwrap();
+        let options = effective_diff_options(&repo, settings.diff_config(), settings.overrides());
+        assert_eq!(options.config.algorithm, DiffAlgorithm::Patience);
+        assert_eq!(options.overrides, ["algorithm"]);
+        assert_eq!(options.autocrlf.as_deref(), Some("input"));
+        assert!(!options.ignore_whitespace);
+
+        // Nothing overridden: plain defaults
+        let settings = store.repo_settings("/other").unwrap();
+        let options = effective_diff_options(&repo, settings.diff_config(), settings.overrides());
+        assert_eq!(options.config.algorithm, DiffAlgorithm::Myers);
+        assert!(options.overrides.is_empty());
+
+        // Saving writes every field, but only changed ones count as overrides
+        let saved = RepoSettings {
+            max_file_size: Some(1 << 20),
+            ..Default::default()
+        };
+        store.save_repo_settings("/other", &saved).unwrap();
+        let settings = store.repo_settings("/other").unwrap();
+        assert_eq!(settings.overrides(), ["max_file_size"]);
+        store
+            .save_repo_settings("/other", &RepoSettings::default())
+            .unwrap();
+        let settings = store.repo_settings("/other").unwrap();
+        assert!(settings.overrides().is_empty());
+    }
+
+    #[test]
+    fn test_rekey() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let old = DiffId::new("main", "feature");
+        let new = DiffId::new("main", "feature-renamed");
+
+        store.mark_reviewed(&old, "src/a.rs", None).unwrap();
+        store.mark_reviewed(&old, "src/b.rs", None).unwrap();
+        store
+            .add_comment(&old, &Comment::new("src/a.rs", Span::new(1, 2), "Hm"))
+            .unwrap();
+        store
+            .add_edit(&old, &Edit::new("src/a.rs", "-x\n+y"))
+            .unwrap();
+        // The target already has a review, sharing one reviewed file
+        store.mark_reviewed(&new, "src/b.rs", None).unwrap();
+
+        store.rekey(&old, &new).unwrap();
+
+        let moved = store.get(&new).unwrap();
+        let mut reviewed = moved.reviewed.clone();
+        reviewed.sort();
+        assert_eq!(reviewed, ["src/a.rs", "src/b.rs"]);
+        assert_eq!(moved.comments.len(), 1);
+        assert_eq!(moved.edits.len(), 1);
+
+        let conn = store.conn.lock().unwrap();
+        for table in ["reviews", "reviewed_files", "comments", "edits"] {
+            let orphans: i64 = conn
+                .query_row(
+                    &format!(
+                        "SELECT COUNT(*) FROM {} WHERE before_ref = ?1 AND after_ref = ?2",
+                        table
+                    ),
+                    params![&old.before, &old.after],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(orphans, 0, "{} still has rows for the old key", table);
+        }
+        drop(conn);
+
+        assert!(store.rekey(&old, &new).is_err());
+    }
+
     #[test]
     fn test_export_markdown() {
         let id = DiffId::new("main", "feature");
@@ -572,6 +1990,8 @@ mod tests {
             path: "src/lib.rs".into(),
             span: Span::new(10, 11),
             content: "Fix this".into(),
+            anchor_hash: None,
+            columns: None,
         });
 
         review.edits.push(Edit {
@@ -580,10 +2000,488 @@ mod tests {
             diff: "-old\n+new".into(),
         });
 
-        let md = export_markdown(&review);
+        let md = export_markdown(&review, None, &[]);
         assert!(md.contains("## src/lib.rs"));
         assert!(md.contains("Line 11")); // 0-indexed to 1-indexed
         assert!(md.contains("Fix this"));
-        assert!(md.contains("-old"));
+        assert!(md.contains("**Suggested change:**\n```suggestion\nnew\n```"));
+    }
+
+    #[test]
+    fn test_export_markdown_quotes_code() {
+        let source: String = (1..=20).map(|n| format!("let x{} = {};\n", n, n)).collect();
+        let diffs = vec![FileDiff {
+            before: None,
+            after: Some(crate::diff::types::File::from_bytes(
+                "src/lib.rs",
+                source.as_bytes(),
+            )),
+            ..Default::default()
+        }];
+        let mut review = Review::new(DiffId::new("main", "feature"));
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(10, 11), "Fix this"));
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(2, 8), "Long range"));
+        // Past the end of the file, e.g. made before lines were removed
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(40, 41), "Stale"));
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(0, 0), "Whole file"));
+
+        let md = export_markdown(&review, None, &diffs);
+        assert!(md.contains(
+            "- **Line 11**:\n  ```rust\n  let x10 = 10;\n  let x11 = 11;\n  let x12 = 12;\n  ```\n  Fix this\n"
+        ));
+        assert!(md.contains(
+            "- **Lines 3-8**:\n  ```rust\n  let x3 = 3;\n  let x4 = 4;\n  let x5 = 5;\n  ```\n  Long range\n"
+        ));
+        assert!(md.contains("- **Line 41**: Stale\n"));
+        assert!(md.contains("- **File**: Whole file\n"));
+    }
+
+    #[test]
+    fn test_suggestion_block() {
+        let one_hunk = "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -3,3 +3,3 @@\n fn a() {\n-    old();\n+    new();\n }\n";
+        assert_eq!(
+            suggestion_block(one_hunk).unwrap(),
+            "```suggestion\nfn a() {\n    new();\n}\n```\n"
+        );
+        // A pure deletion suggests the remaining lines, here none
+        assert_eq!(
+            suggestion_block("@@ -1 +0,0 @@\n-gone\n").unwrap(),
+            "```suggestion\n```\n"
+        );
+
+        let two_hunks = "@@ -1 +1 @@\n-a\n+b\n@@ -9 +9 @@\n-c\n+d\n";
+        assert_eq!(suggestion_block(two_hunks), None);
+        assert_eq!(suggestion_block("@@ -1 +1 @@\n same\n"), None);
+
+        // Multi-hunk edits keep the plain diff in the markdown export
+        let mut review = Review::new(DiffId::new("main", "feature"));
+        review.edits.push(Edit::new("src/lib.rs", two_hunks));
+        let md = export_markdown(&review, None, &[]);
+        assert!(md.contains("**Edit applied:**\n```diff\n@@ -1 +1 @@"));
+        assert!(!md.contains("```suggestion"));
+    }
+
+    #[test]
+    fn test_summary_markdown() {
+        let mut review = Review::new(DiffId::new("main", "feature"));
+        review.comments.push(Comment::new(
+            "src/main.rs",
+            Span::new(4, 7),
+            "Handle the error\nrather than unwrap",
+        ));
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(0, 0), "Needs docs"));
+        review.edits.push(Edit::new("src/lib.rs", "-a\n+b"));
+        review.edits.push(Edit::new("src/lib.rs", "-c\n+d"));
+        let summary = DiffSummary {
+            files: 2,
+            additions: 10,
+            deletions: 3,
+        };
+
+        let md = summary_markdown(&review, &summary);
+        assert!(md.starts_with("## Summary\n\n2 files changed, +10 -3\n"));
+        let lib = md.find("- `src/lib.rs` (File): Needs docs").unwrap();
+        let main = md
+            .find("- `src/main.rs` (Lines 5-7): Handle the error\n")
+            .unwrap();
+        assert!(lib < main);
+        assert!(!md.contains("rather than unwrap"));
+        assert_eq!(
+            md.matches("## Suggested edits\n\n- `src/lib.rs`\n").count(),
+            1
+        );
+
+        let empty = summary_markdown(&Review::new(DiffId::new("a", "b")), &summary);
+        assert!(!empty.contains("Review notes"));
+        assert!(!empty.contains("Suggested edits"));
+    }
+
+    #[test]
+    fn test_export_filter() {
+        let mut review = Review::new(DiffId::new("main", "feature"));
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(0, 0), "Overall fine"));
+        review
+            .comments
+            .push(Comment::new("src/lib.rs", Span::new(3, 5), "Rename this"));
+        review
+            .comments
+            .push(Comment::new("docs/guide.md", Span::new(0, 0), "Typos"));
+        review.edits.push(Edit::new("docs/guide.md", "-teh\n+the"));
+
+        let all = ExportFilter::default().apply(&review).unwrap();
+        assert_eq!(all.comments.len(), 3);
+        assert_eq!(all.edits.len(), 1);
+
+        let src_only = ExportFilter {
+            paths: vec!["src/".into()],
+            ..Default::default()
+        }
+        .apply(&review)
+        .unwrap();
+        assert_eq!(src_only.comments.len(), 2);
+        assert!(src_only.edits.is_empty());
+        let md = export_markdown(&src_only, None, &[]);
+        assert!(md.contains("## src/lib.rs"));
+        assert!(!md.contains("guide.md"));
+
+        let global = ExportFilter {
+            comments: CommentScope::Global,
+            ..Default::default()
+        }
+        .apply(&review)
+        .unwrap();
+        let contents: Vec<&str> = global.comments.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(contents, ["Overall fine", "Typos"]);
+
+        let md_globs = ExportFilter {
+            paths: vec!["*.md".into()],
+            comments: CommentScope::Lines,
+        }
+        .apply(&review)
+        .unwrap();
+        assert!(md_globs.comments.is_empty());
+        assert_eq!(md_globs.edits.len(), 1);
+    }
+
+    #[test]
+    fn test_export_jsonl() {
+        let mut review = Review::new(DiffId::new("main", "feature"));
+        review.reviewed.push("src/done.rs".into());
+        review.comments.push(Comment::new(
+            "src/lib.rs",
+            Span::new(3, 5),
+            "Multi\nline \"quoted\"",
+        ));
+        review
+            .comments
+            .push(Comment::new("src/main.rs", Span::new(0, 1), "Nit"));
+        review.edits.push(Edit::new("src/lib.rs", "-old\n+new"));
+
+        let mut out = Vec::new();
+        export_jsonl_to_writer(&review, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        let records: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["type"], "review");
+        assert_eq!(records[0]["id"]["after"], "feature");
+        assert_eq!(records[0]["reviewed"][0], "src/done.rs");
+        assert_eq!(records[1]["type"], "comment");
+        assert_eq!(records[1]["content"], "Multi\nline \"quoted\"");
+        assert_eq!(records[1]["span"]["start"], 3);
+        assert_eq!(records[2]["path"], "src/main.rs");
+        assert_eq!(records[3]["type"], "edit");
+        assert_eq!(records[3]["diff"], "-old\n+new");
+    }
+
+    #[test]
+    fn test_export_markdown_merges_renamed_paths() {
+        let id = DiffId::new("main", "feature");
+        let mut review = Review::new(id);
+
+        // One comment anchored on each side of the rename
+        review.comments.push(Comment {
+            id: "c1".into(),
+            path: "src/old.rs".into(),
+            span: Span::new(0, 1),
+            content: "Removed too much".into(),
+            anchor_hash: None,
+            columns: None,
+        });
+        review.comments.push(Comment {
+            id: "c2".into(),
+            path: "src/new.rs".into(),
+            span: Span::new(4, 5),
+            content: "Nice rename".into(),
+            anchor_hash: None,
+            columns: None,
+        });
+
+        let mut aliases = PathAliases::new();
+        aliases.insert("src/old.rs".into(), "src/new.rs".into());
+
+        let md = export_markdown(&review, Some(&aliases), &[]);
+        assert!(md.contains("## src/old.rs → src/new.rs"));
+        assert_eq!(md.matches("## ").count(), 1);
+        assert!(md.contains("Removed too much"));
+        assert!(md.contains("Nice rename"));
+
+        // Without aliases the two paths stay in separate sections
+        let md = export_markdown(&review, None, &[]);
+        assert_eq!(md.matches("## ").count(), 2);
+    }
+
+    #[test]
+    fn test_init_store_at() {
+        // The global store is per-process, so this is the only test that touches it
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.db");
+        let second = dir.path().join("second.db");
+
+        init_store_at(first.clone()).unwrap();
+        init_store_at(first).unwrap();
+        assert!(init_store_at(second.clone()).is_err());
+
+        // Stores opened directly at other paths are independent of it
+        let id = DiffId::new("main", "feature");
+        get_store()
+            .unwrap()
+            .mark_reviewed(&id, "global.rs", None)
+            .unwrap();
+        let other = ReviewStore::open(second).unwrap();
+        other.mark_reviewed(&id, "other.rs", None).unwrap();
+        assert_eq!(
+            get_store().unwrap().get(&id).unwrap().reviewed,
+            ["global.rs"]
+        );
+        assert_eq!(other.get(&id).unwrap().reviewed, ["other.rs"]);
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_relocate_comment() {
+        let before = lines("a\nb\ntarget\nnext\nc");
+        let comment = Comment::new("f.rs", Span::new(2, 4), "Look here").anchored_to(&before[2..4]);
+
+        // Unchanged
+        assert_eq!(relocate_comment(&comment, &before), Some(2));
+        // Lines inserted above: moves down
+        assert_eq!(
+            relocate_comment(&comment, &lines("x\ny\na\nb\ntarget\nnext\nc")),
+            Some(4)
+        );
+        // Lines removed above, and re-indented: moves up
+        assert_eq!(
+            relocate_comment(&comment, &lines("    target\n    next\nc")),
+            Some(0)
+        );
+        // Nearest match wins when the content appears twice
+        assert_eq!(
+            relocate_comment(&comment, &lines("target\nnext\na\nb\nc\ntarget\nnext")),
+            Some(0)
+        );
+        // Anchored content deleted
+        assert_eq!(relocate_comment(&comment, &lines("a\nb\nnext\nc")), None);
+        assert_eq!(relocate_comment(&comment, &[]), None);
+
+        // No anchor: stays on the stored line
+        let plain = Comment::new("f.rs", Span::new(2, 3), "Plain");
+        assert_eq!(relocate_comment(&plain, &lines("x")), Some(2));
+    }
+
+    #[test]
+    fn test_anchor_hash_stored() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+
+        let comment = Comment::new("src/lib.rs", Span::new(0, 1), "Anchored")
+            .anchored_to(&lines("fn main() {}"));
+        store.add_comment(&id, &comment).unwrap();
+
+        let review = store.get(&id).unwrap();
+        assert_eq!(review.comments[0].anchor_hash, comment.anchor_hash);
+        assert_eq!(
+            review.comments[0].anchor_hash.as_deref(),
+            Some(anchor_hash(&lines("fn main() {}")).as_str())
+        );
+    }
+
+    #[test]
+    fn test_recovers_corrupted_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let garbage = b"not a sqlite database\n".repeat(200);
+        std::fs::write(&db_path, &garbage).unwrap();
+
+        let store = ReviewStore::open(db_path.clone()).unwrap();
+        let id = DiffId::new("main", "feature");
+        assert!(store.get_or_create(&id).unwrap().reviewed.is_empty());
+        store.mark_reviewed(&id, "src/main.rs", None).unwrap();
+        assert_eq!(store.get(&id).unwrap().reviewed, vec!["src/main.rs"]);
+
+        let backup = dir.path().join("test.db.corrupt");
+        assert_eq!(std::fs::read(&backup).unwrap(), garbage);
+
+        // A healthy database is opened as is
+        drop(store);
+        std::fs::remove_file(&backup).unwrap();
+        ReviewStore::open(db_path).unwrap();
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn test_comment_counts() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+        assert!(store.comment_counts(&id).unwrap().is_empty());
+
+        for (path, count) in [("src/a.rs", 3), ("src/b.rs", 1), ("README.md", 2)] {
+            for i in 0..count {
+                store
+                    .add_comment(&id, &Comment::new(path, Span::new(i, i + 1), "Hm"))
+                    .unwrap();
+            }
+        }
+        // Comments on other diffs aren't counted
+        let other = DiffId::new("main", "other");
+        store
+            .add_comment(&other, &Comment::new("src/a.rs", Span::new(0, 1), "Hm"))
+            .unwrap();
+
+        let counts = store.comment_counts(&id).unwrap();
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("src/a.rs".to_string(), 3),
+                ("src/b.rs".to_string(), 1),
+                ("README.md".to_string(), 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_wal_checkpoint() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+
+        let mode: String = store
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        let id = DiffId::new("main", "feature");
+        store.mark_reviewed(&id, "src/main.rs", None).unwrap();
+        store.checkpoint().unwrap();
+
+        // Checkpointed data is visible to a fresh connection
+        let conn = Connection::open(dir.path().join("test.db")).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM reviewed_files", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_concurrent_reads_and_writes() {
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let shared = DiffId::new("main", "feature");
+        let threads = 8;
+        let per_thread = 25;
+
+        std::thread::scope(|scope| {
+            for t in 0..threads {
+                let (store, shared) = (&store, &shared);
+                scope.spawn(move || {
+                    let own = DiffId::new("main", format!("thread-{}", t));
+                    for i in 0..per_thread {
+                        let comment = Comment::new("f.txt", Span::new(i, i + 1), "Hm");
+                        store.add_comment(shared, &comment).unwrap();
+                        store
+                            .mark_reviewed(&own, &format!("file-{}", i), None)
+                            .unwrap();
+                        // Reads see a consistent, monotonically growing review
+                        let review = store.get(&own).unwrap();
+                        assert_eq!(review.reviewed.len(), i as usize + 1);
+                        store.get(shared).unwrap();
+                    }
+                });
+            }
+        });
+
+        let review = store.get(&shared).unwrap();
+        assert_eq!(review.comments.len(), (threads * per_thread) as usize);
+        for t in 0..threads {
+            let own = DiffId::new("main", format!("thread-{}", t));
+            assert_eq!(store.get(&own).unwrap().reviewed.len(), per_thread as usize);
+        }
+    }
+
+    #[test]
+    fn test_validate_span() {
+        assert!(validate_span(Span::new(0, 1), 10).is_ok());
+        assert!(validate_span(Span::new(9, 10), 10).is_ok());
+
+        let err = validate_span(Span::new(10, 11), 10).unwrap_err();
+        assert!(err.0.contains("past the end"), "{}", err);
+        assert!(validate_span(Span::new(5, 3), 10).is_err());
+        assert!(validate_span(Span::new(0, 1), 0).is_err());
+    }
+
+    #[test]
+    fn test_grapheme_columns() {
+        // A family emoji is five code points (18 bytes), and "e" with a
+        // combining accent is two (3 bytes), but each is one character
+        let line = "hi \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} e\u{301}!";
+        assert_eq!(line.len(), 26);
+        assert_eq!(grapheme_len(line), 7);
+        assert_eq!(grapheme_column(line, 3), Some(3));
+        assert_eq!(grapheme_column(line, 21), Some(4));
+        assert_eq!(grapheme_column(line, 26), Some(7));
+        assert_eq!(grapheme_column(line, 7), None);
+        assert_eq!(grapheme_column(line, 23), None);
+        assert_eq!(grapheme_column(line, 27), None);
+
+        let lines = vec![line.to_string(), "abc".to_string()];
+        let cols = |start, end| ColumnRange { start, end };
+        assert!(validate_columns(Span::new(0, 1), cols(3, 21), &lines).is_ok());
+        assert!(validate_columns(Span::new(0, 1), cols(22, 25), &lines).is_ok());
+        let err = validate_columns(Span::new(0, 1), cols(3, 7), &lines).unwrap_err();
+        assert!(err.0.contains("inside a character"), "{}", err);
+        assert!(validate_columns(Span::new(0, 1), cols(22, 23), &lines).is_err());
+        let err = validate_columns(Span::new(0, 1), cols(0, 40), &lines).unwrap_err();
+        assert!(err.0.contains("past the end"), "{}", err);
+        assert!(validate_columns(Span::new(0, 1), cols(21, 3), &lines).is_err());
+        // Across lines the end may come before the start's column
+        assert!(validate_columns(Span::new(0, 2), cols(21, 1), &lines).is_ok());
+        assert!(validate_columns(Span::new(1, 1), cols(0, 0), &lines).is_err());
+
+        // Columns are stored with the comment
+        let dir = tempdir().unwrap();
+        let store = ReviewStore::open(dir.path().join("test.db")).unwrap();
+        let id = DiffId::new("main", "feature");
+        let mut comment = Comment::new("src/lib.rs", Span::new(0, 1), "This emoji");
+        comment.columns = Some(cols(3, 21));
+        store.add_comment(&id, &comment).unwrap();
+        store
+            .add_comment(
+                &id,
+                &Comment::new("src/lib.rs", Span::new(1, 2), "Whole line"),
+            )
+            .unwrap();
+        let review = store.get(&id).unwrap();
+        let stored = |content: &str| {
+            review
+                .comments
+                .iter()
+                .find(|c| c.content == content)
+                .unwrap()
+                .columns
+        };
+        assert_eq!(stored("This emoji"), Some(cols(3, 21)));
+        assert_eq!(stored("Whole line"), None);
     }
 }
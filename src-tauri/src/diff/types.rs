@@ -3,14 +3,19 @@
 //! These types represent the minimal information needed to display diffs
 //! and track reviews. The design prioritizes simplicity and statelessness.
 
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
 use serde::{Deserialize, Serialize};
 
-use super::git::WORKDIR;
+use super::git::is_working_tree_ref;
+use super::language::detect_language;
 
 /// Identifies a diff between two repository states.
 ///
 /// - `before`: A ref (branch name, tag), SHA, or "HEAD"
-/// - `after`: A ref, SHA, or "WORKDIR" for the working tree
+/// - `after`: A ref, SHA, or "WORKDIR" for the working tree ("STAGED" and
+///   "UNSTAGED" scope it to just the staged or unstaged changes)
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DiffId {
     pub before: String,
@@ -27,7 +32,7 @@ impl DiffId {
 
     /// Returns true if this diff includes the working tree.
     pub fn is_working_tree(&self) -> bool {
-        self.after == WORKDIR
+        is_working_tree_ref(&self.after)
     }
 }
 
@@ -36,10 +41,178 @@ impl DiffId {
 pub struct File {
     pub path: String,
     pub content: FileContent,
+    /// Syntax highlighting language (Shiki ID), if recognized
+    pub language: Option<String>,
+    /// Lines (0-indexed into `content`) cut short for transport because they
+    /// were too long
+    pub truncated_lines: Vec<u32>,
+    /// True if this is a symbolic link; `content` is then its target as one line
+    pub is_symlink: bool,
+    /// Encoding the text was decoded from, if not plain UTF-8 (e.g. "UTF-16LE",
+    /// "windows-1252"; "UTF-8" means UTF-8 with a byte order mark)
+    pub encoding: Option<String>,
+    /// Set if this is a Git LFS pointer; `content` is then a short summary
+    /// of the object it points to, not the pointer text
+    pub lfs: Option<LfsPointer>,
+}
+
+/// A parsed Git LFS pointer: the blob git stores in place of a large file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LfsPointer {
+    /// Object ID with its hash algorithm, e.g. `sha256:4d7a...`
+    pub oid: String,
+    /// Size of the real file in bytes
+    pub size: u64,
+}
+
+/// Pointer files are small; the spec caps them below this many bytes.
+const LFS_POINTER_MAX_LEN: usize = 1024;
+
+impl LfsPointer {
+    /// Parse pointer file content, or None if `bytes` isn't an LFS pointer.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() >= LFS_POINTER_MAX_LEN {
+            return None;
+        }
+        let text = std::str::from_utf8(bytes).ok()?;
+        let mut lines = text.lines();
+        let version = lines.next()?.strip_prefix("version ")?;
+        if !version.starts_with("https://git-lfs.github.com/spec/")
+            && !version.starts_with("https://hawser.github.com/spec/")
+        {
+            return None;
+        }
+
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            if let Some(value) = line.strip_prefix("oid ") {
+                oid = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("size ") {
+                size = value.parse().ok();
+            }
+        }
+        Some(Self {
+            oid: oid?,
+            size: size?,
+        })
+    }
+
+    /// The lines shown in place of the pointer text: the object's size and
+    /// ID, so a diff of two pointers shows what changed about the object.
+    fn summary(&self) -> Vec<String> {
+        vec![
+            format!("Git LFS object ({})", format_size(self.size)),
+            format!("oid {}", self.oid),
+        ]
+    }
+}
+
+/// Format a byte count for display, e.g. "512 bytes" or "1.5 MB".
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+impl File {
+    /// Create a file, detecting its language from the path or shebang.
+    pub fn new(path: impl Into<String>, content: FileContent) -> Self {
+        let path = path.into();
+        let first_line = content.lines().first().map(String::as_str);
+        let language = detect_language(Path::new(&path), first_line);
+        Self {
+            path,
+            content,
+            language,
+            truncated_lines: Vec::new(),
+            is_symlink: false,
+            encoding: None,
+            lfs: None,
+        }
+    }
+
+    /// Create a text file from raw bytes, decoding them with
+    /// `decode_with_encoding` and recording the encoding found.
+    /// A Git LFS pointer becomes a summary of its object instead.
+    pub fn from_bytes(path: impl Into<String>, bytes: &[u8]) -> Self {
+        if let Some(pointer) = LfsPointer::parse(bytes) {
+            let mut file = Self::new(
+                path,
+                FileContent::Text {
+                    lines: pointer.summary(),
+                },
+            );
+            // Highlighting the summary as the file's language would be noise
+            file.language = None;
+            file.lfs = Some(pointer);
+            return file;
+        }
+        let (text, encoding) = decode_with_encoding(bytes);
+        let mut file = Self::new(path, FileContent::from_text(&text));
+        file.encoding = encoding.map(String::from);
+        file
+    }
+
+    /// Create a symlink entry whose content is its target path.
+    pub fn symlink(path: impl Into<String>, target: &str) -> Self {
+        Self {
+            path: path.into(),
+            content: FileContent::from_text(target),
+            language: None,
+            truncated_lines: Vec::new(),
+            is_symlink: true,
+            encoding: None,
+            lfs: None,
+        }
+    }
+
+    /// Cut lines longer than `max_chars` characters down to that length,
+    /// recording which ones were cut. Line numbers are unaffected.
+    pub fn truncate_long_lines(&mut self, max_chars: usize) {
+        let FileContent::Text { lines } = &mut self.content else {
+            return;
+        };
+        for (index, line) in lines.iter_mut().enumerate() {
+            // Byte length bounds char count, so short lines skip the scan
+            if line.len() <= max_chars {
+                continue;
+            }
+            if let Some((cut, _)) = line.char_indices().nth(max_chars) {
+                line.truncate(cut);
+                self.truncated_lines.push(index as u32);
+            }
+        }
+    }
+
+    /// Remove the lines in the given (sorted, non-overlapping) spans,
+    /// renumbering `truncated_lines` to match the lines that remain.
+    fn drop_lines(&mut self, spans: &[Span]) {
+        if spans.is_empty() {
+            return;
+        }
+        self.content.drop_lines(spans);
+        self.truncated_lines = self
+            .truncated_lines
+            .iter()
+            .filter(|&&line| !spans.iter().any(|s| s.start <= line && line < s.end))
+            .map(|&line| {
+                let dropped: u32 = spans.iter().filter(|s| s.end <= line).map(Span::len).sum();
+                line - dropped
+            })
+            .collect();
+    }
 }
 
 /// The diff for a single file between two states.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FileDiff {
     /// The file before the change (None if file was added)
     pub before: Option<File>,
@@ -47,6 +220,45 @@ pub struct FileDiff {
     pub after: Option<File>,
     /// Alignments mapping regions between before/after for scroll sync and display
     pub alignments: Vec<Alignment>,
+    /// Set when the file mode changed, e.g. made executable or turned into a symlink
+    pub mode_change: Option<ModeChange>,
+    /// Set for files matching `DiffConfig::collapse_files`: both sides carry
+    /// only their path, with no lines or alignments, until loaded in full
+    #[serde(default)]
+    pub collapsed_by_default: bool,
+    /// True if this side is binary. When only one side is, the other still
+    /// has its text, e.g. to show what a file was before it became binary.
+    #[serde(default)]
+    pub before_binary: bool,
+    #[serde(default)]
+    pub after_binary: bool,
+    /// Set when a side is bigger than `DiffConfig::max_file_size`. Like a
+    /// collapsed file, both sides carry only their path, with no lines or
+    /// alignments, until loaded in full
+    #[serde(default)]
+    pub too_large: bool,
+    /// Sizes in bytes of each side that exists, set for `too_large` files
+    #[serde(default)]
+    pub before_size: Option<u64>,
+    #[serde(default)]
+    pub after_size: Option<u64>,
+}
+
+/// Git file modes of both sides of a diff (e.g. `0o100644`, `0o100755`, `0o120000`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeChange {
+    pub before: u32,
+    pub after: u32,
+}
+
+/// Git mode for symbolic links.
+pub const SYMLINK_MODE: u32 = 0o120000;
+
+impl ModeChange {
+    /// True if the entry changed kind (file <-> symlink), not just permissions.
+    pub fn is_type_change(&self) -> bool {
+        (self.before == SYMLINK_MODE) != (self.after == SYMLINK_MODE)
+    }
 }
 
 impl FileDiff {
@@ -77,24 +289,56 @@ impl FileDiff {
         }
     }
 
+    /// Drop the middle of unchanged regions longer than `min_len` lines from
+    /// the transported content, keeping `context` lines at each end.
+    ///
+    /// Alignment spans keep their original line numbers; each trimmed region
+    /// records what was elided in `Alignment::collapsed`.
+    pub fn collapse_unchanged(&mut self, min_len: u32, context: u32) {
+        // Elided line ranges, absolute per side
+        let mut before_elided = Vec::new();
+        let mut after_elided = Vec::new();
+
+        for alignment in &mut self.alignments {
+            let len = alignment.before.len();
+            if alignment.changed
+                || len != alignment.after.len()
+                || len <= min_len
+                || len <= context * 2
+            {
+                continue;
+            }
+            let elided = Span::new(context, len - context);
+            before_elided.push(offset_span(alignment.before.start, elided));
+            after_elided.push(offset_span(alignment.after.start, elided));
+            alignment.collapsed = Some(elided);
+        }
+
+        if let Some(file) = self.before.as_mut() {
+            file.drop_lines(&before_elided);
+        }
+        if let Some(file) = self.after.as_mut() {
+            file.drop_lines(&after_elided);
+        }
+    }
+
     /// Returns true if either side is binary.
     pub fn is_binary(&self) -> bool {
-        matches!(
-            &self.before,
-            Some(File {
-                content: FileContent::Binary,
-                ..
-            })
-        ) || matches!(
-            &self.after,
-            Some(File {
-                content: FileContent::Binary,
-                ..
-            })
-        )
+        is_binary_side(&self.before) || is_binary_side(&self.after)
     }
 }
 
+/// True if a diff side exists and is binary.
+pub fn is_binary_side(file: &Option<File>) -> bool {
+    matches!(
+        file,
+        Some(File {
+            content: FileContent::Binary,
+            ..
+        })
+    )
+}
+
 /// The type of change a file underwent.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -119,13 +363,38 @@ impl FileContent {
         Self::Text { lines }
     }
 
+    /// Create text content from raw bytes, decoded with `decode_text`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::from_text(&decode_text(bytes))
+    }
+
     /// Check if content appears to be binary.
-    pub fn is_binary_data(bytes: &[u8]) -> bool {
-        // Check for null bytes in first 8KB (common heuristic)
-        let check_len = bytes.len().min(8192);
+    ///
+    /// Looks for null bytes in the first `scan_len` bytes (common heuristic).
+    /// UTF-16 text is full of null bytes, so a UTF-16 byte order mark means text.
+    pub fn is_binary_data(bytes: &[u8], scan_len: usize) -> bool {
+        if utf16_bom(bytes).is_some() {
+            return false;
+        }
+        let check_len = bytes.len().min(scan_len);
         bytes[..check_len].contains(&0)
     }
 
+    /// Remove the lines in the given (sorted, non-overlapping) spans.
+    fn drop_lines(&mut self, spans: &[Span]) {
+        if spans.is_empty() {
+            return;
+        }
+        if let FileContent::Text { lines } = self {
+            let mut index = 0u32;
+            lines.retain(|_| {
+                let keep = !spans.iter().any(|s| s.start <= index && index < s.end);
+                index += 1;
+                keep
+            });
+        }
+    }
+
     /// Get lines if this is text content.
     pub fn lines(&self) -> &[String] {
         match self {
@@ -135,6 +404,76 @@ impl FileContent {
     }
 }
 
+/// Default number of leading bytes scanned for null bytes when sniffing binary content.
+pub const DEFAULT_BINARY_SCAN_LEN: usize = 8192;
+
+/// Shift a region-relative span to absolute line numbers.
+fn offset_span(start: u32, span: Span) -> Span {
+    Span::new(start + span.start, start + span.end)
+}
+
+/// Decode text bytes with `decode_with_encoding`, dropping the encoding.
+pub fn decode_text(bytes: &[u8]) -> String {
+    decode_with_encoding(bytes).0
+}
+
+/// Decode text bytes, detecting their encoding: a byte order mark (UTF-8 or
+/// UTF-16), else UTF-8 if valid, else windows-1252. That last one is a
+/// superset of Latin-1 that maps every byte, so `encode_text` gives the
+/// original bytes back.
+///
+/// Returns the text and the encoding's name, None for plain UTF-8.
+pub fn decode_with_encoding(bytes: &[u8]) -> (String, Option<&'static str>) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return (text.into_owned(), Some(encoding.name()));
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), None),
+        Err(_) => {
+            let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+            (text.into_owned(), Some(WINDOWS_1252.name()))
+        }
+    }
+}
+
+/// Encode text as `encoding` (a name from `decode_with_encoding`, None for
+/// plain UTF-8), restoring its byte order mark.
+pub fn encode_text(text: &str, encoding: Option<&str>) -> Vec<u8> {
+    let Some(encoding) = encoding.and_then(|name| Encoding::for_label(name.as_bytes())) else {
+        return text.as_bytes().to_vec();
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() + 3);
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        // encoding_rs only encodes to ASCII-compatible encodings
+        let little_endian = encoding == UTF_16LE;
+        for unit in std::iter::once(0xFEFF).chain(text.encode_utf16()) {
+            let pair = if little_endian {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            };
+            bytes.extend_from_slice(&pair);
+        }
+    } else if encoding == UTF_8 {
+        bytes.extend_from_slice(b"\xEF\xBB\xBF");
+        bytes.extend_from_slice(text.as_bytes());
+    } else {
+        bytes.extend_from_slice(&encoding.encode(text).0);
+    }
+    bytes
+}
+
+/// Detect a UTF-16 byte order mark. Returns true for little-endian.
+fn utf16_bom(bytes: &[u8]) -> Option<bool> {
+    match bytes {
+        [0xFF, 0xFE, ..] => Some(true),
+        [0xFE, 0xFF, ..] => Some(false),
+        _ => None,
+    }
+}
+
 /// An alignment between a region in the before file and a region in the after file.
 ///
 /// Alignments exhaustively partition both files - every line belongs to exactly
@@ -145,6 +484,10 @@ pub struct Alignment {
     pub after: Span,
     /// True if this region contains changes (content differs between before/after)
     pub changed: bool,
+    /// Lines of this unchanged region whose content was omitted from the payload,
+    /// as offsets from the start of the region (the same on both sides).
+    /// Spans still use the original line numbers.
+    pub collapsed: Option<Span>,
 }
 
 /// A contiguous range of lines (0-indexed, exclusive end).
@@ -171,6 +514,27 @@ impl Span {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diff::git::{STAGED, UNSTAGED, WORKDIR};
+
+    #[test]
+    fn test_truncate_long_lines() {
+        let long = "é".repeat(50_000);
+        let mut file = File::new(
+            "min.js",
+            FileContent::Text {
+                lines: vec!["short".into(), long, "after".into()],
+            },
+        );
+
+        file.truncate_long_lines(100);
+
+        let lines = file.content.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "short");
+        assert_eq!(lines[1].chars().count(), 100);
+        assert_eq!(lines[2], "after");
+        assert_eq!(file.truncated_lines, vec![1]);
+    }
 
     #[test]
     fn test_diff_id_working_tree() {
@@ -179,76 +543,177 @@ mod tests {
 
         let historical = DiffId::new("main", "feature");
         assert!(!historical.is_working_tree());
+
+        // Each uncommitted scope is its own review
+        let staged = DiffId::new("HEAD", STAGED);
+        let unstaged = DiffId::new("HEAD", UNSTAGED);
+        assert!(!staged.is_working_tree());
+        assert!(unstaged.is_working_tree());
+        assert_ne!(staged, working);
+        assert_ne!(unstaged, working);
+        assert_ne!(staged, unstaged);
+    }
+
+    /// A diff between empty text files at the given paths.
+    fn file_diff(before: Option<&str>, after: Option<&str>) -> FileDiff {
+        let file = |path: &str| File::new(path, FileContent::from_text(""));
+        FileDiff {
+            before: before.map(file),
+            after: after.map(file),
+            ..Default::default()
+        }
     }
 
     #[test]
     fn test_change_kind() {
-        let added = FileDiff {
-            before: None,
-            after: Some(File {
-                path: "new.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            alignments: vec![],
-        };
+        let added = file_diff(None, Some("new.txt"));
         assert_eq!(added.change_kind(), ChangeKind::Added);
 
-        let deleted = FileDiff {
-            before: Some(File {
-                path: "old.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            after: None,
-            alignments: vec![],
-        };
+        let deleted = file_diff(Some("old.txt"), None);
         assert_eq!(deleted.change_kind(), ChangeKind::Deleted);
 
-        let modified = FileDiff {
-            before: Some(File {
-                path: "changed.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            after: Some(File {
-                path: "changed.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            alignments: vec![],
-        };
+        let modified = file_diff(Some("changed.txt"), Some("changed.txt"));
         assert_eq!(modified.change_kind(), ChangeKind::Modified);
     }
 
+    #[test]
+    fn test_collapse_unchanged_renumbers_truncated_lines() {
+        let text: String = (0..20)
+            .map(|i| match i {
+                1 | 10 | 18 => format!("{}\n", "x".repeat(40)),
+                _ => format!("line {}\n", i),
+            })
+            .collect();
+        let mut file = File::new("a.txt", FileContent::from_text(&text));
+        file.truncate_long_lines(20);
+        assert_eq!(file.truncated_lines, vec![1, 10, 18]);
+
+        let mut diff = FileDiff {
+            before: Some(file.clone()),
+            after: Some(file),
+            alignments: vec![Alignment {
+                before: Span::new(0, 20),
+                after: Span::new(0, 20),
+                changed: false,
+                collapsed: None,
+            }],
+            ..Default::default()
+        };
+        diff.collapse_unchanged(5, 3);
+
+        // Lines 3..17 are dropped: line 10 goes with them, line 18 moves up
+        let after = diff.after.as_ref().unwrap();
+        assert_eq!(after.content.lines().len(), 6);
+        assert_eq!(after.truncated_lines, vec![1, 4]);
+        for &line in &after.truncated_lines {
+            assert_eq!(after.content.lines()[line as usize], "x".repeat(20));
+        }
+        assert_eq!(diff.before.unwrap().truncated_lines, vec![1, 4]);
+    }
+
     #[test]
     fn test_binary_detection() {
-        assert!(FileContent::is_binary_data(&[0x00, 0x01, 0x02]));
-        assert!(!FileContent::is_binary_data(b"hello world"));
+        assert!(FileContent::is_binary_data(
+            &[0x00, 0x01, 0x02],
+            DEFAULT_BINARY_SCAN_LEN
+        ));
+        assert!(!FileContent::is_binary_data(
+            b"hello world",
+            DEFAULT_BINARY_SCAN_LEN
+        ));
+    }
+
+    #[test]
+    fn test_binary_detection_null_past_scan_window() {
+        let mut bytes = vec![b'a'; 10_000];
+        bytes[9_000] = 0;
+        assert!(!FileContent::is_binary_data(
+            &bytes,
+            DEFAULT_BINARY_SCAN_LEN
+        ));
+        assert!(FileContent::is_binary_data(&bytes, 16_384));
+    }
+
+    #[test]
+    fn test_lfs_pointer() {
+        let pointer = "version https://git-lfs.github.com/spec/v1\n\
+                       oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n\
+                       size 1572864\n";
+        let file = File::from_bytes("assets/video.mp4", pointer.as_bytes());
+        let lfs = file.lfs.as_ref().unwrap();
+        assert_eq!(lfs.size, 1_572_864);
+        assert!(lfs.oid.starts_with("sha256:4d7a"));
+        assert_eq!(file.content.lines()[0], "Git LFS object (1.5 MB)");
+        assert_eq!(file.language, None);
+
+        // Not pointers: plain text, a missing size, an oversized blob
+        assert_eq!(LfsPointer::parse(b"version 1\noid x\nsize 1\n"), None);
+        assert_eq!(
+            LfsPointer::parse(b"version https://git-lfs.github.com/spec/v1\noid sha256:ab\n"),
+            None
+        );
+        let mut long = pointer.to_string();
+        long.push_str(&"x".repeat(LFS_POINTER_MAX_LEN));
+        assert_eq!(LfsPointer::parse(long.as_bytes()), None);
+        assert!(File::from_bytes("a.txt", b"hello\n").lfs.is_none());
+
+        assert_eq!(format_size(512), "512 bytes");
+        assert_eq!(format_size(2048), "2.0 KB");
+    }
+
+    #[test]
+    fn test_utf16_is_text() {
+        // "hi\nyo" in UTF-16 LE with BOM
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "hi\nyo".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert!(!FileContent::is_binary_data(
+            &bytes,
+            DEFAULT_BINARY_SCAN_LEN
+        ));
+        assert_eq!(FileContent::from_bytes(&bytes).lines(), ["hi", "yo"]);
+
+        // Big-endian too
+        let mut bytes = vec![0xFE, 0xFF];
+        for unit in "ok".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+        assert_eq!(FileContent::from_bytes(&bytes).lines(), ["ok"]);
+    }
+
+    #[test]
+    fn test_encoding_round_trip() {
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("héllo\r\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let latin1 = b"caf\xe9\n".to_vec();
+        let utf8_bom = b"\xEF\xBB\xBFhi\n".to_vec();
+        let plain = "plain ✓\n".as_bytes().to_vec();
+
+        for (bytes, name, text) in [
+            (utf16, Some("UTF-16LE"), "héllo\r\n"),
+            (latin1, Some("windows-1252"), "café\n"),
+            (utf8_bom, Some("UTF-8"), "hi\n"),
+            (plain, None, "plain ✓\n"),
+        ] {
+            let (decoded, encoding) = decode_with_encoding(&bytes);
+            assert_eq!((decoded.as_str(), encoding), (text, name));
+            assert_eq!(encode_text(&decoded, encoding), bytes);
+        }
+
+        let file = File::from_bytes("notes.txt", b"na\xefve\n");
+        assert_eq!(file.content.lines(), ["naïve"]);
+        assert_eq!(file.encoding.as_deref(), Some("windows-1252"));
     }
 
     #[test]
     fn test_is_rename() {
-        let rename = FileDiff {
-            before: Some(File {
-                path: "old_name.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            after: Some(File {
-                path: "new_name.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            alignments: vec![],
-        };
+        let rename = file_diff(Some("old_name.txt"), Some("new_name.txt"));
         assert!(rename.is_rename());
 
-        let not_rename = FileDiff {
-            before: Some(File {
-                path: "same.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            after: Some(File {
-                path: "same.txt".into(),
-                content: FileContent::Text { lines: vec![] },
-            }),
-            alignments: vec![],
-        };
+        let not_rename = file_diff(Some("same.txt"), Some("same.txt"));
         assert!(!not_rename.is_rename());
     }
 }
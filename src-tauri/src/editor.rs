@@ -0,0 +1,159 @@
+//! Opening files in the user's editor.
+//!
+//! The editor is a command template like `code -g {path}:{line}`, taken from
+//! the repo's settings, then `$VISUAL`, `$EDITOR`, or git's `core.editor`.
+//! A command without placeholders gets the arguments its editor is known to
+//! take for jumping to a line.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Serialize;
+
+use crate::executable::find_executable;
+
+/// Why the editor couldn't be opened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum EditorError {
+    /// No editor setting, `$VISUAL`, `$EDITOR`, or `core.editor`
+    NotConfigured,
+    /// The repository, the file, or the editor process couldn't be opened
+    Failed(String),
+}
+
+impl std::fmt::Display for EditorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditorError::NotConfigured => write!(
+                f,
+                "No editor configured. Set one in the repository settings, or set $VISUAL or $EDITOR."
+            ),
+            EditorError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for EditorError {}
+
+/// Pick the editor command template: the configured one, then `$VISUAL`,
+/// `$EDITOR`, and git's `core.editor`. Blank values are skipped.
+pub fn resolve_editor(
+    configured: Option<&str>,
+    repo: &git2::Repository,
+) -> Result<String, EditorError> {
+    let core_editor = repo
+        .config()
+        .ok()
+        .and_then(|config| config.get_string("core.editor").ok());
+    [
+        configured.map(String::from),
+        std::env::var("VISUAL").ok(),
+        std::env::var("EDITOR").ok(),
+        core_editor,
+    ]
+    .into_iter()
+    .flatten()
+    .find(|editor| !editor.trim().is_empty())
+    .ok_or(EditorError::NotConfigured)
+}
+
+/// Build the program and arguments for opening `path` at `line` (1-indexed).
+///
+/// `{path}` and `{line}` in the template are replaced after splitting it on
+/// whitespace, so paths with spaces stay one argument. Without `{path}`,
+/// the editor's usual line-jump arguments are appended.
+pub fn editor_command(template: &str, path: &Path, line: u32) -> Option<(String, Vec<String>)> {
+    let mut words = template.split_whitespace();
+    let program = words.next()?.to_string();
+    let mut args: Vec<&str> = words.collect();
+    if !template.contains("{path}") {
+        args.extend(line_args(&program));
+    }
+
+    let path = path.to_string_lossy();
+    let line = line.to_string();
+    let args = args
+        .into_iter()
+        .map(|arg| arg.replace("{path}", &path).replace("{line}", &line))
+        .collect();
+    Some((program, args))
+}
+
+/// Arguments that open `{path}` at `{line}` in a known editor.
+fn line_args(program: &str) -> &'static [&'static str] {
+    let name = Path::new(program)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "code" | "code-insiders" | "codium" | "cursor" | "windsurf" => &["-g", "{path}:{line}"],
+        "zed" | "subl" | "hx" | "mate" => &["{path}:{line}"],
+        "vi" | "vim" | "nvim" | "emacs" | "emacsclient" | "nano" | "micro" | "kak" => {
+            &["+{line}", "{path}"]
+        }
+        _ => &["{path}"],
+    }
+}
+
+/// Launch the editor on `path` at `line` (1-indexed) without waiting for it.
+///
+/// Terminal editors (vim, nano) have no terminal to draw in when launched
+/// from the app; configure a command that opens one to use them.
+pub fn open_in_editor(template: &str, path: &Path, line: u32) -> Result<(), EditorError> {
+    let (program, args) = editor_command(template, path, line).ok_or(EditorError::NotConfigured)?;
+    Command::new(find_executable(&program, None).unwrap_or_else(|| PathBuf::from(&program)))
+        .args(&args)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| EditorError::Failed(format!("Failed to start '{}': {}", program, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_command_template() {
+        let path = Path::new("/repo/my file.rs");
+        assert_eq!(
+            editor_command("code -g {path}:{line}", path, 12),
+            Some((
+                "code".to_string(),
+                vec!["-g".to_string(), "/repo/my file.rs:12".to_string()]
+            ))
+        );
+        assert_eq!(
+            editor_command("my-editor --line={line} {path}", path, 3),
+            Some((
+                "my-editor".to_string(),
+                vec!["--line=3".to_string(), "/repo/my file.rs".to_string()]
+            ))
+        );
+        assert_eq!(editor_command("   ", path, 1), None);
+    }
+
+    #[test]
+    fn test_editor_command_known_editors() {
+        let path = Path::new("/repo/a.rs");
+        assert_eq!(
+            editor_command("nvim", path, 7).unwrap().1,
+            vec!["+7", "/repo/a.rs"]
+        );
+        // Existing flags are kept ahead of the line arguments
+        assert_eq!(
+            editor_command("/usr/local/bin/code --wait", path, 7)
+                .unwrap()
+                .1,
+            vec!["--wait", "-g", "/repo/a.rs:7"]
+        );
+        assert_eq!(
+            editor_command("zed", path, 7).unwrap().1,
+            vec!["/repo/a.rs:7"]
+        );
+        assert_eq!(
+            editor_command("unknown-editor", path, 7).unwrap().1,
+            vec!["/repo/a.rs"]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Finding external programs (git, gh, editors) to run.
+//!
+//! GUI apps on macOS don't inherit the shell's PATH, so besides PATH we
+//! check the usual install locations explicitly.
+
+use std::path::{Path, PathBuf};
+
+/// Common directories where command-line tools and editor launchers are
+/// installed, searched after PATH.
+const SEARCH_PATHS: &[&str] = &[
+    "/opt/homebrew/bin",              // Homebrew on Apple Silicon
+    "/usr/local/bin",                 // Homebrew on Intel Mac, common Linux location
+    "/usr/bin",                       // System binaries (Xcode CLT shim on macOS)
+    "/home/linuxbrew/.linuxbrew/bin", // Linuxbrew
+    "/Applications/Visual Studio Code.app/Contents/Resources/app/bin",
+    "/Applications/Cursor.app/Contents/Resources/app/bin",
+];
+
+/// Find the executable `name`.
+///
+/// If `env_override` names an environment variable that is set to a file,
+/// that file wins. A `name` that is already a path is used as given. Otherwise
+/// PATH is searched, then `SEARCH_PATHS`. Returns None if nothing was found.
+pub fn find_executable(name: &str, env_override: Option<&str>) -> Option<PathBuf> {
+    let override_path = env_override.and_then(|var| {
+        let path = std::env::var_os(var).filter(|p| !p.is_empty())?;
+        Some((var, PathBuf::from(path)))
+    });
+    let path_dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    let search: Vec<&Path> = path_dirs
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(SEARCH_PATHS.iter().map(Path::new))
+        .collect();
+    discover(name, override_path, &search)
+}
+
+/// The lookup order behind `find_executable`, with its inputs injected.
+fn discover(
+    name: &str,
+    override_path: Option<(&str, PathBuf)>,
+    search_dirs: &[&Path],
+) -> Option<PathBuf> {
+    if let Some((var, path)) = override_path {
+        if path.is_file() {
+            return Some(path);
+        }
+        log::warn!(
+            "{} is set to {} but it isn't a file; ignoring it",
+            var,
+            path.display()
+        );
+    }
+
+    if name.contains('/') || name.contains('\\') {
+        let path = PathBuf::from(name);
+        return path.is_file().then_some(path);
+    }
+
+    let file_names = [
+        name.to_string(),
+        format!("{}{}", name, std::env::consts::EXE_SUFFIX),
+    ];
+    search_dirs
+        .iter()
+        .flat_map(|dir| file_names.iter().map(|file| dir.join(file)))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_discover() {
+        let dir = TempDir::new().unwrap();
+        let empty = dir.path().join("empty");
+        let bin = dir.path().join("bin");
+        std::fs::create_dir_all(&empty).unwrap();
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("git"), "").unwrap();
+        let custom = dir.path().join("my-git");
+        std::fs::write(&custom, "").unwrap();
+        let search = [empty.as_path(), bin.as_path()];
+
+        // Override beats everything
+        assert_eq!(
+            discover("git", Some(("GIT", custom.clone())), &search),
+            Some(custom.clone())
+        );
+        // A missing override is ignored
+        assert_eq!(
+            discover("git", Some(("GIT", dir.path().join("nope"))), &search),
+            Some(bin.join("git"))
+        );
+        // First search dir that has it
+        assert_eq!(discover("git", None, &search), Some(bin.join("git")));
+        assert_eq!(discover("git", None, &[empty.as_path()]), None);
+
+        // Paths are used as given, if they exist
+        let given = custom.to_string_lossy();
+        assert_eq!(discover(&given, None, &[]), Some(custom));
+        let missing = dir.path().join("missing").to_string_lossy().to_string();
+        assert_eq!(discover(&missing, None, &search), None);
+    }
+}
@@ -1,15 +1,27 @@
+mod diagnostics;
 pub mod diff;
+mod editor;
+mod executable;
 mod refresh;
 mod themes;
 mod watcher;
 
+use diff::types::Span;
 use diff::{
-    Comment, DiffId, Edit, GitHubAuthStatus, GitRef, NewComment, NewEdit, PRFetchResult,
-    PullRequest, RepoInfo, Review,
+    BlameLine, ChangeLocation, ChangedPath, Comment, CommitChanges, CommitDiff, CommitMessage,
+    CommitPreview, ConflictExport, DiffConfig, DiffId, DiffRefsValidation, DiffResult, DiffSummary,
+    Divergence, Edit, EffectiveDiffOptions, ExportFilter, FileContents, FileDiff, GitHubAuthStatus,
+    GitRef, HeadSha, HunkDensity, HunkSlices, IgnoreStatus, LogEntry, NewComment, NewEdit,
+    NumstatEntry, OperationState, PRFetchResult, PathAliases, PullRequest, RefResolution, RepoInfo,
+    RepoSettings, Review, ReviewStore, TripleDiff, WorkingTreeFile,
 };
-use refresh::RefreshController;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use refresh::{EventSink, RefreshController};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tauri::{Manager, State};
 
 // =============================================================================
@@ -25,26 +37,73 @@ fn open_repo_from_path(repo_path: Option<&str>) -> Result<git2::Repository, Stri
 }
 
 /// Resolve a ref to a full SHA for use as a stable storage key.
-/// WORKDIR is kept as-is (represents working tree).
+/// WORKDIR, STAGED and UNSTAGED are kept as-is (they name uncommitted scopes),
+/// as is EMPTY (the empty tree).
 /// Full SHAs (40 hex chars) are kept as-is - they're already stable.
 /// All other refs are resolved to their full SHA.
 fn resolve_for_storage(repo: &git2::Repository, ref_str: &str) -> Result<String, String> {
-    if ref_str == diff::WORKDIR {
-        return Ok(diff::WORKDIR.to_string());
-    }
+    // Without a store there's nothing to remember names in; resolving still works
+    resolve_for_storage_in(repo, ref_str, diff::get_store().ok())
+}
 
-    // If it's already a full SHA, use it directly.
-    // This handles cases where the SHA might be from a fetched PR ref
-    // that isn't reachable from local branches.
-    if is_full_sha(ref_str) {
+/// `resolve_for_storage`, remembering what names resolve to in `store`. A
+/// name that no longer resolves (a deleted branch) falls back to the SHA it
+/// last resolved to, which is what its reviews are keyed by.
+fn resolve_for_storage_in(
+    repo: &git2::Repository,
+    ref_str: &str,
+    store: Option<&ReviewStore>,
+) -> Result<String, String> {
+    if [diff::WORKDIR, diff::STAGED, diff::UNSTAGED, diff::EMPTY].contains(&ref_str) {
         return Ok(ref_str.to_string());
     }
 
-    let obj = repo
-        .revparse_single(ref_str)
-        .map_err(|e| format!("Cannot resolve '{}': {}", ref_str, e))?;
+    let repo_key = settings_key(repo);
+    // Annotated tags peel to their commit, so a review keyed by a tag name
+    // and one keyed by the commit are the same review.
+    match diff::resolve_commit_sha(repo, ref_str) {
+        Ok(sha) => {
+            if let Some(store) = store.filter(|_| sha != ref_str) {
+                if let Err(e) = store.remember_ref(&repo_key, ref_str, &sha) {
+                    log::warn!("Cannot remember what {} resolves to: {}", ref_str, e.0);
+                }
+            }
+            Ok(sha)
+        }
+        // A full SHA is kept as-is even if it doesn't resolve, e.g. a
+        // fetched PR ref that isn't reachable from local branches.
+        Err(_) if is_full_sha(ref_str) => Ok(ref_str.to_string()),
+        Err(e) => store
+            .and_then(|store| store.last_known_sha(&repo_key, ref_str).ok().flatten())
+            .ok_or(e.0),
+    }
+}
+
+/// Normalize a path from the frontend (maybe absolute, maybe with
+/// backslashes) to the repo-relative form git lookups need.
+fn repo_relative(repo: &git2::Repository, path: &str) -> Result<String, String> {
+    diff::repo_relative_path(repo, path).map_err(|e| e.0)
+}
 
-    Ok(obj.id().to_string())
+/// The key a repository's settings are stored under: its working directory,
+/// or the git directory for a bare repository.
+fn settings_key(repo: &git2::Repository) -> String {
+    repo.workdir()
+        .unwrap_or_else(|| repo.path())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The diff options to use when a command isn't given any: the repository's
+/// saved settings, or plain defaults if they can't be read.
+fn default_diff_config(repo: &git2::Repository) -> DiffConfig {
+    diff::get_store()
+        .and_then(|store| store.repo_settings(&settings_key(repo)))
+        .map(|settings| settings.diff_config())
+        .unwrap_or_else(|e| {
+            log::warn!("Cannot load repo settings, using defaults: {}", e.0);
+            DiffConfig::default()
+        })
 }
 
 /// Check if a string is a full 40-character SHA.
@@ -53,10 +112,12 @@ fn is_full_sha(s: &str) -> bool {
 }
 
 /// Create a DiffId with resolved SHAs for stable storage.
-fn make_diff_id(repo_path: Option<&str>, base: &str, head: &str) -> Result<DiffId, String> {
-    let repo = open_repo_from_path(repo_path)?;
-    let resolved_base = resolve_for_storage(&repo, base)?;
-    let resolved_head = resolve_for_storage(&repo, head)?;
+///
+/// Every review command resolves its refs in the repository it was given,
+/// so a review is read and written under the same id.
+fn make_diff_id(repo: &git2::Repository, base: &str, head: &str) -> Result<DiffId, String> {
+    let resolved_base = resolve_for_storage(repo, base)?;
+    let resolved_head = resolve_for_storage(repo, head)?;
     Ok(DiffId::new(resolved_base, resolved_head))
 }
 
@@ -64,18 +125,501 @@ fn make_diff_id(repo_path: Option<&str>, base: &str, head: &str) -> Result<DiffI
 // Diff Commands
 // =============================================================================
 
+/// The flags cancelling the `get_diff` in progress in each repository (by
+/// `settings_key`), if any, so windows on different repositories don't
+/// cancel each other.
+#[derive(Default)]
+struct DiffCancellation(Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+impl DiffCancellation {
+    /// Cancel the previous diff in `repo` and hand out a flag for the next one.
+    fn next(&self, repo: &git2::Repository) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut current = self.0.lock().unwrap();
+        if let Some(previous) = current.insert(settings_key(repo), flag.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+        flag
+    }
+}
+
 /// Get the full diff between two refs.
 ///
 /// If `use_merge_base` is true, diffs from the merge-base instead of base directly.
+/// `config` controls excluded paths and binary detection (the repo's saved
+/// settings if omitted). Starting another `get_diff` in the same repository
+/// cancels this one, which then fails with "Diff cancelled".
 #[tauri::command]
-fn get_diff(
+async fn get_diff(
     repo_path: Option<String>,
     base: String,
     head: String,
     use_merge_base: Option<bool>,
-) -> Result<Vec<diff::FileDiff>, String> {
+    config: Option<DiffConfig>,
+    cancellation: State<'_, DiffCancellation>,
+) -> Result<DiffResult, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let cancel = cancellation.next(&repo);
+    tauri::async_runtime::spawn_blocking(move || {
+        let config = config.unwrap_or_else(|| default_diff_config(&repo));
+        diff::compute_diff_cancellable(
+            &repo,
+            &base,
+            &head,
+            use_merge_base.unwrap_or(false),
+            &config,
+            &cancel,
+        )
+        .map_err(|e| e.0)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Event name for one file of a `stream_diff`, sent as soon as it's computed.
+/// Payload is `DiffFileReady`.
+const EVENT_DIFF_FILE_READY: &str = "diff-file-ready";
+
+/// Event name sent once a `stream_diff` has sent all its files.
+/// Payload is `DiffComplete`.
+const EVENT_DIFF_COMPLETE: &str = "diff-complete";
+
+/// Payload for `EVENT_DIFF_FILE_READY`. `base` and `head` are as requested,
+/// so files from a superseded diff can be told apart.
+#[derive(Serialize)]
+struct DiffFileReady<'a> {
+    base: &'a str,
+    head: &'a str,
+    file: &'a FileDiff,
+}
+
+/// Payload for `EVENT_DIFF_COMPLETE`.
+#[derive(Serialize)]
+struct DiffComplete<'a> {
+    base: &'a str,
+    head: &'a str,
+    file_count: usize,
+    excluded_count: usize,
+}
+
+/// Send `payload` as `event`, logging (not returning) failures.
+fn send_event<E: EventSink>(events: &E, event: &str, payload: impl Serialize) {
+    let result = serde_json::to_value(payload)
+        .map_err(|e| e.to_string())
+        .and_then(|value| events.send(event, value));
+    if let Err(e) = result {
+        log::error!("Failed to emit {} event: {}", event, e);
+    }
+}
+
+/// Compute a diff, sending each file to `events` as it's ready and then
+/// `EVENT_DIFF_COMPLETE`. Returns the number of files sent. Nothing is sent
+/// after a failure or cancellation.
+fn stream_diff_to<E: EventSink>(
+    events: &E,
+    repo: &git2::Repository,
+    base: &str,
+    head: &str,
+    use_merge_base: bool,
+    config: &DiffConfig,
+    cancel: &AtomicBool,
+) -> Result<usize, String> {
+    let result =
+        diff::compute_diff_streaming(repo, base, head, use_merge_base, config, cancel, |file| {
+            send_event(
+                events,
+                EVENT_DIFF_FILE_READY,
+                DiffFileReady { base, head, file },
+            );
+        })
+        .map_err(|e| e.0)?;
+    let file_count = result.files.len();
+    send_event(
+        events,
+        EVENT_DIFF_COMPLETE,
+        DiffComplete {
+            base,
+            head,
+            file_count,
+            excluded_count: result.excluded_count,
+        },
+    );
+    Ok(file_count)
+}
+
+/// Like `get_diff`, but delivers files as `diff-file-ready` events as they're
+/// computed, in no particular order, then `diff-complete`. Returns the file
+/// count. Shares `get_diff`'s cancellation: starting either in the same
+/// repository cancels this.
+#[tauri::command]
+async fn stream_diff(
+    app: tauri::AppHandle,
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    use_merge_base: Option<bool>,
+    config: Option<DiffConfig>,
+    cancellation: State<'_, DiffCancellation>,
+) -> Result<usize, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let cancel = cancellation.next(&repo);
+    tauri::async_runtime::spawn_blocking(move || {
+        let config = config.unwrap_or_else(|| default_diff_config(&repo));
+        stream_diff_to(
+            &app,
+            &repo,
+            &base,
+            &head,
+            use_merge_base.unwrap_or(false),
+            &config,
+            &cancel,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Get the full diff of one file, e.g. one `get_diff` listed collapsed
+/// (`collapsed_by_default`) because it matched `collapse_files`. Pass a
+/// renamed file's old path as `old_path`. Null if the file has no changes.
+#[tauri::command]
+fn get_file_ref_diff(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    old_path: Option<String>,
+    use_merge_base: Option<bool>,
+    config: Option<DiffConfig>,
+) -> Result<Option<FileDiff>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    let path = repo_relative(&repo, &path)?;
+    let old_path = old_path.map(|p| repo_relative(&repo, &p)).transpose()?;
+    diff::file_ref_diff(
+        &repo,
+        &base,
+        &head,
+        use_merge_base.unwrap_or(false),
+        &path,
+        old_path.as_deref(),
+        &config,
+    )
+    .map_err(|e| e.0)
+}
+
+/// Get one file's staged (HEAD to index) and unstaged (index to working
+/// tree) changes together, with rows lining up all three versions.
+#[tauri::command]
+fn get_file_triple_diff(repo_path: Option<String>, path: String) -> Result<TripleDiff, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    diff::triple_diff(&repo, &path, &default_diff_config(&repo)).map_err(|e| e.0)
+}
+
+/// Get what would be pushed: the current branch's upstream to HEAD,
+/// from their merge-base.
+#[tauri::command]
+fn get_outgoing_diff(
+    repo_path: Option<String>,
+    config: Option<DiffConfig>,
+) -> Result<DiffResult, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let upstream = diff::upstream_of_head(&repo).map_err(|e| e.0)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::compute_diff(&repo, &upstream, "HEAD", true, &config).map_err(|e| e.0)
+}
+
+/// Get what would be pulled: HEAD to the current branch's upstream,
+/// from their merge-base.
+#[tauri::command]
+fn get_incoming_diff(
+    repo_path: Option<String>,
+    config: Option<DiffConfig>,
+) -> Result<DiffResult, String> {
     let repo = open_repo_from_path(repo_path.as_deref())?;
-    diff::compute_diff(&repo, &base, &head, use_merge_base.unwrap_or(false)).map_err(|e| e.0)
+    let upstream = diff::upstream_of_head(&repo).map_err(|e| e.0)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::compute_diff(&repo, "HEAD", &upstream, true, &config).map_err(|e| e.0)
+}
+
+/// List the commits in base..head, newest first. With `first_parent`, side
+/// branches merged in along the way are skipped.
+#[tauri::command]
+fn get_commit_log(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    first_parent: bool,
+) -> Result<Vec<LogEntry>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::commit_log(&repo, &base, &head, first_parent).map_err(|e| e.0)
+}
+
+/// Get the diff introduced by a single commit, against its first parent.
+/// `config` is as for `get_diff` (the repo's saved settings if omitted).
+#[tauri::command]
+fn get_commit_diff(
+    repo_path: Option<String>,
+    sha: String,
+    config: Option<DiffConfig>,
+) -> Result<CommitDiff, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::compute_commit_diff(&repo, &sha, &config).map_err(|e| e.0)
+}
+
+/// Commits returned by `get_per_commit_diffs` unless the caller says otherwise.
+const DEFAULT_MAX_WALKTHROUGH_COMMITS: usize = 50;
+
+/// Get the diff each commit in base..head introduces, oldest first, for a
+/// commit-by-commit walkthrough. At most `max_commits` (default 50) are returned.
+/// `config` is as for `get_diff` (the repo's saved settings if omitted).
+#[tauri::command]
+fn get_per_commit_diffs(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    max_commits: Option<usize>,
+    config: Option<DiffConfig>,
+) -> Result<Vec<CommitChanges>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::per_commit_diffs(
+        &repo,
+        &base,
+        &head,
+        max_commits.unwrap_or(DEFAULT_MAX_WALKTHROUGH_COMMITS),
+        &config,
+    )
+    .map_err(|e| e.0)
+}
+
+/// Diff a file at a ref (or the working tree) against a file elsewhere on disk.
+/// `config` is as for `get_diff` (the repo's saved settings if omitted).
+#[tauri::command]
+fn get_external_file_diff(
+    repo_path: Option<String>,
+    base_ref: String,
+    file_path: String,
+    external_abs_path: String,
+    config: Option<DiffConfig>,
+) -> Result<FileDiff, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let file_path = repo_relative(&repo, &file_path)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::compute_external_file_diff(
+        &repo,
+        &base_ref,
+        &file_path,
+        Path::new(&external_abs_path),
+        &config,
+    )
+    .map_err(|e| e.0)
+}
+
+/// Get a unified diff (with context) for one file, for copying elsewhere.
+#[tauri::command]
+fn get_file_patch(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    config: Option<DiffConfig>,
+) -> Result<String, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::file_patch(&repo, &base, &head, &path, config.algorithm).map_err(|e| e.0)
+}
+
+/// Get who last changed lines `start..end` (0-indexed) of a file at a ref.
+/// `WORKDIR` blames HEAD.
+#[tauri::command]
+fn get_blame(
+    repo_path: Option<String>,
+    ref_str: String,
+    path: String,
+    start: u32,
+    end: u32,
+) -> Result<Vec<BlameLine>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    diff::blame_lines(&repo, &ref_str, &path, start, end).map_err(|e| e.0)
+}
+
+/// Find what a file at `base_path` is called at `head`, following renames
+/// across the commits in between. Returns None if it was deleted.
+#[tauri::command]
+fn trace_rename(
+    repo_path: Option<String>,
+    base_path: String,
+    base: String,
+    head: String,
+) -> Result<Option<String>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let base_path = repo_relative(&repo, &base_path)?;
+    diff::trace_rename(&repo, &base_path, &base, &head).map_err(|e| e.0)
+}
+
+/// Get the raw text of one file on both sides of a diff, for frontends that
+/// run their own diff algorithm. Binary detection follows `config` (the
+/// repo's saved settings if omitted), as `get_diff` does.
+#[tauri::command]
+fn get_file_contents(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    config: Option<DiffConfig>,
+) -> Result<FileContents, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    let path = repo_relative(&repo, &path)?;
+    diff::file_contents(&repo, &base, &head, &path, &config).map_err(|e| e.0)
+}
+
+/// Largest file `get_blob_bytes` returns unless the caller says otherwise.
+const DEFAULT_MAX_BLOB_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Get the exact bytes of one side of a file (a ref, or the working tree for
+/// WORKDIR), for previewing images and other binary files. The bytes are sent
+/// raw rather than as a JSON array. Fails with a `missing` error if the file
+/// doesn't exist on that side; files over `max_bytes` (default 20 MB) are
+/// refused with a `too_large` error.
+#[tauri::command]
+fn get_blob_bytes(
+    repo_path: Option<String>,
+    ref_str: String,
+    path: String,
+    max_bytes: Option<u64>,
+) -> Result<tauri::ipc::Response, diff::BlobError> {
+    let failed = |message| diff::BlobError::Failed { message };
+    let repo = open_repo_from_path(repo_path.as_deref()).map_err(failed)?;
+    let path = repo_relative(&repo, &path).map_err(failed)?;
+    diff::blob_bytes(
+        &repo,
+        &ref_str,
+        &path,
+        max_bytes.unwrap_or(DEFAULT_MAX_BLOB_BYTES),
+    )?
+    .map(tauri::ipc::Response::new)
+    .ok_or(diff::BlobError::Missing)
+}
+
+/// Get per-hunk change counts for one file, for a scrollbar minimap.
+#[tauri::command]
+fn get_change_density(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    config: Option<DiffConfig>,
+) -> Result<Vec<HunkDensity>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::change_density(&repo, &base, &head, &path, config.algorithm).map_err(|e| e.0)
+}
+
+/// Get the before and after lines of one changed region of a file, by its
+/// index in the file's alignments, ready to hand to a hunk description.
+#[tauri::command]
+fn get_hunk_slices(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    alignment_index: usize,
+    use_merge_base: Option<bool>,
+    config: Option<DiffConfig>,
+) -> Result<HunkSlices, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::hunk_slices(
+        &repo,
+        &base,
+        &head,
+        use_merge_base.unwrap_or(false),
+        &path,
+        alignment_index,
+        &config,
+    )
+    .map_err(|e| e.0)
+}
+
+/// Get every changed region of a diff in display order, for next/previous
+/// change navigation. `config` defaults to the repo's saved settings, as for
+/// `get_diff`, so alignment indices match what it returned.
+#[tauri::command]
+fn get_change_index(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    use_merge_base: Option<bool>,
+    config: Option<DiffConfig>,
+) -> Result<Vec<ChangeLocation>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::change_index(
+        &repo,
+        &base,
+        &head,
+        use_merge_base.unwrap_or(false),
+        &config,
+    )
+    .map_err(|e| e.0)
+}
+
+/// Get the merge-base of two refs and how many commits each has beyond it.
+#[tauri::command]
+fn get_divergence(repo_path: Option<String>, a: String, b: String) -> Result<Divergence, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::divergence(&repo, &a, &b).map_err(|e| e.0)
+}
+
+/// List the files with uncommitted changes, with their staged and unstaged
+/// status and line counts, for the sidebar.
+#[tauri::command]
+fn get_working_tree_overview(repo_path: Option<String>) -> Result<Vec<WorkingTreeFile>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::working_tree_overview(&repo).map_err(|e| e.0)
+}
+
+/// Count the files and lines changed between two refs, without loading content.
+/// Cheap enough to call before deciding whether to load a huge diff.
+#[tauri::command]
+fn get_diff_summary(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<DiffSummary, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::diff_summary(&repo, &base, &head).map_err(|e| e.0)
+}
+
+/// Get the list of changed files with line counts, without loading content.
+#[tauri::command]
+fn get_changed_paths(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<Vec<ChangedPath>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::get_changed_paths(&repo, &base, &head).map_err(|e| e.0)
+}
+
+/// Per-file added/removed line counts, like `git diff --numstat`. Binary
+/// files have no counts.
+#[tauri::command]
+fn get_numstat(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<Vec<NumstatEntry>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::numstat(&repo, &base, &head).map_err(|e| e.0)
 }
 
 /// Get list of refs (branches, tags, special) with type info for autocomplete.
@@ -92,6 +636,30 @@ fn resolve_ref(repo_path: Option<String>, ref_str: String) -> Result<String, Str
     diff::resolve_ref(&repo, &ref_str).map_err(|e| e.0)
 }
 
+/// Resolve several refs at once, opening the repo only once.
+///
+/// Returns each ref paired with its short SHA or an error message.
+#[tauri::command]
+fn resolve_refs(
+    repo_path: Option<String>,
+    refs: Vec<String>,
+) -> Result<Vec<RefResolution>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    Ok(diff::resolve_refs(&repo, &refs))
+}
+
+/// Check that a base/head pair resolves before starting a review, saying
+/// which side is wrong if not.
+#[tauri::command]
+fn validate_diff_refs(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<DiffRefsValidation, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    Ok(diff::validate_diff_refs(&repo, &base, &head))
+}
+
 // =============================================================================
 // Git Commands
 // =============================================================================
@@ -103,6 +671,44 @@ fn get_repo_info(repo_path: Option<String>) -> Result<RepoInfo, String> {
     diff::get_repo_info(&repo).map_err(|e| e.0)
 }
 
+/// Get the commit HEAD is at, without a general ref lookup. Null if the
+/// repository has no commits yet.
+#[tauri::command]
+fn get_head_sha(repo_path: Option<String>) -> Result<Option<HeadSha>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::head_sha(&repo).map_err(|e| e.0)
+}
+
+/// Get the git operation (merge, rebase, ...) in progress, if any.
+#[tauri::command]
+fn get_operation_state(repo_path: Option<String>) -> Result<OperationState, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    Ok(diff::operation_state(&repo))
+}
+
+/// Get a conflicted file's base, ours, and theirs from the index, with the
+/// merge written out in diff3 format for resolving it in another tool.
+#[tauri::command]
+fn export_conflict(
+    repo_path: Option<String>,
+    path: String,
+    config: Option<DiffConfig>,
+) -> Result<ConflictExport, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::export_conflict(&repo, &path, &config).map_err(|e| e.0)
+}
+
+/// Check whether git ignores `path`, and which rule matched, so the UI can
+/// explain why a file doesn't show up in status.
+#[tauri::command]
+fn is_path_ignored(repo_path: Option<String>, path: String) -> Result<IgnoreStatus, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    diff::path_ignore_status(&repo, &path).map_err(|e| e.0)
+}
+
 /// Get the last commit message (for amend UI).
 #[tauri::command]
 fn get_last_commit_message(repo_path: Option<String>) -> Result<Option<String>, String> {
@@ -110,6 +716,16 @@ fn get_last_commit_message(repo_path: Option<String>) -> Result<Option<String>,
     diff::last_commit_message(&repo).map_err(|e| e.0)
 }
 
+/// Get the messages of the last `n` commits from HEAD, newest first (for reword UI).
+#[tauri::command]
+fn get_recent_commit_messages(
+    repo_path: Option<String>,
+    n: usize,
+) -> Result<Vec<CommitMessage>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::recent_commit_messages(&repo, n).map_err(|e| e.0)
+}
+
 /// Create a commit with the specified files and message.
 ///
 /// Returns the short SHA of the new commit.
@@ -120,9 +736,104 @@ fn create_commit(
     message: String,
 ) -> Result<String, String> {
     let repo = open_repo_from_path(repo_path.as_deref())?;
+    let paths = paths
+        .iter()
+        .map(|path| repo_relative(&repo, path))
+        .collect::<Result<Vec<_>, _>>()?;
     diff::create_commit(&repo, &paths, &message).map_err(|e| e.0)
 }
 
+/// Report what committing the specified files would change, without committing.
+#[tauri::command]
+fn preview_commit(repo_path: Option<String>, paths: Vec<String>) -> Result<CommitPreview, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let paths = paths
+        .iter()
+        .map(|path| repo_relative(&repo, path))
+        .collect::<Result<Vec<_>, _>>()?;
+    diff::commit_preview(&repo, &paths).map_err(|e| e.0)
+}
+
+/// Stage one file as it is in the working tree. Fails if that would
+/// overwrite partially staged changes; see `stage_file_force`.
+#[tauri::command]
+fn stage_file(repo_path: Option<String>, path: String) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    diff::stage_file(&repo, &path).map_err(|e| e.0)
+}
+
+/// Stage one file as it is in the working tree, replacing anything staged.
+#[tauri::command]
+fn stage_file_force(repo_path: Option<String>, path: String) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    diff::stage_file_force(&repo, &path).map_err(|e| e.0)
+}
+
+/// Stage all working-tree changes, optionally including untracked files and
+/// limited to paths under `prefix`. Returns the number of paths staged.
+#[tauri::command]
+fn stage_all(
+    repo_path: Option<String>,
+    include_untracked: Option<bool>,
+    prefix: Option<String>,
+) -> Result<usize, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let prefix = prefix.map(|p| repo_relative(&repo, &p)).transpose()?;
+    diff::stage_all(&repo, include_untracked.unwrap_or(true), prefix.as_deref()).map_err(|e| e.0)
+}
+
+/// Unstage all staged changes, optionally limited to paths under `prefix`.
+/// Returns the number of paths unstaged.
+#[tauri::command]
+fn unstage_all(repo_path: Option<String>, prefix: Option<String>) -> Result<usize, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let prefix = prefix.map(|p| repo_relative(&repo, &p)).transpose()?;
+    diff::unstage_all(&repo, prefix.as_deref()).map_err(|e| e.0)
+}
+
+/// Undo one change (a changed alignment's before/after spans) in a working
+/// tree file, restoring its lines from `base`.
+#[tauri::command]
+fn revert_hunk(
+    repo_path: Option<String>,
+    base: String,
+    path: String,
+    before: Span,
+    after: Span,
+    config: Option<DiffConfig>,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let path = repo_relative(&repo, &path)?;
+    let config = config.unwrap_or_else(|| default_diff_config(&repo));
+    diff::revert_hunk(&repo, &base, &path, before, after, config.algorithm).map_err(|e| e.0)
+}
+
+/// Discard all staged, unstaged, and untracked changes, stashing them first
+/// so `undo_last_discard` can restore them. Returns the stash's SHA.
+#[tauri::command]
+fn discard_all_with_backup(repo_path: Option<String>) -> Result<String, String> {
+    let mut repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::discard_all_with_backup(&mut repo).map_err(|e| e.0)
+}
+
+/// Restore the changes the last `discard_all_with_backup` stashed.
+#[tauri::command]
+fn undo_last_discard(repo_path: Option<String>) -> Result<(), String> {
+    let mut repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::undo_last_discard(&mut repo).map_err(|e| e.0)
+}
+
+/// Reword the last commit without changing its content.
+///
+/// Returns the short SHA of the amended commit.
+#[tauri::command]
+fn amend_commit_message(repo_path: Option<String>, message: String) -> Result<String, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    diff::amend_commit_message(&repo, &message).map_err(|e| e.0)
+}
+
 // =============================================================================
 // GitHub Commands
 // =============================================================================
@@ -176,17 +887,54 @@ fn fetch_pr_branch(
 // =============================================================================
 
 #[tauri::command]
-fn get_review(base: String, head: String) -> Result<Review, String> {
+fn get_review(repo_path: Option<String>, base: String, head: String) -> Result<Review, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
+    let id = make_diff_id(&repo, &base, &head)?;
     store.get_or_create(&id).map_err(|e| e.0)
 }
 
+/// Add a comment, rejecting line ranges outside the file (e.g. from a stale view)
+/// and columns that would split a character.
+/// The file's length is taken at `head`, or at `base` if it was deleted.
 #[tauri::command]
-fn add_comment(base: String, head: String, comment: NewComment) -> Result<Comment, String> {
+fn add_comment(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    mut comment: NewComment,
+) -> Result<Comment, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    comment.path = repo_relative(&repo, &comment.path)?;
+    let line_count = match diff::file_line_count(&repo, &head, &comment.path).map_err(|e| e.0)? {
+        Some(count) => Some(count),
+        None => diff::file_line_count(&repo, &base, &comment.path).map_err(|e| e.0)?,
+    };
+    let line_count = line_count.ok_or_else(|| format!("'{}' is not in this diff", comment.path))?;
+    diff::validate_span(comment.span, line_count).map_err(|e| e.0)?;
+    if let Some(columns) = comment.columns {
+        let config = default_diff_config(&repo);
+        let contents =
+            diff::file_contents(&repo, &base, &head, &comment.path, &config).map_err(|e| e.0)?;
+        let text = contents.after.or(contents.before).unwrap_or_default();
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        diff::validate_columns(comment.span, columns, &lines).map_err(|e| e.0)?;
+    }
+
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
-    let comment = Comment::new(comment.path, comment.span, comment.content);
+    let id = make_diff_id(&repo, &base, &head)?;
+    let NewComment {
+        path,
+        span,
+        content,
+        anchor_lines,
+        columns,
+    } = comment;
+    let mut comment = Comment::new(path, span, content);
+    comment.columns = columns;
+    if let Some(lines) = anchor_lines {
+        comment = comment.anchored_to(&lines);
+    }
     store.add_comment(&id, &comment).map_err(|e| e.0)?;
     Ok(comment)
 }
@@ -203,44 +951,415 @@ fn delete_comment(comment_id: String) -> Result<(), String> {
     store.delete_comment(&comment_id).map_err(|e| e.0)
 }
 
+/// Delete every comment on one file of a review. Returns how many were deleted.
+#[tauri::command]
+fn delete_comments_for_path(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+) -> Result<usize, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
+    store.delete_comments_for_path(&id, &path).map_err(|e| e.0)
+}
+
+/// Delete several comments in one transaction. Returns how many were deleted.
+#[tauri::command]
+fn delete_comments(comment_ids: Vec<String>) -> Result<usize, String> {
+    let store = diff::get_store().map_err(|e| e.0)?;
+    store.delete_comments(&comment_ids).map_err(|e| e.0)
+}
+
+/// Mark a file as reviewed, remembering its content at `head` so a later
+/// change can be flagged by `get_stale_reviewed`.
 #[tauri::command]
-fn mark_reviewed(base: String, head: String, path: String) -> Result<(), String> {
+fn mark_reviewed(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
-    store.mark_reviewed(&id, &path).map_err(|e| e.0)
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
+    let oid = diff::blob_oid(&repo, &head, &path).map_err(|e| e.0)?;
+    store
+        .mark_reviewed(&id, &path, oid.as_deref())
+        .map_err(|e| e.0)
 }
 
+/// List reviewed files whose content at `head` has changed since they were
+/// marked reviewed ("reviewed, but changed since").
 #[tauri::command]
-fn unmark_reviewed(base: String, head: String, path: String) -> Result<(), String> {
+fn get_stale_reviewed(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<Vec<String>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let mut current_oids = HashMap::new();
+    for path in store.get(&id).map_err(|e| e.0)?.reviewed {
+        if let Some(oid) = diff::blob_oid(&repo, &head, &path).map_err(|e| e.0)? {
+            current_oids.insert(path, oid);
+        }
+    }
+    store.stale_reviewed(&id, &current_oids).map_err(|e| e.0)
+}
+
+#[tauri::command]
+fn unmark_reviewed(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
     store.unmark_reviewed(&id, &path).map_err(|e| e.0)
 }
 
+/// Set the note on a whole file; an empty note removes it.
+#[tauri::command]
+fn set_file_note(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    note: String,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
+    store.set_file_note(&id, &path, &note).map_err(|e| e.0)
+}
+
+#[tauri::command]
+fn get_file_note(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+) -> Result<Option<String>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
+    store.get_file_note(&id, &path).map_err(|e| e.0)
+}
+
+/// Get how many comments each file has, for sidebar badges. Files without
+/// comments are left out.
+#[tauri::command]
+fn get_comment_counts(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<HashMap<String, usize>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    store.comment_counts(&id).map_err(|e| e.0)
+}
+
+/// Save the UI state for a file in a diff (expanded regions, folding).
+/// The JSON is the frontend's own; it's stored and returned as is.
+#[tauri::command]
+fn set_view_state(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    state: serde_json::Value,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
+    store
+        .set_view_state(&id, &path, &state.to_string())
+        .map_err(|e| e.0)
+}
+
+#[tauri::command]
+fn get_view_state(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+) -> Result<Option<serde_json::Value>, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &path)?;
+    let state = store.get_view_state(&id, &path).map_err(|e| e.0)?;
+    state
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .transpose()
+}
+
 #[tauri::command]
-fn record_edit(base: String, head: String, edit: NewEdit) -> Result<Edit, String> {
+fn record_edit(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    edit: NewEdit,
+) -> Result<Edit, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
-    let edit = Edit::new(edit.path, edit.diff);
+    let id = make_diff_id(&repo, &base, &head)?;
+    let path = repo_relative(&repo, &edit.path)?;
+    let edit = Edit::new(path, edit.diff);
     store.add_edit(&id, &edit).map_err(|e| e.0)?;
     Ok(edit)
 }
 
+/// Apply a recorded edit to the working tree. Fails without writing
+/// anything if it no longer applies cleanly.
+#[tauri::command]
+fn apply_edit(repo_path: Option<String>, edit: Edit) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let edit = Edit {
+        path: repo_relative(&repo, &edit.path)?,
+        ..edit
+    };
+    diff::apply_edit(&repo, &edit).map_err(|e| e.0)
+}
+
+/// Export a review as markdown.
+///
+/// `aliases` maps renamed files' old paths to new paths, so comments on
+/// either side are grouped under one heading; omitted, renames between
+/// `base` and `head` are detected. `filter` narrows the export
+/// to some paths or kinds of comment; omitted, everything is exported.
+/// Line comments quote the code they're on, if the diff can still be loaded.
+#[tauri::command]
+fn export_review_markdown(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    aliases: Option<PathAliases>,
+    filter: Option<ExportFilter>,
+) -> Result<String, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let review = store.get_or_create(&id).map_err(|e| e.0)?;
+    let review = filter.unwrap_or_default().apply(&review).map_err(|e| e.0)?;
+    let aliases = match aliases {
+        Some(aliases) => aliases,
+        None => diff::renamed_paths(&repo, &base, &head).unwrap_or_else(|e| {
+            log::warn!("Exporting review without rename detection: {}", e.0);
+            PathAliases::new()
+        }),
+    };
+
+    // Only the commented files are diffed, plus the other side of renames
+    let mut paths: Vec<String> = review.comments.iter().map(|c| c.path.clone()).collect();
+    for (old, new) in &aliases {
+        if paths.contains(old) || paths.contains(new) {
+            paths.extend([old.clone(), new.clone()]);
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    let config = default_diff_config(&repo);
+    let files = match diff::full_file_diffs(&repo, &base, &head, false, &paths, &config) {
+        Ok(files) => files,
+        Err(e) => {
+            log::warn!("Exporting review without code context: {}", e.0);
+            Vec::new()
+        }
+    };
+    Ok(diff::export_markdown(&review, Some(&aliases), &files))
+}
+
+/// Draft a PR description for a review: the diff's size plus the review's
+/// comments and edited files, as markdown.
+#[tauri::command]
+fn generate_review_summary(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+) -> Result<String, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let summary = diff::diff_summary(&repo, &base, &head).map_err(|e| e.0)?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let id = make_diff_id(&repo, &base, &head)?;
+    let review = store.get_or_create(&id).map_err(|e| e.0)?;
+    Ok(diff::summary_markdown(&review, &summary))
+}
+
+/// Export a review as JSON lines to `path` (picked by the user in a save
+/// dialog), streaming instead of building the whole export in memory.
 #[tauri::command]
-fn export_review_markdown(base: String, head: String) -> Result<String, String> {
+fn export_review_jsonl(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    path: String,
+    filter: Option<ExportFilter>,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
+    let id = make_diff_id(&repo, &base, &head)?;
     let review = store.get_or_create(&id).map_err(|e| e.0)?;
-    Ok(diff::export_markdown(&review))
+    let review = filter.unwrap_or_default().apply(&review).map_err(|e| e.0)?;
+    let file =
+        std::fs::File::create(&path).map_err(|e| format!("Cannot create {}: {}", path, e))?;
+    diff::export_jsonl_to_writer(&review, std::io::BufWriter::new(file)).map_err(|e| e.0)
 }
 
 #[tauri::command]
-fn clear_review(base: String, head: String) -> Result<(), String> {
+fn clear_review(repo_path: Option<String>, base: String, head: String) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
     let store = diff::get_store().map_err(|e| e.0)?;
-    let id = make_diff_id(None, &base, &head)?;
+    let id = make_diff_id(&repo, &base, &head)?;
     store.delete(&id).map_err(|e| e.0)
 }
 
+/// Delete every stored review and shrink the database file. The frontend
+/// asks for confirmation before calling this.
+#[tauri::command]
+fn clear_all_reviews() -> Result<(), String> {
+    let store = diff::get_store().map_err(|e| e.0)?;
+    store.clear_all().map_err(|e| e.0)?;
+    store.vacuum().map_err(|e| e.0)
+}
+
+/// Delete reviews whose commits exist in none of `repo_paths`, then shrink
+/// the database file. Reviews aren't stored per repository, so pass every
+/// repository they may belong to (e.g. the recent repos list).
+/// Returns how many reviews were deleted.
+#[tauri::command]
+fn prune_reviews(repo_paths: Vec<String>) -> Result<usize, String> {
+    let repos: Vec<git2::Repository> = repo_paths
+        .iter()
+        .filter_map(|path| match open_repo_from_path(Some(path)) {
+            Ok(repo) => Some(repo),
+            Err(e) => {
+                log::warn!("Skipping {} when pruning reviews: {}", path, e);
+                None
+            }
+        })
+        .collect();
+    // With nothing to check against, every review would look stale
+    if repos.is_empty() {
+        return Err("No repositories to check reviews against".to_string());
+    }
+
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let pruned = store
+        .prune_missing(|r| {
+            r == diff::EMPTY || repos.iter().any(|repo| repo.revparse_single(r).is_ok())
+        })
+        .map_err(|e| e.0)?;
+    store.vacuum().map_err(|e| e.0)?;
+    Ok(pruned)
+}
+
+/// Move a review from `base..head` to `new_base..new_head`, e.g. after a
+/// branch rename, keeping its comments, edits, and reviewed files.
+#[tauri::command]
+fn move_review(
+    repo_path: Option<String>,
+    base: String,
+    head: String,
+    new_base: String,
+    new_head: String,
+) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let old = make_diff_id(&repo, &base, &head)?;
+    let new = make_diff_id(&repo, &new_base, &new_head)?;
+    store.rekey(&old, &new).map_err(|e| e.0)
+}
+
+// =============================================================================
+// Repo Settings Commands
+// =============================================================================
+
+/// Load the repository's saved settings, with defaults for anything unset.
+#[tauri::command]
+fn get_repo_settings(repo_path: Option<String>) -> Result<RepoSettings, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    store.repo_settings(&settings_key(&repo)).map_err(|e| e.0)
+}
+
+/// Report the diff options the backend uses for this repository: its
+/// settings over the defaults, which of them it overrides, and the git
+/// config that affects comparison (autocrlf, eol).
+#[tauri::command]
+fn effective_diff_options(repo_path: Option<String>) -> Result<EffectiveDiffOptions, String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    let key = settings_key(&repo);
+    let settings = store.repo_settings(&key).map_err(|e| e.0)?;
+    Ok(diff::effective_diff_options(
+        &repo,
+        settings.diff_config(),
+        settings.overrides(),
+    ))
+}
+
+/// Save the repository's settings; diffs requested without options use them.
+#[tauri::command]
+fn save_repo_settings(repo_path: Option<String>, settings: RepoSettings) -> Result<(), String> {
+    let repo = open_repo_from_path(repo_path.as_deref())?;
+    let store = diff::get_store().map_err(|e| e.0)?;
+    store
+        .save_repo_settings(&settings_key(&repo), &settings)
+        .map_err(|e| e.0)
+}
+
+// =============================================================================
+// Editor Commands
+// =============================================================================
+
+/// Open a file in the user's editor, at `line` (1-indexed) if given.
+///
+/// The editor comes from the repo settings, then `$VISUAL`, `$EDITOR`, or
+/// `core.editor`; with none of them set this fails with `not_configured`.
+#[tauri::command]
+fn open_in_editor(
+    repo_path: Option<String>,
+    path: String,
+    line: Option<u32>,
+) -> Result<(), editor::EditorError> {
+    let failed = editor::EditorError::Failed;
+    let repo = open_repo_from_path(repo_path.as_deref()).map_err(failed)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| failed("Bare repository has no files to open".to_string()))?;
+    let file = workdir.join(repo_relative(&repo, &path).map_err(failed)?);
+
+    let store = diff::get_store().map_err(|e| failed(e.0))?;
+    let settings = store
+        .repo_settings(&settings_key(&repo))
+        .map_err(|e| failed(e.0))?;
+    let template = editor::resolve_editor(settings.editor.as_deref(), &repo)?;
+    editor::open_in_editor(&template, &file, line.unwrap_or(1))
+}
+
+// =============================================================================
+// Diagnostics Commands
+// =============================================================================
+
+/// Report environment facts (libgit2, CLI tools, repo shape) for bug reports.
+#[tauri::command]
+fn get_diagnostics(repo_path: Option<String>) -> diagnostics::Diagnostics {
+    diagnostics::collect(std::path::Path::new(repo_path.as_deref().unwrap_or(".")))
+}
+
 // =============================================================================
 // Theme Commands
 // =============================================================================
@@ -306,6 +1425,8 @@ fn read_json_file(path: String) -> Result<String, String> {
 /// Wrapped in Option because it's created during setup with the AppHandle.
 struct RefreshControllerState(Mutex<Option<RefreshController>>);
 
+/// Start watching a repository, alongside any already being watched. Its
+/// events carry its `repo_path`.
 #[tauri::command]
 async fn start_watching(
     repo_path: String,
@@ -319,11 +1440,35 @@ async fn start_watching(
     }
 }
 
+/// The repositories currently being watched.
 #[tauri::command]
-fn stop_watching(state: State<RefreshControllerState>) -> Result<(), String> {
+fn get_watched_repos(state: State<RefreshControllerState>) -> Vec<String> {
+    let controller = state.0.lock().unwrap();
+    controller
+        .as_ref()
+        .map(|ctrl| {
+            ctrl.watched_repos()
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Stop watching `repo_path`, or every repository if omitted.
+#[tauri::command]
+fn stop_watching(
+    repo_path: Option<String>,
+    state: State<RefreshControllerState>,
+) -> Result<(), String> {
     let controller = state.0.lock().unwrap();
     if let Some(ref ctrl) = *controller {
-        ctrl.stop();
+        match repo_path {
+            Some(path) => {
+                ctrl.stop(Path::new(&path));
+            }
+            None => ctrl.stop_all(),
+        }
         Ok(())
     } else {
         Err("Refresh controller not initialized".to_string())
@@ -334,39 +1479,131 @@ fn stop_watching(state: State<RefreshControllerState>) -> Result<(), String> {
 // Tauri App Setup
 // =============================================================================
 
+/// Environment variable setting the log level, e.g. `STAGED_LOG=debug`.
+/// Setting it also writes the log to a file in the app data dir.
+const LOG_ENV: &str = "STAGED_LOG";
+
+/// Size at which the log file is rotated; the previous one is kept alongside
+/// it, with the date of the rotation in its name.
+const MAX_LOG_FILE_BYTES: u128 = 5 * 1024 * 1024;
+
+/// Parse a log level name (`off`, `error`, `warn`, `info`, `debug`, `trace`),
+/// ignoring case and surrounding whitespace.
+fn parse_log_level(value: &str) -> Option<log::LevelFilter> {
+    value.trim().parse().ok()
+}
+
+/// The logging plugin: to the console in debug builds, and to a rotating
+/// `staged.log` in `log_dir` when `STAGED_LOG` is set (to stderr if there's
+/// no `log_dir`).
+fn log_plugin(log_dir: Option<PathBuf>) -> tauri::plugin::TauriPlugin<tauri::Wry> {
+    use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+
+    let env_level = std::env::var(LOG_ENV).ok();
+    let level = env_level
+        .as_deref()
+        .and_then(parse_log_level)
+        .unwrap_or(log::LevelFilter::Info);
+
+    let mut builder = tauri_plugin_log::Builder::new()
+        .clear_targets()
+        .level(level);
+    if cfg!(debug_assertions) {
+        builder = builder.target(Target::new(TargetKind::Stdout));
+    }
+    if env_level.is_some() {
+        match log_dir {
+            Some(path) => {
+                builder = builder
+                    .target(Target::new(TargetKind::Folder {
+                        path,
+                        file_name: Some("staged".to_string()),
+                    }))
+                    .max_file_size(MAX_LOG_FILE_BYTES)
+                    .rotation_strategy(RotationStrategy::KeepSome(2));
+            }
+            None => builder = builder.target(Target::new(TargetKind::Stderr)),
+        }
+    }
+    builder.build()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .manage(RefreshControllerState(Mutex::new(None)))
+        .manage(DiffCancellation::default())
         .setup(|app| {
+            // Logging first, so whatever is logged while starting up is kept,
+            // e.g. `init_store` moving a corrupted database aside
+            let log_dir = app.path().app_data_dir().map(|dir| dir.join("logs"));
+            app.handle()
+                .plugin(log_plugin(log_dir.as_ref().ok().cloned()))?;
+            if let Err(e) = &log_dir {
+                log::error!("Cannot get app data dir for the log file: {}", e);
+            }
+
             // Initialize the review store with app data directory
             diff::init_store(app.handle()).map_err(|e| e.0)?;
 
             // Initialize the refresh controller with the app handle
-            let controller = RefreshController::new(app.handle().clone());
+            let controller = RefreshController::new(
+                app.handle().clone(),
+                Duration::from_millis(refresh::DEFAULT_SLOW_REPO_THRESHOLD_MS),
+            );
             let state: State<RefreshControllerState> = app.state();
             *state.0.lock().unwrap() = Some(controller);
-
-            if cfg!(debug_assertions) {
-                app.handle().plugin(
-                    tauri_plugin_log::Builder::default()
-                        .level(log::LevelFilter::Info)
-                        .build(),
-                )?;
-            }
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             // Diff commands
             get_diff,
+            stream_diff,
+            get_outgoing_diff,
+            get_incoming_diff,
+            get_commit_log,
+            get_commit_diff,
+            get_per_commit_diffs,
+            get_external_file_diff,
+            get_file_patch,
+            get_blame,
+            get_change_density,
+            get_hunk_slices,
+            get_change_index,
+            get_file_contents,
+            get_file_ref_diff,
+            get_file_triple_diff,
+            get_blob_bytes,
+            trace_rename,
+            get_changed_paths,
+            get_numstat,
+            get_diff_summary,
+            get_working_tree_overview,
+            get_divergence,
             get_refs,
             resolve_ref,
+            resolve_refs,
+            validate_diff_refs,
             // Git commands
             get_repo_info,
+            get_head_sha,
+            get_operation_state,
+            export_conflict,
+            is_path_ignored,
             get_last_commit_message,
+            get_recent_commit_messages,
             create_commit,
+            preview_commit,
+            stage_file,
+            stage_file_force,
+            stage_all,
+            unstage_all,
+            revert_hunk,
+            discard_all_with_backup,
+            undo_last_discard,
+            amend_commit_message,
             // GitHub commands
             check_github_auth,
             list_pull_requests,
@@ -376,11 +1613,33 @@ pub fn run() {
             add_comment,
             update_comment,
             delete_comment,
+            delete_comments_for_path,
+            delete_comments,
             mark_reviewed,
             unmark_reviewed,
+            get_stale_reviewed,
+            set_file_note,
+            get_file_note,
+            get_comment_counts,
+            set_view_state,
+            get_view_state,
             record_edit,
+            apply_edit,
             export_review_markdown,
+            export_review_jsonl,
+            generate_review_summary,
             clear_review,
+            clear_all_reviews,
+            prune_reviews,
+            move_review,
+            // Repo settings commands
+            get_repo_settings,
+            save_repo_settings,
+            effective_diff_options,
+            // Editor commands
+            open_in_editor,
+            // Diagnostics commands
+            get_diagnostics,
             // Theme commands
             get_custom_themes,
             read_custom_theme,
@@ -392,7 +1651,162 @@ pub fn run() {
             // Watcher commands
             start_watching,
             stop_watching,
+            get_watched_repos,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Make sure review data is in the database file before quitting
+                if let Ok(store) = diff::get_store() {
+                    if let Err(e) = store.checkpoint() {
+                        log::warn!("Failed to checkpoint review database: {}", e);
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level("debug"), Some(log::LevelFilter::Debug));
+        assert_eq!(parse_log_level(" WARN\n"), Some(log::LevelFilter::Warn));
+        assert_eq!(parse_log_level("Trace"), Some(log::LevelFilter::Trace));
+        assert_eq!(parse_log_level("off"), Some(log::LevelFilter::Off));
+        assert_eq!(parse_log_level("loud"), None);
+        assert_eq!(parse_log_level(""), None);
+    }
+
+    struct Recorder(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl EventSink for Recorder {
+        fn send(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+            self.0.lock().unwrap().push((event.to_string(), payload));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_diff_cancellation_is_per_repo() {
+        let (dir_a, dir_b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let repo_a = git2::Repository::init(dir_a.path()).unwrap();
+        let repo_b = git2::Repository::init(dir_b.path()).unwrap();
+        let cancellation = DiffCancellation::default();
+
+        let first = cancellation.next(&repo_a);
+        let other = cancellation.next(&repo_b);
+        assert!(!first.load(Ordering::Relaxed));
+
+        let second = cancellation.next(&repo_a);
+        assert!(first.load(Ordering::Relaxed));
+        assert!(!second.load(Ordering::Relaxed));
+        assert!(!other.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_stream_diff_sends_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        for name in ["a.txt", "b.txt", "clean.txt"] {
+            std::fs::write(dir.path().join(name), "one\n").unwrap();
+        }
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+        std::fs::write(dir.path().join("a.txt"), "two\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "two\n").unwrap();
+        std::fs::write(dir.path().join("new.txt"), "new\n").unwrap();
+
+        let events = Recorder(Mutex::new(Vec::new()));
+        let count = stream_diff_to(
+            &events,
+            &repo,
+            "HEAD",
+            diff::WORKDIR,
+            false,
+            &DiffConfig::default(),
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+
+        let events = events.0.into_inner().unwrap();
+        let (ready, complete) = events.split_at(events.len() - 1);
+        let mut paths: Vec<&str> = ready
+            .iter()
+            .map(|(event, payload)| {
+                assert_eq!(event, EVENT_DIFF_FILE_READY);
+                assert_eq!(payload["head"], diff::WORKDIR);
+                payload["file"]["after"]["path"].as_str().unwrap()
+            })
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["a.txt", "b.txt", "new.txt"]);
+        assert_eq!(complete[0].0, EVENT_DIFF_COMPLETE);
+        assert_eq!(complete[0].1["file_count"], 3);
+
+        // A cancelled diff sends nothing
+        let events = Recorder(Mutex::new(Vec::new()));
+        let cancelled = AtomicBool::new(true);
+        let config = DiffConfig::default();
+        assert!(stream_diff_to(
+            &events,
+            &repo,
+            "HEAD",
+            diff::WORKDIR,
+            false,
+            &config,
+            &cancelled
+        )
+        .is_err());
+        assert!(events.0.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_deleted_branch_keeps_review() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let oid = repo
+            .commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+        let commit = repo.find_commit(oid).unwrap();
+        repo.branch("feature", &commit, false).unwrap();
+
+        let store = ReviewStore::open(dir.path().join("reviews.db")).unwrap();
+        let sha = oid.to_string();
+        assert_eq!(
+            resolve_for_storage_in(&repo, "feature", Some(&store)).unwrap(),
+            sha
+        );
+        let id = DiffId::new(sha.clone(), diff::WORKDIR.to_string());
+        store.get_or_create(&id).unwrap();
+
+        repo.find_branch("feature", git2::BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        // Without the remembered SHA the name no longer resolves
+        assert!(resolve_for_storage_in(&repo, "feature", None).is_err());
+        let resolved = resolve_for_storage_in(&repo, "feature", Some(&store)).unwrap();
+        assert_eq!(resolved, sha);
+        let review = store
+            .get(&DiffId::new(resolved, diff::WORKDIR.to_string()))
+            .unwrap();
+        assert_eq!(review.id, id);
+
+        // Names never seen still fail
+        assert!(resolve_for_storage_in(&repo, "never-existed", Some(&store)).is_err());
+    }
 }
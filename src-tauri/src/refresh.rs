@@ -2,123 +2,657 @@
 //!
 //! This module ties together the watcher and event emission, handling:
 //! - Throttling (don't notify too frequently)
+//! - Status deltas (which paths appeared, disappeared, changed, or moved
+//!   between staged, unstaged and untracked)
+//! - Slow-repo detection (status snapshots that take too long)
 //!
 //! All policy decisions live here, making them easy to modify or remove.
 
+use crate::diff::types::ChangeKind;
+use crate::diff::{self, StatusBucket, StatusEntry};
 use crate::watcher::{NotifyWatcher, WatcherManager};
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tauri::{AppHandle, Emitter};
 
 /// Event name for file change notifications sent to frontend.
-/// Payload is empty - frontend decides what to refresh.
+/// Payload is `FilesChanged` - frontend decides what to refresh.
 pub const EVENT_FILES_CHANGED: &str = "files-changed";
 
+/// Event name for incremental status updates, sent after a watch-triggered
+/// `EVENT_FILES_CHANGED` when the working tree's status differs.
+/// Payload is `StatusDelta`.
+pub const EVENT_STATUS_DELTA: &str = "status-delta";
+
+/// Event name for slow-repo notifications, sent when status snapshots cross
+/// the slow threshold in either direction. Payload is `RepoSpeed`.
+pub const EVENT_REPO_SPEED_CHANGED: &str = "repo-speed-changed";
+
 /// Minimum interval between notifications (1 second)
 const MIN_THROTTLE_INTERVAL_MS: u64 = 1000;
 
-/// State shared between the watcher callback and the controller
-struct RefreshState {
-    last_notify: Instant,
-    repo_path: Option<PathBuf>,
+/// Default time a status snapshot may take before the repo counts as slow
+pub const DEFAULT_SLOW_REPO_THRESHOLD_MS: u64 = 2000;
+
+/// Consecutive fast snapshots needed before a slow repo counts as fast again,
+/// so one lucky refresh doesn't flap the notification
+const FAST_REFRESHES_TO_RECOVER: u32 = 3;
+
+/// What triggered a files-changed notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    /// Sent immediately when watching starts
+    Initial,
+    /// Sent by the file watcher after a relevant change
+    Watch,
 }
 
-impl Default for RefreshState {
-    fn default() -> Self {
+/// Payload for `EVENT_FILES_CHANGED`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilesChanged {
+    pub source: ChangeSource,
+    /// Milliseconds since the previous notification (None for the first one)
+    pub since_last_ms: Option<u64>,
+}
+
+impl FilesChanged {
+    fn new(source: ChangeSource, last_notify: Option<Instant>) -> Self {
         Self {
-            last_notify: Instant::now() - Duration::from_secs(10), // Allow immediate first notify
-            repo_path: None,
+            source,
+            since_last_ms: last_notify.map(|t| t.elapsed().as_millis() as u64),
         }
     }
 }
 
-/// Orchestrates file watching and change event emission.
-pub struct RefreshController {
-    watcher: Mutex<NotifyWatcher>,
+/// A path whose change left one status bucket for another, such as a
+/// modified file being staged.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusMove {
+    pub path: String,
+    pub from: StatusBucket,
+    pub to: StatusBucket,
+    /// The change as it now stands in `to`
+    pub status: ChangeKind,
+}
+
+/// How the working tree's status entries moved between refreshes. Entries
+/// are keyed by path and bucket.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StatusDelta {
+    /// Entries that weren't there before
+    pub added: Vec<StatusEntry>,
+    /// Entries that are gone
+    pub removed: Vec<StatusEntry>,
+    /// Entries still there, but with a different change kind
+    pub changed: Vec<StatusEntry>,
+    /// Paths that left one bucket and entered another
+    pub moved: Vec<StatusMove>,
+}
+
+impl StatusDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.moved.is_empty()
+    }
+}
+
+/// Compare two status snapshots. Output follows the order of `new` (and
+/// `old` for removals). A path that disappears from one bucket and appears
+/// in another is reported as a move rather than a removal plus an addition.
+pub fn diff_status(old: &[StatusEntry], new: &[StatusEntry]) -> StatusDelta {
+    let key = |e: &StatusEntry| (e.path.clone(), e.bucket);
+    let previous: HashMap<(String, StatusBucket), &StatusEntry> =
+        old.iter().map(|e| (key(e), e)).collect();
+    let current: HashMap<(String, StatusBucket), &StatusEntry> =
+        new.iter().map(|e| (key(e), e)).collect();
+
+    let mut delta = StatusDelta::default();
+    let mut removed: Vec<&StatusEntry> = old
+        .iter()
+        .filter(|e| !current.contains_key(&key(e)))
+        .collect();
+    for entry in new {
+        match previous.get(&key(entry)) {
+            Some(before) if *before != entry => delta.changed.push(entry.clone()),
+            Some(_) => {}
+            None => match removed.iter().position(|r| r.path == entry.path) {
+                Some(i) => {
+                    let from = removed.remove(i).bucket;
+                    delta.moved.push(StatusMove {
+                        path: entry.path.clone(),
+                        from,
+                        to: entry.bucket,
+                        status: entry.status,
+                    });
+                }
+                None => delta.added.push(entry.clone()),
+            },
+        }
+    }
+    delta.removed = removed.into_iter().cloned().collect();
+    delta
+}
+
+/// Payload for `EVENT_REPO_SPEED_CHANGED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RepoSpeed {
+    /// True when the repo just became slow, false when it recovered
+    pub slow: bool,
+    /// How long the status snapshot that triggered this took
+    pub duration_ms: u64,
+}
+
+/// Tracks status snapshot times and decides when to notify about speed.
+#[derive(Debug)]
+struct SpeedTracker {
+    threshold: Duration,
+    slow_notification_sent: bool,
+    fast_streak: u32,
+}
+
+impl SpeedTracker {
+    fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            slow_notification_sent: false,
+            fast_streak: 0,
+        }
+    }
+
+    /// Record one snapshot's duration, returning a notification if the repo
+    /// crossed the threshold: once when it turns slow, and again once it has
+    /// stayed under the threshold for `FAST_REFRESHES_TO_RECOVER` snapshots.
+    fn record(&mut self, elapsed: Duration) -> Option<RepoSpeed> {
+        let speed = |slow| RepoSpeed {
+            slow,
+            duration_ms: elapsed.as_millis() as u64,
+        };
+
+        if elapsed >= self.threshold {
+            self.fast_streak = 0;
+            if !self.slow_notification_sent {
+                self.slow_notification_sent = true;
+                return Some(speed(true));
+            }
+        } else if self.slow_notification_sent {
+            self.fast_streak += 1;
+            if self.fast_streak >= FAST_REFRESHES_TO_RECOVER {
+                self.slow_notification_sent = false;
+                self.fast_streak = 0;
+                return Some(speed(false));
+            }
+        }
+        None
+    }
+}
+
+impl Default for SpeedTracker {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(DEFAULT_SLOW_REPO_THRESHOLD_MS))
+    }
+}
+
+/// Snapshot the working tree's status.
+fn status_snapshot(repo_path: &Path) -> Result<Vec<StatusEntry>, String> {
+    let repo = diff::open_repo(repo_path).map_err(|e| e.0)?;
+    diff::status_entries(&repo).map_err(|e| e.0)
+}
+
+/// Where the controller's events go: the frontend in the app, a recorder in
+/// tests.
+pub trait EventSink: Send + Sync + 'static {
+    fn send(&self, event: &str, payload: serde_json::Value) -> Result<(), String>;
+}
+
+impl EventSink for AppHandle {
+    fn send(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.emit(event, payload).map_err(|e| e.to_string())
+    }
+}
+
+/// An event payload tagged with the repository it's about, so events from
+/// several watched repositories can be told apart.
+#[derive(Serialize)]
+struct RepoEvent<'a, T: Serialize> {
+    repo_path: &'a Path,
+    #[serde(flatten)]
+    payload: T,
+}
+
+/// Emit `payload` tagged with `repo_path`, logging (not returning) failures.
+fn emit<E: EventSink>(events: &E, event: &str, repo_path: &Path, payload: impl Serialize) {
+    let result = serde_json::to_value(RepoEvent { repo_path, payload })
+        .map_err(|e| e.to_string())
+        .and_then(|value| events.send(event, value));
+    if let Err(e) = result {
+        log::error!("Failed to emit {} event: {}", event, e);
+    }
+}
+
+/// State shared between a repository's watcher callback and the controller
+#[derive(Default)]
+struct RefreshState {
+    /// When we last notified (None = never, so the first notify isn't throttled)
+    last_notify: Option<Instant>,
+    /// None once watching stopped, so late watcher callbacks do nothing
+    repo_path: Option<PathBuf>,
+    /// Status at the last notification, for computing deltas
+    last_status: Option<Vec<StatusEntry>>,
+    speed: SpeedTracker,
+}
+
+/// One watched repository.
+struct RepoWatch {
+    watcher: NotifyWatcher,
     state: Arc<Mutex<RefreshState>>,
-    app_handle: AppHandle,
 }
 
-impl RefreshController {
-    /// Create a new refresh controller.
-    pub fn new(app_handle: AppHandle) -> Self {
+/// Orchestrates file watching and change event emission.
+///
+/// Any number of repositories can be watched at once; each has its own
+/// watcher, throttle, and status baseline, and every event it sends carries
+/// its `repo_path`.
+pub struct RefreshController<E: EventSink = AppHandle> {
+    repos: Mutex<HashMap<PathBuf, RepoWatch>>,
+    events: Arc<E>,
+    slow_threshold: Duration,
+}
+
+impl<E: EventSink> RefreshController<E> {
+    /// Create a new refresh controller. Status snapshots slower than
+    /// `slow_threshold` mark the repo as slow.
+    pub fn new(events: E, slow_threshold: Duration) -> Self {
         Self {
-            watcher: Mutex::new(NotifyWatcher::new()),
-            state: Arc::new(Mutex::new(RefreshState::default())),
-            app_handle,
+            repos: Mutex::new(HashMap::new()),
+            events: Arc::new(events),
+            slow_threshold,
         }
     }
 
-    /// Start watching a repository for changes.
-    /// Stops any existing watcher first.
+    /// Start watching a repository for changes, alongside any others
+    /// already watched. Restarts the watch if it's already watched.
     pub fn start(&self, repo_path: PathBuf) -> Result<(), String> {
-        // Reset state for new repo
-        {
-            let mut state = self.state.lock().unwrap();
-            *state = RefreshState::default();
-            state.repo_path = Some(repo_path.clone());
-        }
+        self.stop(&repo_path);
 
-        // Set up the callback that will be called on FS changes
-        let state = Arc::clone(&self.state);
-        let app_handle = self.app_handle.clone();
+        let state = Arc::new(Mutex::new(RefreshState {
+            repo_path: Some(repo_path.clone()),
+            speed: SpeedTracker::new(self.slow_threshold),
+            ..Default::default()
+        }));
 
-        let on_change = Box::new(move || {
-            Self::handle_change(&state, &app_handle);
-        });
+        // Set up the callback that will be called on FS changes
+        let on_change = {
+            let state = Arc::clone(&state);
+            let events = Arc::clone(&self.events);
+            Box::new(move || {
+                Self::handle_change(&state, events.as_ref(), ChangeSource::Watch);
+            })
+        };
 
         // Start the watcher
-        let mut watcher = self.watcher.lock().unwrap();
+        let mut watcher = NotifyWatcher::new();
         watcher
             .start(&repo_path, on_change)
             .map_err(|e| e.message)?;
+        self.repos.lock().unwrap().insert(
+            repo_path,
+            RepoWatch {
+                watcher,
+                state: Arc::clone(&state),
+            },
+        );
 
         // Do an initial notification immediately
-        Self::handle_change(&self.state, &self.app_handle);
+        Self::handle_change(&state, self.events.as_ref(), ChangeSource::Initial);
 
         Ok(())
     }
 
-    /// Stop watching the current repository.
-    pub fn stop(&self) {
-        let mut watcher = self.watcher.lock().unwrap();
-        watcher.stop();
+    /// Stop watching one repository. Returns false if it wasn't watched.
+    pub fn stop(&self, repo_path: &Path) -> bool {
+        let watch = self.repos.lock().unwrap().remove(repo_path);
+        match watch {
+            Some(watch) => {
+                Self::stop_watch(watch);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop watching every repository.
+    pub fn stop_all(&self) {
+        let watches: Vec<RepoWatch> = self.repos.lock().unwrap().drain().map(|(_, w)| w).collect();
+        for watch in watches {
+            Self::stop_watch(watch);
+        }
+    }
+
+    /// The repositories being watched, sorted.
+    pub fn watched_repos(&self) -> Vec<PathBuf> {
+        let mut repos: Vec<PathBuf> = self.repos.lock().unwrap().keys().cloned().collect();
+        repos.sort();
+        repos
+    }
 
-        let mut state = self.state.lock().unwrap();
-        state.repo_path = None;
+    fn stop_watch(mut watch: RepoWatch) {
+        watch.watcher.stop();
+        watch.state.lock().unwrap().repo_path = None;
     }
 
     /// Handle a file system change event.
     /// This is called by the watcher when relevant files change.
-    fn handle_change(state: &Arc<Mutex<RefreshState>>, app_handle: &AppHandle) {
+    fn handle_change(state: &Arc<Mutex<RefreshState>>, events: &E, source: ChangeSource) {
         // Check throttle
-        {
+        let (last_notify, repo_path) = {
             let state = state.lock().unwrap();
-            if state.repo_path.is_none() {
+            let Some(repo_path) = state.repo_path.clone() else {
                 return; // No repo to watch
-            }
+            };
 
             let throttle_interval = Duration::from_millis(MIN_THROTTLE_INTERVAL_MS);
-            if state.last_notify.elapsed() < throttle_interval {
-                log::debug!(
-                    "Throttled: {}ms since last notify, need {}ms",
-                    state.last_notify.elapsed().as_millis(),
-                    throttle_interval.as_millis()
-                );
-                return;
+            if let Some(last) = state.last_notify {
+                if last.elapsed() < throttle_interval {
+                    log::debug!(
+                        "Throttled: {}ms since last notify, need {}ms",
+                        last.elapsed().as_millis(),
+                        throttle_interval.as_millis()
+                    );
+                    return;
+                }
             }
-        }
+            (state.last_notify, repo_path)
+        };
 
         // Update state
         {
             let mut state = state.lock().unwrap();
-            state.last_notify = Instant::now();
+            state.last_notify = Some(Instant::now());
         }
 
-        // Emit change notification to frontend (empty payload)
-        if let Err(e) = app_handle.emit(EVENT_FILES_CHANGED, ()) {
-            log::error!("Failed to emit files-changed event: {}", e);
+        // Emit change notification to frontend
+        let payload = FilesChanged::new(source, last_notify);
+        emit(events, EVENT_FILES_CHANGED, &repo_path, payload);
+
+        // Follow up with what moved since last time. Initial loads only get
+        // the full notification; they just record the baseline.
+        let started = Instant::now();
+        let status = match status_snapshot(&repo_path) {
+            Ok(status) => status,
+            Err(e) => {
+                log::warn!("Failed to compute status delta: {}", e);
+                return;
+            }
+        };
+        let (previous, speed) = {
+            let mut state = state.lock().unwrap();
+            let speed = state.speed.record(started.elapsed());
+            (state.last_status.replace(status.clone()), speed)
+        };
+
+        if let Some(speed) = speed {
+            log::info!(
+                "{} is {} ({}ms status)",
+                repo_path.display(),
+                if speed.slow { "slow" } else { "fast again" },
+                speed.duration_ms
+            );
+            emit(events, EVENT_REPO_SPEED_CHANGED, &repo_path, speed);
         }
+
+        if let (ChangeSource::Watch, Some(previous)) = (source, previous) {
+            let delta = diff_status(&previous, &status);
+            if !delta.is_empty() {
+                emit(events, EVENT_STATUS_DELTA, &repo_path, delta);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_changed_payload() {
+        let first = FilesChanged::new(ChangeSource::Initial, None);
+        assert_eq!(first.since_last_ms, None);
+        assert_eq!(
+            serde_json::to_value(&first).unwrap(),
+            serde_json::json!({ "source": "initial", "since_last_ms": null })
+        );
+
+        let last = Instant::now() - Duration::from_millis(1500);
+        let later = FilesChanged::new(ChangeSource::Watch, Some(last));
+        assert!(later.since_last_ms.unwrap() >= 1500);
+        assert_eq!(serde_json::to_value(&later).unwrap()["source"], "watch");
+    }
+
+    /// Records events instead of sending them to a frontend.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl EventSink for Recorder {
+        fn send(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+            self.0.lock().unwrap().push((event.to_string(), payload));
+            Ok(())
+        }
+    }
+
+    impl<E: EventSink> RefreshController<E> {
+        /// Run a watcher-triggered refresh of `repo_path` now, unthrottled.
+        fn trigger(&self, repo_path: &Path) {
+            let state = Arc::clone(&self.repos.lock().unwrap()[repo_path].state);
+            state.lock().unwrap().last_notify = None;
+            Self::handle_change(&state, self.events.as_ref(), ChangeSource::Watch);
+        }
+    }
+
+    #[test]
+    fn test_repos_emit_independently() {
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let [a, b] = [0, 1].map(|i| {
+            git2::Repository::init(dirs[i].path()).unwrap();
+            dirs[i].path().to_path_buf()
+        });
+        let controller = RefreshController::new(Recorder::default(), Duration::from_secs(60));
+        let take = || std::mem::take(&mut *controller.events.0.lock().unwrap());
+        let tagged = |events: &[(String, serde_json::Value)]| -> Vec<(String, String)> {
+            events
+                .iter()
+                .map(|(name, payload)| {
+                    let repo = payload["repo_path"].as_str().unwrap().to_string();
+                    (name.clone(), repo)
+                })
+                .collect()
+        };
+        let path = |p: &PathBuf| p.to_string_lossy().to_string();
+
+        controller.start(a.clone()).unwrap();
+        controller.start(b.clone()).unwrap();
+        assert_eq!(controller.watched_repos().len(), 2);
+        let events = take();
+        assert_eq!(
+            tagged(&events),
+            [
+                (EVENT_FILES_CHANGED.to_string(), path(&a)),
+                (EVENT_FILES_CHANGED.to_string(), path(&b)),
+            ]
+        );
+        // Payload fields stay at the top level next to the tag
+        assert_eq!(events[0].1["source"], "initial");
+
+        // A change in one repo only reports that repo
+        std::fs::write(b.join("new.txt"), "hi\n").unwrap();
+        controller.trigger(&b);
+        let events = take();
+        assert_eq!(
+            tagged(&events),
+            [
+                (EVENT_FILES_CHANGED.to_string(), path(&b)),
+                (EVENT_STATUS_DELTA.to_string(), path(&b)),
+            ]
+        );
+        assert_eq!(events[1].1["added"][0]["path"], "new.txt");
+
+        // Stopping one leaves the other watched
+        assert!(controller.stop(&a));
+        assert!(!controller.stop(&a));
+        assert_eq!(controller.watched_repos(), vec![b.clone()]);
+        controller.trigger(&b);
+        assert_eq!(
+            tagged(&take()),
+            [(EVENT_FILES_CHANGED.to_string(), path(&b))]
+        );
+
+        controller.stop_all();
+        assert!(controller.watched_repos().is_empty());
+    }
+
+    #[test]
+    fn test_speed_tracker_transitions() {
+        let mut tracker = SpeedTracker::new(Duration::from_millis(500));
+        let ms = Duration::from_millis;
+
+        // Fast repo: nothing to say
+        assert_eq!(tracker.record(ms(100)), None);
+
+        // Crossing up notifies once
+        assert_eq!(
+            tracker.record(ms(800)),
+            Some(RepoSpeed {
+                slow: true,
+                duration_ms: 800
+            })
+        );
+        assert_eq!(tracker.record(ms(900)), None);
+
+        // A slow snapshot mid-recovery restarts the streak
+        assert_eq!(tracker.record(ms(100)), None);
+        assert_eq!(tracker.record(ms(100)), None);
+        assert_eq!(tracker.record(ms(600)), None);
+
+        // Crossing down needs consecutive fast snapshots
+        assert_eq!(tracker.record(ms(100)), None);
+        assert_eq!(tracker.record(ms(100)), None);
+        assert_eq!(
+            tracker.record(ms(50)),
+            Some(RepoSpeed {
+                slow: false,
+                duration_ms: 50
+            })
+        );
+        assert_eq!(tracker.record(ms(100)), None);
+
+        // And it can turn slow again
+        assert_eq!(tracker.record(ms(500)).map(|s| s.slow), Some(true));
+    }
+
+    fn entry(path: &str, bucket: StatusBucket, status: ChangeKind) -> StatusEntry {
+        StatusEntry {
+            path: path.to_string(),
+            bucket,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_diff_status() {
+        use ChangeKind::*;
+        use StatusBucket::*;
+        let old = vec![
+            entry("edited.rs", Unstaged, Modified),
+            entry("reverted.rs", Unstaged, Modified),
+            entry("same.rs", Staged, Added),
+            entry("gone.rs", Unstaged, Modified),
+            entry("staged.rs", Unstaged, Modified),
+            entry("added.rs", Untracked, Added),
+            entry("partial.rs", Unstaged, Modified),
+        ];
+        let new = vec![
+            entry("edited.rs", Unstaged, Modified),
+            entry("same.rs", Staged, Added),
+            entry("gone.rs", Unstaged, Deleted),
+            entry("staged.rs", Staged, Modified),
+            entry("added.rs", Staged, Added),
+            entry("partial.rs", Staged, Modified),
+            entry("partial.rs", Unstaged, Modified),
+            entry("new.rs", Untracked, Added),
+        ];
+
+        let delta = diff_status(&old, &new);
+        // Staging part of a file adds a staged entry without moving it
+        assert_eq!(
+            delta.added,
+            vec![
+                entry("partial.rs", Staged, Modified),
+                entry("new.rs", Untracked, Added),
+            ]
+        );
+        assert_eq!(
+            delta.removed,
+            vec![entry("reverted.rs", Unstaged, Modified)]
+        );
+        assert_eq!(delta.changed, vec![entry("gone.rs", Unstaged, Deleted)]);
+        let moves: Vec<_> = delta
+            .moved
+            .iter()
+            .map(|m| (m.path.as_str(), m.from, m.to, m.status))
+            .collect();
+        assert_eq!(
+            moves,
+            [
+                ("staged.rs", Unstaged, Staged, Modified),
+                ("added.rs", Untracked, Staged, Added),
+            ]
+        );
+
+        assert!(diff_status(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_staging_a_modified_file_moves_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+
+        let controller = RefreshController::new(Recorder::default(), Duration::from_secs(60));
+        let root = dir.path().to_path_buf();
+        std::fs::write(root.join("a.txt"), "two\n").unwrap();
+        controller.start(root.clone()).unwrap();
+
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        controller.events.0.lock().unwrap().clear();
+        controller.trigger(&root);
+
+        let events = std::mem::take(&mut *controller.events.0.lock().unwrap());
+        let (name, delta) = &events[1];
+        assert_eq!(name, EVENT_STATUS_DELTA);
+        assert_eq!(
+            delta["moved"],
+            serde_json::json!([{
+                "path": "a.txt",
+                "from": "unstaged",
+                "to": "staged",
+                "status": "modified",
+            }])
+        );
+        assert_eq!(delta["added"], serde_json::json!([]));
+        assert_eq!(delta["removed"], serde_json::json!([]));
+        controller.stop_all();
     }
 }
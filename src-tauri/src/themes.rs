@@ -59,7 +59,7 @@ pub fn discover_custom_themes() -> Vec<CustomTheme> {
     }
 
     // Sort alphabetically by name
-    themes.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    themes.sort_by_key(|a| a.name.to_lowercase());
 
     themes
 }
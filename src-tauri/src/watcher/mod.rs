@@ -48,12 +48,30 @@ impl From<notify::Error> for WatcherError {
     }
 }
 
+/// How many non-ignored directories `NotifyWatcher` enumerates before it
+/// stops walking and watches the repo root recursively instead.
+pub const DEFAULT_MAX_WALKED_DIRS: usize = 5_000;
+
+/// How a repository's working tree is watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchStrategy {
+    /// Each non-ignored directory, non-recursively. Ignored directories
+    /// (node_modules, target/) generate no events at all, but finding the
+    /// directories means walking the whole tree up front.
+    PerDirectory,
+    /// The repo root, recursively. Starts immediately on huge repos, but
+    /// events from ignored directories arrive too and are only dropped
+    /// afterwards by `should_trigger_refresh`.
+    Recursive,
+}
+
 /// FSEvents-based watcher using the `notify` crate.
 /// Debounces rapid changes and filters irrelevant paths.
 /// Uses `ignore` crate to respect .gitignore when setting up watches.
 pub struct NotifyWatcher {
     debouncer: Option<Debouncer<RecommendedWatcher, RecommendedCache>>,
     watched_paths: HashSet<PathBuf>,
+    max_walked_dirs: usize,
 }
 
 impl Default for NotifyWatcher {
@@ -64,9 +82,16 @@ impl Default for NotifyWatcher {
 
 impl NotifyWatcher {
     pub fn new() -> Self {
+        Self::with_max_walked_dirs(DEFAULT_MAX_WALKED_DIRS)
+    }
+
+    /// Watch per directory only in repos with at most `max_walked_dirs`
+    /// non-ignored directories; bigger ones are watched recursively.
+    pub fn with_max_walked_dirs(max_walked_dirs: usize) -> Self {
         Self {
             debouncer: None,
             watched_paths: HashSet::new(),
+            max_walked_dirs,
         }
     }
 }
@@ -117,43 +142,35 @@ impl WatcherManager for NotifyWatcher {
             },
         )?;
 
-        // Use ignore crate to walk only non-ignored directories
-        // This respects .gitignore and skips node_modules, target/, etc.
-        let mut dirs_to_watch: HashSet<PathBuf> = HashSet::new();
-
-        // Always include repo root
-        dirs_to_watch.insert(repo_path.to_path_buf());
-
-        // Walk the repo, collecting directories that aren't ignored
-        let walker = WalkBuilder::new(repo_path)
-            .hidden(false) // Don't skip hidden files (we want .gitignore'd stuff skipped, not hidden)
-            .git_ignore(true) // Respect .gitignore
-            .git_global(true) // Respect global gitignore
-            .git_exclude(true) // Respect .git/info/exclude
-            .ignore(true) // Respect .ignore files
-            .parents(true) // Check parent directories for ignore files
-            .build();
-
-        for entry in walker.flatten() {
-            if entry.file_type().is_some_and(|ft| ft.is_dir()) {
-                dirs_to_watch.insert(entry.path().to_path_buf());
-            }
-        }
+        let (strategy, mut dirs_to_watch) = collect_watch_dirs(repo_path, self.max_walked_dirs);
 
-        // Watch each directory non-recursively
-        // (we've already enumerated the non-ignored dirs)
-        for dir in &dirs_to_watch {
-            if let Err(e) = debouncer.watch(dir, RecursiveMode::NonRecursive) {
-                log::warn!("Failed to watch {}: {}", dir.display(), e);
-            }
-        }
+        match strategy {
+            WatchStrategy::PerDirectory => {
+                // Watch each directory non-recursively
+                // (we've already enumerated the non-ignored dirs)
+                for dir in &dirs_to_watch {
+                    if let Err(e) = debouncer.watch(dir, RecursiveMode::NonRecursive) {
+                        log::warn!("Failed to watch {}: {}", dir.display(), e);
+                    }
+                }
 
-        // Also watch .git directory for index/HEAD changes
-        let git_dir = repo_path.join(".git");
-        if git_dir.exists() {
-            // Watch .git recursively since it's not walked by ignore crate
-            debouncer.watch(&git_dir, RecursiveMode::Recursive)?;
-            dirs_to_watch.insert(git_dir);
+                // Also watch .git directory for index/HEAD changes
+                let git_dir = repo_path.join(".git");
+                if git_dir.exists() {
+                    // Watch .git recursively since it's not walked by ignore crate
+                    debouncer.watch(&git_dir, RecursiveMode::Recursive)?;
+                    dirs_to_watch.insert(git_dir);
+                }
+            }
+            WatchStrategy::Recursive => {
+                log::info!(
+                    "More than {} directories, watching {} recursively",
+                    self.max_walked_dirs,
+                    repo_path.display()
+                );
+                // Covers .git too
+                debouncer.watch(repo_path, RecursiveMode::Recursive)?;
+            }
         }
 
         self.debouncer = Some(debouncer);
@@ -174,6 +191,43 @@ impl WatcherManager for NotifyWatcher {
     }
 }
 
+/// Pick a watch strategy and the directories it watches.
+///
+/// Walks the non-ignored directories (respecting .gitignore, so node_modules,
+/// target/, etc. are skipped), but stops as soon as there are more than
+/// `max_dirs`: past that, a recursive watch of the root is cheaper than
+/// finishing the walk.
+fn collect_watch_dirs(repo_path: &Path, max_dirs: usize) -> (WatchStrategy, HashSet<PathBuf>) {
+    let mut dirs: HashSet<PathBuf> = HashSet::new();
+
+    // Always include repo root
+    dirs.insert(repo_path.to_path_buf());
+
+    // Walk the repo, collecting directories that aren't ignored
+    let walker = WalkBuilder::new(repo_path)
+        .hidden(false) // Don't skip hidden files (we want .gitignore'd stuff skipped, not hidden)
+        .git_ignore(true) // Respect .gitignore
+        .git_global(true) // Respect global gitignore
+        .git_exclude(true) // Respect .git/info/exclude
+        .ignore(true) // Respect .ignore files
+        .parents(true) // Check parent directories for ignore files
+        .build();
+
+    for entry in walker.flatten() {
+        if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+            dirs.insert(entry.path().to_path_buf());
+            if dirs.len() > max_dirs {
+                return (
+                    WatchStrategy::Recursive,
+                    HashSet::from([repo_path.to_path_buf()]),
+                );
+            }
+        }
+    }
+
+    (WatchStrategy::PerDirectory, dirs)
+}
+
 /// Determine if a file change should trigger a status refresh.
 /// Filters out noise like .git/objects, node_modules, etc.
 fn should_trigger_refresh(path: &Path, repo_root: &Path) -> bool {
@@ -182,7 +236,8 @@ fn should_trigger_refresh(path: &Path, repo_root: &Path) -> bool {
         Err(_) => return false,
     };
 
-    let path_str = relative.to_string_lossy();
+    // The rules below use `/`, but Windows paths use `\`
+    let path_str = relative.to_string_lossy().replace('\\', "/");
 
     // Always trigger on key .git files
     if path_str == ".git/index" || path_str == ".git/HEAD" || path_str.starts_with(".git/refs/") {
@@ -245,6 +300,24 @@ mod tests {
     use super::*;
     use std::path::Path;
 
+    #[test]
+    fn test_collect_watch_dirs_strategy() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for sub in ["a", "b", "c/d", "c/e"] {
+            std::fs::create_dir_all(dir.path().join(sub)).unwrap();
+        }
+
+        // Root plus five directories
+        let (strategy, dirs) = collect_watch_dirs(dir.path(), 10);
+        assert_eq!(strategy, WatchStrategy::PerDirectory);
+        assert_eq!(dirs.len(), 6);
+        assert!(dirs.contains(&dir.path().join("c/e")));
+
+        let (strategy, dirs) = collect_watch_dirs(dir.path(), 3);
+        assert_eq!(strategy, WatchStrategy::Recursive);
+        assert_eq!(dirs, HashSet::from([dir.path().to_path_buf()]));
+    }
+
     #[test]
     fn test_should_trigger_refresh() {
         let repo = Path::new("/repo");
@@ -282,4 +355,32 @@ mod tests {
         ));
         assert!(!should_trigger_refresh(Path::new("/repo/foo.pyc"), repo));
     }
+
+    #[test]
+    fn test_should_trigger_refresh_backslash_paths() {
+        let repo = Path::new("/repo");
+
+        assert!(should_trigger_refresh(Path::new(r"/repo/.git\index"), repo));
+        assert!(should_trigger_refresh(
+            Path::new(r"/repo/.git\refs\heads\main"),
+            repo
+        ));
+        assert!(should_trigger_refresh(
+            Path::new(r"/repo/src\main.rs"),
+            repo
+        ));
+
+        assert!(!should_trigger_refresh(
+            Path::new(r"/repo/.git\objects\ab\cdef123"),
+            repo
+        ));
+        assert!(!should_trigger_refresh(
+            Path::new(r"/repo/node_modules\foo.js"),
+            repo
+        ));
+        assert!(!should_trigger_refresh(
+            Path::new(r"/repo/target\debug\build"),
+            repo
+        ));
+    }
 }
@@ -78,12 +78,13 @@ export function getLanguageFromDiff<T>(
 }
 
 /**
- * Check if a diff represents a binary file.
+ * Check if a diff is binary with no text to show. A file that became (or
+ * stopped being) binary still shows its text side.
  */
 export function isBinaryDiff(diff: FileDiff): boolean {
-  const beforeBinary = diff.before?.content.type === 'binary';
-  const afterBinary = diff.after?.content.type === 'binary';
-  return beforeBinary || afterBinary;
+  const beforeText = diff.before !== null && !diff.before_binary;
+  const afterText = diff.after !== null && !diff.after_binary;
+  return (diff.before_binary || diff.after_binary) && !beforeText && !afterText;
 }
 
 /**
@@ -1,8 +1,39 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import type {
   RepoInfo,
+  Diagnostics,
   GitRef,
+  HeadSha,
+  DiffConfig,
+  DiffResult,
+  DiffComplete,
+  DiffFileReady,
+  DiffRefsValidation,
+  DiffSummary,
   FileDiff,
+  FileContents,
+  CommitChanges,
+  ConflictExport,
+  CommitDiff,
+  CommitMessage,
+  CommitPreview,
+  LogEntry,
+  OperationState,
+  IgnoreStatus,
+  RepoSettings,
+  EffectiveDiffOptions,
+  ChangedPath,
+  NumstatEntry,
+  HunkDensity,
+  Divergence,
+  HunkSlices,
+  ChangeLocation,
+  TripleDiff,
+  WorkingTreeFile,
+  BlameLine,
+  BlobError,
+  Span,
   PullRequest,
   GitHubAuthStatus,
   PRFetchResult,
@@ -21,6 +52,56 @@ export async function getRepoInfo(repoPath?: string): Promise<RepoInfo> {
   });
 }
 
+/**
+ * Get the commit HEAD is at, more cheaply than resolving "HEAD" as a ref.
+ * Resolves to null if the repository has no commits yet.
+ */
+export async function getHeadSha(repoPath?: string): Promise<HeadSha | null> {
+  return invoke<HeadSha | null>('get_head_sha', { repoPath: repoPath ?? null });
+}
+
+/**
+ * Get the git operation (merge, rebase, ...) in progress, if any.
+ */
+export async function getOperationState(repoPath?: string): Promise<OperationState> {
+  return invoke<OperationState>('get_operation_state', {
+    repoPath: repoPath ?? null,
+  });
+}
+
+/**
+ * Get a conflicted file's base, ours, and theirs from the index, plus the
+ * merge in diff3 format to copy into another tool.
+ */
+export async function exportConflict(
+  path: string,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<ConflictExport> {
+  return invoke<ConflictExport>('export_conflict', {
+    repoPath: repoPath ?? null,
+    path,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Check whether git ignores a path, and which rule matched, to explain
+ * why a file doesn't appear in status.
+ */
+export async function isPathIgnored(path: string, repoPath?: string): Promise<IgnoreStatus> {
+  return invoke<IgnoreStatus>('is_path_ignored', { repoPath: repoPath ?? null, path });
+}
+
+/**
+ * Get environment diagnostics (libgit2, CLI tools, repo shape) for bug reports.
+ */
+export async function getDiagnostics(repoPath?: string): Promise<Diagnostics> {
+  return invoke<Diagnostics>('get_diagnostics', {
+    repoPath: repoPath ?? null,
+  });
+}
+
 /**
  * Get the last commit message (for amend UI).
  */
@@ -30,6 +111,103 @@ export async function getLastCommitMessage(repoPath?: string): Promise<string |
   });
 }
 
+/**
+ * Stage one file as it is in the working tree (or its deletion). Rejects if the
+ * file has partially staged changes that this would overwrite; use
+ * `stageFileForce` to overwrite them.
+ */
+export async function stageFile(path: string, repoPath?: string): Promise<void> {
+  return invoke('stage_file', { repoPath: repoPath ?? null, path });
+}
+
+/**
+ * Stage one file as it is in the working tree, replacing anything staged for it.
+ */
+export async function stageFileForce(path: string, repoPath?: string): Promise<void> {
+  return invoke('stage_file_force', { repoPath: repoPath ?? null, path });
+}
+
+/**
+ * Stage all working-tree changes (deletions included), optionally limited to paths
+ * under `prefix`. Pass `includeUntracked: false` to stage only tracked changes.
+ * Returns the number of paths staged.
+ */
+export async function stageAll(
+  includeUntracked = true,
+  prefix?: string,
+  repoPath?: string
+): Promise<number> {
+  return invoke<number>('stage_all', {
+    repoPath: repoPath ?? null,
+    includeUntracked,
+    prefix: prefix ?? null,
+  });
+}
+
+/**
+ * Unstage all staged changes, optionally limited to paths under `prefix`.
+ * Returns the number of paths unstaged.
+ */
+export async function unstageAll(prefix?: string, repoPath?: string): Promise<number> {
+  return invoke<number>('unstage_all', {
+    repoPath: repoPath ?? null,
+    prefix: prefix ?? null,
+  });
+}
+
+/**
+ * Undo one change in a working tree file, restoring its lines from `base`.
+ * `before`/`after` are the spans of a changed alignment from a base..WORKDIR diff
+ * made with `config`'s algorithm; fails if that change no longer exists.
+ */
+export async function revertHunk(
+  base: string,
+  path: string,
+  before: Span,
+  after: Span,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<void> {
+  await invoke('revert_hunk', {
+    repoPath: repoPath ?? null,
+    base,
+    path,
+    before,
+    after,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Discard all staged, unstaged, and untracked changes, stashing them first so
+ * undoLastDiscard can bring them back. Resolves to the stash's SHA.
+ */
+export async function discardAllWithBackup(repoPath?: string): Promise<string> {
+  return invoke<string>('discard_all_with_backup', { repoPath: repoPath ?? null });
+}
+
+/**
+ * Restore the changes the last discardAllWithBackup stashed. Fails, keeping
+ * the stash, if they'd conflict with edits made since.
+ */
+export async function undoLastDiscard(repoPath?: string): Promise<void> {
+  await invoke('undo_last_discard', { repoPath: repoPath ?? null });
+}
+
+/**
+ * Get the messages of the last `n` commits from HEAD, newest first.
+ * Empty if nothing has been committed yet.
+ */
+export async function getRecentCommitMessages(
+  n: number,
+  repoPath?: string
+): Promise<CommitMessage[]> {
+  return invoke<CommitMessage[]>('get_recent_commit_messages', {
+    repoPath: repoPath ?? null,
+    n,
+  });
+}
+
 /**
  * Create a commit with the specified files and message.
  * Returns the short SHA of the new commit.
@@ -46,25 +224,463 @@ export async function createCommit(
   });
 }
 
+/**
+ * Report what committing the specified files would change, without committing
+ * or touching the index.
+ */
+export async function previewCommit(paths: string[], repoPath?: string): Promise<CommitPreview> {
+  return invoke<CommitPreview>('preview_commit', {
+    repoPath: repoPath ?? null,
+    paths,
+  });
+}
+
+/**
+ * Reword the last commit without changing its content (staged changes are untouched).
+ * Prefill the message with getLastCommitMessage. Returns the short SHA of the amended commit.
+ */
+export async function amendCommitMessage(message: string, repoPath?: string): Promise<string> {
+  return invoke<string>('amend_commit_message', {
+    repoPath: repoPath ?? null,
+    message,
+  });
+}
+
 // =============================================================================
 // Diff API
 // =============================================================================
 
+/** Error message of a `getDiff` cancelled by a newer one */
+export const DIFF_CANCELLED = 'Diff cancelled';
+
+/** Whether an error from `getDiff` means a newer request superseded it. */
+export function isDiffCancelled(e: unknown): boolean {
+  return String(e) === DIFF_CANCELLED;
+}
+
 /**
  * Get the full diff between two refs.
  * If `useMergeBase` is true, diffs from the merge-base instead of base directly.
+ * `config` controls excluded paths and binary detection.
+ * Starting another `getDiff` in the same repository cancels this one, which then
+ * rejects with "Diff cancelled".
  */
 export async function getDiff(
   base: string,
   head: string,
   repoPath?: string,
-  useMergeBase?: boolean
-): Promise<FileDiff[]> {
-  return invoke<FileDiff[]>('get_diff', {
+  useMergeBase?: boolean,
+  config?: DiffConfig
+): Promise<DiffResult> {
+  return invoke<DiffResult>('get_diff', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    useMergeBase: useMergeBase ?? false,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Like getDiff, but passes each file to `onFile` as soon as the backend has
+ * computed it, in no particular order. Resolves once every file has been
+ * delivered. Shares getDiff's cancellation.
+ */
+export async function streamDiff(
+  base: string,
+  head: string,
+  onFile: (file: FileDiff) => void,
+  repoPath?: string,
+  useMergeBase?: boolean,
+  config?: DiffConfig
+): Promise<DiffComplete> {
+  const isOurs = (payload: { base: string; head: string }) =>
+    payload.base === base && payload.head === head;
+  let onComplete!: (complete: DiffComplete) => void;
+  const completed = new Promise<DiffComplete>((resolve) => (onComplete = resolve));
+
+  const unlistenFile = await listen<DiffFileReady>('diff-file-ready', (event) => {
+    if (isOurs(event.payload)) onFile(event.payload.file);
+  });
+  const unlistenComplete = await listen<DiffComplete>('diff-complete', (event) => {
+    if (isOurs(event.payload)) onComplete(event.payload);
+  });
+  try {
+    await invoke<number>('stream_diff', {
+      repoPath: repoPath ?? null,
+      base,
+      head,
+      useMergeBase: useMergeBase ?? false,
+      config: config ?? null,
+    });
+    return await completed;
+  } finally {
+    unlistenFile();
+    unlistenComplete();
+  }
+}
+
+/**
+ * Get the full diff of one file, e.g. one getDiff listed with
+ * collapsed_by_default or too_large. Pass a renamed file's old path as oldPath.
+ * Resolves to null if the file has no changes.
+ */
+export async function getFileRefDiff(
+  base: string,
+  head: string,
+  path: string,
+  oldPath?: string,
+  useMergeBase?: boolean,
+  config?: DiffConfig,
+  repoPath?: string
+): Promise<FileDiff | null> {
+  return invoke<FileDiff | null>('get_file_ref_diff', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+    oldPath: oldPath ?? null,
+    useMergeBase: useMergeBase ?? false,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get one file's staged (HEAD to index) and unstaged (index to working tree)
+ * changes together, with rows lining up all three versions.
+ */
+export async function getFileTripleDiff(path: string, repoPath?: string): Promise<TripleDiff> {
+  return invoke<TripleDiff>('get_file_triple_diff', { repoPath: repoPath ?? null, path });
+}
+
+/**
+ * Get what would be pushed: the current branch's upstream to HEAD (from their
+ * merge-base). Fails if the branch has no upstream.
+ */
+export async function getOutgoingDiff(
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<DiffResult> {
+  return invoke<DiffResult>('get_outgoing_diff', {
+    repoPath: repoPath ?? null,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get what would be pulled: HEAD to the current branch's upstream (from their
+ * merge-base). Fails if the branch has no upstream.
+ */
+export async function getIncomingDiff(
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<DiffResult> {
+  return invoke<DiffResult>('get_incoming_diff', {
+    repoPath: repoPath ?? null,
+    config: config ?? null,
+  });
+}
+
+/**
+ * List the commits in base..head, newest first. With `firstParent`, side
+ * branches merged in along the way are skipped.
+ */
+export async function getCommitLog(
+  base: string,
+  head: string,
+  firstParent: boolean,
+  repoPath?: string
+): Promise<LogEntry[]> {
+  return invoke<LogEntry[]>('get_commit_log', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    firstParent,
+  });
+}
+
+/**
+ * Get the diff introduced by a single commit (against its first parent).
+ */
+export async function getCommitDiff(
+  sha: string,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<CommitDiff> {
+  return invoke<CommitDiff>('get_commit_diff', {
+    repoPath: repoPath ?? null,
+    sha,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get the diff each commit in base..head introduces against its first parent,
+ * oldest first, for a commit-by-commit walkthrough. At most `maxCommits`
+ * (default 50) are returned.
+ */
+export async function getPerCommitDiffs(
+  base: string,
+  head: string,
+  maxCommits?: number,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<CommitChanges[]> {
+  return invoke<CommitChanges[]>('get_per_commit_diffs', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    maxCommits: maxCommits ?? null,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Diff a file at a ref (or the working tree) against a file elsewhere on disk,
+ * e.g. the same file in another checkout.
+ */
+export async function getExternalFileDiff(
+  baseRef: string,
+  filePath: string,
+  externalAbsPath: string,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<FileDiff> {
+  return invoke<FileDiff>('get_external_file_diff', {
+    repoPath: repoPath ?? null,
+    baseRef,
+    filePath,
+    externalAbsPath,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get a standard unified diff (with context) for one file, for copying elsewhere.
+ */
+export async function getFilePatch(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<string> {
+  return invoke<string>('get_file_patch', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get who last changed lines `start..end` (0-indexed, exclusive) of a file at a ref.
+ * WORKDIR blames HEAD, so uncommitted lines aren't attributed.
+ */
+export async function getBlame(
+  refStr: string,
+  path: string,
+  start: number,
+  end: number,
+  repoPath?: string
+): Promise<BlameLine[]> {
+  return invoke<BlameLine[]>('get_blame', {
+    repoPath: repoPath ?? null,
+    refStr,
+    path,
+    start,
+    end,
+  });
+}
+
+/**
+ * Get the raw text of one file on both sides of a diff, for running a custom
+ * diff algorithm instead of using the backend's alignments.
+ */
+export async function getFileContents(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<FileContents> {
+  return invoke<FileContents>('get_file_contents', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get the exact bytes of one side of a file (a ref, or 'WORKDIR' for the working
+ * tree), e.g. for rendering an image before and after.
+ * Resolves to null if the file doesn't exist there.
+ * Rejects with a BlobError, `too_large` past maxBytes (default 20 MB).
+ */
+export async function getBlobBytes(
+  ref: string,
+  path: string,
+  maxBytes?: number,
+  repoPath?: string
+): Promise<Uint8Array | null> {
+  try {
+    const bytes = await invoke<ArrayBuffer>('get_blob_bytes', {
+      repoPath: repoPath ?? null,
+      refStr: ref,
+      path,
+      maxBytes: maxBytes ?? null,
+    });
+    return new Uint8Array(bytes);
+  } catch (e) {
+    if ((e as BlobError | undefined)?.kind === 'missing') return null;
+    throw e;
+  }
+}
+
+/**
+ * Get per-hunk change counts for one file, for a scrollbar minimap.
+ */
+export async function getChangeDensity(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<HunkDensity[]> {
+  return invoke<HunkDensity[]>('get_change_density', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get the before and after lines of the changed alignment at `alignmentIndex`
+ * in a file's diff, e.g. to describe that hunk. Pass the config the diff was
+ * loaded with so the index refers to the same alignment.
+ */
+export async function getHunkSlices(
+  base: string,
+  head: string,
+  path: string,
+  alignmentIndex: number,
+  useMergeBase?: boolean,
+  repoPath?: string,
+  config?: DiffConfig
+): Promise<HunkSlices> {
+  return invoke<HunkSlices>('get_hunk_slices', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+    alignmentIndex,
+    useMergeBase: useMergeBase ?? null,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Get every changed region of a diff in display order (files by path, then
+ * top to bottom), for next/previous change shortcuts. With the same config,
+ * alignment indices match getDiff's.
+ */
+export async function getChangeIndex(
+  base: string,
+  head: string,
+  repoPath?: string,
+  useMergeBase?: boolean,
+  config?: DiffConfig
+): Promise<ChangeLocation[]> {
+  return invoke<ChangeLocation[]>('get_change_index', {
     repoPath: repoPath ?? null,
     base,
     head,
     useMergeBase: useMergeBase ?? false,
+    config: config ?? null,
+  });
+}
+
+/**
+ * Find what a file at `basePath` is called at `head`, following renames across the
+ * commits in between. Returns null if the file was deleted.
+ */
+export async function traceRename(
+  basePath: string,
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<string | null> {
+  return invoke<string | null>('trace_rename', {
+    repoPath: repoPath ?? null,
+    basePath,
+    base,
+    head,
+  });
+}
+
+/**
+ * Count the files and lines changed between two refs without loading any
+ * content, e.g. to confirm before opening a huge diff.
+ */
+export async function getDiffSummary(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<DiffSummary> {
+  return invoke<DiffSummary>('get_diff_summary', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+  });
+}
+
+/**
+ * Get the merge-base of refs `a` and `b`, with how many commits `a` is ahead of
+ * and behind `b`. Rejects if they have no common ancestor.
+ */
+export async function getDivergence(a: string, b: string, repoPath?: string): Promise<Divergence> {
+  return invoke<Divergence>('get_divergence', { repoPath: repoPath ?? null, a, b });
+}
+
+/**
+ * List files with uncommitted changes, with their staged and unstaged status
+ * and line counts from HEAD to the working tree.
+ */
+export async function getWorkingTreeOverview(repoPath?: string): Promise<WorkingTreeFile[]> {
+  return invoke<WorkingTreeFile[]>('get_working_tree_overview', {
+    repoPath: repoPath ?? null,
+  });
+}
+
+/**
+ * Get per-file line counts like `git diff --numstat` (null counts for binary files).
+ */
+export async function getNumstat(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<NumstatEntry[]> {
+  return invoke<NumstatEntry[]>('get_numstat', { repoPath: repoPath ?? null, base, head });
+}
+
+/**
+ * Get the changed files with line counts, without loading file content.
+ * Much cheaper than getDiff for rendering the file list.
+ */
+export async function getChangedPaths(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<ChangedPath[]> {
+  return invoke<ChangedPath[]>('get_changed_paths', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
   });
 }
 
@@ -88,6 +704,70 @@ export async function resolveRef(refStr: string, repoPath?: string): Promise<str
   });
 }
 
+/**
+ * Resolve several refs in one call (opens the repo once).
+ * Each entry pairs the input ref with its short SHA (`Ok`) or an error message (`Err`).
+ */
+export async function resolveRefs(
+  refs: string[],
+  repoPath?: string
+): Promise<[string, { Ok: string } | { Err: string }][]> {
+  return invoke<[string, { Ok: string } | { Err: string }][]>('resolve_refs', {
+    repoPath: repoPath ?? null,
+    refs,
+  });
+}
+
+/**
+ * Check that a base/head pair resolves before starting a review.
+ * Special refs (WORKDIR, STAGED, UNSTAGED, EMPTY) always resolve, but
+ * WORKDIR and UNSTAGED are rejected as the base.
+ */
+export async function validateDiffRefs(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<DiffRefsValidation> {
+  return invoke<DiffRefsValidation>('validate_diff_refs', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+  });
+}
+
+// =============================================================================
+// Repo Settings
+// =============================================================================
+
+/**
+ * Load the repository's saved settings (defaults for anything unset).
+ */
+export async function getRepoSettings(repoPath?: string): Promise<RepoSettings> {
+  return invoke<RepoSettings>('get_repo_settings', { repoPath: repoPath ?? null });
+}
+
+/**
+ * Save the repository's settings. Diffs requested without a config use them.
+ */
+export async function saveRepoSettings(settings: RepoSettings, repoPath?: string): Promise<void> {
+  return invoke('save_repo_settings', { repoPath: repoPath ?? null, settings });
+}
+
+/**
+ * Report the diff options the backend uses for a repository (read-only).
+ */
+export async function getEffectiveDiffOptions(repoPath?: string): Promise<EffectiveDiffOptions> {
+  return invoke<EffectiveDiffOptions>('effective_diff_options', { repoPath: repoPath ?? null });
+}
+
+/**
+ * Open a file in the user's editor, at a 1-indexed line if given.
+ * Rejects with an EditorError.
+ */
+export async function openInEditor(path: string, line?: number, repoPath?: string): Promise<void> {
+  return invoke('open_in_editor', { repoPath: repoPath ?? null, path, line: line ?? null });
+}
+
 // =============================================================================
 // GitHub API
 // =============================================================================
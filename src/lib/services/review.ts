@@ -1,24 +1,25 @@
 import { invoke } from '@tauri-apps/api/core';
-import type { Review, Comment, Edit, NewComment, NewEdit } from '../types';
+import type { Review, Comment, Edit, ExportFilter, NewComment, NewEdit } from '../types';
 
 /**
  * Get or create a review for a diff.
  * @param base - Base ref (SHA)
  * @param head - Head ref (SHA or "WORKDIR" for working tree)
  */
-export async function getReview(base: string, head: string): Promise<Review> {
-  return invoke<Review>('get_review', { base, head });
+export async function getReview(base: string, head: string, repoPath?: string): Promise<Review> {
+  return invoke<Review>('get_review', { repoPath: repoPath ?? null, base, head });
 }
 
 /**
- * Add a comment to a review.
+ * Add a comment to a review. Fails if its span is outside the file.
  */
 export async function addComment(
   base: string,
   head: string,
-  comment: NewComment
+  comment: NewComment,
+  repoPath?: string
 ): Promise<Comment> {
-  return invoke<Comment>('add_comment', { base, head, comment });
+  return invoke<Comment>('add_comment', { repoPath: repoPath ?? null, base, head, comment });
 }
 
 /**
@@ -35,37 +36,240 @@ export async function deleteComment(commentId: string): Promise<void> {
   return invoke('delete_comment', { commentId });
 }
 
+/**
+ * Delete every comment on one file of a review. Returns how many were deleted.
+ */
+export async function deleteCommentsForPath(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string
+): Promise<number> {
+  return invoke<number>('delete_comments_for_path', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+  });
+}
+
+/**
+ * Delete several comments at once. Returns how many were deleted.
+ */
+export async function deleteComments(commentIds: string[]): Promise<number> {
+  return invoke<number>('delete_comments', { commentIds });
+}
+
 /**
  * Mark a file as reviewed.
  */
-export async function markReviewed(base: string, head: string, path: string): Promise<void> {
-  return invoke('mark_reviewed', { base, head, path });
+export async function markReviewed(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string
+): Promise<void> {
+  return invoke('mark_reviewed', { repoPath: repoPath ?? null, base, head, path });
 }
 
 /**
  * Unmark a file as reviewed.
  */
-export async function unmarkReviewed(base: string, head: string, path: string): Promise<void> {
-  return invoke('unmark_reviewed', { base, head, path });
+export async function unmarkReviewed(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string
+): Promise<void> {
+  return invoke('unmark_reviewed', { repoPath: repoPath ?? null, base, head, path });
+}
+
+/**
+ * List reviewed files whose content at `head` has changed since they were marked
+ * reviewed, to show as "reviewed, but changed since".
+ */
+export async function getStaleReviewed(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<string[]> {
+  return invoke<string[]>('get_stale_reviewed', { repoPath: repoPath ?? null, base, head });
+}
+
+/**
+ * Set the note on a whole file, replacing any previous one. An empty note removes it.
+ */
+export async function setFileNote(
+  base: string,
+  head: string,
+  path: string,
+  note: string,
+  repoPath?: string
+): Promise<void> {
+  return invoke('set_file_note', { repoPath: repoPath ?? null, base, head, path, note });
+}
+
+/**
+ * Get the note on a file, or null if it has none.
+ */
+export async function getFileNote(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string
+): Promise<string | null> {
+  return invoke<string | null>('get_file_note', { repoPath: repoPath ?? null, base, head, path });
+}
+
+/**
+ * Get how many comments each file has, keyed by path, for sidebar badges.
+ * Files without comments are left out.
+ */
+export async function getCommentCounts(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<Record<string, number>> {
+  return invoke<Record<string, number>>('get_comment_counts', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+  });
+}
+
+/**
+ * Save the UI state for a file in a diff (expanded regions, folding), so it
+ * survives a refresh. Any JSON-serializable value; the backend doesn't read it.
+ */
+export async function setViewState(
+  base: string,
+  head: string,
+  path: string,
+  state: unknown,
+  repoPath?: string
+): Promise<void> {
+  return invoke('set_view_state', { repoPath: repoPath ?? null, base, head, path, state });
+}
+
+/**
+ * Get the UI state saved for a file in a diff, or null if none was saved.
+ */
+export async function getViewState<T = unknown>(
+  base: string,
+  head: string,
+  path: string,
+  repoPath?: string
+): Promise<T | null> {
+  return invoke<T | null>('get_view_state', { repoPath: repoPath ?? null, base, head, path });
 }
 
 /**
  * Record an edit made during review.
  */
-export async function recordEdit(base: string, head: string, edit: NewEdit): Promise<Edit> {
-  return invoke<Edit>('record_edit', { base, head, edit });
+export async function recordEdit(
+  base: string,
+  head: string,
+  edit: NewEdit,
+  repoPath?: string
+): Promise<Edit> {
+  return invoke<Edit>('record_edit', { repoPath: repoPath ?? null, base, head, edit });
+}
+
+/**
+ * Apply a recorded edit to the working tree, adopting the suggestion.
+ * Fails without writing anything if it no longer applies cleanly.
+ */
+export async function applyEdit(edit: Edit, repoPath?: string): Promise<void> {
+  return invoke('apply_edit', { repoPath: repoPath ?? null, edit });
 }
 
 /**
  * Export review as markdown for clipboard.
+ * @param aliases - Optional map of renamed files' old path to new path, so
+ *   comments on either side are grouped under one heading (detected from the
+ *   diff if omitted)
+ * @param filter - Optionally export only some paths or kinds of comment
+ */
+export async function exportReviewMarkdown(
+  base: string,
+  head: string,
+  aliases?: Record<string, string>,
+  filter?: ExportFilter,
+  repoPath?: string
+): Promise<string> {
+  return invoke<string>('export_review_markdown', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    aliases: aliases ?? null,
+    filter: filter ?? null,
+  });
+}
+
+/**
+ * Export review as JSON lines to a file, one record per line. Suited to
+ * reviews too large to copy as markdown.
+ */
+export async function exportReviewJsonl(
+  base: string,
+  head: string,
+  path: string,
+  filter?: ExportFilter,
+  repoPath?: string
+): Promise<void> {
+  return invoke('export_review_jsonl', {
+    repoPath: repoPath ?? null,
+    base,
+    head,
+    path,
+    filter: filter ?? null,
+  });
+}
+
+/**
+ * Draft a PR description from a review: the diff's size, the review's
+ * comments as notes, and the files with suggested edits.
  */
-export async function exportReviewMarkdown(base: string, head: string): Promise<string> {
-  return invoke<string>('export_review_markdown', { base, head });
+export async function generateReviewSummary(
+  base: string,
+  head: string,
+  repoPath?: string
+): Promise<string> {
+  return invoke<string>('generate_review_summary', { repoPath: repoPath ?? null, base, head });
 }
 
 /**
  * Clear a review (e.g., after commit).
  */
-export async function clearReview(base: string, head: string): Promise<void> {
-  return invoke('clear_review', { base, head });
+export async function clearReview(base: string, head: string, repoPath?: string): Promise<void> {
+  return invoke('clear_review', { repoPath: repoPath ?? null, base, head });
+}
+
+/**
+ * Delete every stored review and shrink the review database.
+ */
+export async function clearAllReviews(): Promise<void> {
+  return invoke('clear_all_reviews');
+}
+
+/**
+ * Delete reviews whose commits exist in none of the given repositories.
+ * Returns how many reviews were deleted.
+ */
+export async function pruneReviews(repoPaths: string[]): Promise<number> {
+  return invoke<number>('prune_reviews', { repoPaths });
+}
+
+/**
+ * Move a review to a new base/head (e.g. after a branch rename), keeping
+ * its comments, edits, and reviewed files.
+ */
+export async function moveReview(
+  base: string,
+  head: string,
+  newBase: string,
+  newHead: string,
+  repoPath?: string
+): Promise<void> {
+  return invoke('move_review', { repoPath: repoPath ?? null, base, head, newBase, newHead });
 }
@@ -7,33 +7,113 @@
 
 import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { invoke } from '@tauri-apps/api/core';
+import type { ChangeKind } from '../types';
+
+/** Payload of the 'files-changed' event */
+export interface FilesChanged {
+  /** The watched repository this is about */
+  repo_path: string;
+  /** 'initial' when watching starts, 'watch' for file watcher changes */
+  source: 'initial' | 'watch';
+  /** Milliseconds since the previous notification (null for the first) */
+  since_last_ms: number | null;
+}
+
+/** Which part of `git status` a change belongs to */
+export type StatusBucket = 'staged' | 'unstaged' | 'untracked';
+
+/** One path's change in one status bucket; a partly staged file has two */
+export interface StatusEntry {
+  path: string;
+  bucket: StatusBucket;
+  status: ChangeKind;
+}
+
+/** A path whose change left one status bucket for another */
+export interface StatusMove {
+  path: string;
+  from: StatusBucket;
+  to: StatusBucket;
+  /** The change as it now stands in `to` */
+  status: ChangeKind;
+}
+
+/** Payload of the 'status-delta' event: how the working tree's status entries moved */
+export interface StatusDelta {
+  /** The watched repository this is about */
+  repo_path: string;
+  /** Entries that weren't there before */
+  added: StatusEntry[];
+  /** Entries that are gone */
+  removed: StatusEntry[];
+  /** Entries still there, but with a different change kind */
+  changed: StatusEntry[];
+  /** Paths that left one bucket and entered another */
+  moved: StatusMove[];
+}
+
+/** Payload of the 'repo-speed-changed' event */
+export interface RepoSpeed {
+  /** The watched repository this is about */
+  repo_path: string;
+  /** True when the repo just became slow, false when it recovered */
+  slow: boolean;
+  /** How long the status snapshot that triggered this took */
+  duration_ms: number;
+}
 
 /** Callback for file change notifications */
-export type FilesChangedCallback = () => void;
+export type FilesChangedCallback = (event: FilesChanged) => void;
+
+/** Callback for incremental changed-path updates */
+export type StatusDeltaCallback = (delta: StatusDelta) => void;
+
+/** Callback for slow-repo notifications */
+export type RepoSpeedCallback = (speed: RepoSpeed) => void;
 
 /** Cleanup function returned by subscribe */
 export type Unsubscribe = () => void;
 
-// Active listener
+// Active listeners
 let filesChangedUnlisten: UnlistenFn | null = null;
+let statusDeltaUnlisten: UnlistenFn | null = null;
+let repoSpeedUnlisten: UnlistenFn | null = null;
 
 /**
  * Subscribe to file change events from the backend.
  *
  * @param onFilesChanged - Called whenever files in the repo change
+ * @param onStatusDelta - Optionally called after watch-triggered changes with
+ *   just the status entries that changed, so lists can update incrementally
+ * @param onRepoSpeedChanged - Optionally called when the repo turns slow, and
+ *   again once it's been fast for a few refreshes
  * @returns Cleanup function to unsubscribe
  */
 export async function subscribeToFileChanges(
-  onFilesChanged: FilesChangedCallback
+  onFilesChanged: FilesChangedCallback,
+  onStatusDelta?: StatusDeltaCallback,
+  onRepoSpeedChanged?: RepoSpeedCallback
 ): Promise<Unsubscribe> {
   // Clean up any existing listener first
   await unsubscribeAll();
 
   // Listen for file change notifications
-  filesChangedUnlisten = await listen('files-changed', () => {
-    onFilesChanged();
+  filesChangedUnlisten = await listen<FilesChanged>('files-changed', (event) => {
+    onFilesChanged(event.payload);
   });
 
+  if (onStatusDelta) {
+    statusDeltaUnlisten = await listen<StatusDelta>('status-delta', (event) => {
+      onStatusDelta(event.payload);
+    });
+  }
+
+  if (onRepoSpeedChanged) {
+    repoSpeedUnlisten = await listen<RepoSpeed>('repo-speed-changed', (event) => {
+      onRepoSpeedChanged(event.payload);
+    });
+  }
+
   return unsubscribeAll;
 }
 
@@ -45,19 +125,35 @@ async function unsubscribeAll(): Promise<void> {
     filesChangedUnlisten();
     filesChangedUnlisten = null;
   }
+  if (statusDeltaUnlisten) {
+    statusDeltaUnlisten();
+    statusDeltaUnlisten = null;
+  }
+  if (repoSpeedUnlisten) {
+    repoSpeedUnlisten();
+    repoSpeedUnlisten = null;
+  }
 }
 
 /**
- * Start watching a repository for changes.
- * The backend will emit 'files-changed' events when files change.
+ * Start watching a repository for changes, alongside any already watched.
+ * The backend will emit 'files-changed' events, tagged with the repo path,
+ * when files change.
  */
 export async function startWatching(repoPath: string): Promise<void> {
   await invoke('start_watching', { repoPath });
 }
 
 /**
- * Stop watching the current repository.
+ * Stop watching a repository, or every watched repository if none is given.
+ */
+export async function stopWatching(repoPath?: string): Promise<void> {
+  await invoke('stop_watching', { repoPath: repoPath ?? null });
+}
+
+/**
+ * List the repositories currently being watched.
  */
-export async function stopWatching(): Promise<void> {
-  await invoke('stop_watching');
+export async function getWatchedRepos(): Promise<string[]> {
+  return invoke<string[]>('get_watched_repos');
 }
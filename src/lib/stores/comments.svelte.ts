@@ -16,8 +16,11 @@ import {
   markReviewed as apiMarkReviewed,
   unmarkReviewed as apiUnmarkReviewed,
   exportReviewMarkdown,
+  exportReviewJsonl,
+  clearAllReviews,
 } from '../services/review';
 import { writeText } from '@tauri-apps/plugin-clipboard-manager';
+import { ask, save } from '@tauri-apps/plugin-dialog';
 
 // =============================================================================
 // State
@@ -268,6 +271,54 @@ export async function copyCommentsToClipboard(): Promise<boolean> {
   }
 }
 
+/**
+ * Ask for a destination and export the review there as JSON lines.
+ * Returns false if cancelled or the export failed.
+ */
+export async function saveCommentsAsJsonl(): Promise<boolean> {
+  if (!commentsState.diffBase || !commentsState.diffHead) {
+    console.error('Cannot export: no diff selected');
+    return false;
+  }
+
+  try {
+    const path = await save({
+      defaultPath: 'review.jsonl',
+      filters: [{ name: 'JSON Lines', extensions: ['jsonl'] }],
+    });
+    if (!path) return false;
+
+    await exportReviewJsonl(commentsState.diffBase, commentsState.diffHead, path);
+    return true;
+  } catch (e) {
+    console.error('Failed to export comments:', e);
+    return false;
+  }
+}
+
+/**
+ * Ask for confirmation, then delete every stored review in every repository.
+ * Returns false if cancelled or the deletion failed.
+ */
+export async function confirmClearAllReviews(): Promise<boolean> {
+  try {
+    const confirmed = await ask(
+      'Delete all comments, edits, and reviewed files for every repository? This cannot be undone.',
+      { title: 'Clear all reviews', kind: 'warning' }
+    );
+    if (!confirmed) return false;
+
+    await clearAllReviews();
+    // Keep the selected diff; its review is now empty
+    commentsState.comments = [];
+    commentsState.reviewedPaths = [];
+    return true;
+  } catch (e) {
+    console.error('Failed to clear reviews:', e);
+    return false;
+  }
+}
+
 /**
  * Clear comments state (e.g., when switching repos).
  */
@@ -21,6 +21,14 @@ import type { DiffSpec } from '../types';
  */
 export const WORKDIR = 'WORKDIR';
 
+/**
+ * Special refs scoping uncommitted changes: `HEAD..STAGED` is what's staged,
+ * and UNSTAGED (as head) is always index..working tree. Each scope keeps its
+ * own review. Must match the backend constants in git.rs.
+ */
+export const STAGED = 'STAGED';
+export const UNSTAGED = 'UNSTAGED';
+
 // =============================================================================
 // Presets
 // =============================================================================
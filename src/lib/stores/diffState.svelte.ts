@@ -7,7 +7,7 @@
  * the reactive state object directly.
  */
 
-import { getDiff } from '../services/git';
+import { getDiff, isDiffCancelled } from '../services/git';
 import { getFilePath } from '../diffUtils';
 import type { FileDiff } from '../types';
 
@@ -18,6 +18,8 @@ import type { FileDiff } from '../types';
 export const diffState = $state({
   /** All diffs for the current base..head */
   diffs: [] as FileDiff[],
+  /** Number of changed files hidden by exclude globs */
+  excludedCount: 0,
   /** Whether diffs are currently loading (initial load only) */
   loading: true,
   /** Error message if loading failed */
@@ -78,15 +80,26 @@ export async function loadDiffs(
 ): Promise<void> {
   diffState.loading = true;
   diffState.error = null;
+  // A newer load superseded this one; leave the state to it
+  let superseded = false;
 
   try {
-    diffState.diffs = await getDiff(base, head, repoPath, useMergeBase);
+    const result = await getDiff(base, head, repoPath, useMergeBase);
+    diffState.diffs = result.files;
+    diffState.excludedCount = result.excluded_count;
     updateSelection();
   } catch (e) {
+    if (isDiffCancelled(e)) {
+      superseded = true;
+      return;
+    }
     diffState.error = e instanceof Error ? e.message : String(e);
     diffState.diffs = [];
+    diffState.excludedCount = 0;
   } finally {
-    diffState.loading = false;
+    if (!superseded) {
+      diffState.loading = false;
+    }
   }
 }
 
@@ -101,11 +114,17 @@ export async function refreshDiffs(
   useMergeBase?: boolean
 ): Promise<void> {
   try {
-    diffState.diffs = await getDiff(base, head, repoPath, useMergeBase);
+    const result = await getDiff(base, head, repoPath, useMergeBase);
+    diffState.diffs = result.files;
+    diffState.excludedCount = result.excluded_count;
+    // This may have superseded a load, which then won't clear its loading state
+    diffState.loading = false;
     updateSelection();
   } catch (e) {
     // On refresh errors, keep existing state (don't disrupt UI)
-    console.error('Refresh failed:', e);
+    if (!isDiffCancelled(e)) {
+      console.error('Refresh failed:', e);
+    }
   }
 }
 
@@ -130,6 +149,7 @@ export function clearScrollTarget(): void {
 export function resetState(): void {
   diffState.selectedFile = null;
   diffState.diffs = [];
+  diffState.excludedCount = 0;
   diffState.error = null;
   diffState.loading = true;
 }
@@ -9,6 +9,24 @@ export type FileContent = { type: 'text'; lines: string[] } | { type: 'binary' }
 export interface File {
   path: string;
   content: FileContent;
+  /** Syntax highlighting language (Shiki ID), detected by the backend */
+  language: string | null;
+  /** Lines (0-indexed into content) cut short for transport because they were too long */
+  truncated_lines: number[];
+  /** True for a symbolic link; content is then its target as a single line */
+  is_symlink: boolean;
+  /** Encoding the text was decoded from, null for plain UTF-8 (e.g. "UTF-16LE") */
+  encoding: string | null;
+  /** Set for a Git LFS pointer; content is then a summary of the object */
+  lfs: LfsPointer | null;
+}
+
+/** A parsed Git LFS pointer */
+export interface LfsPointer {
+  /** Object ID with its hash algorithm, e.g. "sha256:4d7a..." */
+  oid: string;
+  /** Size of the real file in bytes */
+  size: number;
 }
 
 /** A contiguous range of lines (0-indexed, exclusive end) */
@@ -23,6 +41,8 @@ export interface Alignment {
   after: Span;
   /** True if this region contains changes */
   changed: boolean;
+  /** Lines omitted from the payload, as offsets from the start of this region */
+  collapsed: Span | null;
 }
 
 /** The diff for a single file between two states */
@@ -33,22 +53,375 @@ export interface FileDiff {
   after: File | null;
   /** Alignments mapping regions between before/after */
   alignments: Alignment[];
+  /** Set when the file mode changed (e.g. made executable, or file <-> symlink) */
+  mode_change: ModeChange | null;
+  /**
+   * Set for files matching DiffConfig.collapse_files: sides carry only their
+   * path, with no lines or alignments. Load it with getFileRefDiff.
+   */
+  collapsed_by_default: boolean;
+  /** True if this side is binary; when only one side is, the other still has its text */
+  before_binary: boolean;
+  after_binary: boolean;
+  /**
+   * Set when a side is bigger than DiffConfig.max_file_size: like a collapsed
+   * file, sides carry only their path. Load it with getFileRefDiff.
+   */
+  too_large: boolean;
+  /** Sizes in bytes of each side, set for too_large files */
+  before_size: number | null;
+  after_size: number | null;
+}
+
+/** Git file modes of both sides, e.g. 0o100644, 0o100755, 0o120000 (symlink) */
+export interface ModeChange {
+  before: number;
+  after: number;
+}
+
+/** Who last changed a single line */
+/** Change counts for one hunk, for a change-density minimap */
+export interface HunkDensity {
+  /** Where the hunk sits in the after file (0-indexed, exclusive end; empty for a pure deletion) */
+  line_start: number;
+  line_end: number;
+  added: number;
+  removed: number;
+}
+
+/** The lines on each side of one changed region of a file */
+export interface HunkSlices {
+  before: string[];
+  after: string[];
+}
+
+/** Where one changed region of a diff is, for stepping between changes */
+export interface ChangeLocation {
+  path: string;
+  /** Index of the region in the file's alignments */
+  alignment_index: number;
+  before_span: Span;
+  after_span: Span;
+}
+
+export interface BlameLine {
+  /** Line number (0-indexed) */
+  lineno: number;
+  /** Short SHA of the commit that last changed the line */
+  sha: string;
+  author: string;
+  /** First line of that commit's message */
+  summary: string;
+}
+
+/** Options for get_diff. All fields are optional; omitted ones use backend defaults */
+export interface DiffConfig {
+  /** Gitignore-style globs for paths to drop (e.g. `package-lock.json`, `vendor/`) */
+  exclude?: string[];
+  /** Globs for generated files (e.g. `*.lock`) listed collapsed, without their content */
+  collapse_files?: string[];
+  /** How many leading bytes to scan for null bytes when sniffing binary files */
+  binary_scan_len?: number;
+  /** Globs for paths always shown as text */
+  force_text?: string[];
+  /** Globs for paths always treated as binary */
+  force_binary?: string[];
+  /** Cut lines longer than this many characters (null disables; default 10000) */
+  max_line_length?: number | null;
+  /** Trim unchanged regions longer than this many lines, keeping a little context */
+  collapse_unchanged?: number;
+  /** Line-matching algorithm (default 'myers'); 'patience' keeps moved blocks intact */
+  algorithm?: DiffAlgorithm;
+  /** Keep only files with these kinds of change (empty or omitted keeps all) */
+  kinds?: ChangeKind[];
+  /** Files with a side bigger than this many bytes are listed too_large, undiffed */
+  max_file_size?: number | null;
+}
+
+/** How changed lines are matched up */
+export type DiffAlgorithm = 'myers' | 'minimal' | 'patience';
+
+/** The type of change a file underwent */
+export type ChangeKind = 'added' | 'modified' | 'deleted';
+
+/** Per-repository preferences; diffs requested without a config use these */
+export interface RepoSettings {
+  exclude: string[];
+  collapse_files: string[];
+  algorithm: DiffAlgorithm;
+  collapse_unchanged: number | null;
+  /** Size in bytes above which files aren't diffed */
+  max_file_size: number | null;
+  /** Command template for opening files, e.g. `code -g {path}:{line}` */
+  editor: string | null;
+}
+
+/** The diff options the backend uses for a repository, for debugging */
+export interface EffectiveDiffOptions {
+  /** Config used when a command isn't given one */
+  config: DiffConfig;
+  /** RepoSettings fields this repository overrides */
+  overrides: string[];
+  /** Always false: whitespace-only changes are shown */
+  ignore_whitespace: boolean;
+  /** git's core.autocrlf, if set */
+  autocrlf: string | null;
+  /** git's core.eol, if set */
+  eol: string | null;
+  /** Similarity percent for rename detection; null if renames aren't detected */
+  rename_threshold: number | null;
+}
+
+/** Why open_in_editor failed; `not_configured` means no editor is set anywhere */
+export type EditorError = { kind: 'not_configured' } | { kind: 'failed'; message: string };
+
+/** Result of get_diff: the changed files plus how many were excluded */
+export interface DiffResult {
+  files: FileDiff[];
+  /** Number of changed files dropped by exclude globs or the kinds filter */
+  excluded_count: number;
+}
+
+/** Payload of the 'diff-file-ready' event: one file of a streamed diff */
+export interface DiffFileReady {
+  /** The base and head the diff was requested with */
+  base: string;
+  head: string;
+  file: FileDiff;
+}
+
+/** Payload of the 'diff-complete' event, sent after a streamed diff's last file */
+export interface DiffComplete {
+  base: string;
+  head: string;
+  file_count: number;
+  /** Number of changed files dropped by exclude globs or the kinds filter */
+  excluded_count: number;
+}
+
+/** How two refs have diverged from their merge-base */
+export interface Divergence {
+  merge_base: string;
+  /** Commits on the first ref that aren't on the second */
+  ahead: number;
+  /** Commits on the second ref that aren't on the first */
+  behind: number;
+}
+
+/** A file with uncommitted changes, as listed in the sidebar */
+export interface WorkingTreeFile {
+  path: string;
+  /** Change staged in the index, if any */
+  staged_status: ChangeKind | null;
+  /** Change not yet staged, if any */
+  unstaged_status: ChangeKind | null;
+  /** Lines added from HEAD to the working tree; 0 for binary files */
+  added: number;
+  removed: number;
+  binary: boolean;
+}
+
+/** Totals for a diff, for warning before loading a very large one */
+export interface DiffSummary {
+  files: number;
+  additions: number;
+  deletions: number;
+}
+
+/** The changes introduced by a single commit */
+export interface CommitDiff {
+  /** Full SHA of the commit being shown */
+  sha: string;
+  /** Full SHA it's diffed against (first parent, or the empty tree for a root commit) */
+  base: string;
+  /** True if the commit has more than one parent (diffed against the first) */
+  is_merge: boolean;
+  files: FileDiff[];
+}
+
+/** One commit's own changes, for stepping through a branch commit by commit */
+export interface CommitChanges {
+  sha: string;
+  /** First line of the commit message */
+  summary: string;
+  /** Diff against the commit's first parent */
+  files: FileDiff[];
+}
+
+/** Both sides of a file as whole text, for running a diff client-side */
+export interface FileContents {
+  /** null if the file doesn't exist on this side, or is binary */
+  before: string | null;
+  after: string | null;
+  before_binary: boolean;
+  after_binary: boolean;
+}
+
+/** Why get_blob_bytes failed; sizes are in bytes */
+export type BlobError =
+  | { kind: 'too_large'; size: number; limit: number }
+  | { kind: 'failed'; message: string }
+  | { kind: 'missing' };
+
+/** Whether each side of a diff resolves, from validate_diff_refs */
+export interface DiffRefsValidation {
+  base_ok: boolean;
+  head_ok: boolean;
+  /** Full commit SHA, or the special ref itself (e.g. WORKDIR) */
+  base_resolved: string | null;
+  head_resolved: string | null;
+  /** Why the pair can't be diffed, naming the side(s) at fault */
+  error: string | null;
+}
+
+/** One file's staged and unstaged changes, for a HEAD | index | working tree view */
+export interface TripleDiff {
+  /** null if the file is the same in HEAD and the index */
+  head_vs_index: FileDiff | null;
+  /** null if the file is the same in the index and the working tree */
+  index_vs_workdir: FileDiff | null;
+  /** Every line of all three versions in display order, lined up through the index */
+  rows: TripleRow[];
+}
+
+/** A line's number (0-indexed) in each version that has it */
+export interface TripleRow {
+  head: number | null;
+  index: number | null;
+  workdir: number | null;
+}
+
+/** A commit's full message, for reword UIs */
+export interface CommitMessage {
+  sha: string;
+  message: string;
+}
+
+/** One commit in a base..head log */
+export interface LogEntry {
+  sha: string;
+  short_sha: string;
+  author: string;
+  /** First line of the commit message */
+  summary: string;
+  /** Commit time, seconds since the Unix epoch */
+  time: number;
+  is_merge: boolean;
+}
+
+/** A changed file without content, for listing the file tree cheaply */
+export interface ChangedPath {
+  path: string;
+  status: 'added' | 'modified' | 'deleted';
+  /** Number of lines added */
+  added: number;
+  /** Number of lines removed */
+  removed: number;
+}
+
+/** One file's line counts, like a line of `git diff --numstat` */
+export interface NumstatEntry {
+  path: string;
+  /** Lines added; null for binary files */
+  added: number | null;
+  /** Lines removed; null for binary files */
+  removed: number | null;
+  binary: boolean;
 }
 
 // =============================================================================
 // Git types
 // =============================================================================
 
+/** What committing a set of files would change, from a dry run */
+export interface CommitPreview {
+  /** Paths whose content would change relative to HEAD */
+  staged_paths: string[];
+  additions: number;
+  deletions: number;
+}
+
 /** Basic repository info */
 export interface RepoInfo {
+  /** Repository root (the git directory for bare repos) */
   repo_path: string;
+  /** Working tree root, or null for a bare repository */
+  workdir: string | null;
+  branch: string | null;
+  /** Merge, rebase, etc. in progress ('clean' if none) */
+  operation: OperationState;
+}
+
+/** The commit HEAD is at */
+export interface HeadSha {
+  full: string;
+  /** The first 8 characters */
+  short: string;
+  /** True if HEAD points straight at a commit rather than a branch */
+  detached: boolean;
+  /** The checked-out branch, null when detached */
   branch: string | null;
 }
 
+/** A multi-step git operation the repository is in the middle of */
+export type OperationState =
+  | 'clean'
+  | 'merge'
+  | 'rebase'
+  | 'cherry_pick'
+  | 'revert'
+  | 'bisect'
+  | 'apply_mailbox';
+
+/** A conflicted file's three index stages */
+export interface ConflictExport {
+  /** The common ancestor; null for an add/add conflict */
+  base: string | null;
+  /** Our side; empty if we deleted the file */
+  ours: string;
+  /** Their side; empty if they deleted the file */
+  theirs: string;
+  /** The merge with diff3-style conflict markers, ready to copy */
+  diff3: string;
+}
+
+/** Whether git ignores a path, and which rule matched */
+export interface IgnoreStatus {
+  ignored: boolean;
+  /** The ignore rule as written, e.g. "target/" */
+  matching_pattern: string | null;
+  /** File the rule came from, e.g. ".gitignore" */
+  source: string | null;
+}
+
+/** An external CLI tool the backend uses */
+export interface ToolInfo {
+  path: string;
+  version: string | null;
+}
+
+/** Environment facts for bug reports */
+export interface Diagnostics {
+  app_version: string;
+  libgit2_version: string;
+  git: ToolInfo | null;
+  gh: ToolInfo | null;
+  repo: {
+    git_dir: string;
+    workdir: string | null;
+    is_bare: boolean;
+    branch: string | null;
+  } | null;
+  /** Why the repository couldn't be opened, if it couldn't */
+  repo_error: string | null;
+}
+
 /** A git reference for autocomplete */
 export interface GitRef {
   name: string;
   ref_type: 'branch' | 'tag' | 'special';
+  /** True for the currently checked-out branch */
+  current: boolean;
 }
 
 // =============================================================================
@@ -109,6 +482,19 @@ export interface Comment {
   /** The line range this comment applies to (0-indexed, exclusive end) */
   span: Span;
   content: string;
+  /** Hash of the commented lines' content, used to re-place the comment after edits */
+  anchor_hash: string | null;
+  /** Where on its first and last lines the comment starts and ends; null covers whole lines */
+  columns: ColumnRange | null;
+}
+
+/**
+ * UTF-8 byte offsets of a comment's start within its first line and its end
+ * within its last line. Both must fall between characters (grapheme clusters).
+ */
+export interface ColumnRange {
+  start: number;
+  end: number;
 }
 
 /** An edit made during review, stored as a unified diff */
@@ -118,12 +504,27 @@ export interface Edit {
   diff: string;
 }
 
+/** Which comments a filtered export keeps */
+export type CommentScope = 'all' | 'global' | 'lines';
+
+/** Narrows a review export; omitted fields keep everything */
+export interface ExportFilter {
+  /** Gitignore-style globs for the paths to keep */
+  paths?: string[];
+  /** 'global' keeps file-level (0..0 span) comments only */
+  comments?: CommentScope;
+}
+
 /** A review attached to a specific diff */
 export interface Review {
   id: DiffId;
   reviewed: string[];
+  /** Blob id each reviewed file had when marked, where known */
+  reviewed_oids: Record<string, string>;
   comments: Comment[];
   edits: Edit[];
+  /** Notes on whole files, by path */
+  notes: Record<string, string>;
 }
 
 /** Input for creating a new comment */
@@ -131,6 +532,10 @@ export interface NewComment {
   path: string;
   span: Span;
   content: string;
+  /** Content of the commented lines, to anchor the comment to */
+  anchor_lines?: string[];
+  /** Start and end within the first and last lines, for part of a line */
+  columns?: ColumnRange;
 }
 
 /** Input for recording a new edit */